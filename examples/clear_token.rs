@@ -2,10 +2,14 @@ use anyhow::Result;
 use std::fs;
 use std::path::PathBuf;
 
-fn get_token_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
+/// `profile` が None / "default" なら `~/.config/hakuhyo`、それ以外は `profiles/<name>` のトークン
+fn get_token_path(profile: Option<&str>) -> Result<PathBuf> {
+    let mut config_dir = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Failed to get config directory"))?
         .join("hakuhyo");
+    if let Some(name) = profile.filter(|name| *name != "default") {
+        config_dir = config_dir.join("profiles").join(name);
+    }
 
     Ok(config_dir.join("token.txt"))
 }
@@ -13,7 +17,8 @@ fn get_token_path() -> Result<PathBuf> {
 fn main() -> Result<()> {
     println!("Clearing saved Discord token from file...");
 
-    let token_path = get_token_path()?;
+    let profile = std::env::args().nth(1);
+    let token_path = get_token_path(profile.as_deref())?;
 
     if token_path.exists() {
         fs::remove_file(&token_path)?;
@@ -1,12 +1,26 @@
-use crate::discord::{Channel, Guild, Message, User};
+use crate::channel_ref::resolve_channel;
+use crate::config::{
+    ChannelOrder, Config, FavoriteOrder, MessageOrder, NonFriendDm, QuitKey, StartupChannel,
+};
+use crate::discord::{
+    signed_url_expiry, Activity, Attachment, Channel, Guild, GuildMember, Message, MessageReply,
+    Presence, Relationship, User, UserStatus, VoiceState, MEMBER_REQUEST_MAX_IDS,
+};
+use crate::emoji::{filter_emoji, PickableEmoji, COMMON_EMOJI};
 use crate::events::AppEvent;
+use crate::glyphs::Glyphs;
+use crate::keywords::contains_keyword;
+use crate::links::{extract_links, parse_message_link};
 use crossterm::event::KeyCode;
 use ratatui::widgets::ListState;
 use ratatui_image::picker::Picker;
 use ratatui_image::protocol::StatefulProtocol;
 // ratatui-image 2.x では StatefulProtocol は trait なので Box<dyn ...> で保持する
 type BoxedImageProtocol = Box<dyn StatefulProtocol>;
-use std::collections::{HashMap, HashSet};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// アプリケーション全体の状態
 pub struct AppState {
@@ -16,6 +30,19 @@ pub struct AppState {
     pub picker: Option<Picker>,
     /// ターミナル背景色 (透明 PNG のアルファ合成用)
     pub bg_color: [u8; 3],
+    /// ターミナルが Kitty/iTerm2/Sixel 等の画像プロトコルに対応しているか
+    /// (halfblocks のみの場合は false。アバター等の重い画像表示の判定に使う)
+    pub graphics_supported: bool,
+    /// 設定ファイルの内容 (お気に入りは ui.favorites 側で管理)
+    pub config: Config,
+    /// 絵文字を使わず ASCII 記号で描画する (`--ascii`)
+    pub ascii_mode: bool,
+    /// 読み取り専用モード: 送信・既読化・削除など Discord への書き込みを一切行わない
+    pub read_only: bool,
+    /// Gateway を使わず REST のポーリングで新着を取得している (`--no-gateway`)
+    pub polling: bool,
+    /// 使用中のプロファイル名 (`--profile`、`A` で切り替え)
+    pub profile: String,
 }
 
 /// Discord関連の状態
@@ -26,6 +53,12 @@ pub struct DiscordState {
     pub users: HashMap<String, User>,            // user_id -> user (DM表示用)
     pub current_user: Option<User>,
     pub connected: bool,
+    /// 一度接続した後に切断され、自動再接続中か
+    pub reconnecting: bool,
+    /// REST の失敗が続き、サーキットブレーカーでリクエストを止めているか
+    pub rest_outage: bool,
+    /// REST が 429 で待っている間の再送時刻
+    pub rest_rate_limited_until: Option<Instant>,
     /// attachment_id -> (area_w_cells, 最後に使った clip_top, 描画用プロトコル)
     /// clip_top: None = 完全表示 (Fit) で使用中、Some(bool) = Crop モードで使用中
     /// CropOptions の切り替え時に ratatui-image 側で再 encode が起きないため、
@@ -43,6 +76,10 @@ pub struct DiscordState {
     pub image_downloading: HashSet<String>,
     /// 過去メッセージ追加読み込み中の channel_id (重複防止)
     pub loading_older: HashSet<String>,
+    /// メッセージ一覧 (LoadMessages) の読み込み結果を待っている channel_id
+    pub messages_loading: HashSet<String>,
+    /// channel_id -> REST でメッセージを最後に取得した時刻 (キャッシュの鮮度判定用)
+    pub messages_fetched_at: HashMap<String, Instant>,
     /// channel_id -> 最後に既読化した message_id (未読判定用)
     pub read_states: HashMap<String, Option<String>>,
     /// channel_id -> 未読メンション数 (ミュート時もメンションがあれば例外的に未読表示)
@@ -72,6 +109,46 @@ pub struct DiscordState {
     pub emoji_protocols: HashMap<String, BoxedImageProtocol>,
     /// ダウンロード中の emoji_id (重複防止)
     pub emoji_downloading: HashSet<String>,
+    /// emoji_id -> 現在の絵文字名 (サーバーの emojis から。content 内の名前が古い場合の解決用)
+    pub emoji_names: HashMap<String, String>,
+    /// user_id -> アバター描画用プロトコル
+    pub avatar_protocols: HashMap<String, BoxedImageProtocol>,
+    /// ダウンロード中 / 取得失敗済みの user_id (重複防止)
+    pub avatar_requested: HashSet<String>,
+    /// guild_id -> サーバーアイコン描画用プロトコル
+    pub guild_icon_protocols: HashMap<String, BoxedImageProtocol>,
+    /// ダウンロード中 / 取得失敗済みの guild_id (重複防止)
+    pub guild_icon_requested: HashSet<String>,
+    /// REST でのユーザー取得を要求済みの user_id (重複リクエスト防止)
+    pub user_lookup_requested: HashSet<String>,
+    /// REST でのチャンネル取得を要求済みの channel_id (重複リクエスト防止)
+    pub channel_lookup_requested: HashSet<String>,
+    /// ポーリングで新着を取得中のチャンネル (応答を待つ間は重ねて取得しない)
+    pub polls_in_flight: HashSet<String>,
+    /// フレンドの user_id (READY の relationships から)
+    pub friends: HashSet<String>,
+    /// READY に relationships が含まれていたか (無ければ DM をメッセージリクエスト扱いしない)
+    pub relationships_loaded: bool,
+    /// guild_id -> (user_id -> メンバー情報)。REST で取得したメッセージには member が
+    /// 含まれないため、ニックネーム・ロールの解決に使う
+    pub members: HashMap<String, HashMap<String, GuildMember>>,
+    /// user_id -> オンライン状態 (READY_SUPPLEMENTAL の merged_presences と PRESENCE_UPDATE)
+    pub presences: HashMap<String, UserStatus>,
+    /// guild_id -> (REQUEST_GUILD_MEMBERS (op 8) を要求済み / 要求待ちの user_id -> 要求した時刻)。
+    /// GUILD_MEMBERS_CHUNK で届いたか MEMBER_REQUEST_TIMEOUT 経ったものは消す
+    pub member_requested: HashMap<String, HashMap<String, Instant>>,
+    /// guild_id -> op 8 で要求する user_id の待ち行列 (Tick で間隔を空けて送る)
+    pub member_request_queue: HashMap<String, Vec<String>>,
+    /// 直近に op 8 を送った時刻
+    pub last_member_request: Option<Instant>,
+    /// READY で unavailable だった (GUILD_CREATE 待ちの) guild_id。
+    /// 一時的な障害中のギルドのチャンネルは未取得なので、残っている間はお気に入りを整理しない
+    pub unavailable_guilds: HashSet<String>,
+    /// guild_id -> (user_id -> 接続中のボイスチャンネル ID)
+    pub voice_states: HashMap<String, HashMap<String, String>>,
+    /// attachment_id -> (再署名した URL, 有効期限の UNIX 秒)。
+    /// メッセージ内の署名付き URL が期限切れになった後の再取得に使う
+    pub attachment_urls: HashMap<String, (String, Option<i64>)>,
 }
 
 /// UI関連の状態
@@ -83,7 +160,7 @@ pub struct UiState {
     pub input_mode: InputMode,
     pub input_buffer: String,
     // 検索・お気に入り関連
-    pub favorites: HashSet<String>,     // お気に入りチャンネルID
+    pub favorites: Vec<String>,          // お気に入りチャンネルID (登録順)
     pub search_mode: bool,               // 検索モードフラグ
     pub search_buffer: String,           // 検索クエリ
     // メッセージリストのスクロール位置 (最新基準のオフセット行数)
@@ -92,20 +169,222 @@ pub struct UiState {
     /// ack で read_states が更新されても、ここの値は変わらないので
     /// チャンネルを開いている間は境界が固定される
     pub unread_boundaries: HashMap<String, String>,
-    /// 描画時に計算した scroll_offset の上限 (ui.rs から書き戻し)。
-    /// 最古到達判定 (apply_scroll 時の過去ロード起動) に使う。
+    /// 選択中チャンネルの "New messages" 区切り線が一度画面に表示されたか。
+    /// 表示後に画面上端より上へスクロールして通過したら区切り線を消す。
+    pub unread_divider_seen: bool,
+    /// チャンネルを開いた直後で、メッセージが揃ったら未読セパレータの位置までスクロールする
+    pub scroll_to_unread: bool,
+    /// リンクからジャンプした先のメッセージ。読み込まれたら画面の中ほどまでスクロールする
+    pub scroll_to_message: Option<String>,
+    /// リンクからジャンプした先として強調表示するメッセージ
+    pub highlighted_message: Option<String>,
+    /// メッセージ選択モードで選択中のメッセージ
+    pub selected_message: Option<String>,
+    /// キャッシュがジャンプ先の前後だけで、最新のメッセージに繋がっていないチャンネル
+    pub detached_history: HashSet<String>,
+    /// channel_id -> 送信待ち (429 を受けて再送待ちのチャンネル)
+    pub send_waits: HashMap<String, SendWait>,
+    /// 読み込み中スピナーのコマ。REST が 429 で待っている間は進めない
+    pub spinner_frame: usize,
+    /// ステータスバーに一時的に表示するメッセージ (次のキー入力で消える)
+    pub status_message: Option<String>,
+    /// 一括削除の確認入力 (`delete <N>`)
+    pub purge_buffer: String,
+    /// ジャンプするメッセージリンクの入力 (`m`)
+    pub jump_buffer: String,
+    /// 実行中 / 直近に終了した一括削除
+    pub purge: Option<PurgeState>,
+    /// 描画時に計算した scroll_offset の上限 (ui.rs から書き戻し。最古まで並べずに
+    /// 描画を終えた場合は usize::MAX)。最古到達判定 (apply_scroll 時の過去ロード起動) に使う。
     pub cached_max_scroll_offset: usize,
+    /// 描画時に並べたメッセージの (id, 最新側の端からの距離で測った始まりと終わり) と
+    /// メッセージ欄の高さ (ui.rs から書き戻し)。選択中のメッセージを画面に収めるのに使う
+    pub cached_message_extents: Vec<(String, u32, u32)>,
+    pub cached_viewport_height: u16,
+    /// 自動追従: true なら最下部表示中の新着で最新へ追従、false なら表示位置を動かさない
+    pub auto_follow: bool,
+    /// 前回描画時の最新メッセージ ID (スクロール中に新着が来ても表示位置を保つために使う)
+    pub newest_rendered_message: Option<String>,
     /// サイドバーで現在カーソルが乗っているリスト (Favorites / Unread)
     pub sidebar_focus: SidebarFocus,
+    /// フォーラム表示中にハイライトしている投稿の index
+    pub forum_post_index: usize,
+    /// フォーラムから開いた投稿を閲覧中の場合、戻り先のフォーラム channel_id
+    pub forum_return: Option<String>,
+    /// 設定したアクティビティを表示中か (`P` で切り替え、保存はしない)
+    pub activity_enabled: bool,
+    /// 表示中のリアクション絵文字ピッカー (None なら非表示)
+    pub emoji_picker: Option<EmojiPicker>,
+    /// 画像プレビューの表示を設定の既定から反転しているメッセージの id (`v` で切り替え)
+    pub image_preview_toggled: HashSet<String>,
+    /// 表示中のアカウント切り替えオーバーレイ (None なら非表示)
+    pub account_switcher: Option<AccountSwitcher>,
+    /// 作成中の返信の返信先 (None なら通常の送信)
+    pub reply_to: Option<ReplyTarget>,
+    /// 入力欄で編集中の自分のメッセージ (channel_id, message_id)。None なら通常の送信
+    pub editing_message: Option<(String, String)>,
+    /// 選択モードで `d` を 1 回押して削除の確認待ちになっているメッセージの id
+    pub pending_delete: Option<String>,
+    /// 作成中のメッセージだけ @everyone / @here の通知を設定の既定から反転しているか
+    /// (入力中に Shift+Tab で切り替え、送信したら戻す)
+    pub mass_mentions_toggled: bool,
+    /// 直前に `g` を押したか (`gg` で最古へジャンプ)
+    pub pending_g: bool,
+    /// 最後に `L` で開いたリンク (message_id, 本文中のリンクの何番目か)
+    pub link_cursor: Option<(String, usize)>,
+    /// 切断中に送信しようとして、再接続待ちのメッセージ (古い順)
+    pub pending_sends: Vec<PendingSend>,
+    /// 送信中・送信に失敗したメッセージ (古い順)
+    pub outgoing_sends: Vec<OutgoingSend>,
+    /// j/k で移動した先のチャンネルと移動した時刻。
+    /// CHANNEL_LOAD_DEBOUNCE の間移動が無ければ Tick でメッセージを読み込む
+    pub pending_channel_load: Option<(String, Instant)>,
+    /// F5 の再読み込み中か (チャンネル一覧の取得完了で結果を表示する)
+    pub refreshing: bool,
+    /// スポイラーの添付を表示しているチャンネル (`s` で切り替え)
+    pub revealed_spoilers: HashSet<String>,
+    /// 添付・埋め込みを上限で省略せずにすべて表示しているチャンネル (`E` で切り替え)
+    pub expanded_channels: HashSet<String>,
+    /// このセッションで警告を確認して表示した NSFW チャンネル
+    pub nsfw_acknowledged: HashSet<String>,
+    /// 終了確認のオーバーレイを表示中か (`quit_key` が "confirm" のとき)
+    pub quit_confirm: bool,
+    /// キーバインド一覧のオーバーレイを表示中か (`?` で開閉)
+    pub help_open: bool,
+    /// キーバインド一覧のスクロール位置 (行)。描画時に末尾を超えないよう切り詰める
+    pub help_scroll: u16,
 }
 
 /// 入力モード
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InputMode {
-    Normal,  // ナビゲーションモード
-    Editing, // 入力モード
+    Normal,       // ナビゲーションモード
+    Editing,      // 入力モード
+    PurgeConfirm, // 自分のメッセージ一括削除の確認入力
+    JumpPrompt,   // メッセージリンクを入力してジャンプ
+    MessageSelect, // メッセージを 1 件選んで操作する (リアクション・返信・リンク)
 }
 
+/// 自分のメッセージ一括削除の進行状況
+#[derive(Debug, Clone)]
+pub struct PurgeState {
+    pub channel_id: String,
+    /// 削除済み件数
+    pub deleted: usize,
+    /// 削除対象件数 (履歴の走査が終わるまでは 0)
+    pub total: usize,
+    /// 終了時の結果表示 (Some なら終了済み。次のキー入力で消える)
+    pub result: Option<String>,
+    /// キャンセル要求フラグ (削除タスクと共有)
+    cancel: Arc<AtomicBool>,
+}
+
+/// リアクション用の絵文字ピッカー (オーバーレイ) の状態
+#[derive(Debug, Clone)]
+pub struct EmojiPicker {
+    pub channel_id: String,
+    /// リアクションを付けるメッセージ
+    pub message_id: String,
+    /// 絞り込みクエリ
+    pub query: String,
+    /// 候補リストの選択位置
+    pub list_state: ListState,
+}
+
+/// アカウント (プロファイル) 切り替えオーバーレイの状態
+#[derive(Debug, Clone)]
+pub struct AccountSwitcher {
+    /// トークンを保存済みのプロファイル名
+    pub profiles: Vec<String>,
+    /// リストの選択位置
+    pub list_state: ListState,
+}
+
+/// 返信先メッセージ。返信を始めた時点の作者名と本文を控えておくので、
+/// スクロールや履歴の入れ替えでメッセージが手元から消えても表示・送信できる
+#[derive(Debug, Clone)]
+pub struct ReplyTarget {
+    pub channel_id: String,
+    pub message_id: String,
+    pub author: String,
+    /// 本文の先頭 (1 行目を REPLY_SNIPPET_MAX_CHARS 文字まで)
+    pub snippet: String,
+    /// 返信先の作者にメンション (通知) するか (入力中に Tab で切り替え、既定は通知する)
+    pub mention: bool,
+}
+
+/// 切断中に送信待ちにしたメッセージ。再接続したら送信し、
+/// PENDING_SEND_TIMEOUT 経っても再接続しなければ取り消す
+#[derive(Debug, Clone)]
+pub struct PendingSend {
+    pub channel_id: String,
+    pub content: String,
+    pub reply_to: Option<MessageReply>,
+    pub mass_mentions: bool,
+    pub queued_at: Instant,
+}
+
+/// 送信中・送信に失敗した自分のメッセージ。メッセージ一覧の最新側に仮の行として表示し、
+/// 送信が完了したら取り除く。失敗したものは `R` で同じ内容を送り直せるよう残しておく
+#[derive(Debug, Clone)]
+pub struct OutgoingSend {
+    pub channel_id: String,
+    pub content: String,
+    pub reply_to: Option<MessageReply>,
+    pub mass_mentions: bool,
+    pub status: SendStatus,
+}
+
+/// 送信中のメッセージの状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendStatus {
+    Sending,
+    /// 送信に失敗した (理由)
+    Failed(String),
+}
+
+/// 送信待ちのメッセージを取り消すまでの時間
+const PENDING_SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// チャンネル移動が止まってからメッセージを読み込むまでの待ち時間
+/// (j/k を押しっぱなしにしたとき、通過したチャンネルごとに REST を呼ばないため)
+const CHANNEL_LOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// キャッシュの差分取得で一度に取得する件数 (これだけ返ったら全件取り直す)
+pub const MESSAGE_DELTA_LIMIT: u8 = 100;
+
+/// MESSAGE_CREATE の重複を確かめる、キャッシュの新しい側からの件数。
+/// 再送されるのは RESUME までに取りこぼした分やポーリング 1 回分なので、最新付近だけを見る
+const DUPLICATE_CHECK_WINDOW: usize = MESSAGE_DELTA_LIMIT as usize;
+
+/// 返信バナーに表示する本文の最大文字数
+const REPLY_SNIPPET_MAX_CHARS: usize = 60;
+
+/// REQUEST_GUILD_MEMBERS (op 8) の送信間隔 (Gateway のレート制限対策)
+const MEMBER_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// op 8 で要求したのに GUILD_MEMBERS_CHUNK に含まれなかった (退出済み等) user_id を
+/// 再要求しないでおく時間
+const MEMBER_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 最近使ったリアクションとして保存する最大件数
+const RECENT_REACTIONS_MAX: usize = 16;
+
+/// 既読位置 (`last_viewed`) を覚えておくチャンネル数の上限
+const LAST_VIEWED_MAX_CHANNELS: usize = 500;
+
+/// 送信待ち (レート制限 / slowmode) の状態
+#[derive(Debug, Clone, Copy)]
+pub struct SendWait {
+    /// 次に送信できる時刻
+    pub until: Instant,
+    /// slowmode による待ちか
+    pub slowmode: bool,
+}
+
+/// 一括削除の確認で入力させるキーワード (`delete <N>`)
+const PURGE_CONFIRM_KEYWORD: &str = "delete";
+
 /// サイドバーでカーソルが乗っているリスト
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SidebarFocus {
@@ -116,17 +395,84 @@ pub enum SidebarFocus {
 /// コマンド（副作用を持つ処理）
 #[derive(Debug, Clone)]
 pub enum Command {
+    /// REST でギルド/チャンネル/DM 一覧を取得
+    /// (READY にギルド情報が含まれない場合のフォールバック、および F5 での再読み込み)
+    LoadChannels,
     LoadMessages(String),
+    /// `GET /users/@me` でトークンがまだ有効か確認する
+    ValidateToken,
+    /// フォーラムの投稿 (アクティブなスレッド) 一覧を取得
+    LoadForumPosts { guild_id: String, forum_id: String },
+    /// 指定 message_id より新しいメッセージを取得 (キャッシュの差分更新)
+    LoadNewerMessages { channel_id: String, after: String },
+    /// ポーリング: 指定 message_id より新しいメッセージを取得して新着として扱う
+    PollChannel { channel_id: String, after: String },
     /// 指定 message_id より古いメッセージを追加読み込み
     LoadOlderMessages { channel_id: String, before: String },
-    SendMessage { channel_id: String, content: String },
+    /// 指定 message_id の前後のメッセージを取得 (リンクからのジャンプ)
+    LoadMessagesAround { channel_id: String, message_id: String },
+    /// メッセージ送信 (`reply_to` があればそのメッセージへの返信)
+    SendMessage {
+        channel_id: String,
+        content: String,
+        reply_to: Option<MessageReply>,
+        /// @everyone / @here / ロールへのメンションで通知するか
+        mass_mentions: bool,
+    },
+    /// 自分のメッセージの本文を書き換える
+    EditMessage {
+        channel_id: String,
+        message_id: String,
+        content: String,
+    },
+    /// 自分のメッセージを 1 件削除
+    DeleteMessage { channel_id: String, message_id: String },
+    /// テキストを端末のクリップボードへコピー (OSC 52)
+    CopyToClipboard(String),
     OpenInDiscord { guild_id: Option<String>, channel_id: String },
+    /// URL を既定のブラウザで開く
+    OpenUrl(String),
     /// 画像添付ファイルのダウンロード (attachment_id, url)
     DownloadImages(Vec<(String, String)>),
     /// カスタム絵文字のダウンロード (emoji_id, url)
     DownloadEmojis(Vec<(String, String)>),
+    /// ユーザーアバターのダウンロード (user_id, url)
+    DownloadAvatars(Vec<(String, String)>),
+    /// サーバーアイコンのダウンロード (guild_id, url)
+    DownloadGuildIcons(Vec<(String, String)>),
     /// チャンネルの最新メッセージを既読化 (公式クライアントにも反映)
     AckChannel { channel_id: String, message_id: String },
+    /// 自分のメッセージを新しい順に最大 count 件削除 (author が user_id のものだけ)
+    PurgeOwnMessages {
+        channel_id: String,
+        user_id: String,
+        count: usize,
+        cancel: Arc<AtomicBool>,
+    },
+    /// ユーザー情報を REST (`GET /users/{id}`) で取得 (READY に含まれない DM 相手の解決用)
+    FetchUsers(Vec<String>),
+    /// 一覧に無いチャンネルを REST (`GET /channels/{id}`) で取得
+    FetchChannel(String),
+    /// 自分のプレゼンスを Gateway (op 3) で更新
+    UpdatePresence(Presence),
+    /// アクティビティだけを Gateway (op 3) で更新 (None で解除)
+    SetActivity(Option<Activity>),
+    /// メッセージにリアクションを付ける (emoji は Unicode 絵文字 or `name:id`)
+    AddReaction {
+        channel_id: String,
+        message_id: String,
+        emoji: String,
+    },
+    /// サーバーメンバーを Gateway (op 8) で要求 (ニックネーム解決用)
+    RequestGuildMembers { guild_id: String, user_ids: Vec<String> },
+    /// 設定ファイルを保存
+    SaveConfig(Box<Config>),
+    /// トークンを保存済みのプロファイルを列挙 (結果は ProfilesListed で返る)
+    ListProfiles,
+    /// 指定プロファイルのアカウントに切り替える (接続を閉じて張り直す)
+    SwitchProfile(String),
+    /// アプリを終了する (run_session のメインループで処理する)
+    Quit,
     /// 複数 Command を一括発火 (例: 画像ダウンロード + ack)
     Batch(Vec<Command>),
     None,
@@ -143,12 +489,17 @@ impl AppState {
                 users: HashMap::new(),
                 current_user: None,
                 connected: false,
+                reconnecting: false,
+                rest_outage: false,
+                rest_rate_limited_until: None,
                 image_protocols: HashMap::new(),
                 image_resized: HashMap::new(),
                 image_partial_protocols: HashMap::new(),
                 image_sources: HashMap::new(),
                 image_downloading: HashSet::new(),
                 loading_older: HashSet::new(),
+                messages_loading: HashSet::new(),
+                messages_fetched_at: HashMap::new(),
                 read_states: HashMap::new(),
                 mention_counts: HashMap::new(),
                 muted_guilds: HashSet::new(),
@@ -160,6 +511,24 @@ impl AppState {
                 unread_cache_dirty: true,
                 emoji_protocols: HashMap::new(),
                 emoji_downloading: HashSet::new(),
+                emoji_names: HashMap::new(),
+                avatar_protocols: HashMap::new(),
+                avatar_requested: HashSet::new(),
+                guild_icon_protocols: HashMap::new(),
+                guild_icon_requested: HashSet::new(),
+                user_lookup_requested: HashSet::new(),
+                channel_lookup_requested: HashSet::new(),
+                polls_in_flight: HashSet::new(),
+                friends: HashSet::new(),
+                relationships_loaded: false,
+                members: HashMap::new(),
+                presences: HashMap::new(),
+                member_requested: HashMap::new(),
+                member_request_queue: HashMap::new(),
+                last_member_request: None,
+                unavailable_guilds: HashSet::new(),
+                voice_states: HashMap::new(),
+                attachment_urls: HashMap::new(),
             },
             ui: UiState {
                 selected_channel: None,
@@ -167,16 +536,60 @@ impl AppState {
                 message_list_state: ListState::default(),
                 input_mode: InputMode::Normal,
                 input_buffer: String::new(),
-                favorites: HashSet::new(),
+                favorites: Vec::new(),
                 search_mode: false,
                 search_buffer: String::new(),
                 message_scroll_offset: 0,
                 cached_max_scroll_offset: 0,
+                cached_message_extents: Vec::new(),
+                cached_viewport_height: 0,
                 sidebar_focus: SidebarFocus::Favorites,
                 unread_boundaries: HashMap::new(),
+                unread_divider_seen: false,
+                scroll_to_unread: false,
+                scroll_to_message: None,
+                highlighted_message: None,
+                selected_message: None,
+                detached_history: HashSet::new(),
+                newest_rendered_message: None,
+                auto_follow: true,
+                send_waits: HashMap::new(),
+                spinner_frame: 0,
+                status_message: None,
+                purge_buffer: String::new(),
+                jump_buffer: String::new(),
+                purge: None,
+                forum_post_index: 0,
+                forum_return: None,
+                activity_enabled: true,
+                emoji_picker: None,
+                image_preview_toggled: HashSet::new(),
+                account_switcher: None,
+                reply_to: None,
+                editing_message: None,
+                pending_delete: None,
+                mass_mentions_toggled: false,
+                pending_g: false,
+                link_cursor: None,
+                pending_sends: Vec::new(),
+                outgoing_sends: Vec::new(),
+                pending_channel_load: None,
+                refreshing: false,
+                revealed_spoilers: HashSet::new(),
+                expanded_channels: HashSet::new(),
+                nsfw_acknowledged: HashSet::new(),
+                quit_confirm: false,
+                help_open: false,
+                help_scroll: 0,
             },
             picker: None,
             bg_color: [28, 28, 32],
+            graphics_supported: false,
+            config: Config::default(),
+            ascii_mode: false,
+            read_only: false,
+            polling: false,
+            profile: crate::profile::DEFAULT_PROFILE.to_string(),
         }
     }
 
@@ -190,1007 +603,5276 @@ impl AppState {
         self.bg_color = bg;
     }
 
-    /// メッセージ群からカスタム絵文字 ID を抽出し、未取得/未進行のものをキューに入れる。
-    /// 返り値はダウンロード対象 (emoji_id, url) のリスト。
-    fn collect_pending_emoji_downloads(&mut self, messages: &[Message]) -> Vec<(String, String)> {
-        let mut to_download = Vec::new();
-        let mut seen: HashSet<String> = HashSet::new();
-        for msg in messages {
-            for seg in crate::emoji::parse_message_segments(&msg.content) {
-                if let crate::emoji::MessageSegment::Emoji { id, .. } = seg {
-                    if seen.contains(&id) {
-                        continue;
-                    }
-                    seen.insert(id.clone());
-                    if self.discord.emoji_protocols.contains_key(&id)
-                        || self.discord.emoji_downloading.contains(&id)
-                    {
-                        continue;
-                    }
-                    self.discord.emoji_downloading.insert(id.clone());
-                    to_download.push((id.clone(), crate::emoji::emoji_cdn_url(&id)));
-                }
-            }
-        }
-        to_download
+    /// 画像プロトコル (Kitty/iTerm2/Sixel) 対応かどうかを設定
+    pub fn set_graphics_supported(&mut self, supported: bool) {
+        self.graphics_supported = supported;
     }
 
-    /// メッセージ内の画像 attachment のうち、まだ未ダウンロード/未進行のものをキューに入れる。
-    /// 返り値はダウンロード対象 (attachment_id, url) のリスト。
-    fn collect_pending_image_downloads(
-        &mut self,
-        messages: &[Message],
-    ) -> Vec<(String, String)> {
-        let mut to_download = Vec::new();
-        for msg in messages {
-            for att in &msg.attachments {
-                let is_image = att
-                    .content_type
-                    .as_deref()
-                    .is_some_and(|ct| ct.starts_with("image/"));
-                if !is_image {
-                    continue;
-                }
-                // image_sources にあれば既にデコード済み (protocols は描画時に生成されるため未生成でも skip)
-                if self.discord.image_sources.contains_key(&att.id)
-                    || self.discord.image_downloading.contains(&att.id)
-                {
-                    continue;
-                }
-                if let Some(url) = &att.url {
-                    self.discord.image_downloading.insert(att.id.clone());
-                    to_download.push((att.id.clone(), url.clone()));
-                }
-            }
-        }
-        to_download
+    /// 設定ファイルの内容を反映
+    pub fn set_config(&mut self, config: Config) {
+        self.ui.auto_follow = config.auto_follow;
+        self.config = config;
     }
 
-    /// お気に入り設定を読み込み
-    pub fn load_favorites(&mut self, favorites: HashSet<String>) {
-        self.ui.favorites = favorites;
-        log::debug!("Loaded {} favorites", self.ui.favorites.len());
+    /// 現在のプレゼンス (`P` でアクティビティを隠している場合は除く)
+    pub fn presence(&self) -> Presence {
+        let mut presence = self.config.presence();
+        if !self.ui.activity_enabled {
+            presence.activity = None;
+        }
+        presence
     }
 
-    /// お気に入り設定を取得
-    pub fn get_favorites(&self) -> &HashSet<String> {
-        &self.ui.favorites
+    /// 終了時などに保存する設定 (お気に入りの現在の並びを反映したもの)
+    pub fn config_to_save(&self) -> Config {
+        Config {
+            favorites: self.ui.favorites.clone(),
+            last_channel: self
+                .ui
+                .selected_channel
+                .clone()
+                .or_else(|| self.config.last_channel.clone()),
+            ..self.config.clone()
+        }
     }
 
-    /// イベントを処理して状態を更新
-    pub fn update(&mut self, event: AppEvent) -> Command {
-        match event {
-            // Gateway イベント
-            AppEvent::GatewayReady(ready_data) => {
-                // ユーザー情報を抽出
-                if let Some(user_data) = ready_data.get("user") {
-                    if let Ok(user) = serde_json::from_value(user_data.clone()) {
-                        self.discord.current_user = Some(user);
-                    }
-                }
-                self.discord.connected = true;
+    /// 読み取り専用モードではオンライン状態・アクティビティも変えない。
+    /// 変えられない場合はステータスバーで知らせて true を返す
+    fn presence_locked(&mut self) -> bool {
+        if self.read_only {
+            self.ui.status_message =
+                Some("Read-only mode: presence and activity are not changed".to_string());
+        }
+        self.read_only
+    }
 
-                // users フィールドからユーザー情報をキャッシュ（DM表示用）
-                if let Some(users_array) = ready_data.get("users").and_then(|v| v.as_array()) {
-                    log::info!("Found {} users in READY event", users_array.len());
-                    for user_data in users_array {
-                        if let Ok(user) = serde_json::from_value::<User>(user_data.clone()) {
-                            self.discord.users.insert(user.id.clone(), user);
-                        }
-                    }
-                    log::info!("Cached {} users", self.discord.users.len());
-                } else {
-                    log::warn!("READY event does NOT contain users field");
-                }
+    /// オンライン状態を online → idle → dnd → invisible の順に切り替え
+    fn cycle_status(&mut self) -> Command {
+        if self.presence_locked() {
+            return Command::None;
+        }
+        self.config.status = self.config.status.next();
+        log::info!("Presence: {}", self.config.status.as_str());
+        Command::UpdatePresence(self.presence())
+    }
 
-                // ギルド情報を抽出して登録
-                if let Some(guilds_array) = ready_data.get("guilds").and_then(|v| v.as_array()) {
-                    for guild_data in guilds_array {
-                        // ギルド情報を抽出
-                        if let (Some(guild_id), Some(guild_name), Some(owner_id)) = (
-                            guild_data.get("id").and_then(|v| v.as_str()),
-                            guild_data.get("properties").and_then(|p| p.get("name")).and_then(|v| v.as_str()),
-                            guild_data.get("properties").and_then(|p| p.get("owner_id")).and_then(|v| v.as_str()),
-                        ) {
-                            let guild = crate::discord::Guild {
-                                id: guild_id.to_string(),
-                                name: guild_name.to_string(),
-                                icon: guild_data.get("properties").and_then(|p| p.get("icon")).and_then(|v| v.as_str()).map(|s| s.to_string()),
-                                owner_id: owner_id.to_string(),
-                            };
+    /// 設定したアクティビティの表示 / 非表示を切り替え
+    fn toggle_activity(&mut self) -> Command {
+        if self.presence_locked() {
+            return Command::None;
+        }
+        let Some(activity) = self.config.activity() else {
+            self.ui.status_message = Some("No valid activity configured".to_string());
+            return Command::None;
+        };
+        self.ui.activity_enabled = !self.ui.activity_enabled;
+        log::info!("Activity enabled: {}", self.ui.activity_enabled);
+        Command::SetActivity(self.ui.activity_enabled.then_some(activity))
+    }
 
-                            self.discord.guilds.insert(guild.id.clone(), guild.clone());
+    /// 使用中のプロファイル名を設定
+    pub fn set_profile(&mut self, profile: String) {
+        self.profile = profile;
+    }
 
-                            // チャンネル情報を抽出（フォーラム/メディアの親解決のため全種類を保存し、
-                            // 表示・検索時に is_messageable() でフィルタする）
-                            if let Some(channels_array) = guild_data.get("channels").and_then(|v| v.as_array()) {
-                                for channel_data in channels_array {
-                                    if let Ok(mut channel) = serde_json::from_value::<crate::discord::Channel>(channel_data.clone()) {
-                                        channel.guild_id = Some(guild.id.clone());
-                                        self.discord.channels.insert(channel.id.clone(), channel);
-                                    }
-                                }
-                            }
+    /// 読み取り専用モード (`--read-only` または設定の `read_only`)
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
 
-                            // スレッド情報を抽出（フォーラム投稿含む）
-                            // ユーザーアカウントの READY では guilds[].threads[] にアクティブなスレッドが入る
-                            if let Some(threads_array) = guild_data.get("threads").and_then(|v| v.as_array()) {
-                                for thread_data in threads_array {
-                                    if let Ok(mut thread) = serde_json::from_value::<crate::discord::Channel>(thread_data.clone()) {
-                                        thread.guild_id = Some(guild.id.clone());
-                                        log::debug!(
-                                            "Adding thread: id={}, type={}, name={:?}, parent_id={:?}",
-                                            thread.id, thread.channel_type, thread.name, thread.parent_id
-                                        );
-                                        self.discord.channels.insert(thread.id.clone(), thread);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    /// Gateway の代わりに REST のポーリングで新着を取得するか (`--no-gateway` / `no_gateway`)
+    pub fn set_polling(&mut self, polling: bool) {
+        self.polling = polling;
+    }
 
-                // read_state エントリを抽出 (チャンネル毎の既読位置 + mention 数)
-                let read_entries = ready_data
-                    .get("read_state")
-                    .and_then(|rs| rs.get("entries").and_then(|v| v.as_array()).or(rs.as_array()));
-                if let Some(entries) = read_entries {
-                    log::info!("READY contains {} read_state entries", entries.len());
-                    for entry in entries {
-                        if let Ok(rs) = serde_json::from_value::<crate::discord::ReadStateEntry>(
-                            entry.clone(),
-                        ) {
-                            self.discord.read_states.insert(rs.id.clone(), rs.last_message_id);
-                            if rs.mention_count > 0 {
-                                self.discord.mention_counts.insert(rs.id, rs.mention_count);
-                            }
-                        }
-                    }
-                }
+    /// 記号を ASCII のみで描画するか (`--ascii` / `ascii_mode`)
+    pub fn set_ascii_mode(&mut self, ascii: bool) {
+        self.ascii_mode = ascii;
+    }
 
-                // ユーザーのギルド毎の通知設定 (ミュート) を抽出
-                let guild_settings = ready_data
-                    .get("user_guild_settings")
-                    .and_then(|s| s.get("entries").and_then(|v| v.as_array()).or(s.as_array()));
-                if let Some(entries) = guild_settings {
-                    log::info!("READY contains {} user_guild_settings entries", entries.len());
-                    for entry in entries {
-                        if let Ok(gs) = serde_json::from_value::<
-                            crate::discord::UserGuildSettingsEntry,
-                        >(entry.clone())
-                        {
-                            if let Some(gid) = gs.guild_id.as_deref() {
-                                if gs.muted {
-                                    self.discord.muted_guilds.insert(gid.to_string());
-                                }
-                            }
-                            for over in gs.channel_overrides {
-                                if over.muted {
-                                    self.discord.muted_channels.insert(over.channel_id);
-                                }
-                            }
-                        }
-                    }
-                }
+    /// 描画に使う記号の表 (`--ascii` / `ascii_mode` なら ASCII のみ)
+    pub fn glyphs(&self) -> &'static Glyphs {
+        Glyphs::for_mode(self.ascii_mode)
+    }
 
-                // DM チャンネルを抽出
-                if let Some(private_channels) = ready_data.get("private_channels").and_then(|v| v.as_array()) {
-                    log::info!("Found {} private channels", private_channels.len());
-                    for channel_data in private_channels.iter() {
-                        if let Ok(mut channel) = serde_json::from_value::<crate::discord::Channel>(channel_data.clone()) {
-                            // recipient_ids から recipients を構築
-                            if let Some(recipient_ids) = &channel.recipient_ids {
-                                let mut recipients = Vec::new();
-                                for user_id in recipient_ids {
-                                    if let Some(user) = self.discord.users.get(user_id) {
-                                        recipients.push(user.clone());
-                                    } else {
-                                        log::warn!("User not found in cache: {}", user_id);
-                                    }
-                                }
-                                channel.recipients = Some(recipients);
-                            }
+    /// チャンネル種別のプレフィックス (`#` / `@` など)
+    pub fn channel_prefix(&self, channel: &Channel) -> &'static str {
+        self.glyphs().channel_prefix(channel.channel_type)
+    }
 
-                            log::debug!(
-                                "Adding DM channel: id={}, type={}, display_name={}",
-                                channel.id,
-                                channel.channel_type,
-                                channel.display_name()
-                            );
-                            self.discord.channels.insert(channel.id.clone(), channel);
-                        } else {
-                            log::warn!("Failed to parse channel data: {}", channel_data);
-                        }
-                    }
-                }
-                log::info!("Total channels after READY: {}", self.discord.channels.len());
+    /// お気に入りマーク (設定値 > 記号の表の既定)
+    pub fn favorite_marker(&self) -> &str {
+        match &self.config.favorite_marker {
+            Some(marker) => marker,
+            None => self.glyphs().favorite,
+        }
+    }
 
-                // 最初のチャンネルを選択（お気に入りを優先）
-                if self.ui.selected_channel.is_none() {
-                    let first_channel_id = {
-                        let favorites = self.get_favorite_channels();
-                        if let Some(ch) = favorites.first() {
-                            Some(ch.id.clone())
-                        } else {
-                            self.get_channel_list().first().map(|ch| ch.id.clone())
-                        }
-                    };
+    /// リスト選択行の記号 (設定値 > 記号の表の既定)
+    pub fn highlight_symbol(&self) -> &str {
+        match &self.config.highlight_symbol {
+            Some(symbol) => symbol,
+            None => self.glyphs().highlight,
+        }
+    }
 
-                    if let Some(channel_id) = first_channel_id {
-                        self.ui.selected_channel = Some(channel_id.clone());
-                        self.ui.channel_list_state.select(Some(0));
-                        return self.select_channel_commands(channel_id);
-                    }
-                }
+    /// 1:1 DM の相手のオンライン状態 (プレゼンス未受信なら None)
+    pub fn dm_status(&self, channel: &Channel) -> Option<UserStatus> {
+        if channel.channel_type != 1 {
+            return None;
+        }
+        let recipient = channel.recipients.as_ref()?.first()?;
+        self.discord.presences.get(&recipient.id).copied()
+    }
 
-                Command::None
-            }
+    /// オンライン状態の記号
+    pub fn status_marker(&self, status: UserStatus) -> &'static str {
+        match status {
+            UserStatus::Offline => self.glyphs().offline,
+            _ => self.glyphs().online,
+        }
+    }
 
-            AppEvent::GuildCreate { guild, channels } => {
-                // ギルド情報を登録
-                self.discord.guilds.insert(guild.id.clone(), guild);
+    /// アバター表示が有効か (設定で opt-in かつ画像プロトコル対応端末のときのみ)
+    pub fn avatars_enabled(&self) -> bool {
+        self.config.show_avatars && self.graphics_supported && self.picker.is_some()
+    }
 
-                // ギルドのチャンネル情報を追加
-                for channel in channels {
-                    self.discord.channels.insert(channel.id.clone(), channel);
-                }
+    /// 指定メッセージの画像添付のプレビューを表示するか
+    /// (設定の `image_previews` を `v` で反転したもの。画像プロトコル非対応端末では常に false)
+    pub fn image_previews_enabled(&self, message_id: &str) -> bool {
+        self.picker.is_some()
+            && self.config.image_previews != self.ui.image_preview_toggled.contains(message_id)
+    }
 
-                // 最初のチャンネルを選択（お気に入りを優先）
-                if self.ui.selected_channel.is_none() {
-                    let first_channel_id = {
-                        let favorites = self.get_favorite_channels();
-                        if let Some(ch) = favorites.first() {
-                            Some(ch.id.clone())
-                        } else {
-                            self.get_channel_list().first().map(|ch| ch.id.clone())
-                        }
-                    };
+    /// 開いていないチャンネルの新着がキーワードを含んでいれば、メンションと同様に
+    /// 未読に数えてステータスバーで知らせる (`highlight_notify` が有効な場合のみ)
+    fn notify_keyword(&mut self, message: &Message) {
+        if !self.config.highlight_notify
+            || !contains_keyword(&message.content, &self.config.highlight_keywords)
+        {
+            return;
+        }
+        let own = self.discord.current_user.as_ref().map(|u| &u.id) == Some(&message.author.id);
+        if own {
+            return;
+        }
+        *self
+            .discord
+            .mention_counts
+            .entry(message.channel_id.clone())
+            .or_default() += 1;
+        let channel = self
+            .discord
+            .channels
+            .get(&message.channel_id)
+            .map(|ch| ch.display_name())
+            .unwrap_or_else(|| message.channel_id.clone());
+        self.ui.status_message = Some(format!(
+            "Keyword in {} from {}",
+            channel,
+            message.author_display_name()
+        ));
+    }
 
-                    if let Some(channel_id) = first_channel_id {
-                        self.ui.selected_channel = Some(channel_id.clone());
-                        self.ui.channel_list_state.select(Some(0));
-                        return self.select_channel_commands(channel_id);
-                    }
-                }
+    /// `non_friend_dm` が "notify" なら、フレンドでない相手からの DM の新着をステータスバーで知らせる
+    fn notify_message_request(&mut self, message: &Message) {
+        if self.config.non_friend_dm != NonFriendDm::Notify {
+            return;
+        }
+        let is_request = self
+            .discord
+            .channels
+            .get(&message.channel_id)
+            .is_some_and(|ch| self.is_message_request(ch));
+        let own = self.discord.current_user.as_ref().map(|u| &u.id) == Some(&message.author.id);
+        if is_request && !own {
+            self.ui.status_message =
+                Some(format!("Message request from {}", message.author_display_name()));
+        }
+    }
 
-                Command::None
-            }
+    /// フレンド一覧に関係 (READY の relationships / RELATIONSHIP_ADD) を反映する。
+    /// フレンド以外 (ブロック・申請中) に変わった相手はフレンドから外す
+    fn apply_relationship(&mut self, relationship: Relationship) {
+        if relationship.is_friend() {
+            self.discord.friends.insert(relationship.id.clone());
+        } else {
+            self.discord.friends.remove(&relationship.id);
+        }
+        if let Some(user) = relationship.user {
+            self.discord.users.entry(user.id.clone()).or_insert(user);
+        }
+    }
 
-            AppEvent::ThreadUpsert(channel) => {
-                log::info!(
-                    "Thread upsert: id={}, name={:?}, parent={:?}",
-                    channel.id, channel.name, channel.parent_id
-                );
-                self.discord.channels.insert(channel.id.clone(), channel);
-                Command::None
-            }
+    /// フレンドでない相手との 1:1 DM (メッセージリクエスト) か。
+    /// READY にフレンド一覧が無かった場合は判定できないので false
+    pub fn is_message_request(&self, channel: &Channel) -> bool {
+        if !self.discord.relationships_loaded || channel.channel_type != 1 {
+            return false;
+        }
+        let recipient = channel
+            .recipient_ids
+            .as_ref()
+            .and_then(|ids| ids.first())
+            .or_else(|| channel.recipients.as_ref()?.first().map(|u| &u.id));
+        recipient.is_some_and(|id| !self.discord.friends.contains(id))
+    }
 
-            AppEvent::ThreadDelete { id } => {
-                self.discord.channels.remove(&id);
-                Command::None
-            }
+    /// `non_friend_dm` が "hide" で、未読一覧・検索に出さない DM か (お気に入りは除く)
+    fn is_hidden_request(&self, channel: &Channel) -> bool {
+        self.config.non_friend_dm == NonFriendDm::Hide
+            && !self.is_favorite(&channel.id)
+            && self.is_message_request(channel)
+    }
 
-            AppEvent::MessageCreate(message) => {
-                let img_pending = self.collect_pending_image_downloads(std::slice::from_ref(&message));
-                let emoji_pending =
-                    self.collect_pending_emoji_downloads(std::slice::from_ref(&message));
-                // 該当チャンネルの last_message_id を更新 (未読判定用)
-                if let Some(channel) = self.discord.channels.get_mut(&message.channel_id) {
-                    channel.last_message_id = Some(message.id.clone());
-                }
-                // 新着が来たら既読化フラグを解除し、未読マークを立てる
-                self.discord.acked_in_session.remove(&message.channel_id);
-                // 現在開いているチャンネルへの新着は自動既読扱いとする (UI上で見えているので)
-                if self.ui.selected_channel.as_deref() != Some(message.channel_id.as_str()) {
-                    self.discord.session_unread.insert(message.channel_id.clone());
-                }
-                self.invalidate_unread_cache();
-                self.discord
-                    .messages
-                    .entry(message.channel_id.clone())
-                    .or_default()
-                    .push(message);
-                batch_commands(img_pending, emoji_pending)
-            }
+    /// 未読一覧で "request" の印を付ける DM か (`non_friend_dm` が "request" のとき)
+    pub fn shows_request_marker(&self, channel: &Channel) -> bool {
+        self.config.non_friend_dm == NonFriendDm::Request && self.is_message_request(channel)
+    }
 
-            AppEvent::MessageUpdate(message) => {
-                // メッセージを更新（簡略化: 既存のメッセージを置き換え）
-                if let Some(messages) = self.discord.messages.get_mut(&message.channel_id) {
-                    if let Some(pos) = messages.iter().position(|m| m.id == message.id) {
-                        messages[pos] = message;
-                    }
-                }
-                Command::None
-            }
+    /// 選択中チャンネルのスポイラー添付を表示 / 非表示 (`s`)。
+    /// 表示したスポイラー画像はここで初めて取得する
+    fn toggle_spoilers(&mut self) -> Command {
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        if !self.ui.revealed_spoilers.remove(&channel_id) {
+            self.ui.revealed_spoilers.insert(channel_id.clone());
+        }
+        let revealed = self.spoilers_revealed(&channel_id);
+        self.ui.status_message = Some(
+            if revealed { "Spoilers shown" } else { "Spoilers hidden" }.to_string(),
+        );
+        let messages = self
+            .discord
+            .messages
+            .get(&channel_id)
+            .cloned()
+            .unwrap_or_default();
+        batch_commands(
+            self.collect_pending_image_downloads(&messages),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
 
-            AppEvent::MessageDelete { id, channel_id } => {
-                // メッセージを削除
-                if let Some(messages) = self.discord.messages.get_mut(&channel_id) {
-                    messages.retain(|m| m.id != id);
-                }
-                Command::None
+    /// `E`: 開いているチャンネルの添付・埋め込みを上限で省略するかを切り替える
+    fn toggle_expanded(&mut self) -> Command {
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        if !self.ui.expanded_channels.remove(&channel_id) {
+            self.ui.expanded_channels.insert(channel_id.clone());
+        }
+        let expanded = self.ui.expanded_channels.contains(&channel_id);
+        self.ui.status_message = Some(
+            if expanded {
+                "Showing every attachment and embed"
+            } else {
+                "Collapsing extra attachments and embeds"
             }
+            .to_string(),
+        );
+        // 展開して見えるようになった画像を取得する
+        let messages = self
+            .discord
+            .messages
+            .get(&channel_id)
+            .cloned()
+            .unwrap_or_default();
+        batch_commands(
+            self.collect_pending_image_downloads(&messages),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
 
-            // コマンド完了イベント
-            AppEvent::MessagesLoaded {
-                channel_id,
-                messages,
-            } => {
-                // 「last_message_id があるはずなのに空が返る」場合は権限なしの可能性 → 除外
-                let has_history = self
-                    .discord
-                    .channels
-                    .get(&channel_id)
-                    .and_then(|c| c.last_message_id.as_ref())
-                    .is_some();
-                if messages.is_empty() && has_history {
-                    log::info!(
-                        "Channel {} appears inaccessible (empty result with last_message_id)",
-                        channel_id
-                    );
-                    self.discord.inaccessible_channels.insert(channel_id.clone());
-                    self.invalidate_unread_cache();
-                }
-                let img_pending = self.collect_pending_image_downloads(&messages);
-                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
-                self.discord.messages.insert(channel_id, messages);
-                batch_commands(img_pending, emoji_pending)
-            }
+    /// 1 メッセージに表示する添付ファイルの上限 (None なら制限しない)
+    pub fn attachment_limit(&self, channel_id: &str) -> Option<usize> {
+        let limit = self.config.max_attachments_per_message;
+        (limit > 0 && !self.ui.expanded_channels.contains(channel_id)).then_some(limit)
+    }
 
-            AppEvent::MessagesLoadFailed {
-                channel_id,
-                permanent,
-            } => {
-                if permanent {
-                    self.discord.inaccessible_channels.insert(channel_id);
-                    self.invalidate_unread_cache();
-                }
-                Command::None
-            }
+    /// 1 メッセージに表示する埋め込みの上限 (None なら制限しない)
+    pub fn embed_limit(&self, channel_id: &str) -> Option<usize> {
+        let limit = self.config.max_embeds_per_message;
+        (limit > 0 && !self.ui.expanded_channels.contains(channel_id)).then_some(limit)
+    }
 
-            AppEvent::AttachmentImageLoaded { attachment_id, image } => {
-                self.discord.image_downloading.remove(&attachment_id);
-                // protocol / resized キャッシュは描画時に area_w が判明してから生成する
-                self.discord.image_sources.insert(attachment_id, *image);
-                Command::None
+    /// チャンネルのスポイラー添付を表示するか
+    pub fn spoilers_revealed(&self, channel_id: &str) -> bool {
+        self.ui.revealed_spoilers.contains(channel_id)
+    }
+
+    /// 選択中のチャンネルが NSFW で、まだ警告を確認していないか
+    /// (スレッドは親チャンネルの指定に従う)
+    pub fn nsfw_gate_active(&self) -> bool {
+        self.ui
+            .selected_channel
+            .as_deref()
+            .is_some_and(|id| self.nsfw_gated(id))
+    }
+
+    /// `channel_id` が NSFW で、まだ警告を確認していないか。確認するまではメッセージの
+    /// 読み込みも既読化もしない
+    fn nsfw_gated(&self, channel_id: &str) -> bool {
+        if self.config.nsfw_acknowledged {
+            return false;
+        }
+        let Some(channel) = self.discord.channels.get(channel_id) else {
+            return false;
+        };
+        let nsfw = channel.nsfw
+            || channel
+                .parent_id
+                .as_ref()
+                .and_then(|pid| self.discord.channels.get(pid))
+                .is_some_and(|parent| parent.nsfw);
+        nsfw && !self.ui.nsfw_acknowledged.contains(&channel.id)
+    }
+
+    /// 画像を含む最新のメッセージ (選択モードでは選択中のメッセージ) の画像プレビューを
+    /// 切り替える。表示に切り替えたメッセージの画像だけを取得する
+    fn toggle_image_previews(&mut self) -> Command {
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        if self.picker.is_none() {
+            self.ui.status_message =
+                Some("Image previews are not supported in this terminal".to_string());
+            return Command::None;
+        }
+        let has_images = |msg: &Message| msg.attachments.iter().any(|a| a.is_image());
+        let target = if self.ui.input_mode == InputMode::MessageSelect {
+            self.target_message(&channel_id).filter(|msg| has_images(msg)).cloned()
+        } else {
+            // メッセージは新しい順に並んでいる
+            self.get_current_messages()
+                .into_iter()
+                .find(|msg| has_images(msg))
+                .cloned()
+        };
+        let Some(message) = target else {
+            let scope = if self.ui.input_mode == InputMode::MessageSelect {
+                "message"
+            } else {
+                "channel"
+            };
+            self.ui.status_message = Some(format!("No images in this {}", scope));
+            return Command::None;
+        };
+        if !self.ui.image_preview_toggled.remove(&message.id) {
+            self.ui.image_preview_toggled.insert(message.id.clone());
+        }
+        let enabled = self.image_previews_enabled(&message.id);
+        log::info!("Image previews of message {}: {}", message.id, enabled);
+        self.ui.status_message = Some(
+            if enabled { "Showing image previews" } else { "Hiding image previews" }.to_string(),
+        );
+        batch_commands(
+            self.collect_pending_image_downloads(std::slice::from_ref(&message)),
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    /// 指定ユーザー群のアバターのうち、未取得/未進行のものをキューに入れる。
+    /// 返り値はダウンロード対象 (user_id, url) のリスト。
+    fn collect_pending_avatar_downloads<'a>(
+        &mut self,
+        users: impl IntoIterator<Item = &'a User>,
+    ) -> Vec<(String, String)> {
+        if !self.avatars_enabled() {
+            return Vec::new();
+        }
+        let mut to_download = Vec::new();
+        for user in users {
+            if self.discord.avatar_requested.contains(&user.id) {
+                continue;
             }
-            AppEvent::AttachmentImageFailed { attachment_id } => {
-                self.discord.image_downloading.remove(&attachment_id);
-                Command::None
+            if let Some(url) = user.avatar_url() {
+                self.discord.avatar_requested.insert(user.id.clone());
+                to_download.push((user.id.clone(), url));
             }
-            AppEvent::EmojiImageLoaded { emoji_id, image } => {
-                self.discord.emoji_downloading.remove(&emoji_id);
-                if let Some(picker) = self.picker.as_mut() {
-                    let protocol =
-                        crate::emoji::prepare_emoji_protocol(picker, *image, self.bg_color);
-                    self.discord.emoji_protocols.insert(emoji_id, protocol);
-                }
-                Command::None
+        }
+        to_download
+    }
+
+    /// サイドバーのバッジ用に、未取得のサーバーアイコンをダウンロードする Command を返す
+    /// (アイコンの無いサーバーと画像非対応端末では頭文字のバッジのまま)
+    fn collect_pending_guild_icon_downloads(&mut self) -> Command {
+        if !self.config.guild_badges || !self.avatars_enabled() {
+            return Command::None;
+        }
+        let mut to_download = Vec::new();
+        for guild in self.discord.guilds.values() {
+            if self.discord.guild_icon_requested.contains(&guild.id) {
+                continue;
             }
-            AppEvent::EmojiImageFailed { emoji_id } => {
-                self.discord.emoji_downloading.remove(&emoji_id);
-                Command::None
+            if let Some(url) = guild.icon_url() {
+                self.discord.guild_icon_requested.insert(guild.id.clone());
+                to_download.push((guild.id.clone(), url));
             }
+        }
+        if to_download.is_empty() {
+            Command::None
+        } else {
+            Command::DownloadGuildIcons(to_download)
+        }
+    }
 
-            AppEvent::MessageSent(message) => {
-                // メッセージ送信後にメッセージリストを再読み込みして最新の状態を取得
-                self.ui.message_scroll_offset = 0;
-                self.select_channel_commands(message.channel_id)
-            }
+    /// 削除されたチャンネルを取り除く。開いていた場合は選択を残し、メッセージ欄に
+    /// 「もう表示できない」旨を出す (古いメッセージを表示し続けない)
+    fn remove_channel(&mut self, channel_id: &str) {
+        let Some(channel) = self.discord.channels.remove(channel_id) else {
+            return;
+        };
+        self.discord.messages.remove(channel_id);
+        if self.ui.selected_channel.as_deref() == Some(channel_id) {
+            log::info!("Selected channel {} was deleted", channel_id);
+            self.leave_message_select();
+            self.ui.status_message = Some(format!("{} was deleted", channel.display_name()));
+        }
+    }
 
-            AppEvent::ScrollMessages(delta) => {
-                self.apply_scroll(delta);
-                if delta > 0 {
-                    // 上方向 (古い側) かつ最古到達時のみ追加読み込みを起動
-                    self.maybe_load_older_messages_if_at_top()
-                } else {
-                    Command::None
-                }
+    /// 選択中のチャンネルが削除されるなどして、もうチャンネル一覧に無いか
+    pub fn selected_channel_unavailable(&self) -> bool {
+        self.ui
+            .selected_channel
+            .as_ref()
+            .is_some_and(|id| !self.discord.channels.contains_key(id))
+    }
+
+    /// サーバーのカスタム絵文字を ID -> 名前の索引に登録
+    fn index_guild_emojis(&mut self, guild: &Guild) {
+        for emoji in &guild.emojis {
+            self.discord
+                .emoji_names
+                .insert(emoji.id.clone(), emoji.name.clone());
+        }
+    }
+
+    /// MESSAGE_CREATE に含まれる member をメンバーキャッシュに保存
+    fn cache_message_member(&mut self, message: &Message) {
+        let Some(member) = &message.member else {
+            return;
+        };
+        let Some(guild_id) = self.channel_guild_id(&message.channel_id) else {
+            return;
+        };
+        self.discord.members.entry(guild_id).or_default().insert(
+            message.author.id.clone(),
+            GuildMember {
+                user: Some(message.author.clone()),
+                nick: member.nick.clone(),
+                roles: member.roles.clone(),
+            },
+        );
+    }
+
+    fn channel_guild_id(&self, channel_id: &str) -> Option<String> {
+        self.discord
+            .channels
+            .get(channel_id)
+            .and_then(|ch| ch.guild_id.clone())
+    }
+
+    /// REST で取得したメッセージ (member なし) の作者をメンバーキャッシュで補完し、
+    /// キャッシュに無い作者は op 8 の要求待ちに入れる
+    fn resolve_members(&mut self, channel_id: &str, messages: &mut [Message]) {
+        let Some(guild_id) = self.channel_guild_id(channel_id) else {
+            return;
+        };
+        self.apply_cached_members(&guild_id, messages);
+        let requested = self.discord.member_requested.entry(guild_id.clone()).or_default();
+        let queue = self.discord.member_request_queue.entry(guild_id).or_default();
+        for msg in messages.iter().filter(|m| m.member.is_none()) {
+            if let Entry::Vacant(entry) = requested.entry(msg.author.id.clone()) {
+                entry.insert(Instant::now());
+                queue.push(msg.author.id.clone());
             }
+        }
+    }
 
-            AppEvent::OlderMessagesLoaded {
-                channel_id,
-                messages,
-            } => {
-                self.discord.loading_older.remove(&channel_id);
-                let img_pending = self.collect_pending_image_downloads(&messages);
-                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
-                // 未初期化チャンネルでも取得結果が破棄されないよう entry().or_default() で挿入
-                self.discord
-                    .messages
-                    .entry(channel_id)
-                    .or_default()
-                    .extend(messages);
-                batch_commands(img_pending, emoji_pending)
+    /// 既に読み込んだギルド内のメッセージにメンバーキャッシュを反映する
+    fn refresh_loaded_members(&mut self, guild_id: &str) {
+        let channel_ids: Vec<String> = self
+            .discord
+            .channels
+            .values()
+            .filter(|ch| ch.guild_id.as_deref() == Some(guild_id))
+            .map(|ch| ch.id.clone())
+            .collect();
+        for channel_id in channel_ids {
+            if let Some(mut messages) = self.discord.messages.remove(&channel_id) {
+                self.apply_cached_members(guild_id, &mut messages);
+                self.discord.messages.insert(channel_id, messages);
             }
+        }
+    }
 
-            // UI イベント
-            AppEvent::KeyPress(key) => self.handle_key_press(key),
-            AppEvent::Input(c) => {
-                if self.ui.input_mode == InputMode::Editing {
-                    self.ui.input_buffer.push(c);
-                }
-                Command::None
+    /// メンバーキャッシュにある作者の member を埋める
+    fn apply_cached_members(&self, guild_id: &str, messages: &mut [Message]) {
+        let Some(cache) = self.discord.members.get(guild_id) else {
+            return;
+        };
+        for msg in messages.iter_mut().filter(|m| m.member.is_none()) {
+            if let Some(member) = cache.get(&msg.author.id) {
+                msg.member = Some(member.to_message_member());
             }
+        }
+    }
 
-            // システムイベント
-            AppEvent::Tick => Command::None,
-            AppEvent::Quit => Command::None,
+    /// 再接続したので送信待ちのメッセージを古い順に送信する
+    fn flush_pending_sends(&mut self) -> Command {
+        let pending = std::mem::take(&mut self.ui.pending_sends);
+        if pending.is_empty() {
+            return Command::None;
         }
+        log::info!("Reconnected, sending {} queued message(s)", pending.len());
+        let cmds = pending
+            .into_iter()
+            .map(|p| self.send_message(p.channel_id, p.content, p.reply_to, p.mass_mentions))
+            .collect();
+        Command::Batch(cmds)
     }
 
-    /// キー入力を処理
-    fn handle_key_press(&mut self, key: KeyCode) -> Command {
-        // 検索モード時の処理
-        if self.ui.search_mode {
-            return match key {
-                KeyCode::Esc => {
-                    self.toggle_search_mode();
-                    Command::None
-                }
-                KeyCode::Backspace => {
-                    self.search_backspace();
-                    Command::None
-                }
-                KeyCode::Up => self.select_previous_channel(),
-                KeyCode::Down => self.select_next_channel(),
-                KeyCode::Enter => {
-                    // チャンネル選択確定して検索モードを終了
-                    self.toggle_search_mode();
-                    self.ui.message_scroll_offset = 0;
-                    if let Some(channel_id) = self.ui.selected_channel.clone() {
-                        self.select_channel_commands(channel_id)
-                    } else {
-                        Command::None
+    /// メッセージを送信する。完了 (MessageSent) か失敗 (SendFailed) まで送信中として表示する。
+    /// 読み取り専用モードでは送信しないので、送信中の表示も残さない
+    fn send_message(
+        &mut self,
+        channel_id: String,
+        content: String,
+        reply_to: Option<MessageReply>,
+        mass_mentions: bool,
+    ) -> Command {
+        if self.read_only {
+            log::info!("Read-only mode: dropped send to {}", channel_id);
+            self.ui.status_message = Some("Read-only mode: message not sent".to_string());
+            return Command::None;
+        }
+        self.start_slowmode_cooldown(&channel_id);
+        self.ui.outgoing_sends.push(OutgoingSend {
+            channel_id: channel_id.clone(),
+            content: content.clone(),
+            reply_to: reply_to.clone(),
+            mass_mentions,
+            status: SendStatus::Sending,
+        });
+        Command::SendMessage {
+            channel_id,
+            content,
+            reply_to,
+            mass_mentions,
+        }
+    }
+
+    /// チャンネルで最も古い送信中のメッセージ。送信はチャンネルごとに順番に行われるので、
+    /// 完了・失敗の通知はこれに対応する
+    fn oldest_sending(&self, channel_id: &str) -> Option<usize> {
+        self.ui
+            .outgoing_sends
+            .iter()
+            .position(|s| s.channel_id == channel_id && s.status == SendStatus::Sending)
+    }
+
+    /// 選択中チャンネルで送信に失敗したメッセージを 1 件送り直す。無ければ None
+    fn retry_failed_send(&mut self) -> Option<Command> {
+        let channel_id = self.ui.selected_channel.as_deref()?;
+        let index = self.ui.outgoing_sends.iter().position(|s| {
+            s.channel_id == channel_id && matches!(s.status, SendStatus::Failed(_))
+        })?;
+        let failed = self.ui.outgoing_sends.remove(index);
+        log::info!("Retrying failed send to {}", failed.channel_id);
+        self.ui.status_message = None;
+        Some(self.send_message(
+            failed.channel_id,
+            failed.content,
+            failed.reply_to,
+            failed.mass_mentions,
+        ))
+    }
+
+    /// 選択中チャンネルの送信中・送信に失敗したメッセージ
+    pub fn current_outgoing_sends(&self) -> Vec<&OutgoingSend> {
+        self.ui
+            .outgoing_sends
+            .iter()
+            .filter(|s| self.ui.selected_channel.as_ref() == Some(&s.channel_id))
+            .collect()
+    }
+
+    /// PENDING_SEND_TIMEOUT 経っても再接続できなかった送信待ちを取り消す。
+    /// 入力欄が空ならそこへ本文を戻し、書いた内容を失わないようにする
+    fn expire_pending_sends(&mut self) {
+        let (expired, pending): (Vec<PendingSend>, Vec<PendingSend>) =
+            std::mem::take(&mut self.ui.pending_sends)
+                .into_iter()
+                .partition(|p| p.queued_at.elapsed() >= PENDING_SEND_TIMEOUT);
+        self.ui.pending_sends = pending;
+        if expired.is_empty() {
+            return;
+        }
+        log::warn!("Cancelled {} queued message(s) after timeout", expired.len());
+        let restored = match expired.first() {
+            Some(first)
+                if expired.len() == 1
+                    && self.ui.input_buffer.is_empty()
+                    && self.ui.selected_channel.as_ref() == Some(&first.channel_id) =>
+            {
+                self.ui.input_buffer = first.content.clone();
+                true
+            }
+            _ => false,
+        };
+        self.ui.status_message = Some(format!(
+            "Still disconnected: cancelled {} queued message(s){}",
+            expired.len(),
+            if restored { " (restored to input)" } else { "" }
+        ));
+    }
+
+    /// 選択中チャンネルの送信待ちメッセージ
+    pub fn current_pending_sends(&self) -> Vec<&PendingSend> {
+        self.ui
+            .pending_sends
+            .iter()
+            .filter(|p| self.ui.selected_channel.as_ref() == Some(&p.channel_id))
+            .collect()
+    }
+
+    /// 要求待ちのメンバーを 1 サーバー分 (最大 MEMBER_REQUEST_MAX_IDS 件) 取り出して op 8 を発行する。
+    /// 前回の送信から MEMBER_REQUEST_INTERVAL 経っていなければ何もしない。
+    /// 応答の無いまま MEMBER_REQUEST_TIMEOUT 経った要求済みの記録もここで消す
+    fn flush_member_requests(&mut self) -> Command {
+        self.discord.member_requested.retain(|_, requested| {
+            requested.retain(|_, at| at.elapsed() < MEMBER_REQUEST_TIMEOUT);
+            !requested.is_empty()
+        });
+        if !self.discord.connected
+            || self
+                .discord
+                .last_member_request
+                .is_some_and(|at| at.elapsed() < MEMBER_REQUEST_INTERVAL)
+        {
+            return Command::None;
+        }
+        let Some(guild_id) = self
+            .discord
+            .member_request_queue
+            .iter()
+            .find(|(_, ids)| !ids.is_empty())
+            .map(|(gid, _)| gid.clone())
+        else {
+            return Command::None;
+        };
+        let Some(queue) = self.discord.member_request_queue.get_mut(&guild_id) else {
+            return Command::None;
+        };
+        let take = queue.len().min(MEMBER_REQUEST_MAX_IDS);
+        let user_ids: Vec<String> = queue.drain(..take).collect();
+        self.discord.last_member_request = Some(Instant::now());
+        log::debug!("Requesting {} members of guild {}", user_ids.len(), guild_id);
+        Command::RequestGuildMembers { guild_id, user_ids }
+    }
+
+    /// カスタム絵文字をテキストで描画する場合の表記 (`:name:`)。
+    /// 画像で描画できる (取得済み / 取得中) 場合は None。
+    /// 名前はサーバーの絵文字一覧を優先する (content 内の名前は改名前のことがある)
+    pub fn custom_emoji_text(&self, id: &str, name: &str) -> Option<String> {
+        let has_image = self.discord.emoji_protocols.contains_key(id)
+            || self.discord.emoji_downloading.contains(id);
+        if self.picker.is_some() && has_image {
+            return None;
+        }
+        let name = self.discord.emoji_names.get(id).map_or(name, String::as_str);
+        Some(format!(":{}:", name))
+    }
+
+    /// メッセージ群からカスタム絵文字 ID を抽出し、未取得/未進行のものをキューに入れる。
+    /// 返り値はダウンロード対象 (emoji_id, url) のリスト。
+    /// 画像を描画できない端末では `:name:` 表記になるので取得しない
+    fn collect_pending_emoji_downloads(&mut self, messages: &[Message]) -> Vec<(String, String)> {
+        let mut to_download = Vec::new();
+        if self.picker.is_none() {
+            return to_download;
+        }
+        let mut seen: HashSet<String> = HashSet::new();
+        for msg in messages {
+            for seg in crate::emoji::parse_message_segments(&msg.content) {
+                if let crate::emoji::MessageSegment::Emoji { id, .. } = seg {
+                    if seen.contains(&id) {
+                        continue;
                     }
+                    seen.insert(id.clone());
+                    if self.discord.emoji_protocols.contains_key(&id)
+                        || self.discord.emoji_downloading.contains(&id)
+                    {
+                        continue;
+                    }
+                    self.discord.emoji_downloading.insert(id.clone());
+                    to_download.push((id.clone(), crate::emoji::emoji_cdn_url(&id)));
                 }
-                KeyCode::Char(c) => {
-                    self.search_input(c);
-                    Command::None
-                }
-                _ => Command::None,
-            };
+            }
         }
+        to_download
+    }
 
-        // 通常モード・編集モードの処理
-        match self.ui.input_mode {
-            InputMode::Normal => match key {
-                KeyCode::Char('q') => Command::None, // Quit は main.rs で処理
-                KeyCode::Char('i') => {
-                    self.ui.input_mode = InputMode::Editing;
-                    Command::None
-                }
-                KeyCode::Char('/') => {
-                    // 検索モードに切り替え
-                    self.toggle_search_mode();
-                    Command::None
-                }
-                KeyCode::Char('f') => {
-                    // お気に入り登録/解除
-                    self.toggle_favorite();
-                    Command::None
+    /// 添付ファイルの取得に使う URL (再署名済みで期限内のものがあればそちらを優先)
+    pub fn attachment_url(&self, attachment: &Attachment) -> Option<String> {
+        if let Some((url, expiry)) = self.discord.attachment_urls.get(&attachment.id) {
+            let now = chrono::Utc::now().timestamp();
+            if !matches!(expiry, Some(expiry) if *expiry <= now) {
+                return Some(url.clone());
+            }
+        }
+        attachment.url.clone()
+    }
+
+    /// メッセージ内の画像 attachment のうち、まだ未ダウンロード/未進行のものをキューに入れる。
+    /// 返り値はダウンロード対象 (attachment_id, url) のリスト。
+    /// プレビューを表示しないメッセージの画像は `[Image: name]` 表記のままにして取得しない
+    fn collect_pending_image_downloads(
+        &mut self,
+        messages: &[Message],
+    ) -> Vec<(String, String)> {
+        let mut to_download = Vec::new();
+        for msg in messages {
+            if !self.image_previews_enabled(&msg.id) {
+                continue;
+            }
+            let reveal = self.spoilers_revealed(&msg.channel_id);
+            // 上限で省略して表示しない添付は取得しない
+            let limit = self.attachment_limit(&msg.channel_id).unwrap_or(usize::MAX);
+            let attachments = msg.attachments.iter().take(limit);
+            for att in attachments.filter(|a| reveal || !a.is_spoiler()) {
+                if !att.is_image() {
+                    continue;
                 }
-                KeyCode::Tab | KeyCode::Char('u') => self.toggle_sidebar_focus(),
-                KeyCode::Char('e') => {
-                    self.apply_scroll(1);
-                    self.maybe_load_older_messages_if_at_top()
+                // image_sources にあれば既にデコード済み (protocols は描画時に生成されるため未生成でも skip)
+                if self.discord.image_sources.contains_key(&att.id)
+                    || self.discord.image_downloading.contains(&att.id)
+                {
+                    continue;
                 }
-                KeyCode::Char('d') => {
-                    self.apply_scroll(-1);
-                    Command::None
+                if let Some(url) = self.attachment_url(att) {
+                    self.discord.image_downloading.insert(att.id.clone());
+                    to_download.push((att.id.clone(), url));
                 }
-                KeyCode::Char('o') => {
-                    // 現在のチャンネルを Discord アプリで開く
-                    if let Some(channel_id) = &self.ui.selected_channel {
-                        let guild_id = self
-                            .discord
-                            .channels
-                            .get(channel_id)
-                            .and_then(|ch| ch.guild_id.clone());
-                        Command::OpenInDiscord {
-                            guild_id,
-                            channel_id: channel_id.clone(),
-                        }
-                    } else {
-                        Command::None
-                    }
+            }
+        }
+        to_download
+    }
+
+    /// お気に入り設定を読み込み
+    pub fn load_favorites(&mut self, favorites: Vec<String>) {
+        // 手動で編集された設定ファイルに重複があっても 1 件にまとめる
+        let mut seen = HashSet::new();
+        self.ui.favorites = favorites
+            .into_iter()
+            .filter(|id| seen.insert(id.clone()))
+            .collect();
+        log::debug!("Loaded {} favorites", self.ui.favorites.len());
+    }
+
+    /// お気に入りに登録済みか
+    pub fn is_favorite(&self, channel_id: &str) -> bool {
+        self.ui.favorites.iter().any(|id| id == channel_id)
+    }
+
+    /// 旧形式 (順序なし) のお気に入りを、それまでの表示順 (channel_order 順) に並べ直す。
+    /// 集合の保存順は不定なので、手動順に切り替えた際に並びが崩れないよう一度だけ行う。
+    /// `all_loaded` が false (REST でギルドを順次取得中) の場合は、全お気に入りの
+    /// チャンネル情報が揃うまで次の機会に回す
+    fn migrate_legacy_favorites(&mut self, all_loaded: bool) {
+        if !self.config.legacy_favorites {
+            return;
+        }
+        let resolved = self
+            .ui
+            .favorites
+            .iter()
+            .all(|id| self.discord.channels.contains_key(id));
+        if !all_loaded && !resolved {
+            return;
+        }
+        let mut favorites = std::mem::take(&mut self.ui.favorites);
+        favorites.sort_by(|a, b| {
+            match (self.discord.channels.get(a), self.discord.channels.get(b)) {
+                (Some(a), Some(b)) => self.compare_channels(a, b),
+                // 未取得のチャンネルは末尾へ
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a.cmp(b),
+            }
+        });
+        self.ui.favorites = favorites;
+        self.config.legacy_favorites = false;
+        log::info!("Migrated {} favorites to the ordered format", self.ui.favorites.len());
+    }
+
+    /// `guild_allowlist` で読み込む対象にしているサーバーか (未設定・DM (None) なら常に true)
+    fn guild_allowed(&self, guild_id: Option<&str>) -> bool {
+        match guild_id {
+            Some(id) if !self.config.guild_allowlist.is_empty() => {
+                self.config.guild_allowlist.iter().any(|allowed| allowed == id)
+            }
+            _ => true,
+        }
+    }
+
+    /// アクセスできなくなった (退出したサーバー・削除されたチャンネルの) お気に入りを整理する。
+    /// 全チャンネル情報が揃った時点で呼ぶこと。unavailable なギルドが残っている間は何もしない。
+    /// 一覧に無いだけでは消さない (REST の一覧にはスレッドが無く、READY にもアーカイブ済みの
+    /// スレッドは無い)。GET /channels/{id} で確かめ、404 / 403 が返ったものだけを
+    /// ChannelFetchFailed で削除する
+    fn prune_stale_favorites(&mut self) -> Command {
+        // 読み込まなかったサーバーのお気に入りは、退出したものと区別できないので消さない
+        if !self.config.guild_allowlist.is_empty() {
+            log::debug!("Skipping favorites cleanup: guild_allowlist is set");
+            return Command::None;
+        }
+        if !self.discord.unavailable_guilds.is_empty() {
+            log::debug!(
+                "Skipping favorites cleanup: {} guilds unavailable",
+                self.discord.unavailable_guilds.len()
+            );
+            return Command::None;
+        }
+        let missing: Vec<String> = self
+            .ui
+            .favorites
+            .iter()
+            .filter(|id| !self.discord.channels.contains_key(*id))
+            .cloned()
+            .collect();
+        let checks: Vec<Command> = missing
+            .into_iter()
+            .filter(|id| self.discord.channel_lookup_requested.insert(id.clone()))
+            .map(Command::FetchChannel)
+            .collect();
+        if checks.is_empty() {
+            return Command::None;
+        }
+        log::info!("Checking {} favorites missing from the channel list", checks.len());
+        Command::Batch(checks)
+    }
+
+    /// 削除された・アクセスできなくなったことが確かなチャンネルをお気に入りから外して保存する
+    fn remove_favorite_if_gone(&mut self, channel_id: &str) -> Command {
+        // 消えたチャンネルの既読位置も使うことはないので忘れる (保存は他の変更と一緒に)
+        self.config.last_viewed.remove(channel_id);
+        let before = self.ui.favorites.len();
+        self.ui.favorites.retain(|id| id != channel_id);
+        if self.ui.favorites.len() == before {
+            return Command::None;
+        }
+        log::info!("Removed inaccessible favorite {}", channel_id);
+        Command::SaveConfig(Box::new(self.config_to_save()))
+    }
+
+    /// イベントを処理して状態を更新
+    pub fn update(&mut self, event: AppEvent) -> Command {
+        let command = self.handle_event(event);
+        self.track_message_loads(&command);
+        if self.read_only {
+            strip_outgoing(command)
+        } else {
+            command
+        }
+    }
+
+    /// 発行する LoadMessages のチャンネルを読み込み中として記録する (結果が届いたら外す)
+    fn track_message_loads(&mut self, command: &Command) {
+        match command {
+            Command::LoadMessages(channel_id) => {
+                self.discord.messages_loading.insert(channel_id.clone());
+            }
+            Command::Batch(cmds) => {
+                for c in cmds {
+                    self.track_message_loads(c);
                 }
-                KeyCode::Up | KeyCode::Char('k') => self.select_previous_channel(),
-                KeyCode::Down | KeyCode::Char('j') => self.select_next_channel(),
-                KeyCode::Enter => {
-                    // チャンネル選択確定
-                    self.ui.message_scroll_offset = 0;
-                    if let Some(channel_id) = self.ui.selected_channel.clone() {
-                        self.select_channel_commands(channel_id)
-                    } else {
-                        Command::None
+            }
+            _ => {}
+        }
+    }
+
+    /// イベントごとの状態更新本体
+    fn handle_event(&mut self, event: AppEvent) -> Command {
+        match event {
+            // Gateway イベント
+            AppEvent::GatewayReady(ready_data) => {
+                // ユーザー情報を抽出
+                if let Some(user_data) = ready_data.get("user") {
+                    if let Ok(user) = serde_json::from_value(user_data.clone()) {
+                        self.discord.current_user = Some(user);
                     }
                 }
-                _ => Command::None,
-            },
-            InputMode::Editing => match key {
-                KeyCode::Esc => {
-                    self.ui.input_mode = InputMode::Normal;
-                    Command::None
-                }
-                KeyCode::Enter => {
-                    if !self.ui.input_buffer.is_empty() {
-                        let content = self.ui.input_buffer.clone();
-                        self.ui.input_buffer.clear();
+                self.discord.connected = true;
+                self.discord.reconnecting = false;
 
-                        if let Some(channel_id) = &self.ui.selected_channel {
-                            return Command::SendMessage {
-                                channel_id: channel_id.clone(),
-                                content,
-                            };
+                // users フィールドからユーザー情報をキャッシュ（DM表示用）
+                if let Some(users_array) = ready_data.get("users").and_then(|v| v.as_array()) {
+                    log::info!("Found {} users in READY event", users_array.len());
+                    for user_data in users_array {
+                        if let Ok(user) = serde_json::from_value::<User>(user_data.clone()) {
+                            self.discord.users.insert(user.id.clone(), user);
                         }
                     }
-                    Command::None
-                }
-                KeyCode::Backspace => {
-                    self.ui.input_buffer.pop();
-                    Command::None
-                }
-                KeyCode::Char(c) => {
-                    self.ui.input_buffer.push(c);
-                    Command::None
+                    log::info!("Cached {} users", self.discord.users.len());
+                } else {
+                    log::warn!("READY event does NOT contain users field");
                 }
-                _ => Command::None,
-            },
-        }
+
+                // READY にギルド情報が無い場合は REST で取得する
+                let needs_rest_channels =
+                    match ready_data.get("guilds").and_then(|v| v.as_array()) {
+                        Some(guilds) => guilds.is_empty(),
+                        None => true,
+                    };
+
+                // ギルド情報を抽出して登録
+                self.discord.unavailable_guilds.clear();
+                if let Some(guilds_array) = ready_data.get("guilds").and_then(|v| v.as_array()) {
+                    let mut skipped = 0;
+                    for guild_data in guilds_array {
+                        // guild_allowlist に無いサーバーはチャンネル等を一切取り込まない
+                        if !self.guild_allowed(guild_data.get("id").and_then(|v| v.as_str())) {
+                            skipped += 1;
+                            continue;
+                        }
+                        // 障害中のギルドは id のみ届き、復旧後に GUILD_CREATE で送られてくる
+                        if guild_data.get("unavailable").and_then(|v| v.as_bool()) == Some(true) {
+                            if let Some(guild_id) = guild_data.get("id").and_then(|v| v.as_str()) {
+                                log::info!("Guild {} is unavailable", guild_id);
+                                self.discord.unavailable_guilds.insert(guild_id.to_string());
+                            }
+                            continue;
+                        }
+                        // ギルド情報を抽出
+                        if let (Some(guild_id), Some(guild_name), Some(owner_id)) = (
+                            guild_data.get("id").and_then(|v| v.as_str()),
+                            guild_data.get("properties").and_then(|p| p.get("name")).and_then(|v| v.as_str()),
+                            guild_data.get("properties").and_then(|p| p.get("owner_id")).and_then(|v| v.as_str()),
+                        ) {
+                            let guild = crate::discord::Guild {
+                                id: guild_id.to_string(),
+                                name: guild_name.to_string(),
+                                icon: guild_data.get("properties").and_then(|p| p.get("icon")).and_then(|v| v.as_str()).map(|s| s.to_string()),
+                                owner_id: owner_id.to_string(),
+                                emojis: crate::discord::GuildEmoji::parse_list(guild_data.get("emojis")),
+                                roles: crate::discord::GuildRole::parse_list(guild_data.get("roles")),
+                                voice_states: Vec::new(),
+                            };
+
+                            self.index_guild_emojis(&guild);
+                            let voice_states =
+                                VoiceState::parse_list(guild_data.get("voice_states"));
+                            self.replace_voice_states(&guild.id, voice_states);
+                            self.discord.guilds.insert(guild.id.clone(), guild.clone());
+
+                            // チャンネル情報を抽出（フォーラム/メディアの親解決のため全種類を保存し、
+                            // 表示・検索時に is_messageable() でフィルタする）
+                            if let Some(channels_array) = guild_data.get("channels").and_then(|v| v.as_array()) {
+                                for channel_data in channels_array {
+                                    if let Ok(mut channel) = serde_json::from_value::<crate::discord::Channel>(channel_data.clone()) {
+                                        channel.guild_id = Some(guild.id.clone());
+                                        self.discord.channels.insert(channel.id.clone(), channel);
+                                    }
+                                }
+                            }
+
+                            // スレッド情報を抽出（フォーラム投稿含む）
+                            // ユーザーアカウントの READY では guilds[].threads[] にアクティブなスレッドが入る
+                            if let Some(threads_array) = guild_data.get("threads").and_then(|v| v.as_array()) {
+                                for thread_data in threads_array {
+                                    if let Ok(mut thread) = serde_json::from_value::<crate::discord::Channel>(thread_data.clone()) {
+                                        thread.guild_id = Some(guild.id.clone());
+                                        log::debug!(
+                                            "Adding thread: id={}, type={}, name={:?}, parent_id={:?}",
+                                            thread.id, thread.channel_type, thread.name, thread.parent_id
+                                        );
+                                        self.discord.channels.insert(thread.id.clone(), thread);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if skipped > 0 {
+                        log::info!("Skipped {} guilds not in guild_allowlist", skipped);
+                    }
+                }
+
+                // フレンド一覧 (フレンドでない相手からの DM をメッセージリクエストとして扱う)
+                if let Some(entries) = ready_data.get("relationships").and_then(|v| v.as_array()) {
+                    self.discord.friends.clear();
+                    for entry in entries {
+                        if let Ok(relationship) = serde_json::from_value(entry.clone()) {
+                            self.apply_relationship(relationship);
+                        }
+                    }
+                    self.discord.relationships_loaded = true;
+                    log::info!("READY contains {} friends", self.discord.friends.len());
+                }
+
+                // read_state エントリを抽出 (チャンネル毎の既読位置 + mention 数)
+                let read_entries = ready_data
+                    .get("read_state")
+                    .and_then(|rs| rs.get("entries").and_then(|v| v.as_array()).or(rs.as_array()));
+                if let Some(entries) = read_entries {
+                    log::info!("READY contains {} read_state entries", entries.len());
+                    for entry in entries {
+                        if let Ok(rs) = serde_json::from_value::<crate::discord::ReadStateEntry>(
+                            entry.clone(),
+                        ) {
+                            self.discord.read_states.insert(rs.id.clone(), rs.last_message_id);
+                            if rs.mention_count > 0 {
+                                self.discord.mention_counts.insert(rs.id, rs.mention_count);
+                            }
+                        }
+                    }
+                }
+
+                // ユーザーのギルド毎の通知設定 (ミュート) を抽出
+                let guild_settings = ready_data
+                    .get("user_guild_settings")
+                    .and_then(|s| s.get("entries").and_then(|v| v.as_array()).or(s.as_array()));
+                if let Some(entries) = guild_settings {
+                    log::info!("READY contains {} user_guild_settings entries", entries.len());
+                    for entry in entries {
+                        if let Ok(gs) = serde_json::from_value::<
+                            crate::discord::UserGuildSettingsEntry,
+                        >(entry.clone())
+                        {
+                            if let Some(gid) = gs.guild_id.as_deref() {
+                                if gs.muted {
+                                    self.discord.muted_guilds.insert(gid.to_string());
+                                }
+                            }
+                            for over in gs.channel_overrides {
+                                if over.muted {
+                                    self.discord.muted_channels.insert(over.channel_id);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // DM チャンネルを抽出
+                // READY のユーザーキャッシュに居なかった DM 相手 (REST で解決する)
+                let mut missing_users: Vec<String> = Vec::new();
+                if let Some(private_channels) = ready_data.get("private_channels").and_then(|v| v.as_array()) {
+                    log::info!("Found {} private channels", private_channels.len());
+                    for channel_data in private_channels.iter() {
+                        if let Ok(mut channel) = serde_json::from_value::<crate::discord::Channel>(channel_data.clone()) {
+                            // recipient_ids から recipients を構築
+                            if let Some(recipient_ids) = &channel.recipient_ids {
+                                let mut recipients = Vec::new();
+                                for user_id in recipient_ids {
+                                    if let Some(user) = self.discord.users.get(user_id) {
+                                        recipients.push(user.clone());
+                                    } else {
+                                        // キャッシュに無いユーザーは後で REST で解決する
+                                        log::debug!("User not found in cache: {}", user_id);
+                                        if self.discord.user_lookup_requested.insert(user_id.clone()) {
+                                            missing_users.push(user_id.clone());
+                                        }
+                                    }
+                                }
+                                channel.recipients = Some(recipients);
+                            }
+
+                            log::debug!(
+                                "Adding DM channel: id={}, type={}, display_name={}",
+                                channel.id,
+                                channel.channel_type,
+                                channel.display_name()
+                            );
+                            self.discord.channels.insert(channel.id.clone(), channel);
+                        } else {
+                            log::warn!("Failed to parse channel data: {}", channel_data);
+                        }
+                    }
+                }
+                log::info!("Total channels after READY: {}", self.discord.channels.len());
+                let mut cmd = Command::None;
+                if !needs_rest_channels {
+                    self.migrate_legacy_favorites(true);
+                    cmd = self.prune_stale_favorites();
+                }
+
+                // お気に入り DM の相手アバターを先読み (アバター表示有効時のみ)
+                let dm_recipients: Vec<User> = self
+                    .get_favorite_channels()
+                    .iter()
+                    .filter(|ch| ch.channel_type == 1)
+                    .filter_map(|ch| ch.recipients.as_ref().and_then(|r| r.first()).cloned())
+                    .collect();
+                let avatar_pending = self.collect_pending_avatar_downloads(dm_recipients.iter());
+                cmd = merge_commands(cmd, batch_commands(Vec::new(), Vec::new(), avatar_pending));
+                cmd = merge_commands(cmd, self.collect_pending_guild_icon_downloads());
+
+                // 起動時のチャンネルを選択 (READY にギルド情報が無ければ REST の取得を待つ)
+                let select = self.select_startup_channel(!needs_rest_channels);
+                cmd = merge_commands(select, cmd);
+
+                if !missing_users.is_empty() {
+                    log::info!("Resolving {} DM recipients via REST", missing_users.len());
+                    cmd = merge_commands(cmd, Command::FetchUsers(missing_users));
+                }
+                if needs_rest_channels {
+                    log::info!("READY has no guild data, loading channels via REST");
+                    cmd = merge_commands(Command::LoadChannels, cmd);
+                }
+                merge_commands(cmd, self.flush_pending_sends())
+            }
+
+            AppEvent::GatewayResumed => {
+                self.discord.connected = true;
+                self.discord.reconnecting = false;
+                self.flush_pending_sends()
+            }
+
+            AppEvent::GatewayDisconnected => {
+                self.discord.connected = false;
+                self.discord.reconnecting = true;
+                Command::None
+            }
+
+            AppEvent::RestOutage(down) => {
+                self.discord.rest_outage = down;
+                self.ui.status_message = Some(if down {
+                    "Discord appears to be down, pausing requests".to_string()
+                } else {
+                    "Discord is reachable again".to_string()
+                });
+                Command::None
+            }
+
+            AppEvent::RestRateLimited(until) => {
+                self.discord.rest_rate_limited_until = until;
+                Command::None
+            }
+
+            AppEvent::GuildMembersChunk { guild_id, members } => {
+                log::debug!("Received {} members for guild {}", members.len(), guild_id);
+                let cache = self.discord.members.entry(guild_id.clone()).or_default();
+                let mut requested = self.discord.member_requested.get_mut(&guild_id);
+                for member in members {
+                    if let Some(user_id) = member.user.as_ref().map(|u| u.id.clone()) {
+                        if let Some(requested) = requested.as_mut() {
+                            requested.remove(&user_id);
+                        }
+                        cache.insert(user_id, member);
+                    }
+                }
+                self.refresh_loaded_members(&guild_id);
+                Command::None
+            }
+
+            AppEvent::VoiceStateUpdate(state) => {
+                if let Some(guild_id) = state.guild_id.clone() {
+                    self.apply_voice_state(&guild_id, state);
+                }
+                Command::None
+            }
+
+            AppEvent::ReadySupplemental(supplemental) => {
+                for (guild_id, members) in supplemental.members {
+                    self.discord
+                        .members
+                        .entry(guild_id.clone())
+                        .or_default()
+                        .extend(members);
+                    self.refresh_loaded_members(&guild_id);
+                }
+                self.discord.presences.extend(supplemental.presences);
+                Command::None
+            }
+
+            AppEvent::PresenceUpdate { user_id, status } => {
+                self.discord.presences.insert(user_id, status);
+                Command::None
+            }
+
+            AppEvent::RelationshipAdd(relationship) => {
+                self.apply_relationship(relationship);
+                self.invalidate_unread_cache();
+                Command::None
+            }
+
+            AppEvent::RelationshipRemove { id } => {
+                self.discord.friends.remove(&id);
+                self.invalidate_unread_cache();
+                Command::None
+            }
+
+            AppEvent::UsersResolved(users) => {
+                for user in users {
+                    // recipient_ids に含まれているのに recipients に居ない DM へ補完
+                    for channel in self.discord.channels.values_mut() {
+                        let is_recipient = channel
+                            .recipient_ids
+                            .as_ref()
+                            .is_some_and(|ids| ids.contains(&user.id));
+                        if !is_recipient {
+                            continue;
+                        }
+                        let recipients = channel.recipients.get_or_insert_with(Vec::new);
+                        if !recipients.iter().any(|u| u.id == user.id) {
+                            recipients.push(user.clone());
+                        }
+                    }
+                    self.discord.users.insert(user.id.clone(), user);
+                }
+                Command::None
+            }
+
+            AppEvent::GuildsCreated(guilds) => {
+                // READY 時点で未取得だったギルドなら、最後の 1 つが揃った時点でお気に入りを整理する
+                let mut recovered = false;
+                let guilds: Vec<_> = guilds
+                    .into_iter()
+                    .filter(|(guild, _)| self.guild_allowed(Some(&guild.id)))
+                    .collect();
+                for (mut guild, channels) in guilds {
+                    recovered |= self.discord.unavailable_guilds.remove(&guild.id);
+                    self.index_guild_emojis(&guild);
+                    // REST で取得したギルドには接続状況が無いので、既存の状況を消さない
+                    if !guild.voice_states.is_empty() {
+                        let voice_states = std::mem::take(&mut guild.voice_states);
+                        self.replace_voice_states(&guild.id, voice_states);
+                    }
+                    self.discord.guilds.insert(guild.id.clone(), guild);
+                    self.discord
+                        .channels
+                        .extend(channels.into_iter().map(|ch| (ch.id.clone(), ch)));
+                }
+                let recovered = recovered && self.discord.unavailable_guilds.is_empty();
+                self.migrate_legacy_favorites(false);
+                let cmd = if recovered {
+                    self.prune_stale_favorites()
+                } else {
+                    Command::None
+                };
+
+                // 先頭のチャンネルは READY / 一覧の取得完了で 1 度だけ選ぶ。ここでは前回・指定の
+                // チャンネルが届いたときと、READY で未取得だったギルドが揃ったときだけ開く
+                let select = self.select_startup_channel(recovered);
+                let icons = self.collect_pending_guild_icon_downloads();
+                merge_commands(merge_commands(select, cmd), icons)
+            }
+
+            AppEvent::ProfilesListed(profiles) => {
+                self.open_account_switcher(profiles);
+                Command::None
+            }
+
+            AppEvent::ChannelsLoaded { complete } => {
+                if std::mem::take(&mut self.ui.refreshing) {
+                    self.ui.status_message = Some(if complete {
+                        "Refreshed".to_string()
+                    } else {
+                        "Refresh incomplete: some channels could not be loaded".to_string()
+                    });
+                }
+                // 指定のチャンネルが見つからないまま取得が終わったら先頭を開く
+                let select = self.select_startup_channel(true);
+                // 一部のギルドの取得に失敗した場合は、そのチャンネルを誤って消さないよう整理しない
+                if !complete {
+                    log::info!("Channel list incomplete, keeping all favorites");
+                    return select;
+                }
+                self.migrate_legacy_favorites(true);
+                merge_commands(select, self.prune_stale_favorites())
+            }
+
+            AppEvent::MessageViewed {
+                channel_id,
+                message_id,
+            } => {
+                self.mark_viewed(&channel_id, &message_id);
+                Command::None
+            }
+
+            AppEvent::TokenInvalid => {
+                log::warn!("Token was rejected by the API");
+                self.ui.refreshing = false;
+                self.ui.status_message =
+                    Some("Token is no longer valid; restart hakuhyo to log in again".to_string());
+                Command::None
+            }
+
+            AppEvent::DmChannelsLoaded(channels) => {
+                log::info!("Loaded {} DM channels via REST", channels.len());
+                for channel in channels {
+                    // REST の DM は recipients に完全なユーザー情報を含むのでキャッシュしておく
+                    if let Some(recipients) = &channel.recipients {
+                        for user in recipients {
+                            self.discord.users.insert(user.id.clone(), user.clone());
+                        }
+                    }
+                    self.discord.channels.insert(channel.id.clone(), channel);
+                }
+                self.invalidate_unread_cache();
+                Command::None
+            }
+
+            AppEvent::ChannelFetched(mut channel) => {
+                if !self.guild_allowed(channel.guild_id.as_deref()) {
+                    log::debug!("Dropping channel {} outside guild_allowlist", channel.id);
+                    self.discord.messages.remove(&channel.id);
+                    return Command::None;
+                }
+                log::info!("Fetched channel {} ({})", channel.id, channel.display_name());
+                let mut recipients = channel.recipients.take().unwrap_or_default();
+                for user in &recipients {
+                    self.discord.users.insert(user.id.clone(), user.clone());
+                }
+                // recipients に入っていない相手はキャッシュから補い、無ければ REST で解決する
+                let mut missing_users = Vec::new();
+                for user_id in channel.recipient_ids.iter().flatten() {
+                    if recipients.iter().any(|u| &u.id == user_id) {
+                        continue;
+                    }
+                    match self.discord.users.get(user_id) {
+                        Some(user) => recipients.push(user.clone()),
+                        None if self.discord.user_lookup_requested.insert(user_id.clone()) => {
+                            missing_users.push(user_id.clone());
+                        }
+                        None => {}
+                    }
+                }
+                if channel.recipient_ids.is_some() {
+                    channel.recipients = Some(recipients);
+                }
+                // 取得中に届いた新着の方が新しければそちらを最新とする
+                let cached = self.discord.messages.get(&channel.id).and_then(|m| m.first());
+                if let Some(newest) = cached {
+                    let stale = match &channel.last_message_id {
+                        Some(id) => snowflake_gt(&newest.id, id),
+                        None => true,
+                    };
+                    if stale {
+                        channel.last_message_id = Some(newest.id.clone());
+                    }
+                }
+                self.discord.channels.insert(channel.id.clone(), channel);
+                self.invalidate_unread_cache();
+                if missing_users.is_empty() {
+                    Command::None
+                } else {
+                    Command::FetchUsers(missing_users)
+                }
+            }
+
+            AppEvent::ForumPostsLoaded { forum_id, threads } => {
+                log::info!("Loaded {} posts for forum {}", threads.len(), forum_id);
+                for thread in threads {
+                    self.discord.channels.insert(thread.id.clone(), thread);
+                }
+                Command::None
+            }
+
+            AppEvent::ThreadUpsert(channel) => {
+                if !self.guild_allowed(channel.guild_id.as_deref()) {
+                    return Command::None;
+                }
+                log::info!(
+                    "Thread upsert: id={}, name={:?}, parent={:?}",
+                    channel.id, channel.name, channel.parent_id
+                );
+                self.discord.channels.insert(channel.id.clone(), channel);
+                Command::None
+            }
+
+            AppEvent::ThreadDelete { id } | AppEvent::ChannelDelete { id } => {
+                self.remove_channel(&id);
+                self.remove_favorite_if_gone(&id)
+            }
+
+            AppEvent::ChannelFetchFailed { channel_id, gone } => {
+                // 一時的な失敗なら次に一覧が揃ったときに確かめ直す
+                self.discord.channel_lookup_requested.remove(&channel_id);
+                if gone {
+                    self.remove_favorite_if_gone(&channel_id)
+                } else {
+                    Command::None
+                }
+            }
+
+            AppEvent::MessageCreate(message) => {
+                if !self.guild_allowed(message.guild_id.as_deref()) {
+                    return Command::None;
+                }
+                // RESUME 後の再送やポーリングとの重複で、取得済みのメッセージが再び届くことがある。
+                // 二重に表示したり未読・通知を立て直したりしないよう読み捨てる
+                // (キャッシュは新しい順なので、先頭の DUPLICATE_CHECK_WINDOW 件だけを見る)
+                let cached = self
+                    .discord
+                    .messages
+                    .get(&message.channel_id)
+                    .is_some_and(|cached| {
+                        cached
+                            .iter()
+                            .take(DUPLICATE_CHECK_WINDOW)
+                            .any(|m| m.id == message.id)
+                    });
+                if cached {
+                    log::debug!("Ignoring duplicate MESSAGE_CREATE {}", message.id);
+                    return Command::None;
+                }
+                let img_pending = self.collect_pending_image_downloads(std::slice::from_ref(&message));
+                let emoji_pending =
+                    self.collect_pending_emoji_downloads(std::slice::from_ref(&message));
+                let avatar_pending =
+                    self.collect_pending_avatar_downloads(std::iter::once(&message.author));
+                // 該当チャンネルの last_message_id を更新 (未読判定用)。一覧に無いチャンネル
+                // (知らない相手からの新しい DM など) はサイドバーから開けるよう取得しに行く
+                let fetch_channel = match self.discord.channels.get_mut(&message.channel_id) {
+                    Some(channel) => {
+                        channel.last_message_id = Some(message.id.clone());
+                        Command::None
+                    }
+                    None if self
+                        .discord
+                        .channel_lookup_requested
+                        .insert(message.channel_id.clone()) =>
+                    {
+                        Command::FetchChannel(message.channel_id.clone())
+                    }
+                    None => Command::None,
+                };
+                // 新着が来たら既読化フラグを解除し、未読マークを立てる
+                self.discord.acked_in_session.remove(&message.channel_id);
+                // 現在開いているチャンネルへの新着は自動既読扱いとする (UI上で見えているので)
+                if self.ui.selected_channel.as_deref() != Some(message.channel_id.as_str()) {
+                    self.discord.session_unread.insert(message.channel_id.clone());
+                    self.notify_keyword(&message);
+                    self.notify_message_request(&message);
+                }
+                self.invalidate_unread_cache();
+                self.cache_message_member(&message);
+                // キャッシュは REST と同じく新しい順
+                self.discord
+                    .messages
+                    .entry(message.channel_id.clone())
+                    .or_default()
+                    .insert(0, message);
+                merge_commands(
+                    fetch_channel,
+                    batch_commands(img_pending, emoji_pending, avatar_pending),
+                )
+            }
+
+            AppEvent::MessageUpdate(message) => {
+                // メッセージを更新（簡略化: 既存のメッセージを置き換え）
+                if let Some(messages) = self.discord.messages.get_mut(&message.channel_id) {
+                    if let Some(pos) = messages.iter().position(|m| m.id == message.id) {
+                        messages[pos] = message;
+                    }
+                }
+                Command::None
+            }
+
+            AppEvent::MessageDelete { id, channel_id } => {
+                // メッセージを削除
+                if let Some(messages) = self.discord.messages.get_mut(&channel_id) {
+                    messages.retain(|m| m.id != id);
+                }
+                Command::None
+            }
+
+            AppEvent::MessageDeleteBulk { ids, channel_id } => {
+                // キャッシュに無い ID は単に無視される
+                let ids: HashSet<String> = ids.into_iter().collect();
+                if let Some(messages) = self.discord.messages.get_mut(&channel_id) {
+                    messages.retain(|m| !ids.contains(&m.id));
+                }
+                Command::None
+            }
+
+            // コマンド完了イベント
+            AppEvent::MessagesLoaded {
+                channel_id,
+                messages,
+            } => {
+                // 「last_message_id があるはずなのに空が返る」場合は権限なしの可能性 → 除外
+                let has_history = self
+                    .discord
+                    .channels
+                    .get(&channel_id)
+                    .and_then(|c| c.last_message_id.as_ref())
+                    .is_some();
+                if messages.is_empty() && has_history {
+                    log::info!(
+                        "Channel {} appears inaccessible (empty result with last_message_id)",
+                        channel_id
+                    );
+                    self.discord.inaccessible_channels.insert(channel_id.clone());
+                    self.invalidate_unread_cache();
+                }
+                self.discord.messages_loading.remove(&channel_id);
+                let mut messages = messages;
+                self.resolve_members(&channel_id, &mut messages);
+                let img_pending = self.collect_pending_image_downloads(&messages);
+                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
+                let avatar_pending =
+                    self.collect_pending_avatar_downloads(messages.iter().map(|m| &m.author));
+                self.discord
+                    .messages_fetched_at
+                    .insert(channel_id.clone(), Instant::now());
+                self.ui.detached_history.remove(&channel_id);
+                self.discord.messages.insert(channel_id, messages);
+                batch_commands(img_pending, emoji_pending, avatar_pending)
+            }
+
+            AppEvent::MessagesAroundLoaded {
+                channel_id,
+                message_id,
+                mut messages,
+            } => {
+                if !messages.iter().any(|m| m.id == message_id) {
+                    self.ui.status_message = Some("The linked message was not found".to_string());
+                    self.ui.scroll_to_message = None;
+                    self.ui.highlighted_message = None;
+                }
+                if messages.is_empty() {
+                    return Command::None;
+                }
+                self.resolve_members(&channel_id, &mut messages);
+                let img_pending = self.collect_pending_image_downloads(&messages);
+                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
+                let avatar_pending =
+                    self.collect_pending_avatar_downloads(messages.iter().map(|m| &m.author));
+                // 最新に繋がっていないので、次に開いたとき・最新へ戻ったときは全件取り直す
+                self.discord.messages_fetched_at.remove(&channel_id);
+                self.ui.detached_history.insert(channel_id.clone());
+                self.discord.messages.insert(channel_id, messages);
+                batch_commands(img_pending, emoji_pending, avatar_pending)
+            }
+
+            AppEvent::NewerMessagesLoaded {
+                channel_id,
+                messages,
+            } => {
+                if messages.len() >= MESSAGE_DELTA_LIMIT as usize {
+                    // 差分が上限まで返った = 取りこぼしがあり得るので全件取り直す
+                    return Command::LoadMessages(channel_id);
+                }
+                let mut messages = messages;
+                self.resolve_members(&channel_id, &mut messages);
+                let img_pending = self.collect_pending_image_downloads(&messages);
+                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
+                let avatar_pending =
+                    self.collect_pending_avatar_downloads(messages.iter().map(|m| &m.author));
+                self.discord
+                    .messages_fetched_at
+                    .insert(channel_id.clone(), Instant::now());
+                let cached = self.discord.messages.entry(channel_id).or_default();
+                // Gateway で受信済みのものは除き、新しい順で先頭に足す
+                messages.retain(|m| !cached.iter().any(|c| c.id == m.id));
+                messages.sort_by(|a, b| {
+                    if snowflake_gt(&a.id, &b.id) {
+                        std::cmp::Ordering::Less
+                    } else if snowflake_gt(&b.id, &a.id) {
+                        std::cmp::Ordering::Greater
+                    } else {
+                        std::cmp::Ordering::Equal
+                    }
+                });
+                cached.splice(0..0, messages);
+                batch_commands(img_pending, emoji_pending, avatar_pending)
+            }
+
+            AppEvent::MessagesLoadFailed {
+                channel_id,
+                permanent,
+            } => {
+                self.discord.messages_loading.remove(&channel_id);
+                // リンクからのジャンプ先を読み込めなかった
+                let jumping = self.ui.selected_channel.as_deref() == Some(channel_id.as_str())
+                    && self.ui.scroll_to_message.take().is_some();
+                if jumping {
+                    self.ui.highlighted_message = None;
+                    self.ui.status_message = Some("Could not load the linked message".to_string());
+                }
+                if permanent {
+                    self.discord.inaccessible_channels.insert(channel_id);
+                    self.invalidate_unread_cache();
+                }
+                Command::None
+            }
+
+            AppEvent::AttachmentImageLoaded { attachment_id, image } => {
+                self.discord.image_downloading.remove(&attachment_id);
+                // protocol / resized キャッシュは描画時に area_w が判明してから生成する
+                self.discord.image_sources.insert(attachment_id, *image);
+                Command::None
+            }
+            AppEvent::AttachmentUrlRefreshed { attachment_id, url } => {
+                let expiry = signed_url_expiry(&url);
+                self.discord.attachment_urls.insert(attachment_id, (url, expiry));
+                Command::None
+            }
+
+            AppEvent::AttachmentImageFailed { attachment_id } => {
+                self.discord.image_downloading.remove(&attachment_id);
+                Command::None
+            }
+            AppEvent::EmojiImageLoaded { emoji_id, image } => {
+                self.discord.emoji_downloading.remove(&emoji_id);
+                if let Some(picker) = self.picker.as_mut() {
+                    let protocol =
+                        crate::emoji::prepare_emoji_protocol(picker, *image, self.bg_color);
+                    self.discord.emoji_protocols.insert(emoji_id, protocol);
+                }
+                Command::None
+            }
+            AppEvent::EmojiImageFailed { emoji_id } => {
+                self.discord.emoji_downloading.remove(&emoji_id);
+                Command::None
+            }
+            AppEvent::AvatarImageLoaded { user_id, image } => {
+                if let Some(picker) = self.picker.as_mut() {
+                    // 絵文字と同じ 2 セル x 1 セルで描画する
+                    let protocol =
+                        crate::emoji::prepare_emoji_protocol(picker, *image, self.bg_color);
+                    self.discord.avatar_protocols.insert(user_id, protocol);
+                }
+                Command::None
+            }
+            AppEvent::AvatarImageFailed { user_id } => {
+                // avatar_requested には残し、同セッション中は再試行しない
+                log::debug!("Avatar unavailable for user {}", user_id);
+                Command::None
+            }
+            AppEvent::GuildIconLoaded { guild_id, image } => {
+                if let Some(picker) = self.picker.as_mut() {
+                    // 頭文字のバッジと同じ 2 セル x 1 セルで描画する
+                    let protocol =
+                        crate::emoji::prepare_emoji_protocol(picker, *image, self.bg_color);
+                    self.discord.guild_icon_protocols.insert(guild_id, protocol);
+                }
+                Command::None
+            }
+            AppEvent::GuildIconFailed { guild_id } => {
+                // guild_icon_requested には残し、頭文字のバッジのままにする
+                log::debug!("Icon unavailable for guild {}", guild_id);
+                Command::None
+            }
+
+            AppEvent::SendRateLimited {
+                channel_id,
+                retry_after,
+                slowmode,
+            } => {
+                self.ui.send_waits.insert(
+                    channel_id,
+                    SendWait {
+                        until: Instant::now() + Duration::from_secs_f64(retry_after.max(0.0)),
+                        slowmode,
+                    },
+                );
+                Command::None
+            }
+
+            AppEvent::SendFailed { channel_id, error } => {
+                if let Some(index) = self.oldest_sending(&channel_id) {
+                    self.ui.outgoing_sends[index].status = SendStatus::Failed(error.clone());
+                }
+                self.ui.send_waits.remove(&channel_id);
+                self.ui.status_message = Some(format!("Failed to send message: {}", error));
+                Command::None
+            }
+
+            AppEvent::ActionFailed(error) => {
+                self.ui.status_message = Some(error);
+                Command::None
+            }
+
+            AppEvent::MessageSent(message) => {
+                if let Some(index) = self.oldest_sending(&message.channel_id) {
+                    self.ui.outgoing_sends.remove(index);
+                }
+                // 送信できたので 429 の待ちは解除。slowmode のクールダウンは
+                // サーバー側で実際に送信された時点から数え直す
+                self.ui.send_waits.remove(&message.channel_id);
+                self.start_slowmode_cooldown(&message.channel_id);
+                // `/to` や再接続後の送信などで、開いていないチャンネルへ送った場合は
+                // そのチャンネルを既読にしたり、開いている画面の表示位置を変えたりしない。
+                // 読み込み済みのキャッシュにだけ足しておく
+                if self.ui.selected_channel.as_deref() != Some(message.channel_id.as_str()) {
+                    if let Some(cached) = self.discord.messages.get_mut(&message.channel_id) {
+                        if !cached.iter().any(|m| m.id == message.id) {
+                            cached.insert(0, message);
+                        }
+                    }
+                    return Command::None;
+                }
+                // メッセージ送信後にメッセージリストを再読み込みして最新の状態を取得
+                self.ui.message_scroll_offset = 0;
+                self.select_channel_commands(message.channel_id)
+            }
+
+            AppEvent::ScrollMessages(lines) => self.scroll_view(lines),
+
+            AppEvent::OlderMessagesLoaded {
+                channel_id,
+                messages,
+            } => {
+                self.discord.loading_older.remove(&channel_id);
+                let mut messages = messages;
+                self.resolve_members(&channel_id, &mut messages);
+                let img_pending = self.collect_pending_image_downloads(&messages);
+                let emoji_pending = self.collect_pending_emoji_downloads(&messages);
+                let avatar_pending =
+                    self.collect_pending_avatar_downloads(messages.iter().map(|m| &m.author));
+                // 未初期化チャンネルでも取得結果が破棄されないよう entry().or_default() で挿入
+                self.discord
+                    .messages
+                    .entry(channel_id)
+                    .or_default()
+                    .extend(messages);
+                batch_commands(img_pending, emoji_pending, avatar_pending)
+            }
+
+            AppEvent::PurgeProgress {
+                channel_id,
+                message_id,
+                deleted,
+                total,
+            } => {
+                if let Some(messages) = self.discord.messages.get_mut(&channel_id) {
+                    messages.retain(|m| m.id != message_id);
+                }
+                if let Some(purge) = self.ui.purge.as_mut() {
+                    purge.deleted = deleted;
+                    purge.total = total;
+                }
+                Command::None
+            }
+
+            AppEvent::PurgeFinished {
+                deleted,
+                cancelled,
+                error,
+            } => {
+                let result = match (error, cancelled) {
+                    (Some(e), _) => format!("Purge failed after {} deleted: {}", deleted, e),
+                    (None, true) => format!("Purge cancelled ({} deleted)", deleted),
+                    (None, false) => format!("Purge done ({} deleted)", deleted),
+                };
+                log::info!("{}", result);
+                if let Some(purge) = self.ui.purge.as_mut() {
+                    purge.deleted = deleted;
+                    purge.result = Some(result);
+                }
+                Command::None
+            }
+
+            // UI イベント
+            AppEvent::KeyPress(key) => self.handle_key_press(key),
+            AppEvent::Paste(text) => {
+                // 改行は Enter として扱わず本文に含める (行ごとに送信されないように)
+                if self.ui.input_mode == InputMode::Editing {
+                    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                    self.ui.input_buffer.push_str(&text);
+                } else if self.ui.input_mode == InputMode::JumpPrompt {
+                    self.ui.jump_buffer.push_str(text.trim());
+                }
+                Command::None
+            }
+            AppEvent::Input(c) => {
+                if self.ui.input_mode == InputMode::Editing {
+                    self.ui.input_buffer.push(c);
+                }
+                Command::None
+            }
+
+            // システムイベント
+            AppEvent::Tick => {
+                if self.rest_rate_limit_wait().is_none() {
+                    self.ui.spinner_frame = self.ui.spinner_frame.wrapping_add(1);
+                }
+                self.expire_pending_sends();
+                merge_commands(self.flush_channel_load(), self.flush_member_requests())
+            }
+            AppEvent::PollTick => self.poll_commands(),
+            AppEvent::ChannelPolled {
+                channel_id,
+                mut messages,
+            } => {
+                self.discord.polls_in_flight.remove(&channel_id);
+                self.resolve_members(&channel_id, &mut messages);
+                // 上限まで返った = まだ続きがあるので、受け取った中の最新からすぐに続きを取る
+                let next_after = messages
+                    .iter()
+                    .map(|m| &m.id)
+                    .reduce(|a, b| if snowflake_gt(b, a) { b } else { a })
+                    .filter(|_| messages.len() >= MESSAGE_DELTA_LIMIT as usize)
+                    .cloned();
+                // Gateway の MESSAGE_CREATE と同じく古い順に 1 件ずつ新着として扱う
+                messages.reverse();
+                // 取得済みのものは MessageCreate 側で読み捨てる
+                let mut cmd = Command::None;
+                for message in messages {
+                    let created = self.handle_event(AppEvent::MessageCreate(message));
+                    cmd = merge_commands(cmd, created);
+                }
+                if let Some(after) = next_after {
+                    self.discord.polls_in_flight.insert(channel_id.clone());
+                    cmd = merge_commands(cmd, Command::PollChannel { channel_id, after });
+                }
+                cmd
+            }
+            AppEvent::Quit | AppEvent::GatewayFailed(_) => Command::None,
+        }
+    }
+
+    /// キー入力を処理
+    fn handle_key_press(&mut self, key: KeyCode) -> Command {
+        self.ui.status_message = None;
+        // 終了済みの一括削除の結果表示は次のキー入力で消す
+        if self
+            .ui
+            .purge
+            .as_ref()
+            .is_some_and(|p| p.result.is_some())
+        {
+            self.ui.purge = None;
+        }
+
+        // 終了確認中は y / q / Enter で終了、それ以外のキーで取り消す
+        if self.ui.quit_confirm {
+            self.ui.quit_confirm = false;
+            return match key {
+                KeyCode::Char('y') | KeyCode::Char('q') | KeyCode::Enter => Command::Quit,
+                _ => Command::None,
+            };
+        }
+
+        // キーバインド一覧の表示中は ? / Esc で閉じ、↑/k ↓/j でスクロールする
+        if self.ui.help_open {
+            match key {
+                KeyCode::Char('?') | KeyCode::Esc => self.ui.help_open = false,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.ui.help_scroll = self.ui.help_scroll.saturating_sub(1);
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.ui.help_scroll = self.ui.help_scroll.saturating_add(1);
+                }
+                _ => {}
+            }
+            return Command::None;
+        }
+
+        // 絵文字ピッカー表示中の処理
+        if self.ui.emoji_picker.is_some() {
+            return self.handle_emoji_picker_key(key);
+        }
+
+        // アカウント切り替え表示中の処理
+        if self.ui.account_switcher.is_some() {
+            return self.handle_account_switcher_key(key);
+        }
+
+        // 検索モード時の処理
+        if self.ui.search_mode {
+            return match key {
+                KeyCode::Esc => {
+                    self.toggle_search_mode();
+                    Command::None
+                }
+                KeyCode::Backspace => {
+                    self.search_backspace();
+                    Command::None
+                }
+                KeyCode::Up => self.select_previous_channel(),
+                KeyCode::Down => self.select_next_channel(),
+                KeyCode::Enter => self.confirm_search(false),
+                // 開いてそのまま入力モードへ (「さっと DM する」用)
+                KeyCode::Tab => self.confirm_search(true),
+                KeyCode::Char(c) => {
+                    self.search_input(c);
+                    Command::None
+                }
+                _ => Command::None,
+            };
+        }
+
+        // 通常モード・編集モードの処理
+        let pending_g = std::mem::take(&mut self.ui.pending_g);
+        let pending_delete = self.ui.pending_delete.take();
+        match self.ui.input_mode {
+            InputMode::Normal => match key {
+                KeyCode::Char('q') => match self.config.quit_key {
+                    QuitKey::Quit => Command::Quit,
+                    QuitKey::Confirm => {
+                        self.ui.quit_confirm = true;
+                        Command::None
+                    }
+                    QuitKey::Disabled => {
+                        self.ui.status_message =
+                            Some("q is disabled (quit_key); press Ctrl+C to quit".to_string());
+                        Command::None
+                    }
+                },
+                KeyCode::Char('i') => {
+                    if self.read_only {
+                        self.ui.status_message =
+                            Some("Read-only mode: sending is disabled".to_string());
+                    } else if !self.is_forum_selected() && !self.is_voice_selected() {
+                        // フォーラム自体には投稿できない (投稿を開いてから返信する)。
+                        // ボイスチャンネルも接続人数の表示のみ
+                        self.ui.input_mode = InputMode::Editing;
+                    }
+                    Command::None
+                }
+                KeyCode::Char('/') => {
+                    // 検索モードに切り替え
+                    self.toggle_search_mode();
+                    Command::None
+                }
+                KeyCode::Char('f') => {
+                    // お気に入り登録/解除
+                    self.toggle_favorite();
+                    Command::None
+                }
+                KeyCode::Char('a') => {
+                    // 自動追従の切り替え (ON にしたら最新へ戻る)
+                    self.ui.auto_follow = !self.ui.auto_follow;
+                    if self.ui.auto_follow {
+                        self.ui.message_scroll_offset = 0;
+                    }
+                    log::info!("Auto-follow: {}", self.ui.auto_follow);
+                    Command::None
+                }
+                KeyCode::Char('v') => self.toggle_image_previews(),
+                KeyCode::Char('s') => self.toggle_spoilers(),
+                KeyCode::Char('E') => self.toggle_expanded(),
+                KeyCode::Char('A') => Command::ListProfiles,
+                KeyCode::Char('X') => {
+                    self.start_purge_prompt();
+                    Command::None
+                }
+                KeyCode::Char('r') => {
+                    self.open_emoji_picker();
+                    Command::None
+                }
+                KeyCode::Char('R') => {
+                    // 送信に失敗したメッセージがあれば、返信より先に送り直す
+                    if let Some(command) = self.retry_failed_send() {
+                        return command;
+                    }
+                    self.start_reply();
+                    Command::None
+                }
+                KeyCode::Char('p') => self.cycle_status(),
+                KeyCode::Char('P') => self.toggle_activity(),
+                KeyCode::Char('m') => {
+                    self.ui.jump_buffer.clear();
+                    self.ui.input_mode = InputMode::JumpPrompt;
+                    Command::None
+                }
+                KeyCode::Char('V') => {
+                    self.enter_message_select();
+                    Command::None
+                }
+                KeyCode::Char('T') => self.toggle_text_channels_only(),
+                KeyCode::Char('?') => {
+                    self.open_help();
+                    Command::None
+                }
+                KeyCode::Esc => {
+                    // 実行中の一括削除をキャンセル
+                    if let Some(purge) = &self.ui.purge {
+                        log::info!("Cancelling purge in {}", purge.channel_id);
+                        purge.cancel.store(true, Ordering::Relaxed);
+                    }
+                    Command::None
+                }
+                KeyCode::Tab | KeyCode::Char('u') => self.toggle_sidebar_focus(),
+                KeyCode::F(5) => self.refresh(),
+                KeyCode::Char('e') if self.is_forum_selected() => {
+                    self.ui.forum_post_index = self.ui.forum_post_index.saturating_sub(1);
+                    Command::None
+                }
+                KeyCode::Char('d') if self.is_forum_selected() => {
+                    let count = self.get_current_forum_posts().len();
+                    if self.ui.forum_post_index + 1 < count {
+                        self.ui.forum_post_index += 1;
+                    }
+                    Command::None
+                }
+                KeyCode::Char('e') => self.scroll_view(1),
+                KeyCode::Char('d') => self.scroll_view(-1),
+                KeyCode::Char('G') | KeyCode::End => self.jump_to_bottom(),
+                KeyCode::Char('g') if !pending_g => {
+                    self.ui.pending_g = true;
+                    Command::None
+                }
+                KeyCode::Char('g') | KeyCode::Home => self.jump_to_top(),
+                KeyCode::Backspace => {
+                    // フォーラム投稿の閲覧中なら親フォーラムの一覧に戻る
+                    if let Some(forum_id) = self.ui.forum_return.take() {
+                        let index = self
+                            .get_forum_posts(&forum_id)
+                            .iter()
+                            .position(|p| Some(&p.id) == self.ui.selected_channel.as_ref())
+                            .unwrap_or(0);
+                        self.ui.selected_channel = Some(forum_id.clone());
+                        let cmd = self.select_channel_commands(forum_id);
+                        self.ui.forum_post_index = index;
+                        cmd
+                    } else {
+                        Command::None
+                    }
+                }
+                KeyCode::Char('o') => {
+                    // 現在のチャンネルを Discord アプリで開く
+                    if let Some(channel_id) = &self.ui.selected_channel {
+                        let guild_id = self
+                            .discord
+                            .channels
+                            .get(channel_id)
+                            .and_then(|ch| ch.guild_id.clone());
+                        Command::OpenInDiscord {
+                            guild_id,
+                            channel_id: channel_id.clone(),
+                        }
+                    } else {
+                        Command::None
+                    }
+                }
+                KeyCode::Char('L') => self.open_next_link(),
+                KeyCode::Char('K') => {
+                    self.move_favorite(-1);
+                    Command::None
+                }
+                KeyCode::Char('J') => {
+                    self.move_favorite(1);
+                    Command::None
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.select_previous_channel(),
+                KeyCode::Down | KeyCode::Char('j') => self.select_next_channel(),
+                KeyCode::Enter if self.nsfw_gate_active() => {
+                    // 確認してから初めてメッセージを読み込み、既読にする
+                    match self.ui.selected_channel.clone() {
+                        Some(channel_id) => {
+                            log::info!("NSFW warning acknowledged for {}", channel_id);
+                            self.ui.nsfw_acknowledged.insert(channel_id.clone());
+                            self.select_channel_commands(channel_id)
+                        }
+                        None => Command::None,
+                    }
+                }
+                KeyCode::Enter if self.is_forum_selected() => self.open_selected_forum_post(),
+                KeyCode::Enter => {
+                    // チャンネル選択確定
+                    self.ui.message_scroll_offset = 0;
+                    if let Some(channel_id) = self.ui.selected_channel.clone() {
+                        self.select_channel_commands(channel_id)
+                    } else {
+                        Command::None
+                    }
+                }
+                _ => Command::None,
+            },
+            InputMode::Editing => match key {
+                KeyCode::Esc => {
+                    // 返信中なら返信も、編集中なら編集も取り消す
+                    self.ui.reply_to = None;
+                    if self.ui.editing_message.take().is_some() {
+                        self.ui.input_buffer.clear();
+                    }
+                    self.ui.input_mode = InputMode::Normal;
+                    Command::None
+                }
+                KeyCode::Enter if self.ui.editing_message.is_some() => self.finish_edit(),
+                KeyCode::Enter => {
+                    // `/to <チャンネル> <本文>` は開いているチャンネルのまま別のチャンネルへ送る
+                    if self.ui.input_buffer.starts_with("/to ") {
+                        let input = std::mem::take(&mut self.ui.input_buffer);
+                        return self.quick_send(input);
+                    }
+                    // slowmode のクールダウン中は失敗が確定しているので送信しない
+                    if let Some((secs, true)) = self.current_send_wait() {
+                        self.ui.status_message =
+                            Some(format!("Slowmode: wait {}s before sending", secs));
+                        return Command::None;
+                    }
+                    if !self.ui.input_buffer.is_empty() {
+                        let content = self.ui.input_buffer.clone();
+                        self.ui.input_buffer.clear();
+
+                        if let Some(channel_id) = self.ui.selected_channel.clone() {
+                            let reply_to = self.take_reply(&channel_id);
+                            let mass_mentions = self.mass_mentions_allowed();
+                            self.ui.mass_mentions_toggled = false;
+                            // 切断中は再接続まで送信を待たせる
+                            if !self.discord.connected {
+                                self.ui.pending_sends.push(PendingSend {
+                                    channel_id,
+                                    content,
+                                    reply_to,
+                                    mass_mentions,
+                                    queued_at: Instant::now(),
+                                });
+                                self.ui.status_message =
+                                    Some("Disconnected: message will be sent after reconnecting".to_string());
+                                return Command::None;
+                            }
+                            return self.send_message(channel_id, content, reply_to, mass_mentions);
+                        }
+                    }
+                    Command::None
+                }
+                KeyCode::Backspace => {
+                    self.ui.input_buffer.pop();
+                    Command::None
+                }
+                KeyCode::Tab => {
+                    // 返信先の作者に通知するかを切り替え
+                    if self.current_reply().is_some() {
+                        if let Some(reply) = self.ui.reply_to.as_mut() {
+                            reply.mention = !reply.mention;
+                        }
+                    }
+                    Command::None
+                }
+                KeyCode::BackTab => {
+                    // このメッセージの @everyone / @here / ロールへの通知を切り替え
+                    self.ui.mass_mentions_toggled = !self.ui.mass_mentions_toggled;
+                    Command::None
+                }
+                KeyCode::Char(c) => {
+                    self.ui.input_buffer.push(c);
+                    Command::None
+                }
+                _ => Command::None,
+            },
+            InputMode::PurgeConfirm => match key {
+                KeyCode::Esc => {
+                    self.ui.purge_buffer.clear();
+                    self.ui.input_mode = InputMode::Normal;
+                    Command::None
+                }
+                KeyCode::Enter => self.confirm_purge(),
+                KeyCode::Backspace => {
+                    self.ui.purge_buffer.pop();
+                    Command::None
+                }
+                KeyCode::Char(c) => {
+                    self.ui.purge_buffer.push(c);
+                    Command::None
+                }
+                _ => Command::None,
+            },
+            InputMode::JumpPrompt => match key {
+                KeyCode::Esc => {
+                    self.ui.jump_buffer.clear();
+                    self.ui.input_mode = InputMode::Normal;
+                    Command::None
+                }
+                KeyCode::Enter => self.confirm_jump(),
+                KeyCode::Backspace => {
+                    self.ui.jump_buffer.pop();
+                    Command::None
+                }
+                KeyCode::Char(c) => {
+                    self.ui.jump_buffer.push(c);
+                    Command::None
+                }
+                _ => Command::None,
+            },
+            InputMode::MessageSelect => match key {
+                KeyCode::Esc | KeyCode::Char('V') => {
+                    self.leave_message_select();
+                    Command::None
+                }
+                KeyCode::Char('?') => {
+                    self.open_help();
+                    Command::None
+                }
+                // 上下の移動は画面上の向き (newest-first なら上が新しい側)
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.move_message_selection(!self.newest_first())
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.move_message_selection(self.newest_first())
+                }
+                KeyCode::Char('r') => {
+                    self.open_emoji_picker();
+                    Command::None
+                }
+                KeyCode::Char('R') => {
+                    self.start_reply();
+                    Command::None
+                }
+                KeyCode::Char('e') => {
+                    self.start_edit();
+                    Command::None
+                }
+                KeyCode::Char('d') => self.delete_selected_message(pending_delete),
+                KeyCode::Char('y') => self.copy_selected_message(),
+                KeyCode::Char('L') | KeyCode::Enter => self.open_next_link(),
+                KeyCode::Char('v') => self.toggle_image_previews(),
+                _ => Command::None,
+            },
+        }
+    }
+
+    /// キーバインド一覧を先頭から表示する
+    fn open_help(&mut self) {
+        self.ui.help_open = true;
+        self.ui.help_scroll = 0;
+    }
+
+    /// メッセージ選択モードに入り、最新のメッセージを選択する
+    fn enter_message_select(&mut self) {
+        if self.is_forum_selected() {
+            return;
+        }
+        let Some(newest) = self.get_current_messages().first().map(|m| m.id.clone()) else {
+            self.ui.status_message = Some("No messages to select".to_string());
+            return;
+        };
+        // ジャンプ先の強調と選択の強調が紛らわしいので、ジャンプ先の強調は消す
+        self.ui.highlighted_message = None;
+        self.ui.selected_message = Some(newest);
+        self.ui.input_mode = InputMode::MessageSelect;
+        self.scroll_selection_into_view();
+    }
+
+    /// メッセージ選択モードを抜ける
+    fn leave_message_select(&mut self) {
+        self.ui.selected_message = None;
+        if self.ui.input_mode == InputMode::MessageSelect {
+            self.ui.input_mode = InputMode::Normal;
+        }
+    }
+
+    /// 選択を 1 つ古い (`older`) / 新しいメッセージへ動かす。
+    /// 読み込み済みの最古より古い側へ動かそうとしたら、さらに古いメッセージを読み込む
+    fn move_message_selection(&mut self, older: bool) -> Command {
+        // メッセージは新しい順に並んでいる
+        let messages = self.get_current_messages();
+        let index = self
+            .ui
+            .selected_message
+            .as_ref()
+            .and_then(|id| messages.iter().position(|m| &m.id == id));
+        let next = match (index, older) {
+            (None, _) => Some(0),
+            (Some(i), true) => Some(i + 1),
+            (Some(i), false) => i.checked_sub(1),
+        };
+        match next.and_then(|i| messages.get(i)).map(|m| m.id.clone()) {
+            Some(id) => {
+                self.ui.selected_message = Some(id);
+                self.scroll_selection_into_view();
+                Command::None
+            }
+            None if older => self.maybe_load_older_messages(),
+            None => Command::None,
+        }
+    }
+
+    /// 選択中のメッセージが画面に収まるように、前回描画したレイアウトから最小限スクロールする。
+    /// まだ並べていない古い側のメッセージなら並べた範囲の端までスクロールし、
+    /// 次の描画でその先が並ぶようにする
+    fn scroll_selection_into_view(&mut self) {
+        let Some(id) = self.ui.selected_message.as_deref() else {
+            return;
+        };
+        let extents = &self.ui.cached_message_extents;
+        // 並べた範囲の端のメッセージが今のチャンネルのものか (別のチャンネルの描画結果は使わない)
+        let same_channel = |last: &(String, u32, u32)| {
+            self.get_current_messages().iter().any(|m| m.id == last.0)
+        };
+        let Some(&(_, start, end)) = extents
+            .iter()
+            .find(|(m, _, _)| m == id)
+            .or_else(|| extents.last().filter(|last| same_channel(last)))
+        else {
+            return;
+        };
+        let offset = self.ui.message_scroll_offset as u32;
+        let height = self.ui.cached_viewport_height as u32;
+        if start < offset {
+            self.ui.message_scroll_offset = start as usize;
+        } else if end > offset.saturating_add(height) {
+            self.ui.message_scroll_offset = end.saturating_sub(height) as usize;
+        }
+    }
+
+    /// 操作の対象にするメッセージ。選択モードなら選択中のメッセージ、それ以外は最新のメッセージ
+    fn target_message(&self, channel_id: &str) -> Option<&Message> {
+        let messages = self.discord.messages.get(channel_id)?;
+        let selected = self
+            .ui
+            .selected_message
+            .as_ref()
+            .filter(|_| self.ui.input_mode == InputMode::MessageSelect);
+        if let Some(id) = selected {
+            return messages.iter().find(|m| &m.id == id);
+        }
+        messages
+            .iter()
+            .reduce(|a, b| if snowflake_gt(&b.id, &a.id) { b } else { a })
+    }
+
+    /// 入力したメッセージリンクのチャンネルを開き、そのメッセージまでスクロールして強調する。
+    /// 読み込み済みでなければ前後のメッセージを取得する
+    fn confirm_jump(&mut self) -> Command {
+        let input = std::mem::take(&mut self.ui.jump_buffer);
+        self.ui.input_mode = InputMode::Normal;
+        let Some(link) = parse_message_link(&input) else {
+            self.ui.status_message = Some("Not a Discord message link".to_string());
+            return Command::None;
+        };
+        let unavailable = if !self.discord.channels.contains_key(&link.channel_id) {
+            let member = match &link.guild_id {
+                Some(guild_id) => self.discord.guilds.contains_key(guild_id),
+                None => true,
+            };
+            Some(if member {
+                "That channel is not available"
+            } else {
+                "You are not a member of that server"
+            })
+        } else if self.discord.inaccessible_channels.contains(&link.channel_id) {
+            Some("You cannot view that channel")
+        } else {
+            None
+        };
+        if let Some(reason) = unavailable {
+            log::info!("Cannot jump to {}/{}: {}", link.channel_id, link.message_id, reason);
+            self.ui.status_message = Some(reason.to_string());
+            return Command::None;
+        }
+
+        let channel_id = link.channel_id;
+        let index = self
+            .get_current_display_channels()
+            .iter()
+            .position(|ch| ch.id == channel_id);
+        self.ui.channel_list_state.select(index);
+        self.ui.selected_channel = Some(channel_id.clone());
+        self.ui.message_scroll_offset = 0;
+        let command = self.select_channel_commands(channel_id.clone());
+        // 既読位置ではなくジャンプ先までスクロールし、新着が来ても追従しない
+        self.ui.scroll_to_unread = false;
+        self.ui.auto_follow = false;
+        self.ui.scroll_to_message = Some(link.message_id.clone());
+        self.ui.highlighted_message = Some(link.message_id.clone());
+        let cached = self
+            .discord
+            .messages
+            .get(&channel_id)
+            .is_some_and(|messages| messages.iter().any(|m| m.id == link.message_id));
+        if cached {
+            return command;
+        }
+        // 最新分の読み込みは前後の読み込み結果を置き換えてしまうので送らない
+        merge_commands(
+            without_message_loads(command),
+            Command::LoadMessagesAround {
+                channel_id,
+                message_id: link.message_id,
+            },
+        )
+    }
+
+    /// 選択中のメッセージ (選択モードでなければ最新メッセージ) を対象に絵文字ピッカーを開く
+    fn open_emoji_picker(&mut self) {
+        if self.read_only {
+            self.ui.status_message = Some("Read-only mode: reactions are disabled".to_string());
+            return;
+        }
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return;
+        };
+        let Some(message) = self.target_message(&channel_id) else {
+            self.ui.status_message = Some("No message to react to".to_string());
+            return;
+        };
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        self.ui.emoji_picker = Some(EmojiPicker {
+            channel_id,
+            message_id: message.id.clone(),
+            query: String::new(),
+            list_state,
+        });
+    }
+
+    /// 選択中チャンネルの最新メッセージへの返信を始める (返信バナーを出して入力モードへ)
+    fn start_reply(&mut self) {
+        if self.read_only {
+            self.ui.status_message = Some("Read-only mode: sending is disabled".to_string());
+            return;
+        }
+        if self.is_forum_selected() {
+            return;
+        }
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return;
+        };
+        let Some(message) = self.target_message(&channel_id) else {
+            self.ui.status_message = Some("No message to reply to".to_string());
+            return;
+        };
+        self.ui.reply_to = Some(ReplyTarget {
+            channel_id,
+            message_id: message.id.clone(),
+            author: message.author_display_name().to_string(),
+            snippet: reply_snippet(message),
+            mention: true,
+        });
+        self.ui.selected_message = None;
+        self.ui.input_mode = InputMode::Editing;
+    }
+
+    /// 選択中のメッセージが自分のものならその (channel_id, メッセージ)。
+    /// 他人のメッセージなら `action` できない旨をステータスバーに出す
+    fn selected_own_message(&mut self, action: &str) -> Option<(String, Message)> {
+        let channel_id = self.ui.selected_channel.clone()?;
+        let message = self.target_message(&channel_id)?.clone();
+        let own = self
+            .discord
+            .current_user
+            .as_ref()
+            .is_some_and(|me| me.id == message.author.id);
+        if !own {
+            self.ui.status_message = Some(format!("You can only {} your own messages", action));
+            return None;
+        }
+        Some((channel_id, message))
+    }
+
+    /// 選択中の自分のメッセージの本文を入力欄に入れて編集を始める
+    fn start_edit(&mut self) {
+        if self.read_only {
+            self.ui.status_message = Some("Read-only mode: editing is disabled".to_string());
+            return;
+        }
+        let Some((channel_id, message)) = self.selected_own_message("edit") else {
+            return;
+        };
+        self.ui.input_buffer = message.content;
+        self.ui.reply_to = None;
+        self.ui.editing_message = Some((channel_id, message.id));
+        self.ui.selected_message = None;
+        self.ui.input_mode = InputMode::Editing;
+    }
+
+    /// 入力欄の内容で編集中のメッセージを書き換える
+    fn finish_edit(&mut self) -> Command {
+        if self.ui.input_buffer.trim().is_empty() {
+            self.ui.status_message =
+                Some("A message cannot be empty (Esc to cancel editing)".to_string());
+            return Command::None;
+        }
+        let Some((channel_id, message_id)) = self.ui.editing_message.take() else {
+            return Command::None;
+        };
+        self.ui.input_mode = InputMode::Normal;
+        Command::EditMessage {
+            channel_id,
+            message_id,
+            content: std::mem::take(&mut self.ui.input_buffer),
+        }
+    }
+
+    /// 選択中の自分のメッセージを削除する。
+    /// 誤操作を防ぐため、同じメッセージで続けて 2 回押したときだけ削除する
+    fn delete_selected_message(&mut self, pending_delete: Option<String>) -> Command {
+        if self.read_only {
+            self.ui.status_message = Some("Read-only mode: deleting is disabled".to_string());
+            return Command::None;
+        }
+        let Some((channel_id, message)) = self.selected_own_message("delete") else {
+            return Command::None;
+        };
+        if pending_delete.as_deref() != Some(message.id.as_str()) {
+            self.ui.pending_delete = Some(message.id);
+            self.ui.status_message = Some("Press d again to delete this message".to_string());
+            return Command::None;
+        }
+        self.ui.status_message = None;
+        Command::DeleteMessage {
+            channel_id,
+            message_id: message.id,
+        }
+    }
+
+    /// 選択中のメッセージの本文をクリップボードへコピーする
+    fn copy_selected_message(&mut self) -> Command {
+        let content = self
+            .ui
+            .selected_channel
+            .as_deref()
+            .and_then(|channel_id| self.target_message(channel_id))
+            .map(|message| message.content.clone())
+            .unwrap_or_default();
+        if content.is_empty() {
+            self.ui.status_message = Some("No text to copy".to_string());
+            return Command::None;
+        }
+        self.ui.status_message = Some("Copied the message to the clipboard".to_string());
+        Command::CopyToClipboard(content)
+    }
+
+    /// ギルドのボイスチャンネルの接続状況を READY / GUILD_CREATE の内容で置き換える
+    fn replace_voice_states(&mut self, guild_id: &str, states: Vec<VoiceState>) {
+        self.discord.voice_states.remove(guild_id);
+        for state in states {
+            self.apply_voice_state(guild_id, state);
+        }
+    }
+
+    /// 接続・切断・移動を反映する (移動は同じ user_id の上書きになる)
+    fn apply_voice_state(&mut self, guild_id: &str, state: VoiceState) {
+        let users = self.discord.voice_states.entry(guild_id.to_string()).or_default();
+        match state.channel_id {
+            Some(channel_id) => {
+                users.insert(state.user_id, channel_id);
+            }
+            None => {
+                users.remove(&state.user_id);
+            }
+        }
+    }
+
+    /// ボイスチャンネルに接続中の人数
+    pub fn voice_member_count(&self, channel: &Channel) -> usize {
+        channel
+            .guild_id
+            .as_ref()
+            .and_then(|guild_id| self.discord.voice_states.get(guild_id))
+            .map(|users| users.values().filter(|id| **id == channel.id).count())
+            .unwrap_or(0)
+    }
+
+    /// `/to <チャンネル> <本文>` の送信先を読み込み済みのチャンネルから探して送る。
+    /// 送れなければ理由をステータスバーに出し、入力を戻して直せるようにする
+    fn quick_send(&mut self, input: String) -> Command {
+        match self.resolve_quick_send(&input["/to ".len()..]) {
+            Ok((channel_id, name, content)) => {
+                log::info!("Quick send to {} ({})", name, channel_id);
+                self.ui.status_message = Some(format!("Sent to {}", name));
+                let mass_mentions = self.mass_mentions_allowed();
+                self.ui.mass_mentions_toggled = false;
+                self.send_message(channel_id, content, None, mass_mentions)
+            }
+            Err(e) => {
+                self.ui.status_message = Some(e);
+                self.ui.input_buffer = input;
+                Command::None
+            }
+        }
+    }
+
+    /// `/to` の引数を (チャンネル ID, 表示名, 本文) に分解する
+    fn resolve_quick_send(&self, args: &str) -> Result<(String, String, String), String> {
+        let (target, content) = args
+            .trim_start()
+            .split_once(' ')
+            .filter(|(_, content)| !content.trim().is_empty())
+            .ok_or("Usage: /to <#channel|server/channel|@user> <message>")?;
+        let channel = resolve_channel(target, &self.discord.channels, &self.discord.guilds)
+            .map_err(|e| e.to_string())?;
+        if !self.discord.connected {
+            return Err("Disconnected: try again after reconnecting".to_string());
+        }
+        let name = format!("{}{}", self.channel_prefix(channel), channel.display_name());
+        Ok((channel.id.clone(), name, content.trim().to_string()))
+    }
+
+    /// 作成中のメッセージの @everyone / @here / ロールへのメンションで通知するか
+    pub fn mass_mentions_allowed(&self) -> bool {
+        self.config.allow_mass_mentions != self.ui.mass_mentions_toggled
+    }
+
+    /// 選択中チャンネルの返信バナーに出す返信先 (別チャンネルで始めた返信は出さない)
+    pub fn current_reply(&self) -> Option<&ReplyTarget> {
+        self.ui
+            .reply_to
+            .as_ref()
+            .filter(|reply| self.ui.selected_channel.as_ref() == Some(&reply.channel_id))
+    }
+
+    /// 送信時に返信先を取り出してバナーを消す。送信先と別チャンネルの返信先は破棄する
+    fn take_reply(&mut self, channel_id: &str) -> Option<MessageReply> {
+        self.ui
+            .reply_to
+            .take()
+            .filter(|reply| reply.channel_id == channel_id)
+            .map(|reply| MessageReply {
+                message_id: reply.message_id,
+                mention: reply.mention,
+            })
+    }
+
+    /// ピッカーの全候補 (最近使ったもの → サーバーのカスタム絵文字 → 標準の絵文字、重複なし)
+    fn emoji_candidates(&self, channel_id: &str) -> Vec<PickableEmoji> {
+        let guild_emojis: Vec<PickableEmoji> = self
+            .discord
+            .channels
+            .get(channel_id)
+            .and_then(|ch| ch.guild_id.as_ref())
+            .and_then(|gid| self.discord.guilds.get(gid))
+            .map(|guild| {
+                guild
+                    .emojis
+                    .iter()
+                    .filter(|e| e.available)
+                    .map(|e| PickableEmoji::custom(&e.name, &e.id))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let standard: Vec<PickableEmoji> = COMMON_EMOJI
+            .iter()
+            .map(|(name, emoji)| PickableEmoji::unicode(name, emoji))
+            .collect();
+
+        // 最近使ったものは、このチャンネルで使える候補に含まれるものだけ先頭に出す
+        let mut candidates: Vec<PickableEmoji> = self
+            .config
+            .recent_reactions
+            .iter()
+            .filter_map(|key| {
+                guild_emojis
+                    .iter()
+                    .chain(standard.iter())
+                    .find(|e| e.reaction_key() == *key)
+                    .cloned()
+            })
+            .collect();
+        for emoji in guild_emojis.into_iter().chain(standard) {
+            if !candidates.contains(&emoji) {
+                candidates.push(emoji);
+            }
+        }
+        candidates
+    }
+
+    /// 絵文字ピッカーの絞り込み結果
+    pub fn emoji_picker_results(&self) -> Vec<PickableEmoji> {
+        let Some(picker) = &self.ui.emoji_picker else {
+            return Vec::new();
+        };
+        let candidates = self.emoji_candidates(&picker.channel_id);
+        filter_emoji(&candidates, &picker.query)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    /// 絵文字ピッカー表示中のキー処理
+    fn handle_emoji_picker_key(&mut self, key: KeyCode) -> Command {
+        let count = self.emoji_picker_results().len();
+        let Some(picker) = self.ui.emoji_picker.as_mut() else {
+            return Command::None;
+        };
+        let selected = picker.list_state.selected().unwrap_or(0);
+        match key {
+            KeyCode::Esc => {
+                self.ui.emoji_picker = None;
+            }
+            KeyCode::Up => {
+                let index = if selected == 0 { count.saturating_sub(1) } else { selected - 1 };
+                picker.list_state.select(Some(index));
+            }
+            KeyCode::Down => {
+                let index = if selected + 1 >= count { 0 } else { selected + 1 };
+                picker.list_state.select(Some(index));
+            }
+            KeyCode::Backspace => {
+                picker.query.pop();
+                picker.list_state.select(Some(0));
+            }
+            KeyCode::Char(c) => {
+                picker.query.push(c);
+                picker.list_state.select(Some(0));
+            }
+            KeyCode::Enter => return self.apply_picked_emoji(selected),
+            _ => {}
+        }
+        Command::None
+    }
+
+    /// アカウント切り替えオーバーレイを開く (現在のプロファイルを選択状態にする)
+    fn open_account_switcher(&mut self, mut profiles: Vec<String>) {
+        if !profiles.contains(&self.profile) {
+            profiles.insert(0, self.profile.clone());
+        }
+        let current = profiles.iter().position(|p| *p == self.profile);
+        let mut list_state = ListState::default();
+        list_state.select(current);
+        self.ui.account_switcher = Some(AccountSwitcher {
+            profiles,
+            list_state,
+        });
+    }
+
+    /// アカウント切り替えオーバーレイ表示中のキー入力
+    fn handle_account_switcher_key(&mut self, key: KeyCode) -> Command {
+        let Some(switcher) = self.ui.account_switcher.as_mut() else {
+            return Command::None;
+        };
+        let count = switcher.profiles.len();
+        let selected = switcher.list_state.selected().unwrap_or(0);
+        match key {
+            KeyCode::Esc => {
+                self.ui.account_switcher = None;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                let index = if selected == 0 { count.saturating_sub(1) } else { selected - 1 };
+                switcher.list_state.select(Some(index));
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                let index = if selected + 1 >= count { 0 } else { selected + 1 };
+                switcher.list_state.select(Some(index));
+            }
+            KeyCode::Enter => {
+                let profile = switcher.profiles.get(selected).cloned();
+                self.ui.account_switcher = None;
+                if let Some(profile) = profile.filter(|p| *p != self.profile) {
+                    log::info!("Switching account: {} -> {}", self.profile, profile);
+                    self.ui.status_message = Some(format!("Switching to {}...", profile));
+                    return Command::SwitchProfile(profile);
+                }
+            }
+            _ => {}
+        }
+        Command::None
+    }
+
+    /// ピッカーで選んだ絵文字をリアクションとして付け、最近使った絵文字に記録する
+    fn apply_picked_emoji(&mut self, index: usize) -> Command {
+        let Some(emoji) = self.emoji_picker_results().into_iter().nth(index) else {
+            return Command::None;
+        };
+        let Some(picker) = self.ui.emoji_picker.take() else {
+            return Command::None;
+        };
+        let key = emoji.reaction_key();
+        let recent = &mut self.config.recent_reactions;
+        recent.retain(|k| *k != key);
+        recent.insert(0, key.clone());
+        recent.truncate(RECENT_REACTIONS_MAX);
+        log::info!("Adding reaction {} to {}", key, picker.message_id);
+        Command::AddReaction {
+            channel_id: picker.channel_id,
+            message_id: picker.message_id,
+            emoji: key,
+        }
+    }
+
+    /// 一括削除の確認入力を開始する (メッセージ可能なチャンネルを選択中で、
+    /// 自分のユーザー情報が取得済み、かつ他の一括削除が実行中でない場合のみ)
+    fn start_purge_prompt(&mut self) {
+        if self.read_only {
+            self.ui.status_message = Some("Read-only mode: deleting is disabled".to_string());
+            return;
+        }
+        if self.config.conservative_mode {
+            self.ui.status_message =
+                Some("Conservative mode: bulk delete is disabled".to_string());
+            return;
+        }
+        if self.ui.purge.is_some() || self.discord.current_user.is_none() {
+            return;
+        }
+        let messageable = self
+            .ui
+            .selected_channel
+            .as_ref()
+            .and_then(|id| self.discord.channels.get(id))
+            .is_some_and(|ch| ch.is_messageable());
+        if !messageable {
+            return;
+        }
+        self.ui.purge_buffer.clear();
+        self.ui.input_mode = InputMode::PurgeConfirm;
+    }
+
+    /// `delete <N>` が正しく入力されていれば一括削除を開始する
+    fn confirm_purge(&mut self) -> Command {
+        let input = std::mem::take(&mut self.ui.purge_buffer);
+        self.ui.input_mode = InputMode::Normal;
+
+        let mut parts = input.split_whitespace();
+        let count = match (parts.next(), parts.next(), parts.next()) {
+            (Some(PURGE_CONFIRM_KEYWORD), Some(n), None) => n.parse::<usize>().ok(),
+            _ => None,
+        };
+        let Some(count) = count.filter(|n| *n > 0) else {
+            log::info!("Purge not confirmed (input: {:?})", input);
+            return Command::None;
+        };
+        let (Some(channel_id), Some(user)) =
+            (self.ui.selected_channel.clone(), self.discord.current_user.as_ref())
+        else {
+            return Command::None;
+        };
+
+        log::info!("Starting purge of {} own messages in {}", count, channel_id);
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.ui.purge = Some(PurgeState {
+            channel_id: channel_id.clone(),
+            deleted: 0,
+            total: 0,
+            result: None,
+            cancel: cancel.clone(),
+        });
+        Command::PurgeOwnMessages {
+            channel_id,
+            user_id: user.id.clone(),
+            count,
+            cancel,
+        }
+    }
+
+    /// 現在カーソル操作対象のチャンネルリストを取得
+    fn get_current_display_channels(&self) -> Vec<&Channel> {
+        if self.ui.search_mode {
+            self.search_channels(&self.ui.search_buffer)
+        } else {
+            match self.ui.sidebar_focus {
+                SidebarFocus::Favorites => self.get_favorite_channels(),
+                SidebarFocus::Unread => self.get_unread_channels(),
+            }
+        }
+    }
+
+    /// チャンネル選択時の Command を組み立てる。
+    /// LoadMessages に加えて、未読がある場合は ack も同時に発火する
+    /// (REST のメッセージ取得結果に依存せず、READY 由来の last_message_id を使う)。
+    fn select_channel_commands(&mut self, channel_id: String) -> Command {
+        // 直接開いた場合は、保留中の j/k 移動による読み込みは不要
+        self.ui.pending_channel_load = None;
+        self.ui.scroll_to_message = None;
+        self.ui.highlighted_message = None;
+        self.leave_message_select();
+        // フォーラムは /messages を持たないので投稿一覧を取得する
+        let forum_guild = self
+            .discord
+            .channels
+            .get(&channel_id)
+            .filter(|ch| ch.is_forum())
+            .map(|ch| ch.guild_id.clone());
+        if let Some(guild_id) = forum_guild {
+            self.ui.forum_post_index = 0;
+            self.ui.forum_return = None;
+            return match guild_id {
+                Some(guild_id) => Command::LoadForumPosts {
+                    guild_id,
+                    forum_id: channel_id,
+                },
+                None => Command::None,
+            };
+        }
+        // フォーラム外への移動は戻り先をリセット (投稿を開く処理では後から設定し直す)
+        self.ui.forum_return = None;
+        // ボイスチャンネルは接続人数を表示するだけで、メッセージは読み込まない
+        if self.discord.channels.get(&channel_id).is_some_and(|ch| ch.is_voice()) {
+            return Command::None;
+        }
+
+        self.ui.unread_divider_seen = false;
+        // 開いた瞬間の既読位置を境界として保存 (ack より前のスナップショット)。
+        // このクライアントで前回表示した位置と Discord の既読位置のうち新しい方を使う
+        let server_read = self.discord.read_states.get(&channel_id).cloned().flatten();
+        let viewed = self.config.last_viewed.get(&channel_id).cloned();
+        let last_read = match (server_read, viewed) {
+            (Some(read), Some(viewed)) if self.snowflake_gt(&viewed, &read) => Some(viewed),
+            (read, viewed) => read.or(viewed),
+        };
+        if let Some(last_read) = last_read {
+            self.ui
+                .unread_boundaries
+                .insert(channel_id.clone(), last_read);
+            self.ui.scroll_to_unread = true;
+        }
+
+        if self.nsfw_gated(&channel_id) {
+            log::debug!("Not loading NSFW channel {} until the warning is confirmed", channel_id);
+            return Command::None;
+        }
+
+        let last_msg = self
+            .discord
+            .channels
+            .get(&channel_id)
+            .and_then(|c| c.last_message_id.clone());
+        let mut cmds: Vec<Command> = self.message_load_command(&channel_id).into_iter().collect();
+        if let Some(message_id) = last_msg {
+            let already_read = matches!(
+                self.discord.read_states.get(&channel_id),
+                Some(Some(read)) if read.as_str() == message_id.as_str()
+            );
+            if !already_read {
+                // 楽観的に read_states を更新、セッション中は未読リストに残す (グレーアウト)
+                self.discord
+                    .read_states
+                    .insert(channel_id.clone(), Some(message_id.clone()));
+                self.discord.mention_counts.remove(&channel_id);
+                self.discord.session_unread.remove(&channel_id);
+                self.discord.acked_in_session.insert(channel_id.clone());
+                self.invalidate_unread_cache();
+                cmds.push(Command::AckChannel {
+                    channel_id,
+                    message_id,
+                });
+            }
+        }
+        match cmds.len() {
+            0 => Command::None,
+            1 => cmds.into_iter().next().unwrap(),
+            _ => Command::Batch(cmds),
+        }
+    }
+
+    /// ポーリングで新着を確認するコマンド。開いているチャンネルとお気に入りのうち、
+    /// 既知の最新メッセージがあり、取得中でないものが対象
+    fn poll_commands(&mut self) -> Command {
+        if !self.polling || !self.discord.connected {
+            return Command::None;
+        }
+        let mut targets: Vec<String> = self.ui.selected_channel.iter().cloned().collect();
+        for channel in self.get_favorite_channels() {
+            if !targets.contains(&channel.id) {
+                targets.push(channel.id.clone());
+            }
+        }
+        let mut cmds = Vec::new();
+        for channel_id in targets {
+            let messageable = self
+                .discord
+                .channels
+                .get(&channel_id)
+                .is_some_and(|ch| ch.is_messageable());
+            if !messageable
+                || self.discord.inaccessible_channels.contains(&channel_id)
+                || self.discord.polls_in_flight.contains(&channel_id)
+            {
+                continue;
+            }
+            // 一度もメッセージの無いチャンネルは基準が無いので、最初の投稿を待たない
+            let Some(after) = self.newest_known_message(&channel_id) else {
+                continue;
+            };
+            self.discord.polls_in_flight.insert(channel_id.clone());
+            cmds.push(Command::PollChannel { channel_id, after });
+        }
+        cmds.into_iter().fold(Command::None, merge_commands)
+    }
+
+    /// チャンネルの既知の最新メッセージ ID (キャッシュとチャンネルの last_message_id の新しい方)
+    fn newest_known_message(&self, channel_id: &str) -> Option<String> {
+        let cached = self
+            .discord
+            .messages
+            .get(channel_id)
+            .and_then(|messages| messages.first())
+            .map(|m| m.id.as_str());
+        let last = self
+            .discord
+            .channels
+            .get(channel_id)
+            .and_then(|ch| ch.last_message_id.as_deref());
+        match (cached, last) {
+            (Some(a), Some(b)) => Some(if snowflake_gt(b, a) { b } else { a }),
+            (a, b) => a.or(b),
+        }
+        .map(str::to_string)
+    }
+
+    /// チャンネルを開くときのメッセージ取得。キャッシュが TTL 内なら取得しない、
+    /// 古ければ最新メッセージ以降の差分だけ、未取得なら全件を取得する
+    fn message_load_command(&self, channel_id: &str) -> Option<Command> {
+        let ttl = Duration::from_secs(self.config.message_cache_ttl_secs);
+        let newest = self
+            .discord
+            .messages
+            .get(channel_id)
+            .and_then(|messages| {
+                messages
+                    .iter()
+                    .map(|m| m.id.as_str())
+                    .reduce(|a, b| if snowflake_gt(b, a) { b } else { a })
+            });
+        let fetched_at = self.discord.messages_fetched_at.get(channel_id);
+        match (newest, fetched_at) {
+            (Some(_), Some(at)) if !ttl.is_zero() && at.elapsed() < ttl => None,
+            (Some(after), Some(_)) if !ttl.is_zero() => Some(Command::LoadNewerMessages {
+                channel_id: channel_id.to_string(),
+                after: after.to_string(),
+            }),
+            // Gateway で受信しただけのチャンネルは過去分が無いので全件取得する
+            _ => Some(Command::LoadMessages(channel_id.to_string())),
+        }
+    }
+
+    /// slowmode のチャンネルなら、次に送信できるまでのクールダウンを開始する
+    fn start_slowmode_cooldown(&mut self, channel_id: &str) {
+        let slowmode = self
+            .discord
+            .channels
+            .get(channel_id)
+            .and_then(|ch| ch.slowmode_secs());
+        if let Some(secs) = slowmode {
+            self.ui.send_waits.insert(
+                channel_id.to_string(),
+                SendWait {
+                    until: Instant::now() + Duration::from_secs(secs as u64),
+                    slowmode: true,
+                },
+            );
+        }
+    }
+
+    /// 選択中チャンネルの slowmode 秒数
+    pub fn current_slowmode_secs(&self) -> Option<u32> {
+        self.ui
+            .selected_channel
+            .as_ref()
+            .and_then(|id| self.discord.channels.get(id))
+            .and_then(|ch| ch.slowmode_secs())
+    }
+
+    /// 選択中チャンネルの送信待ち残り秒数 (切り上げ) と slowmode かどうか
+    pub fn current_send_wait(&self) -> Option<(u64, bool)> {
+        let channel_id = self.ui.selected_channel.as_ref()?;
+        let wait = self.ui.send_waits.get(channel_id)?;
+        let remaining = wait.until.checked_duration_since(Instant::now())?;
+        Some((remaining.as_secs_f64().ceil() as u64, wait.slowmode))
+    }
+
+    /// REST が 429 で待っている場合、再送までの残り秒数 (切り上げ)
+    pub fn rest_rate_limit_wait(&self) -> Option<u64> {
+        let until = self.discord.rest_rate_limited_until?;
+        let remaining = until.checked_duration_since(Instant::now())?;
+        Some(remaining.as_secs_f64().ceil() as u64)
+    }
+
+    /// 選択中のチャンネルのメッセージ (全件 / 過去分) を読み込み中なら、スピナーの今のコマ
+    pub fn loading_spinner(&self) -> Option<&'static str> {
+        let channel_id = self.ui.selected_channel.as_ref()?;
+        let loading = self.discord.messages_loading.contains(channel_id)
+            || self.discord.loading_older.contains(channel_id);
+        if !loading {
+            return None;
+        }
+        let frames = self.glyphs().spinner;
+        Some(frames[self.ui.spinner_frame % frames.len()])
+    }
+
+    /// 選択中のチャンネルがフォーラム / メディアチャンネルか
+    pub fn is_forum_selected(&self) -> bool {
+        self.ui
+            .selected_channel
+            .as_ref()
+            .and_then(|id| self.discord.channels.get(id))
+            .is_some_and(|ch| ch.is_forum())
+    }
+
+    /// 選択中のチャンネルがボイスチャンネルか (メッセージは読み込まず、送信もできない)
+    fn is_voice_selected(&self) -> bool {
+        self.ui
+            .selected_channel
+            .as_ref()
+            .and_then(|id| self.discord.channels.get(id))
+            .is_some_and(|ch| ch.is_voice())
+    }
+
+    /// フォーラムの投稿 (親がそのフォーラムのスレッド) を新しい順に取得
+    pub fn get_forum_posts(&self, forum_id: &str) -> Vec<&Channel> {
+        let mut posts: Vec<&Channel> = self
+            .discord
+            .channels
+            .values()
+            .filter(|ch| ch.parent_id.as_deref() == Some(forum_id) && ch.is_messageable())
+            .collect();
+        // 最終投稿 (無ければスレッド作成) が新しい順
+        posts.sort_by(|a, b| {
+            let a_key = a.last_message_id.as_deref().unwrap_or(a.id.as_str());
+            let b_key = b.last_message_id.as_deref().unwrap_or(b.id.as_str());
+            if snowflake_gt(a_key, b_key) {
+                std::cmp::Ordering::Less
+            } else if snowflake_gt(b_key, a_key) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+        posts
+    }
+
+    /// 選択中フォーラムの投稿一覧
+    pub fn get_current_forum_posts(&self) -> Vec<&Channel> {
+        match &self.ui.selected_channel {
+            Some(id) => self.get_forum_posts(id),
+            None => Vec::new(),
+        }
+    }
+
+    /// フォーラム表示中にハイライトしている投稿を開く
+    fn open_selected_forum_post(&mut self) -> Command {
+        let Some(forum_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        let Some(post_id) = self
+            .get_forum_posts(&forum_id)
+            .get(self.ui.forum_post_index)
+            .map(|p| p.id.clone())
+        else {
+            // 投稿がまだ無ければ一覧を取り直す
+            return self.select_channel_commands(forum_id);
+        };
+        log::debug!("Opening forum post {} in {}", post_id, forum_id);
+        self.ui.selected_channel = Some(post_id.clone());
+        self.ui.message_scroll_offset = 0;
+        let cmd = self.select_channel_commands(post_id);
+        self.ui.forum_return = Some(forum_id);
+        cmd
+    }
+
+    /// サイドバーのフォーカスを切り替え (Tab / u キー用)。
+    /// 切り替え先の先頭チャンネルを自動選択してメッセージ画面も切り替える。
+    pub fn toggle_sidebar_focus(&mut self) -> Command {
+        self.ui.sidebar_focus = match self.ui.sidebar_focus {
+            SidebarFocus::Favorites => SidebarFocus::Unread,
+            SidebarFocus::Unread => SidebarFocus::Favorites,
+        };
+        self.ui.channel_list_state.select(Some(0));
+        log::debug!("Sidebar focus: {:?}", self.ui.sidebar_focus);
+
+        // 切り替え先の先頭チャンネルがあれば、それを選択してメッセージをロード
+        let next_channel = self
+            .get_current_display_channels()
+            .first()
+            .map(|ch| ch.id.clone());
+        if let Some(channel_id) = next_channel {
+            self.ui.selected_channel = Some(channel_id.clone());
+            self.ui.message_scroll_offset = 0;
+            self.select_channel_commands(channel_id)
+        } else {
+            Command::None
+        }
+    }
+
+    /// まだチャンネルを開いていなければ、設定 (`startup_channel`) に従って起動時のチャンネルを開く。
+    /// 前回のチャンネル / 指定のチャンネルがまだ無い場合、`fallback` なら先頭を開き、
+    /// そうでなければ次の呼び出し (チャンネル一覧の取得の続き) を待つ。
+    /// 先頭のチャンネルは一覧が揃うまで並びが変わるので `fallback` のときだけ開く
+    fn select_startup_channel(&mut self, fallback: bool) -> Command {
+        if self.ui.selected_channel.is_some() {
+            return Command::None;
+        }
+        let target = match self.config.startup_channel {
+            StartupChannel::None => return Command::None,
+            StartupChannel::First if !fallback => return Command::None,
+            StartupChannel::First => None,
+            StartupChannel::Last => self.config.last_channel.clone(),
+            StartupChannel::Pinned => self.config.startup_channel_id.clone(),
+        };
+        let channel_id = match target {
+            Some(id) if self.discord.channels.contains_key(&id) => Some(id),
+            Some(id) if !fallback => {
+                log::debug!("Startup channel {} not loaded yet", id);
+                return Command::None;
+            }
+            target => {
+                if let Some(id) = target {
+                    log::warn!("Startup channel {} not found, opening the first channel", id);
+                }
+                // お気に入りを優先
+                let favorites = self.get_favorite_channels();
+                match favorites.first() {
+                    Some(ch) => Some(ch.id.clone()),
+                    None => self.get_channel_list().first().map(|ch| ch.id.clone()),
+                }
+            }
+        };
+        let Some(channel_id) = channel_id else {
+            return Command::None;
+        };
+        let index = self
+            .get_current_display_channels()
+            .iter()
+            .position(|ch| ch.id == channel_id);
+        self.ui.channel_list_state.select(index);
+        self.ui.selected_channel = Some(channel_id.clone());
+        self.select_channel_commands(channel_id)
+    }
+
+    /// F5: ギルド/チャンネル/DM 一覧と開いているチャンネルのメッセージを REST で取り直し、
+    /// トークンがまだ有効かも確認する (サーバーとの状態のずれを再起動せずに直す)
+    fn refresh(&mut self) -> Command {
+        log::info!("Refreshing channels, messages and token");
+        self.ui.refreshing = true;
+        self.ui.status_message = Some(format!("Refreshing{}", self.glyphs().ellipsis));
+        let mut cmds = vec![Command::ValidateToken, Command::LoadChannels];
+        if let Some(channel_id) = self.ui.selected_channel.clone() {
+            self.ui.pending_channel_load = None;
+            self.discord.inaccessible_channels.remove(&channel_id);
+            // キャッシュの鮮度に関わらず全件取り直す (NSFW の確認前は読み込まない)
+            let no_messages = self.is_forum_selected() || self.is_voice_selected();
+            cmds.push(if no_messages || self.nsfw_gated(&channel_id) {
+                self.select_channel_commands(channel_id)
+            } else {
+                Command::LoadMessages(channel_id)
+            });
+        }
+        Command::Batch(cmds)
+    }
+
+    /// j/k で移動したチャンネルの読み込みを保留する (移動するたびに時刻を更新)
+    fn defer_channel_load(&mut self, channel_id: String) {
+        self.ui.pending_channel_load = Some((channel_id, Instant::now()));
+    }
+
+    /// 移動が CHANNEL_LOAD_DEBOUNCE 止まっていれば、保留中のチャンネルを読み込む
+    fn flush_channel_load(&mut self) -> Command {
+        let settled = self
+            .ui
+            .pending_channel_load
+            .as_ref()
+            .is_some_and(|(_, moved_at)| moved_at.elapsed() >= CHANNEL_LOAD_DEBOUNCE);
+        if !settled {
+            return Command::None;
+        }
+        let Some((channel_id, _)) = self.ui.pending_channel_load.take() else {
+            return Command::None;
+        };
+        if self.ui.selected_channel.as_ref() != Some(&channel_id) {
+            return Command::None;
+        }
+        self.select_channel_commands(channel_id)
+    }
+
+    /// 前のチャンネルを選択
+    fn select_previous_channel(&mut self) -> Command {
+        let channel_ids: Vec<String> = self
+            .get_current_display_channels()
+            .iter()
+            .map(|ch| ch.id.clone())
+            .collect();
+
+        if channel_ids.is_empty() {
+            return Command::None;
+        }
+
+        let current_index = self.ui.channel_list_state.selected().unwrap_or(0);
+        let new_index = if current_index > 0 {
+            current_index - 1
+        } else {
+            channel_ids.len() - 1
+        };
+
+        self.ui.channel_list_state.select(Some(new_index));
+        self.ui.selected_channel = Some(channel_ids[new_index].clone());
+        self.ui.message_scroll_offset = 0;
+
+        // メッセージの読み込み + 既読化は移動が止まってから行う
+        self.defer_channel_load(channel_ids[new_index].clone());
+        Command::None
+    }
+
+    /// 次のチャンネルを選択
+    fn select_next_channel(&mut self) -> Command {
+        let channel_ids: Vec<String> = self
+            .get_current_display_channels()
+            .iter()
+            .map(|ch| ch.id.clone())
+            .collect();
+
+        if channel_ids.is_empty() {
+            return Command::None;
+        }
+
+        let current_index = self.ui.channel_list_state.selected().unwrap_or(0);
+        let new_index = if current_index < channel_ids.len() - 1 {
+            current_index + 1
+        } else {
+            0
+        };
+
+        self.ui.channel_list_state.select(Some(new_index));
+        self.ui.selected_channel = Some(channel_ids[new_index].clone());
+        self.ui.message_scroll_offset = 0;
+
+        // メッセージの読み込み + 既読化は移動が止まってから行う
+        self.defer_channel_load(channel_ids[new_index].clone());
+        Command::None
+    }
+
+    /// スクロール位置が直近に描画した上限 (= 最古メッセージが画面に出ている) に
+    /// 達したときだけ過去メッセージ読み込みを起動する。
+    fn maybe_load_older_messages_if_at_top(&mut self) -> Command {
+        if self.ui.message_scroll_offset < self.ui.cached_max_scroll_offset {
+            return Command::None;
+        }
+        self.maybe_load_older_messages()
+    }
+
+    /// 古い側にスクロールしたとき、必要なら追加メッセージ読み込みを起動する。
+    /// 既に読み込み中、または最古メッセージが未取得の場合は何もしない。
+    fn maybe_load_older_messages(&mut self) -> Command {
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        if self.discord.loading_older.contains(&channel_id) {
+            return Command::None;
+        }
+        let Some(messages) = self.discord.messages.get(&channel_id) else {
+            return Command::None;
+        };
+        // REST は新→古順なので、配列の末尾が最古
+        let Some(oldest) = messages.last() else {
+            return Command::None;
+        };
+        let before = oldest.id.clone();
+        self.discord.loading_older.insert(channel_id.clone());
+        log::debug!("Loading older messages for {} before {}", channel_id, before);
+        Command::LoadOlderMessages { channel_id, before }
+    }
+
+    /// `message_id` を画面に表示したことを記録する (チャンネルごとに最新のものだけ保持し、
+    /// 終了時に保存する)。記録が上限を超えたら、最後に読んだのが最も古いチャンネルから忘れる
+    fn mark_viewed(&mut self, channel_id: &str, message_id: &str) {
+        let newer = match self.config.last_viewed.get(channel_id) {
+            Some(viewed) => self.snowflake_gt(message_id, viewed),
+            None => true,
+        };
+        if !newer {
+            return;
+        }
+        self.config
+            .last_viewed
+            .insert(channel_id.to_string(), message_id.to_string());
+        if self.config.last_viewed.len() > LAST_VIEWED_MAX_CHANNELS {
+            let stalest = self
+                .config
+                .last_viewed
+                .iter()
+                .reduce(|a, b| if self.snowflake_gt(a.1, b.1) { b } else { a })
+                .map(|(id, _)| id.clone());
+            if let Some(id) = stalest {
+                self.config.last_viewed.remove(&id);
+            }
+        }
+    }
+
+    /// 最新メッセージが上に並ぶ設定か
+    pub fn newest_first(&self) -> bool {
+        self.config.message_order == MessageOrder::NewestFirst
+    }
+
+    /// 画面の上下の向きで `lines` 行スクロールする (正: 上 / 負: 下)。
+    /// 並び順に応じて新しい側 / 古い側に読み替え、古い側の端に着いたら続きを読み込む
+    fn scroll_view(&mut self, lines: i32) -> Command {
+        let delta = if self.newest_first() { -lines } else { lines };
+        self.apply_scroll(delta);
+        if delta > 0 {
+            self.maybe_load_older_messages_if_at_top()
+        } else {
+            Command::None
+        }
+    }
+
+    /// 画面の一番上へジャンプ (oldest-first なら最古、newest-first なら最新)
+    fn jump_to_top(&mut self) -> Command {
+        if self.newest_first() {
+            self.jump_to_newest()
+        } else {
+            self.jump_to_oldest()
+        }
+    }
+
+    /// 画面の一番下へジャンプ (oldest-first なら最新、newest-first なら最古)
+    fn jump_to_bottom(&mut self) -> Command {
+        if self.newest_first() {
+            self.jump_to_oldest()
+        } else {
+            self.jump_to_newest()
+        }
+    }
+
+    /// 最新メッセージへジャンプし、自動追従を ON に戻す。
+    /// リンクからのジャンプで前後だけを読み込んでいたら、最新のメッセージを読み込み直す
+    fn jump_to_newest(&mut self) -> Command {
+        self.ui.message_scroll_offset = 0;
+        self.ui.auto_follow = true;
+        match self.ui.selected_channel.clone() {
+            Some(channel_id) if self.ui.detached_history.remove(&channel_id) => {
+                Command::LoadMessages(channel_id)
+            }
+            _ => Command::None,
+        }
+    }
+
+    /// 読み込み済みの最古メッセージへジャンプし、続きの過去メッセージを読み込む。
+    /// 読み込んだ分は最新から遠い側に足されるので、表示位置はジャンプ先のまま保たれる
+    fn jump_to_oldest(&mut self) -> Command {
+        self.ui.message_scroll_offset = self.ui.cached_max_scroll_offset;
+        self.maybe_load_older_messages()
+    }
+
+    /// メッセージリストを行単位でスクロール (正: 古い側 / 負: 新しい側)。
+    /// 上限のクランプはレイアウト依存のため ui.rs 側で行う。
+    fn apply_scroll(&mut self, delta: i32) {
+        if delta > 0 {
+            self.ui.message_scroll_offset =
+                self.ui.message_scroll_offset.saturating_add(delta as usize);
+        } else if delta < 0 {
+            self.ui.message_scroll_offset =
+                self.ui.message_scroll_offset.saturating_sub((-delta) as usize);
+        }
+        log::debug!("Scroll offset: {}", self.ui.message_scroll_offset);
+    }
+
+    /// チャンネル一覧・検索・未読一覧に出すチャンネルか
+    /// (メッセージ可能なもの + フォーラム + ボイス。`text_channels_only` ならテキストのみ)
+    fn is_shown_in_lists(&self, channel: &Channel) -> bool {
+        channel.is_listable() && (!self.config.text_channels_only || channel.is_plain_text())
+    }
+
+    /// 一覧に出すチャンネルを、すべての種類 / テキストのみで切り替えて保存する
+    fn toggle_text_channels_only(&mut self) -> Command {
+        self.config.text_channels_only = !self.config.text_channels_only;
+        self.ui.status_message = Some(
+            if self.config.text_channels_only {
+                "Showing text channels only (T: show all)"
+            } else {
+                "Showing all channel types (T: text only)"
+            }
+            .to_string(),
+        );
+        self.invalidate_unread_cache();
+        // 一覧の並びが変わるので、開いているチャンネルの位置を選び直す
+        let channels = self.get_current_display_channels();
+        let index = channels
+            .iter()
+            .position(|ch| self.ui.selected_channel.as_deref() == Some(ch.id.as_str()))
+            .or_else(|| (!channels.is_empty()).then_some(0));
+        self.ui.channel_list_state.select(index);
+        Command::SaveConfig(Box::new(self.config_to_save()))
+    }
+
+    /// チャンネルリストを取得（ソート済み、一覧に出すもの）
+    pub fn get_channel_list(&self) -> Vec<&Channel> {
+        let mut channels: Vec<&Channel> = self
+            .discord
+            .channels
+            .values()
+            .filter(|ch| self.is_shown_in_lists(ch))
+            .collect();
+        channels.sort_by(|a, b| self.compare_channels(a, b));
+        channels
+    }
+
+    /// お気に入りチャンネルリストを取得
+    /// (favorite_order が manual なら登録順、sorted なら channel_order 順)
+    pub fn get_favorite_channels(&self) -> Vec<&Channel> {
+        let mut favorites: Vec<&Channel> = self
+            .ui
+            .favorites
+            .iter()
+            .filter_map(|id| self.discord.channels.get(id))
+            .filter(|ch| ch.is_listable())
+            .collect();
+
+        if self.config.favorite_order == FavoriteOrder::Sorted {
+            favorites.sort_by(|a, b| self.compare_channels(a, b));
+        }
+
+        favorites
+    }
+
+    /// 設定 (channel_order) に従ったチャンネルの比較
+    fn compare_channels(&self, a: &Channel, b: &Channel) -> std::cmp::Ordering {
+        let by_type = || {
+            a.channel_type
+                .cmp(&b.channel_type)
+                .then_with(|| a.display_name().cmp(&b.display_name()))
+        };
+        match self.config.channel_order {
+            ChannelOrder::Type => by_type(),
+            // guild_id を持たないのが DM / グループ DM
+            ChannelOrder::DmsFirst => a
+                .guild_id
+                .is_some()
+                .cmp(&b.guild_id.is_some())
+                .then_with(by_type),
+            ChannelOrder::GuildsFirst => b
+                .guild_id
+                .is_some()
+                .cmp(&a.guild_id.is_some())
+                .then_with(by_type),
+            ChannelOrder::Alphabetical => a
+                .display_name()
+                .to_lowercase()
+                .cmp(&b.display_name().to_lowercase())
+                .then_with(|| a.id.cmp(&b.id)),
+        }
+    }
+
+    /// 選択中のお気に入りを表示順で 1 つ上 (delta = -1) / 下 (delta = 1) へ移動。
+    /// 自動ソート中に並べ替えた場合は、現在の表示順を初期値として手動順に切り替える
+    fn move_favorite(&mut self, delta: isize) {
+        if self.ui.search_mode || self.ui.sidebar_focus != SidebarFocus::Favorites {
+            return;
+        }
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return;
+        };
+        let displayed: Vec<String> = self
+            .get_favorite_channels()
+            .iter()
+            .map(|ch| ch.id.clone())
+            .collect();
+        let Some(index) = displayed.iter().position(|id| *id == channel_id) else {
+            return;
+        };
+        let target = index as isize + delta;
+        if target < 0 || target as usize >= displayed.len() {
+            return;
+        }
+        let target = target as usize;
+
+        if self.config.favorite_order != FavoriteOrder::Manual {
+            // 未取得のチャンネル (表示されていない ID) は末尾に残す
+            let hidden: Vec<String> = self
+                .ui
+                .favorites
+                .iter()
+                .filter(|id| !displayed.contains(id))
+                .cloned()
+                .collect();
+            self.ui.favorites = displayed.clone();
+            self.ui.favorites.extend(hidden);
+            self.config.favorite_order = FavoriteOrder::Manual;
+            log::info!("Favorite order switched to manual");
+        }
+
+        let a = self.ui.favorites.iter().position(|id| *id == displayed[index]);
+        let b = self.ui.favorites.iter().position(|id| *id == displayed[target]);
+        if let (Some(a), Some(b)) = (a, b) {
+            self.ui.favorites.swap(a, b);
+            self.ui.channel_list_state.select(Some(target));
+        }
+    }
+
+    /// snowflake 比較ヘルパ (ui.rs から境界判定で利用)
+    pub fn snowflake_gt(&self, a: &str, b: &str) -> bool {
+        snowflake_gt(a, b)
+    }
+
+    /// チャンネル or 親ギルドがミュートされているか
+    fn is_channel_muted(&self, channel: &Channel) -> bool {
+        if self.discord.muted_channels.contains(&channel.id) {
+            return true;
+        }
+        if let Some(gid) = &channel.guild_id {
+            if self.discord.muted_guilds.contains(gid) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// チャンネルが未読かどうか。
+    /// ミュート設定されている場合は基本的に未読扱いしないが、@メンションがある場合は例外。
+    pub fn is_channel_unread(&self, channel: &Channel) -> bool {
+        if channel.last_message_id.is_none() {
+            return false;
+        }
+        let last = channel.last_message_id.as_deref().unwrap();
+        // 起動後に新着があったチャンネルは無条件に未読 (read_state エントリの有無に依存しない)
+        let session_new = self.discord.session_unread.contains(&channel.id);
+        // 起動時点の read_state 比較。エントリ無しは「触れていない」= 既読扱い
+        let stored_new = matches!(
+            self.discord.read_states.get(&channel.id),
+            Some(Some(read)) if snowflake_gt(last, read.as_str())
+        );
+        if !session_new && !stored_new {
+            return false;
+        }
+        // ミュート時はメンションがある場合のみ未読扱い
+        if self.is_channel_muted(channel) {
+            return self
+                .discord
+                .mention_counts
+                .get(&channel.id)
+                .copied()
+                .unwrap_or(0)
+                > 0;
+        }
+        true
+    }
+
+    /// 未読チャンネル一覧を取得。キャッシュ済みの ID 列を Channel 参照に解決して返す。
+    /// 描画前に `refresh_unread_cache()` が呼ばれていることを前提とする。
+    pub fn get_unread_channels(&self) -> Vec<&Channel> {
+        self.discord
+            .unread_cache
+            .iter()
+            .filter_map(|id| self.discord.channels.get(id))
+            .collect()
+    }
+
+    /// 未読リストのキャッシュを再計算する。dirty フラグが立っているときだけ走る。
+    pub fn refresh_unread_cache(&mut self) {
+        if !self.discord.unread_cache_dirty {
+            return;
+        }
+        let mut entries: Vec<&Channel> = self
+            .discord
+            .channels
+            .values()
+            .filter(|ch| {
+                ch.is_messageable()
+                    && self.is_shown_in_lists(ch)
+                    && !self.discord.inaccessible_channels.contains(&ch.id)
+                    && !self.is_hidden_request(ch)
+                    && (self.is_channel_unread(ch)
+                        || self.discord.acked_in_session.contains(&ch.id))
+            })
+            .collect();
+        entries.sort_by(|a, b| match a.channel_type.cmp(&b.channel_type) {
+            std::cmp::Ordering::Equal => a.display_name().cmp(&b.display_name()),
+            other => other,
+        });
+        self.discord.unread_cache = entries.iter().map(|c| c.id.clone()).collect();
+        self.discord.unread_cache_dirty = false;
+    }
+
+    /// 未読リストキャッシュを無効化する (状態変化が起きたとき)
+    fn invalidate_unread_cache(&mut self) {
+        self.discord.unread_cache_dirty = true;
+    }
+
+    /// チャンネルを検索（名前・ギルド名でフィルタリング）
+    pub fn search_channels(&self, query: &str) -> Vec<&Channel> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let query_lower = query.to_lowercase();
+        log::debug!("Searching channels with query: '{}'", query_lower);
+        log::debug!("Total channels to search: {}", self.discord.channels.len());
+
+        let mut results: Vec<&Channel> = self
+            .discord
+            .channels
+            .values()
+            .filter(|ch| self.is_shown_in_lists(ch) && !self.is_hidden_request(ch))
+            .filter(|ch| {
+                // チャンネル名で検索
+                let display_name = ch.display_name();
+                let name_match = display_name.to_lowercase().contains(&query_lower);
+
+                // ギルド名で検索
+                let guild_match = if let Some(guild_id) = &ch.guild_id {
+                    if let Some(guild) = self.discord.guilds.get(guild_id) {
+                        guild.name.to_lowercase().contains(&query_lower)
+                    } else {
+                        false
+                    }
+                } else {
+                    false
+                };
+
+                // 親チャンネル名(フォーラム名等)で検索
+                let parent_match = ch
+                    .parent_id
+                    .as_ref()
+                    .and_then(|pid| self.discord.channels.get(pid))
+                    .map(|p| p.display_name().to_lowercase().contains(&query_lower))
+                    .unwrap_or(false);
+
+                let matched = name_match || guild_match || parent_match;
+                if matched {
+                    log::debug!(
+                        "Matched channel: {} (type={}, guild_id={:?})",
+                        display_name,
+                        ch.channel_type,
+                        ch.guild_id
+                    );
+                }
+
+                matched
+            })
+            .collect();
+
+        log::debug!("Search found {} results", results.len());
+
+        results.sort_by(|a, b| self.compare_channels(a, b));
+
+        results
+    }
+
+    /// お気に入りを登録/解除
+    pub fn toggle_favorite(&mut self) {
+        if let Some(channel_id) = &self.ui.selected_channel {
+            if self.is_favorite(channel_id) {
+                self.ui.favorites.retain(|id| id != channel_id);
+                log::info!("Removed from favorites: {}", channel_id);
+            } else {
+                self.ui.favorites.push(channel_id.clone());
+                log::info!("Added to favorites: {}", channel_id);
+            }
+        }
+    }
+
+    /// 検索モードを切り替え
+    pub fn toggle_search_mode(&mut self) {
+        self.ui.search_mode = !self.ui.search_mode;
+
+        if self.ui.search_mode {
+            // 検索モードに入る時はバッファをクリア
+            self.ui.search_buffer.clear();
+            log::debug!("Entered search mode");
+        } else {
+            // 検索モードを抜ける時はバッファをクリア
+            self.ui.search_buffer.clear();
+            log::debug!("Exited search mode");
+        }
+    }
+
+    /// 開いているチャンネルで本文にリンクを含む最新のメッセージ (選択モードでは選択中の
+    /// メッセージ) のリンクを開く。
+    /// 同じメッセージで続けて押すと次のリンクへ進み、最後まで行ったら先頭に戻る
+    fn open_next_link(&mut self) -> Command {
+        let target = if self.ui.input_mode == InputMode::MessageSelect {
+            // 選択モードでは選択中のメッセージのリンクだけを開く
+            let selected = self.ui.selected_channel.as_deref().and_then(|channel_id| {
+                self.target_message(channel_id)
+                    .map(|msg| (msg.id.clone(), extract_links(&msg.content)))
+            });
+            selected.filter(|(_, links)| !links.is_empty())
+        } else {
+            // メッセージは新しい順に並んでいる
+            self.get_current_messages().into_iter().find_map(|msg| {
+                let links = extract_links(&msg.content);
+                (!links.is_empty()).then(|| (msg.id.clone(), links))
+            })
+        };
+        let Some((message_id, links)) = target else {
+            let scope = if self.ui.input_mode == InputMode::MessageSelect {
+                "message"
+            } else {
+                "channel"
+            };
+            self.ui.status_message = Some(format!("No links in this {}", scope));
+            return Command::None;
+        };
+        let index = match &self.ui.link_cursor {
+            Some((id, i)) if *id == message_id => (i + 1) % links.len(),
+            _ => 0,
+        };
+        let url = links[index].clone();
+        self.ui.status_message =
+            Some(format!("Opening link {}/{}: {}", index + 1, links.len(), url));
+        self.ui.link_cursor = Some((message_id, index));
+        Command::OpenUrl(url)
+    }
+
+    /// `message_id` のメッセージに `L` で最後に開いたリンクがあればその番号
+    pub fn active_link(&self, message_id: &str) -> Option<usize> {
+        self.ui
+            .link_cursor
+            .as_ref()
+            .filter(|(id, _)| id == message_id)
+            .map(|(_, index)| *index)
+    }
+
+    /// 検索で選んだチャンネルを開いて検索モードを終了する。
+    /// `edit` なら続けて入力モードに入る (読み取り専用 / フォーラムでは入らない)
+    fn confirm_search(&mut self, edit: bool) -> Command {
+        self.toggle_search_mode();
+        self.ui.message_scroll_offset = 0;
+        let Some(channel_id) = self.ui.selected_channel.clone() else {
+            return Command::None;
+        };
+        let command = self.select_channel_commands(channel_id);
+        if edit && !self.read_only && !self.is_forum_selected() && !self.is_voice_selected() {
+            self.ui.input_mode = InputMode::Editing;
+        }
+        command
+    }
+
+    /// 検索入力を追加
+    pub fn search_input(&mut self, c: char) {
+        if self.ui.search_mode {
+            self.ui.search_buffer.push(c);
+            log::debug!("Search query: {}", self.ui.search_buffer);
+        }
+    }
+
+    /// 検索入力をバックスペース
+    pub fn search_backspace(&mut self) {
+        if self.ui.search_mode {
+            self.ui.search_buffer.pop();
+            log::debug!("Search query: {}", self.ui.search_buffer);
+        }
+    }
+
+    /// 現在選択中のチャンネルのメッセージリストを取得
+    pub fn get_current_messages(&self) -> Vec<&Message> {
+        if self.selected_channel_unavailable() {
+            return Vec::new();
+        }
+        if let Some(channel_id) = &self.ui.selected_channel {
+            if let Some(messages) = self.discord.messages.get(channel_id) {
+                return messages.iter().collect();
+            }
+        }
+        Vec::new()
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 画像/絵文字/アバターのダウンロード Command を必要に応じて Batch にまとめる
+fn batch_commands(
+    images: Vec<(String, String)>,
+    emojis: Vec<(String, String)>,
+    avatars: Vec<(String, String)>,
+) -> Command {
+    let mut cmds: Vec<Command> = Vec::new();
+    if !images.is_empty() {
+        cmds.push(Command::DownloadImages(images));
+    }
+    if !emojis.is_empty() {
+        cmds.push(Command::DownloadEmojis(emojis));
+    }
+    if !avatars.is_empty() {
+        cmds.push(Command::DownloadAvatars(avatars));
+    }
+    match cmds.len() {
+        0 => Command::None,
+        1 => cmds.into_iter().next().unwrap(),
+        _ => Command::Batch(cmds),
     }
+}
 
-    /// 現在カーソル操作対象のチャンネルリストを取得
-    fn get_current_display_channels(&self) -> Vec<&Channel> {
-        if self.ui.search_mode {
-            self.search_channels(&self.ui.search_buffer)
-        } else {
-            match self.ui.sidebar_focus {
-                SidebarFocus::Favorites => self.get_favorite_channels(),
-                SidebarFocus::Unread => self.get_unread_channels(),
-            }
+/// 読み取り専用モード用に、Discord へ書き込む Command (送信・既読化・削除・
+/// オンライン状態の変更) を取り除く
+fn strip_outgoing(command: Command) -> Command {
+    match command {
+        Command::SendMessage { channel_id, .. } => {
+            log::info!("Read-only mode: dropped send to {}", channel_id);
+            Command::None
         }
+        Command::AckChannel { .. }
+        | Command::EditMessage { .. }
+        | Command::DeleteMessage { .. }
+        | Command::PurgeOwnMessages { .. }
+        | Command::AddReaction { .. }
+        | Command::UpdatePresence(_)
+        | Command::SetActivity(_) => Command::None,
+        Command::Batch(cmds) => Command::Batch(cmds.into_iter().map(strip_outgoing).collect()),
+        other => other,
     }
+}
 
-    /// チャンネル選択時の Command を組み立てる。
-    /// LoadMessages に加えて、未読がある場合は ack も同時に発火する
-    /// (REST のメッセージ取得結果に依存せず、READY 由来の last_message_id を使う)。
-    fn select_channel_commands(&mut self, channel_id: String) -> Command {
-        // 開いた瞬間の last_read を境界として保存 (ack より前のスナップショット)
-        if let Some(Some(last_read)) = self.discord.read_states.get(&channel_id) {
-            self.ui
-                .unread_boundaries
-                .insert(channel_id.clone(), last_read.clone());
-        }
+/// `command` からメッセージ一覧の読み込み (全件 / 差分) を除く
+fn without_message_loads(command: Command) -> Command {
+    match command {
+        Command::LoadMessages(_) | Command::LoadNewerMessages { .. } => Command::None,
+        Command::Batch(cmds) => cmds
+            .into_iter()
+            .map(without_message_loads)
+            .fold(Command::None, merge_commands),
+        other => other,
+    }
+}
 
-        let last_msg = self
-            .discord
-            .channels
-            .get(&channel_id)
-            .and_then(|c| c.last_message_id.clone());
-        let mut cmds = vec![Command::LoadMessages(channel_id.clone())];
-        if let Some(message_id) = last_msg {
-            let already_read = matches!(
-                self.discord.read_states.get(&channel_id),
-                Some(Some(read)) if read.as_str() == message_id.as_str()
-            );
-            if !already_read {
-                // 楽観的に read_states を更新、セッション中は未読リストに残す (グレーアウト)
-                self.discord
-                    .read_states
-                    .insert(channel_id.clone(), Some(message_id.clone()));
-                self.discord.mention_counts.remove(&channel_id);
-                self.discord.session_unread.remove(&channel_id);
-                self.discord.acked_in_session.insert(channel_id.clone());
-                self.invalidate_unread_cache();
-                cmds.push(Command::AckChannel {
-                    channel_id,
-                    message_id,
-                });
-            }
-        }
-        match cmds.len() {
-            1 => cmds.into_iter().next().unwrap(),
-            _ => Command::Batch(cmds),
-        }
+/// 2 つの Command を 1 つにまとめる (None は取り除く)
+fn merge_commands(a: Command, b: Command) -> Command {
+    match (a, b) {
+        (Command::None, other) | (other, Command::None) => other,
+        (a, b) => Command::Batch(vec![a, b]),
     }
+}
 
-    /// サイドバーのフォーカスを切り替え (Tab / u キー用)。
-    /// 切り替え先の先頭チャンネルを自動選択してメッセージ画面も切り替える。
-    pub fn toggle_sidebar_focus(&mut self) -> Command {
-        self.ui.sidebar_focus = match self.ui.sidebar_focus {
-            SidebarFocus::Favorites => SidebarFocus::Unread,
-            SidebarFocus::Unread => SidebarFocus::Favorites,
+/// 返信バナー用の本文の抜粋。本文が無ければ添付ファイル名で代用する
+fn reply_snippet(message: &Message) -> String {
+    let first_line = message.content.lines().next().unwrap_or("");
+    if first_line.is_empty() {
+        return match message.attachments.first() {
+            Some(attachment) => format!("[{}]", attachment.filename),
+            None => String::new(),
         };
-        self.ui.channel_list_state.select(Some(0));
-        log::debug!("Sidebar focus: {:?}", self.ui.sidebar_focus);
+    }
+    let mut snippet: String = first_line.chars().take(REPLY_SNIPPET_MAX_CHARS).collect();
+    if snippet.len() < message.content.len() {
+        snippet.push('…');
+    }
+    snippet
+}
 
-        // 切り替え先の先頭チャンネルがあれば、それを選択してメッセージをロード
-        let next_channel = self
-            .get_current_display_channels()
-            .first()
-            .map(|ch| ch.id.clone());
-        if let Some(channel_id) = next_channel {
-            self.ui.selected_channel = Some(channel_id.clone());
-            self.ui.message_scroll_offset = 0;
-            self.select_channel_commands(channel_id)
-        } else {
-            Command::None
+/// Discord snowflake ID (数値文字列) の大小比較。a > b なら true
+fn snowflake_gt(a: &str, b: &str) -> bool {
+    match a.len().cmp(&b.len()) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => a > b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{connected_app, me, message, with_messages, GENERAL_ID, RANDOM_ID};
+    use serde_json::json;
+
+    /// Batch を展開して Command を平らに並べる
+    fn flatten(command: Command) -> Vec<Command> {
+        match command {
+            Command::None => Vec::new(),
+            Command::Batch(cmds) => cmds.into_iter().flat_map(flatten).collect(),
+            other => vec![other],
         }
     }
 
-    /// 前のチャンネルを選択
-    fn select_previous_channel(&mut self) -> Command {
-        let channel_ids: Vec<String> = self
-            .get_current_display_channels()
+    fn press(app: &mut AppState, keys: &[KeyCode]) -> Vec<Command> {
+        keys.iter()
+            .flat_map(|key| flatten(app.update(AppEvent::KeyPress(*key))))
+            .collect()
+    }
+
+    fn ready() -> serde_json::Value {
+        json!({
+            "session_id": "abc",
+            "user": { "id": "1", "username": "me", "discriminator": "0" },
+            "users": [],
+            "private_channels": [],
+            "guilds": [
+                {
+                    "id": "100",
+                    "properties": { "name": "Hakuhyo Dev", "owner_id": "1" },
+                    "channels": [
+                        { "id": "201", "type": 0, "name": "random", "last_message_id": "900" },
+                        { "id": "200", "type": 0, "name": "general" },
+                        { "id": "202", "type": 2, "name": "lounge" },
+                    ],
+                    "voice_states": [
+                        { "user_id": "10", "channel_id": "202" },
+                        { "user_id": "11", "channel_id": "202" },
+                    ],
+                },
+                { "id": "101", "unavailable": true },
+            ],
+        })
+    }
+
+    #[test]
+    fn ready_registers_guilds_and_opens_the_first_channel() {
+        let mut app = AppState::new();
+        let commands = flatten(app.update(AppEvent::GatewayReady(ready())));
+
+        assert!(app.discord.connected);
+        assert_eq!(app.discord.current_user.as_ref().map(|u| u.username.as_str()), Some("me"));
+        assert_eq!(app.discord.guilds["100"].name, "Hakuhyo Dev");
+        assert_eq!(app.discord.channels["200"].guild_id.as_deref(), Some("100"));
+        assert!(app.discord.unavailable_guilds.contains("101"));
+
+        // お気に入りが無ければ並び順で先頭のチャンネル (名前順で general) を開く
+        assert_eq!(app.ui.selected_channel.as_deref(), Some("200"));
+        assert!(commands
             .iter()
-            .map(|ch| ch.id.clone())
-            .collect();
+            .any(|c| matches!(c, Command::LoadMessages(id) if id == "200")));
+        assert!(!commands.iter().any(|c| matches!(c, Command::LoadChannels)));
+    }
 
-        if channel_ids.is_empty() {
-            return Command::None;
+    #[test]
+    fn guilds_outside_the_allowlist_are_not_loaded() {
+        let mut app = AppState::new();
+        app.config.guild_allowlist = vec!["102".to_string()];
+        app.ui.favorites = vec!["200".to_string()];
+        let mut ready = ready();
+        ready["guilds"].as_array_mut().unwrap().push(json!({
+            "id": "102",
+            "properties": { "name": "Kept", "owner_id": "1" },
+            "channels": [{ "id": "300", "type": 0, "name": "kept" }],
+        }));
+        app.update(AppEvent::GatewayReady(ready));
+
+        assert_eq!(app.discord.guilds.keys().collect::<Vec<_>>(), vec!["102"]);
+        assert!(app.discord.channels.contains_key("300"));
+        assert!(!app.discord.channels.contains_key("200"));
+        // 読み込まなかったサーバーの新着は取り込まず、お気に入りは消さない
+        assert!(app.discord.unavailable_guilds.is_empty());
+        let mut stray = message("901", "200", "alice", "hi", "01:00");
+        stray.guild_id = Some("100".to_string());
+        assert!(flatten(app.update(AppEvent::MessageCreate(stray))).is_empty());
+        assert!(!app.discord.messages.contains_key("200"));
+        app.update(AppEvent::ChannelsLoaded { complete: true });
+        assert_eq!(app.ui.favorites, vec!["200"]);
+    }
+
+    /// フレンド (20) との DM 300 と、フレンドでない相手 (21) との DM 301 がある状態
+    fn app_with_message_request() -> AppState {
+        let mut ready = ready();
+        ready["users"] = json!([
+            { "id": "20", "username": "friend", "discriminator": "0" },
+            { "id": "21", "username": "stranger", "discriminator": "0" },
+        ]);
+        ready["private_channels"] = json!([
+            { "id": "300", "type": 1, "recipient_ids": ["20"], "last_message_id": "1" },
+            { "id": "301", "type": 1, "recipient_ids": ["21"], "last_message_id": "1" },
+        ]);
+        ready["relationships"] = json!([{ "id": "20", "type": 1 }, { "id": "22", "type": 3 }]);
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayReady(ready));
+        app
+    }
+
+    /// 両方の DM に新着を届け、未読一覧に出た channel_id を返す
+    fn unread_dm_ids(app: &mut AppState) -> Vec<String> {
+        for (id, author) in [("300", "friend"), ("301", "stranger")] {
+            app.update(AppEvent::MessageCreate(message("2", id, author, "hi", "01:00")));
         }
+        app.refresh_unread_cache();
+        app.get_unread_channels().iter().map(|c| c.id.clone()).collect()
+    }
 
-        let current_index = self.ui.channel_list_state.selected().unwrap_or(0);
-        let new_index = if current_index > 0 {
-            current_index - 1
-        } else {
-            channel_ids.len() - 1
+    #[test]
+    fn dms_from_non_friends_are_message_requests() {
+        let app = app_with_message_request();
+        assert!(!app.is_message_request(&app.discord.channels["300"]));
+        assert!(app.is_message_request(&app.discord.channels["301"]));
+    }
+
+    #[test]
+    fn message_requests_are_announced_in_notify_mode() {
+        let mut app = app_with_message_request();
+        app.config.non_friend_dm = NonFriendDm::Notify;
+        // 通常の DM と同じく一覧に出し、リクエストだけステータスバーで知らせる
+        assert_eq!(unread_dm_ids(&mut app), vec!["300", "301"]);
+        assert_eq!(app.ui.status_message.as_deref(), Some("Message request from stranger"));
+        assert!(!app.shows_request_marker(&app.discord.channels["301"]));
+    }
+
+    #[test]
+    fn message_requests_are_marked_in_request_mode() {
+        let mut app = app_with_message_request();
+        app.config.non_friend_dm = NonFriendDm::Request;
+        assert_eq!(unread_dm_ids(&mut app), vec!["300", "301"]);
+        assert!(app.shows_request_marker(&app.discord.channels["301"]));
+        assert!(!app.shows_request_marker(&app.discord.channels["300"]));
+    }
+
+    #[test]
+    fn message_requests_are_hidden_unless_favorited_in_hide_mode() {
+        let mut app = app_with_message_request();
+        app.config.non_friend_dm = NonFriendDm::Hide;
+        // 未読一覧にも検索にも出さない
+        assert_eq!(unread_dm_ids(&mut app), vec!["300"]);
+        assert!(app.search_channels("stranger").is_empty());
+        // お気に入りに入れたものは出す
+        app.ui.favorites.push("301".to_string());
+        app.invalidate_unread_cache();
+        assert_eq!(unread_dm_ids(&mut app), vec!["300", "301"]);
+    }
+
+    #[test]
+    fn relationship_events_update_message_requests() {
+        let mut app = app_with_message_request();
+        app.config.non_friend_dm = NonFriendDm::Hide;
+        let relationship = |id: &str, kind: u8| -> Relationship {
+            serde_json::from_value(json!({ "id": id, "type": kind })).unwrap()
         };
+        app.update(AppEvent::RelationshipAdd(relationship("21", 1)));
+        assert!(!app.is_message_request(&app.discord.channels["301"]));
+        assert_eq!(unread_dm_ids(&mut app), vec!["300", "301"]);
 
-        self.ui.channel_list_state.select(Some(new_index));
-        self.ui.selected_channel = Some(channel_ids[new_index].clone());
-        self.ui.message_scroll_offset = 0;
+        app.update(AppEvent::RelationshipRemove { id: "21".to_string() });
+        assert!(app.is_message_request(&app.discord.channels["301"]));
+        // ブロックに変わった相手もフレンドではなくなる
+        app.update(AppEvent::RelationshipAdd(relationship("20", 2)));
+        assert!(app.is_message_request(&app.discord.channels["300"]));
+    }
+
+    #[test]
+    fn unknown_authors_are_requested_once_until_their_members_arrive() {
+        let mut app = connected_app();
+        let load = || AppEvent::MessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![message("310", GENERAL_ID, "carol", "hi", "01:00")],
+        };
+        let requests = |commands: Vec<Command>| -> Vec<Vec<String>> {
+            commands
+                .into_iter()
+                .filter_map(|c| match c {
+                    Command::RequestGuildMembers { user_ids, .. } => Some(user_ids),
+                    _ => None,
+                })
+                .collect()
+        };
+        app.update(load());
+        assert_eq!(requests(flatten(app.update(AppEvent::Tick))), vec![vec!["u-carol"]]);
 
-        // チャンネル切り替え時に自動的にメッセージを読み込む + 既読化
-        self.select_channel_commands(channel_ids[new_index].clone())
+        // 応答を待っている間は再要求しない
+        app.update(load());
+        app.discord.last_member_request = None;
+        assert!(requests(flatten(app.update(AppEvent::Tick))).is_empty());
+
+        let member: GuildMember = serde_json::from_value(json!({
+            "user": { "id": "u-carol", "username": "carol", "discriminator": "0" },
+            "nick": "Carol",
+            "roles": [],
+        }))
+        .unwrap();
+        app.update(AppEvent::GuildMembersChunk {
+            guild_id: "100".to_string(),
+            members: vec![member],
+        });
+        assert!(!app.discord.member_requested["100"].contains_key("u-carol"));
+        let member = app.get_current_messages()[0].member.clone().unwrap();
+        assert_eq!(member.nick.as_deref(), Some("Carol"));
     }
 
-    /// 次のチャンネルを選択
-    fn select_next_channel(&mut self) -> Command {
-        let channel_ids: Vec<String> = self
-            .get_current_display_channels()
+    #[test]
+    fn unanswered_member_requests_expire() {
+        let mut app = connected_app();
+        let stale = Instant::now()
+            .checked_sub(MEMBER_REQUEST_TIMEOUT)
+            .expect("clock too close to boot");
+        let requested = app.discord.member_requested.entry("100".to_string()).or_default();
+        requested.insert("u-gone".to_string(), stale);
+        requested.insert("u-pending".to_string(), Instant::now());
+        app.update(AppEvent::Tick);
+        let ids: Vec<&String> = app.discord.member_requested["100"].keys().collect();
+        assert_eq!(ids, ["u-pending"]);
+
+        app.discord.member_requested.get_mut("100").unwrap().insert("u-pending".to_string(), stale);
+        app.update(AppEvent::Tick);
+        assert!(app.discord.member_requested.is_empty());
+    }
+
+    #[test]
+    fn presence_updates_change_the_dm_status() {
+        let mut ready = ready();
+        ready["users"] = json!([{ "id": "20", "username": "friend", "discriminator": "0" }]);
+        ready["private_channels"] = json!([{ "id": "300", "type": 1, "recipient_ids": ["20"] }]);
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayReady(ready));
+        assert_eq!(app.dm_status(&app.discord.channels["300"]), None);
+
+        for status in [UserStatus::Online, UserStatus::Offline] {
+            app.update(AppEvent::PresenceUpdate { user_id: "20".to_string(), status });
+            assert_eq!(app.dm_status(&app.discord.channels["300"]), Some(status));
+        }
+    }
+
+    #[test]
+    fn favorites_missing_from_the_channel_list_are_only_removed_when_confirmed_gone() {
+        let mut app = connected_app();
+        // REST の一覧に出ないスレッドと、削除済みのチャンネル
+        app.ui.favorites.extend(["500".to_string(), "501".to_string()]);
+        let commands = flatten(app.update(AppEvent::ChannelsLoaded { complete: true }));
+        assert_eq!(app.ui.favorites, vec![GENERAL_ID, "500", "501"]);
+        let checked: Vec<&str> = commands
             .iter()
-            .map(|ch| ch.id.clone())
+            .filter_map(|c| match c {
+                Command::FetchChannel(id) => Some(id.as_str()),
+                _ => None,
+            })
             .collect();
+        assert_eq!(checked, vec!["500", "501"]);
+        assert!(!commands.iter().any(|c| matches!(c, Command::SaveConfig(_))));
 
-        if channel_ids.is_empty() {
-            return Command::None;
-        }
+        // スレッドは取得できれば一覧に入り、お気に入りに残る
+        let thread = serde_json::from_value(json!({
+            "id": "500", "type": 11, "guild_id": "100", "parent_id": GENERAL_ID, "name": "topic",
+        }))
+        .unwrap();
+        app.update(AppEvent::ChannelFetched(thread));
+        let gone = AppEvent::ChannelFetchFailed { channel_id: "501".to_string(), gone: true };
+        let commands = flatten(app.update(gone));
+        assert!(matches!(commands.as_slice(), [Command::SaveConfig(_)]));
+        assert_eq!(app.ui.favorites, vec![GENERAL_ID, "500"]);
 
-        let current_index = self.ui.channel_list_state.selected().unwrap_or(0);
-        let new_index = if current_index < channel_ids.len() - 1 {
-            current_index + 1
-        } else {
-            0
+        // 次に一覧が揃っても、取得済みのスレッドは確かめ直さない
+        let commands = flatten(app.update(AppEvent::ChannelsLoaded { complete: true }));
+        assert!(commands.is_empty());
+        assert_eq!(app.ui.favorites, vec![GENERAL_ID, "500"]);
+    }
+
+    #[test]
+    fn voice_states_track_joins_moves_and_leaves() {
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayReady(ready()));
+        let lounge = app.discord.channels["202"].clone();
+        assert_eq!(app.voice_member_count(&lounge), 2);
+
+        let state = |user: &str, channel: Option<&str>| {
+            let value = json!({ "user_id": user, "channel_id": channel, "guild_id": "100" });
+            AppEvent::VoiceStateUpdate(serde_json::from_value(value).unwrap())
         };
+        app.update(state("12", Some("202")));
+        app.update(state("10", Some("203")));
+        app.update(state("11", None));
+        assert_eq!(app.voice_member_count(&lounge), 1);
+    }
 
-        self.ui.channel_list_state.select(Some(new_index));
-        self.ui.selected_channel = Some(channel_ids[new_index].clone());
-        self.ui.message_scroll_offset = 0;
+    #[test]
+    fn voice_channels_are_listed_but_not_messageable() {
+        let mut app = connected_app();
+        let voice: Channel = serde_json::from_value(serde_json::json!({
+            "id": "250", "type": 2, "guild_id": "100", "name": "voice-room",
+        }))
+        .unwrap();
+        assert!(!voice.is_messageable());
+        assert!(voice.is_listable());
+        app.discord.channels.insert(voice.id.clone(), voice);
+
+        // 開いても (Tab でも) メッセージは読み込まず、入力モードにも入らない
+        let keys = "room".chars().map(KeyCode::Char);
+        let keys: Vec<KeyCode> = std::iter::once(KeyCode::Char('/'))
+            .chain(keys)
+            .chain([KeyCode::Down, KeyCode::Tab])
+            .collect();
+        let commands = press(&mut app, &keys);
+        assert_eq!(app.ui.selected_channel.as_deref(), Some("250"));
+        assert!(commands.is_empty());
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
 
-        // チャンネル切り替え時に自動的にメッセージを読み込む + 既読化
-        self.select_channel_commands(channel_ids[new_index].clone())
+        press(&mut app, &[KeyCode::Char('i')]);
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
     }
 
-    /// スクロール位置が直近に描画した上限 (= 最古メッセージが画面に出ている) に
-    /// 達したときだけ過去メッセージ読み込みを起動する。
-    fn maybe_load_older_messages_if_at_top(&mut self) -> Command {
-        if self.ui.message_scroll_offset < self.ui.cached_max_scroll_offset {
-            return Command::None;
-        }
-        self.maybe_load_older_messages()
+    #[test]
+    fn custom_emoji_render_as_text_with_the_current_guild_name() {
+        let mut data = ready();
+        data["guilds"][0]["emojis"] = json!([{ "id": "777", "name": "party_parrot" }]);
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayReady(data));
+
+        // サーバーの一覧にあれば改名後の名前、無ければ content 内の名前
+        assert_eq!(app.custom_emoji_text("777", "parrot").as_deref(), Some(":party_parrot:"));
+        assert_eq!(app.custom_emoji_text("778", "blob").as_deref(), Some(":blob:"));
+
+        // 画像を描画できない端末では絵文字画像を取得しない
+        let content = "nice <:parrot:777> <a:blob:778>";
+        let commands = flatten(app.update(AppEvent::MessageCreate(message(
+            "310", "200", "alice", content, "01:00",
+        ))));
+        assert!(!commands.iter().any(|c| matches!(c, Command::DownloadEmojis(_))));
     }
 
-    /// 古い側にスクロールしたとき、必要なら追加メッセージ読み込みを起動する。
-    /// 既に読み込み中、または最古メッセージが未取得の場合は何もしない。
-    fn maybe_load_older_messages(&mut self) -> Command {
-        let Some(channel_id) = self.ui.selected_channel.clone() else {
-            return Command::None;
-        };
-        if self.discord.loading_older.contains(&channel_id) {
-            return Command::None;
-        }
-        let Some(messages) = self.discord.messages.get(&channel_id) else {
-            return Command::None;
+    #[test]
+    fn failing_to_connect_at_startup_shows_reconnecting_until_ready() {
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayDisconnected);
+        assert!(!app.discord.connected);
+        assert!(app.discord.reconnecting);
+
+        app.update(AppEvent::GatewayReady(ready()));
+        assert!(app.discord.connected);
+        assert!(!app.discord.reconnecting);
+    }
+
+    #[test]
+    fn ready_without_guilds_falls_back_to_rest() {
+        let mut app = AppState::new();
+        let mut data = ready();
+        data["guilds"] = json!([]);
+        let commands = flatten(app.update(AppEvent::GatewayReady(data)));
+        assert!(commands.iter().any(|c| matches!(c, Command::LoadChannels)));
+        assert_eq!(app.ui.selected_channel, None);
+
+        // 一覧の途中では先頭が変わりうるので、取得が終わってから 1 度だけ選ぶ
+        let guild = |id: &str, channel: serde_json::Value| {
+            let guild = serde_json::from_value(json!({ "id": id, "name": id })).unwrap();
+            (guild, vec![serde_json::from_value(channel).unwrap()])
         };
-        // REST は新→古順なので、配列の末尾が最古
-        let Some(oldest) = messages.last() else {
-            return Command::None;
+        let zeta = json!({ "id": "210", "type": 0, "name": "zeta", "guild_id": "110" });
+        let alpha = json!({ "id": "220", "type": 0, "name": "alpha", "guild_id": "120" });
+        let commands = app.update(AppEvent::GuildsCreated(vec![guild("110", zeta)]));
+        assert!(flatten(commands).is_empty());
+        app.update(AppEvent::GuildsCreated(vec![guild("120", alpha)]));
+        assert_eq!(app.ui.selected_channel, None);
+
+        let commands = flatten(app.update(AppEvent::ChannelsLoaded { complete: true }));
+        assert_eq!(app.ui.selected_channel.as_deref(), Some("220"));
+        assert!(commands
+            .iter()
+            .any(|c| matches!(c, Command::LoadMessages(id) if id == "220")));
+    }
+
+    #[test]
+    fn message_in_another_channel_marks_it_unread() {
+        let mut app = with_messages();
+        let msg = message("400", RANDOM_ID, "bob", "over here", "02:00");
+        app.update(AppEvent::MessageCreate(msg));
+        assert!(app.discord.session_unread.contains(RANDOM_ID));
+        assert_eq!(app.discord.channels[RANDOM_ID].last_message_id.as_deref(), Some("400"));
+
+        // 開いているチャンネルへの新着は既読扱い
+        let msg = message("401", GENERAL_ID, "bob", "and here", "02:01");
+        app.update(AppEvent::MessageCreate(msg));
+        assert!(!app.discord.session_unread.contains(GENERAL_ID));
+        assert_eq!(app.discord.messages[GENERAL_ID].len(), 4);
+    }
+
+    #[test]
+    fn message_for_unknown_channel_is_buffered_and_the_channel_fetched() {
+        let mut app = connected_app();
+        let msg = message("500", "999", "ghost", "boo", "03:00");
+        let command = app.update(AppEvent::MessageCreate(msg));
+        assert!(matches!(&flatten(command)[..], [Command::FetchChannel(id)] if id == "999"));
+        assert_eq!(app.discord.messages["999"].len(), 1);
+        assert!(!app.discord.channels.contains_key("999"));
+        // 取得を待つ間に続けて届いても取り直さない
+        let msg = message("501", "999", "ghost", "boo?", "03:01");
+        assert!(flatten(app.update(AppEvent::MessageCreate(msg))).is_empty());
+
+        // 知らない相手からの DM として一覧に入り、キャッシュに無い相手は REST で解決する
+        let channel: Channel = serde_json::from_value(serde_json::json!({
+            "id": "999",
+            "type": 3,
+            "last_message_id": "500",
+            "recipients": [{ "id": "7", "username": "ghost", "discriminator": "0" }],
+            "recipient_ids": ["7", "8"],
+        }))
+        .unwrap();
+        let command = app.update(AppEvent::ChannelFetched(channel));
+        assert!(matches!(&flatten(command)[..], [Command::FetchUsers(ids)] if ids == &["8"]));
+        let channel = &app.discord.channels["999"];
+        assert_eq!(channel.last_message_id.as_deref(), Some("501"));
+        assert_eq!(channel.recipients.as_ref().map(Vec::len), Some(1));
+        assert!(app.discord.users.contains_key("7"));
+        app.refresh_unread_cache();
+        assert!(app.get_unread_channels().iter().any(|ch| ch.id == "999"));
+        assert!(app.search_channels("ghost").iter().any(|ch| ch.id == "999"));
+    }
+
+    #[test]
+    fn search_filters_and_opens_the_selected_result() {
+        let mut app = connected_app();
+        press(&mut app, &[KeyCode::Char('/')]);
+        assert!(app.ui.search_mode);
+
+        // 入力だけでは選択は動かず、↓ で結果を選ぶ
+        press(&mut app, &[KeyCode::Char('r'), KeyCode::Char('a'), KeyCode::Char('n')]);
+        assert_eq!(app.ui.search_buffer, "ran");
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(GENERAL_ID));
+        let commands = press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+        assert!(!app.ui.search_mode);
+        assert!(app.ui.search_buffer.is_empty());
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(RANDOM_ID));
+        assert!(commands
+            .iter()
+            .any(|c| matches!(c, Command::LoadMessages(id) if id == RANDOM_ID)));
+
+        // Esc は選択を変えずに抜ける
+        press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('g'), KeyCode::Esc]);
+        assert!(!app.ui.search_mode);
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(RANDOM_ID));
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+
+        // Tab は開いてそのまま入力モードに入る
+        let commands =
+            press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('g'), KeyCode::Down, KeyCode::Tab]);
+        assert!(!app.ui.search_mode);
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(GENERAL_ID));
+        assert_eq!(app.ui.input_mode, InputMode::Editing);
+        assert!(commands
+            .iter()
+            .any(|c| matches!(c, Command::LoadMessages(id) if id == GENERAL_ID)));
+    }
+
+    #[test]
+    fn link_key_cycles_through_links_of_the_newest_message() {
+        let mut app = with_messages();
+        app.discord.messages.get_mut(GENERAL_ID).unwrap()[1].content =
+            "docs: https://a.example, (https://b.example)".to_string();
+        let opened = |commands: Vec<Command>| match commands.as_slice() {
+            [Command::OpenUrl(url)] => url.clone(),
+            other => panic!("unexpected commands: {:?}", other.len()),
         };
-        let before = oldest.id.clone();
-        self.discord.loading_older.insert(channel_id.clone());
-        log::debug!("Loading older messages for {} before {}", channel_id, before);
-        Command::LoadOlderMessages { channel_id, before }
+        assert_eq!(opened(press(&mut app, &[KeyCode::Char('L')])), "https://a.example");
+        assert_eq!(app.active_link("302"), Some(0));
+        assert_eq!(opened(press(&mut app, &[KeyCode::Char('L')])), "https://b.example");
+        assert_eq!(opened(press(&mut app, &[KeyCode::Char('L')])), "https://a.example");
     }
 
-    /// メッセージリストを行単位でスクロール (正: 古い側 / 負: 新しい側)。
-    /// 上限のクランプはレイアウト依存のため ui.rs 側で行う。
-    fn apply_scroll(&mut self, delta: i32) {
-        if delta > 0 {
-            self.ui.message_scroll_offset =
-                self.ui.message_scroll_offset.saturating_add(delta as usize);
-        } else if delta < 0 {
-            self.ui.message_scroll_offset =
-                self.ui.message_scroll_offset.saturating_sub((-delta) as usize);
-        }
-        log::debug!("Scroll offset: {}", self.ui.message_scroll_offset);
+    #[test]
+    fn deleting_the_selected_channel_drops_its_stale_messages() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('V')]);
+        app.update(AppEvent::ChannelDelete { id: GENERAL_ID.to_string() });
+        assert!(app.selected_channel_unavailable());
+        assert!(app.get_current_messages().is_empty());
+        assert!(!app.discord.messages.contains_key(GENERAL_ID));
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        assert_eq!(app.ui.status_message.as_deref(), Some("general was deleted"));
+
+        // 他のチャンネルの削除では選択中のチャンネルに影響しない
+        let mut app = with_messages();
+        app.update(AppEvent::ChannelDelete { id: RANDOM_ID.to_string() });
+        assert!(!app.selected_channel_unavailable());
+        assert_eq!(app.get_current_messages().len(), 3);
     }
 
-    /// チャンネルリストを取得（ソート済み、メッセージ可能なもののみ）
-    pub fn get_channel_list(&self) -> Vec<&Channel> {
-        let mut channels: Vec<&Channel> = self
-            .discord
-            .channels
-            .values()
-            .filter(|ch| ch.is_messageable())
-            .collect();
-        channels.sort_by(|a, b| {
-            // タイプでソート、次に名前でソート
-            match a.channel_type.cmp(&b.channel_type) {
-                std::cmp::Ordering::Equal => a.display_name().cmp(&b.display_name()),
-                other => other,
-            }
+    #[test]
+    fn message_select_mode_targets_the_highlighted_message() {
+        let mut app = with_messages();
+        app.discord.messages.get_mut(GENERAL_ID).unwrap()[0].content =
+            "https://newest.example".to_string();
+        press(&mut app, &[KeyCode::Char('V')]);
+        assert_eq!(app.ui.input_mode, InputMode::MessageSelect);
+        assert_eq!(app.ui.selected_message.as_deref(), Some("303"));
+
+        // 最新が下なので、上へ動かすと古いメッセージへ。最古より先へは進まない
+        press(&mut app, &[KeyCode::Up, KeyCode::Char('k'), KeyCode::Char('j')]);
+        assert_eq!(app.ui.selected_message.as_deref(), Some("302"));
+        assert!(press(&mut app, &[KeyCode::Char('L')]).is_empty());
+        assert_eq!(app.ui.status_message.as_deref(), Some("No links in this message"));
+
+        press(&mut app, &[KeyCode::Char('r')]);
+        assert_eq!(app.ui.emoji_picker.as_ref().unwrap().message_id, "302");
+        press(&mut app, &[KeyCode::Esc]);
+        assert_eq!(app.ui.input_mode, InputMode::MessageSelect);
+
+        press(&mut app, &[KeyCode::Char('R')]);
+        assert_eq!(app.ui.reply_to.as_ref().unwrap().message_id, "302");
+        assert_eq!(app.ui.input_mode, InputMode::Editing);
+        assert_eq!(app.ui.selected_message, None);
+
+        // 選択を抜けると、リンクは最新のメッセージが対象に戻る
+        press(&mut app, &[KeyCode::Esc, KeyCode::Char('V'), KeyCode::Esc]);
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        let commands = press(&mut app, &[KeyCode::Char('L')]);
+        let newest = "https://newest.example";
+        assert!(matches!(commands.as_slice(), [Command::OpenUrl(url)] if url == newest));
+    }
+
+    #[test]
+    fn own_selected_messages_can_be_copied_deleted_and_edited() {
+        let mut app = with_messages();
+        app.discord.messages.get_mut(GENERAL_ID).unwrap()[1].author = me();
+        press(&mut app, &[KeyCode::Char('V'), KeyCode::Char('k')]);
+        let commands = press(&mut app, &[KeyCode::Char('y')]);
+        let copied = |text: &String| text == "hi alice";
+        assert!(matches!(commands.as_slice(), [Command::CopyToClipboard(text)] if copied(text)));
+
+        // 削除は同じメッセージで続けて 2 回押したときだけ
+        assert!(press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('j')]).is_empty());
+        assert!(press(&mut app, &[KeyCode::Char('k'), KeyCode::Char('d')]).is_empty());
+        assert_eq!(app.ui.status_message.as_deref(), Some("Press d again to delete this message"));
+        let commands = press(&mut app, &[KeyCode::Char('d')]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::DeleteMessage { message_id, .. }] if message_id == "302"
+        ));
+
+        // 編集は本文を入力欄に入れて始め、Enter で書き換える
+        press(&mut app, &[KeyCode::Char('e')]);
+        assert_eq!(app.ui.input_mode, InputMode::Editing);
+        assert_eq!(app.ui.input_buffer, "hi alice");
+        let commands = press(&mut app, &[KeyCode::Char('!'), KeyCode::Enter]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::EditMessage { message_id, content, .. }]
+                if message_id == "302" && content == "hi alice!"
+        ));
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        assert_eq!(app.ui.editing_message, None);
+
+        // Esc で編集をやめると入力欄も空に戻る
+        press(&mut app, &[KeyCode::Char('V'), KeyCode::Char('k'), KeyCode::Char('e')]);
+        press(&mut app, &[KeyCode::Esc]);
+        assert!(app.ui.input_buffer.is_empty());
+        assert_eq!(app.ui.editing_message, None);
+
+        // 他人のメッセージは編集も削除もできない
+        press(&mut app, &[KeyCode::Char('V')]);
+        assert!(press(&mut app, &[KeyCode::Char('e')]).is_empty());
+        assert_eq!(
+            app.ui.status_message.as_deref(),
+            Some("You can only edit your own messages")
+        );
+        assert!(press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('d')]).is_empty());
+        assert_eq!(app.ui.input_mode, InputMode::MessageSelect);
+    }
+
+    #[test]
+    fn edit_and_delete_failures_are_shown_in_the_status_bar() {
+        let mut app = with_messages();
+        app.update(AppEvent::ActionFailed("Failed to delete message: HTTP 403".to_string()));
+        assert_eq!(app.ui.status_message.as_deref(), Some("Failed to delete message: HTTP 403"));
+
+        // 読み取り専用モードでは編集・削除を送らない
+        let mut app = with_messages();
+        app.read_only = true;
+        app.discord.messages.get_mut(GENERAL_ID).unwrap()[0].author = me();
+        let commands = press(&mut app, &[KeyCode::Char('V'), KeyCode::Char('e')]);
+        assert!(commands.is_empty());
+        assert_eq!(app.ui.status_message.as_deref(), Some("Read-only mode: editing is disabled"));
+        assert!(press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('d')]).is_empty());
+    }
+
+    #[test]
+    fn moving_the_selection_scrolls_it_into_view() {
+        let mut app = with_messages();
+        // 前回の描画で 303 / 302 / 301 を並べた結果 (301 は画面より高い)
+        app.ui.cached_message_extents = vec![
+            ("303".to_string(), 0, 2),
+            ("302".to_string(), 2, 4),
+            ("301".to_string(), 4, 30),
+        ];
+        app.ui.cached_viewport_height = 10;
+        press(&mut app, &[KeyCode::Char('V'), KeyCode::Char('k')]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
+        press(&mut app, &[KeyCode::Char('k')]);
+        assert_eq!(app.ui.message_scroll_offset, 20);
+        press(&mut app, &[KeyCode::Char('j')]);
+        assert_eq!(app.ui.message_scroll_offset, 2);
+    }
+
+    #[test]
+    fn message_links_jump_to_the_message_and_load_its_surroundings() {
+        let mut app = with_messages();
+        let jump = |app: &mut AppState, link: &str| {
+            press(app, &[KeyCode::Char('m')]);
+            app.update(AppEvent::Paste(link.to_string()));
+            press(app, &[KeyCode::Enter])
+        };
+
+        // 読み込み済みのメッセージならそのまま強調する
+        let commands = jump(&mut app, "https://discord.com/channels/100/200/302");
+        assert!(!commands.iter().any(|c| matches!(c, Command::LoadMessagesAround { .. })));
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        assert_eq!(app.ui.highlighted_message.as_deref(), Some("302"));
+        assert_eq!(app.ui.scroll_to_message.as_deref(), Some("302"));
+
+        // 別のチャンネルなら、最新分ではなく前後のメッセージを読み込む
+        let commands = jump(&mut app, "https://discord.com/channels/100/201/50");
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(RANDOM_ID));
+        assert!(!commands.iter().any(|c| matches!(c, Command::LoadMessages(_))));
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            Command::LoadMessagesAround { channel_id, message_id }
+                if channel_id == RANDOM_ID && message_id == "50"
+        )));
+        app.update(AppEvent::MessagesAroundLoaded {
+            channel_id: RANDOM_ID.to_string(),
+            message_id: "50".to_string(),
+            messages: vec![
+                message("51", RANDOM_ID, "bob", "after", "01:01"),
+                message("50", RANDOM_ID, "alice", "target", "01:00"),
+            ],
+        });
+        assert_eq!(app.discord.messages[RANDOM_ID].len(), 2);
+        assert_eq!(app.ui.highlighted_message.as_deref(), Some("50"));
+        // 最新へ戻ると読み込み直す
+        let commands = press(&mut app, &[KeyCode::Char('G')]);
+        assert!(matches!(&commands[..], [Command::LoadMessages(id)] if id == RANDOM_ID));
+
+        assert!(jump(&mut app, "https://example.com/x").is_empty());
+        assert_eq!(app.ui.status_message.as_deref(), Some("Not a Discord message link"));
+        assert!(jump(&mut app, "https://discord.com/channels/999/998/1").is_empty());
+        assert_eq!(
+            app.ui.status_message.as_deref(),
+            Some("You are not a member of that server")
+        );
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(RANDOM_ID));
+    }
+
+    #[test]
+    fn polling_fetches_newer_messages_and_treats_them_as_new() {
+        let mut app = with_messages();
+        app.ui.favorites.push(RANDOM_ID.to_string());
+        app.discord.channels.get_mut(RANDOM_ID).unwrap().last_message_id = Some("50".into());
+        // Gateway を使っているときはポーリングしない
+        assert!(flatten(app.update(AppEvent::PollTick)).is_empty());
+
+        app.set_polling(true);
+        let polls = |commands: Vec<Command>| -> Vec<(String, String)> {
+            commands
+                .into_iter()
+                .filter_map(|c| match c {
+                    Command::PollChannel { channel_id, after } => Some((channel_id, after)),
+                    _ => None,
+                })
+                .collect()
+        };
+        let expected = vec![
+            (GENERAL_ID.to_string(), "303".to_string()),
+            (RANDOM_ID.to_string(), "50".to_string()),
+        ];
+        assert_eq!(polls(flatten(app.update(AppEvent::PollTick))), expected);
+        // 応答を待っている間は重ねて取得しない
+        assert!(polls(flatten(app.update(AppEvent::PollTick))).is_empty());
+
+        app.update(AppEvent::ChannelPolled {
+            channel_id: RANDOM_ID.to_string(),
+            messages: vec![
+                message("52", RANDOM_ID, "bob", "second", "01:06"),
+                message("51", RANDOM_ID, "bob", "first", "01:05"),
+            ],
         });
-        channels
+        let ids: Vec<&str> =
+            app.discord.messages[RANDOM_ID].iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["52", "51"]);
+        assert!(app.discord.session_unread.contains(RANDOM_ID));
+        assert_eq!(app.discord.channels[RANDOM_ID].last_message_id.as_deref(), Some("52"));
+
+        // 取得済みのものは重複させず、次の周期は最新の続きから取得する
+        app.update(AppEvent::ChannelPolled {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![
+                message("304", GENERAL_ID, "bob", "again", "01:03"),
+                message("303", GENERAL_ID, "bob", "see you", "01:02"),
+            ],
+        });
+        assert_eq!(app.discord.messages[GENERAL_ID].len(), 4);
+        let expected = vec![
+            (GENERAL_ID.to_string(), "304".to_string()),
+            (RANDOM_ID.to_string(), "52".to_string()),
+        ];
+        assert_eq!(polls(flatten(app.update(AppEvent::PollTick))), expected);
     }
 
-    /// お気に入りチャンネルリストを取得（ソート済み）
-    pub fn get_favorite_channels(&self) -> Vec<&Channel> {
-        let mut favorites: Vec<&Channel> = self
-            .discord
-            .channels
-            .values()
-            .filter(|ch| ch.is_messageable() && self.ui.favorites.contains(&ch.id))
+    #[test]
+    fn polling_pages_through_a_full_batch_until_caught_up() {
+        let mut app = with_messages();
+        app.set_polling(true);
+        flatten(app.update(AppEvent::PollTick));
+        // 304..=403 の 100 件 (上限いっぱい) が返った
+        let page: Vec<Message> = (304..304 + MESSAGE_DELTA_LIMIT as u32)
+            .rev()
+            .map(|id| message(&id.to_string(), GENERAL_ID, "bob", "busy", "02:00"))
             .collect();
+        let commands = flatten(app.update(AppEvent::ChannelPolled {
+            channel_id: GENERAL_ID.to_string(),
+            messages: page,
+        }));
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            Command::PollChannel { channel_id, after } if channel_id == GENERAL_ID && after == "403"
+        )));
+        // 続きを取得中なので周期の取得とは重ねない
+        assert!(flatten(app.update(AppEvent::PollTick)).is_empty());
 
-        favorites.sort_by(|a, b| {
-            match a.channel_type.cmp(&b.channel_type) {
-                std::cmp::Ordering::Equal => a.display_name().cmp(&b.display_name()),
-                other => other,
-            }
-        });
+        // 上限未満なら追いついたので続きは取らない
+        let commands = flatten(app.update(AppEvent::ChannelPolled {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![message("404", GENERAL_ID, "bob", "done", "02:01")],
+        }));
+        assert!(!commands.iter().any(|c| matches!(c, Command::PollChannel { .. })));
+        assert_eq!(app.discord.messages[GENERAL_ID].len(), 3 + 101);
+    }
 
-        favorites
+    #[test]
+    fn replayed_message_create_is_stored_once() {
+        let mut app = with_messages();
+        app.ui.selected_channel = Some(RANDOM_ID.to_string());
+        let replayed = message("303", GENERAL_ID, "bob", "see you", "01:02");
+        assert!(flatten(app.update(AppEvent::MessageCreate(replayed))).is_empty());
+        let ids: Vec<&str> =
+            app.discord.messages[GENERAL_ID].iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["303", "302", "301"]);
+        // 既に読んだものとして、未読にもしない
+        assert!(!app.discord.session_unread.contains(GENERAL_ID));
+
+        let new = message("304", GENERAL_ID, "bob", "new", "01:03");
+        app.update(AppEvent::MessageCreate(new.clone()));
+        app.update(AppEvent::MessageCreate(new));
+        assert_eq!(app.discord.messages[GENERAL_ID].len(), 4);
+        assert!(app.discord.session_unread.contains(GENERAL_ID));
     }
 
-    /// snowflake 比較ヘルパ (ui.rs から境界判定で利用)
-    pub fn snowflake_gt(&self, a: &str, b: &str) -> bool {
-        snowflake_gt(a, b)
+    #[test]
+    fn legacy_unordered_favorites_are_put_in_display_order_once() {
+        let mut app = connected_app();
+        app.ui.favorites = vec![RANDOM_ID.to_string(), GENERAL_ID.to_string()];
+        app.config.legacy_favorites = true;
+        app.update(AppEvent::ChannelsLoaded { complete: true });
+        assert_eq!(app.ui.favorites, [GENERAL_ID, RANDOM_ID]);
+        assert!(!app.config.legacy_favorites);
+        assert!(app.is_favorite(RANDOM_ID));
+
+        // 移行後は並べ替えた順を保つ
+        app.ui.favorites.reverse();
+        app.update(AppEvent::ChannelsLoaded { complete: true });
+        assert_eq!(app.ui.favorites, [RANDOM_ID, GENERAL_ID]);
     }
 
-    /// チャンネル or 親ギルドがミュートされているか
-    fn is_channel_muted(&self, channel: &Channel) -> bool {
-        if self.discord.muted_channels.contains(&channel.id) {
-            return true;
-        }
-        if let Some(gid) = &channel.guild_id {
-            if self.discord.muted_guilds.contains(gid) {
-                return true;
-            }
-        }
-        false
+    #[test]
+    fn favorite_key_toggles_the_selected_channel() {
+        let mut app = connected_app();
+        app.ui.selected_channel = Some(RANDOM_ID.to_string());
+        press(&mut app, &[KeyCode::Char('f')]);
+        assert_eq!(app.ui.favorites, vec![GENERAL_ID, RANDOM_ID]);
+        press(&mut app, &[KeyCode::Char('f')]);
+        assert_eq!(app.ui.favorites, vec![GENERAL_ID]);
     }
 
-    /// チャンネルが未読かどうか。
-    /// ミュート設定されている場合は基本的に未読扱いしないが、@メンションがある場合は例外。
-    pub fn is_channel_unread(&self, channel: &Channel) -> bool {
-        if channel.last_message_id.is_none() {
-            return false;
-        }
-        let last = channel.last_message_id.as_deref().unwrap();
-        // 起動後に新着があったチャンネルは無条件に未読 (read_state エントリの有無に依存しない)
-        let session_new = self.discord.session_unread.contains(&channel.id);
-        // 起動時点の read_state 比較。エントリ無しは「触れていない」= 既読扱い
-        let stored_new = matches!(
-            self.discord.read_states.get(&channel.id),
-            Some(Some(read)) if snowflake_gt(last, read.as_str())
+    #[test]
+    fn navigating_an_empty_channel_list_does_nothing() {
+        let mut app = AppState::new();
+        let commands = press(
+            &mut app,
+            &[KeyCode::Down, KeyCode::Char('j'), KeyCode::Up, KeyCode::Char('k'), KeyCode::Enter],
         );
-        if !session_new && !stored_new {
-            return false;
-        }
-        // ミュート時はメンションがある場合のみ未読扱い
-        if self.is_channel_muted(channel) {
-            return self
-                .discord
-                .mention_counts
-                .get(&channel.id)
-                .copied()
-                .unwrap_or(0)
-                > 0;
-        }
-        true
+        assert!(commands.is_empty());
+        assert_eq!(app.ui.selected_channel, None);
     }
 
-    /// 未読チャンネル一覧を取得。キャッシュ済みの ID 列を Channel 参照に解決して返す。
-    /// 描画前に `refresh_unread_cache()` が呼ばれていることを前提とする。
-    pub fn get_unread_channels(&self) -> Vec<&Channel> {
-        self.discord
-            .unread_cache
-            .iter()
-            .filter_map(|id| self.discord.channels.get(id))
-            .collect()
+    #[test]
+    fn image_previews_are_toggled_per_message() {
+        let mut app = with_messages();
+        app.set_picker(Some(Picker::new((8, 16))));
+        let image = |id: &str| Attachment {
+            id: id.to_string(),
+            filename: format!("{}.png", id),
+            content_type: Some("image/png".to_string()),
+            size: None,
+            url: Some(format!("https://cdn.example/{}.png", id)),
+            width: Some(10),
+            height: Some(10),
+        };
+        let messages = app.discord.messages.get_mut(GENERAL_ID).unwrap();
+        messages[1].attachments.push(image("a2"));
+        messages[2].attachments.push(image("a1"));
+
+        // 既定では取得しない (新着が来ても取得しない)
+        assert!(!app.config.image_previews);
+        let mut incoming = message("304", GENERAL_ID, "bob", "look", "01:03");
+        incoming.attachments.push(image("a4"));
+        let commands = flatten(app.update(AppEvent::MessageCreate(incoming.clone())));
+        assert!(!commands.iter().any(|c| matches!(c, Command::DownloadImages(_))));
+        assert!(!app.image_previews_enabled("304"));
+
+        // `v` は画像を含む最新のメッセージだけを対象にする
+        let downloads = |commands: Vec<Command>| -> Vec<String> {
+            commands
+                .into_iter()
+                .flat_map(|c| match c {
+                    Command::DownloadImages(images) => images,
+                    _ => Vec::new(),
+                })
+                .map(|(id, _)| id)
+                .collect()
+        };
+        assert_eq!(downloads(press(&mut app, &[KeyCode::Char('v')])), ["a4"]);
+        assert!(app.image_previews_enabled("304"));
+        assert!(!app.image_previews_enabled("302"));
+
+        // 選択モードでは選択中のメッセージを対象にする
+        press(&mut app, &[KeyCode::Char('V')]);
+        app.ui.selected_message = Some("302".to_string());
+        assert_eq!(downloads(press(&mut app, &[KeyCode::Char('v')])), ["a2"]);
+        assert!(app.image_previews_enabled("302"));
+        assert!(!app.image_previews_enabled("301"));
+
+        // 画像の無いメッセージでは切り替えない
+        app.ui.selected_message = Some("303".to_string());
+        assert!(press(&mut app, &[KeyCode::Char('v')]).is_empty());
+        assert_eq!(app.ui.status_message.as_deref(), Some("No images in this message"));
+
+        // もう一度押すと隠す
+        press(&mut app, &[KeyCode::Esc, KeyCode::Char('v')]);
+        assert!(!app.image_previews_enabled("304"));
     }
 
-    /// 未読リストのキャッシュを再計算する。dirty フラグが立っているときだけ走る。
-    pub fn refresh_unread_cache(&mut self) {
-        if !self.discord.unread_cache_dirty {
-            return;
+    #[test]
+    fn read_only_mode_drops_sends() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('h'), KeyCode::Char('i')]);
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SendMessage { channel_id, content, reply_to: None, mass_mentions: true }]
+                if channel_id == GENERAL_ID && content == "hi"
+        ));
+
+        app.set_read_only(true);
+        app.ui.input_buffer = "again".to_string();
+        assert!(press(&mut app, &[KeyCode::Enter]).is_empty());
+        // 送らなかったメッセージは送信中として残さない (読み取り専用にする前の 1 件だけ)
+        assert_eq!(app.ui.outgoing_sends.len(), 1);
+        assert!(app.ui.outgoing_sends.iter().all(|s| s.content == "hi"));
+        assert_eq!(app.ui.status_message.as_deref(), Some("Read-only mode: message not sent"));
+
+        // オンライン状態・アクティビティも変えず、変えないことを知らせる
+        press(&mut app, &[KeyCode::Esc]);
+        let (status, activity_enabled) = (app.config.status, app.ui.activity_enabled);
+        for key in [KeyCode::Char('p'), KeyCode::Char('P')] {
+            app.ui.status_message = None;
+            assert!(press(&mut app, &[key]).is_empty());
+            assert_eq!(
+                app.ui.status_message.as_deref(),
+                Some("Read-only mode: presence and activity are not changed")
+            );
         }
-        let mut entries: Vec<&Channel> = self
-            .discord
-            .channels
-            .values()
-            .filter(|ch| {
-                ch.is_messageable()
-                    && !self.discord.inaccessible_channels.contains(&ch.id)
-                    && (self.is_channel_unread(ch)
-                        || self.discord.acked_in_session.contains(&ch.id))
-            })
-            .collect();
-        entries.sort_by(|a, b| match a.channel_type.cmp(&b.channel_type) {
-            std::cmp::Ordering::Equal => a.display_name().cmp(&b.display_name()),
-            other => other,
-        });
-        self.discord.unread_cache = entries.iter().map(|c| c.id.clone()).collect();
-        self.discord.unread_cache_dirty = false;
+        assert_eq!(app.config.status, status);
+        assert_eq!(app.ui.activity_enabled, activity_enabled);
+        assert!(matches!(
+            strip_outgoing(Command::UpdatePresence(app.presence())),
+            Command::None
+        ));
     }
 
-    /// 未読リストキャッシュを無効化する (状態変化が起きたとき)
-    fn invalidate_unread_cache(&mut self) {
-        self.discord.unread_cache_dirty = true;
+    #[test]
+    fn r_replies_to_the_newest_or_the_selected_message() {
+        let mut app = with_messages();
+        // 選択していなければ最新のメッセージへの返信
+        press(&mut app, &[KeyCode::Char('R')]);
+        let reply = app.current_reply().unwrap();
+        assert_eq!(
+            (reply.message_id.as_str(), reply.author.as_str(), reply.snippet.as_str()),
+            ("303", "bob", "see you")
+        );
+        assert_eq!(app.ui.input_mode, InputMode::Editing);
+        press(&mut app, &[KeyCode::Esc]);
+        assert!(app.current_reply().is_none());
+
+        // 選択モードでは選択中のメッセージへの返信になり、送信で返信先が外れる
+        press(&mut app, &[KeyCode::Char('V'), KeyCode::Char('k'), KeyCode::Char('k')]);
+        press(&mut app, &[KeyCode::Char('R'), KeyCode::Char('o'), KeyCode::Char('k')]);
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SendMessage { reply_to: Some(reply), .. }] if reply.message_id == "301"
+        ));
+        assert!(app.current_reply().is_none());
+
+        // 返信先の無いチャンネルでは知らせるだけ
+        app.discord.messages.remove(GENERAL_ID);
+        press(&mut app, &[KeyCode::Esc, KeyCode::Char('R')]);
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        assert_eq!(app.ui.status_message.as_deref(), Some("No message to reply to"));
     }
 
-    /// チャンネルを検索（名前・ギルド名でフィルタリング）
-    pub fn search_channels(&self, query: &str) -> Vec<&Channel> {
-        if query.is_empty() {
-            return Vec::new();
-        }
+    #[test]
+    fn reply_can_opt_out_of_pinging_the_author() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('R'), KeyCode::Tab, KeyCode::Char('k')]);
+        assert_eq!(app.current_reply().map(|r| r.mention), Some(false));
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        let [Command::SendMessage { reply_to: Some(reply), .. }] = commands.as_slice() else {
+            panic!("expected a reply, got {:?}", commands);
+        };
+        assert_eq!(reply.message_id, "303");
+        assert!(!reply.mention);
+    }
 
-        let query_lower = query.to_lowercase();
-        log::debug!("Searching channels with query: '{}'", query_lower);
-        log::debug!("Total channels to search: {}", self.discord.channels.len());
+    #[test]
+    fn shift_tab_toggles_mass_mentions_for_one_message() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('a'), KeyCode::BackTab]);
+        assert!(!app.mass_mentions_allowed());
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SendMessage { mass_mentions: false, .. }]
+        ));
+        // 切り替えは送信したメッセージだけで、次は設定の既定に戻る
+        assert!(app.mass_mentions_allowed());
 
-        let mut results: Vec<&Channel> = self
-            .discord
-            .channels
-            .values()
-            .filter(|ch| ch.is_messageable())
-            .filter(|ch| {
-                // チャンネル名で検索
-                let display_name = ch.display_name();
-                let name_match = display_name.to_lowercase().contains(&query_lower);
+        // 既定で通知しない設定なら、切り替えで通知する
+        app.config.allow_mass_mentions = false;
+        press(&mut app, &[KeyCode::Char('b'), KeyCode::BackTab]);
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SendMessage { mass_mentions: true, .. }]
+        ));
+        assert!(!app.mass_mentions_allowed());
+    }
 
-                // ギルド名で検索
-                let guild_match = if let Some(guild_id) = &ch.guild_id {
-                    if let Some(guild) = self.discord.guilds.get(guild_id) {
-                        guild.name.to_lowercase().contains(&query_lower)
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
+    #[test]
+    fn channel_navigation_loads_only_after_it_settles() {
+        let mut app = connected_app();
+        app.ui.favorites.push(RANDOM_ID.to_string());
+        let j = KeyCode::Char('j');
+        let commands = press(&mut app, &[j, j, j]);
+        assert!(commands.is_empty());
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(RANDOM_ID));
+        assert!(flatten(app.update(AppEvent::Tick)).is_empty());
 
-                // 親チャンネル名(フォーラム名等)で検索
-                let parent_match = ch
-                    .parent_id
-                    .as_ref()
-                    .and_then(|pid| self.discord.channels.get(pid))
-                    .map(|p| p.display_name().to_lowercase().contains(&query_lower))
-                    .unwrap_or(false);
+        // 移動が止まってから Tick が来ると、最後に選んだチャンネルだけ読み込む
+        if let Some((_, moved_at)) = app.ui.pending_channel_load.as_mut() {
+            *moved_at -= CHANNEL_LOAD_DEBOUNCE;
+        }
+        let commands = flatten(app.update(AppEvent::Tick));
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::LoadMessages(id)] if id == RANDOM_ID
+        ));
+        assert!(flatten(app.update(AppEvent::Tick)).is_empty());
+    }
 
-                let matched = name_match || guild_match || parent_match;
-                if matched {
-                    log::debug!(
-                        "Matched channel: {} (type={}, guild_id={:?})",
-                        display_name,
-                        ch.channel_type,
-                        ch.guild_id
-                    );
-                }
+    #[test]
+    fn reopening_uses_the_newer_of_server_read_and_last_viewed() {
+        let mut app = with_messages();
+        app.discord.read_states.insert(GENERAL_ID.to_string(), Some("301".to_string()));
+        app.config.last_viewed.insert(GENERAL_ID.to_string(), "302".to_string());
+        app.select_channel_commands(GENERAL_ID.to_string());
+        assert_eq!(app.ui.unread_boundaries[GENERAL_ID], "302");
+        assert!(app.ui.scroll_to_unread);
 
-                matched
-            })
-            .collect();
+        // 表示した位置は新しい方向にだけ進む
+        let viewed = |message_id: &str| AppEvent::MessageViewed {
+            channel_id: GENERAL_ID.to_string(),
+            message_id: message_id.to_string(),
+        };
+        assert!(matches!(app.update(viewed("301")), Command::None));
+        assert_eq!(app.config.last_viewed[GENERAL_ID], "302");
+        app.update(viewed("303"));
+        assert_eq!(app.config.last_viewed[GENERAL_ID], "303");
+    }
 
-        log::debug!("Search found {} results", results.len());
+    #[test]
+    fn last_viewed_forgets_deleted_and_stalest_channels() {
+        let mut app = with_messages();
+        app.mark_viewed(RANDOM_ID, "500");
+        app.update(AppEvent::ChannelDelete { id: RANDOM_ID.to_string() });
+        assert!(!app.config.last_viewed.contains_key(RANDOM_ID));
 
-        results.sort_by(|a, b| {
-            match a.channel_type.cmp(&b.channel_type) {
-                std::cmp::Ordering::Equal => a.display_name().cmp(&b.display_name()),
-                other => other,
-            }
+        // 上限を超えたら、最後に読んだメッセージが最も古いチャンネルから忘れる
+        for i in 0..LAST_VIEWED_MAX_CHANNELS {
+            app.mark_viewed(&format!("c{}", i), &(1000 + i).to_string());
+        }
+        app.mark_viewed(GENERAL_ID, "5000");
+        assert_eq!(app.config.last_viewed.len(), LAST_VIEWED_MAX_CHANNELS);
+        assert!(!app.config.last_viewed.contains_key("c0"));
+        assert!(app.config.last_viewed.contains_key("c1"));
+        assert_eq!(app.config.last_viewed[GENERAL_ID], "5000");
+    }
+
+    #[test]
+    fn cached_messages_are_reused_then_refreshed_by_delta() {
+        let mut app = with_messages();
+        // Gateway 経由でしか持っていないチャンネルは全件取得する
+        let commands = flatten(app.select_channel_commands(GENERAL_ID.to_string()));
+        assert!(matches!(commands.as_slice(), [Command::LoadMessages(id)] if id == GENERAL_ID));
+        let cached = app.discord.messages[GENERAL_ID].clone();
+        app.update(AppEvent::MessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: cached,
         });
 
-        results
-    }
+        // TTL 内なら取得しない
+        assert!(flatten(app.select_channel_commands(GENERAL_ID.to_string())).is_empty());
 
-    /// お気に入りを登録/解除
-    pub fn toggle_favorite(&mut self) {
-        if let Some(channel_id) = &self.ui.selected_channel {
-            if self.ui.favorites.contains(channel_id) {
-                self.ui.favorites.remove(channel_id);
-                log::info!("Removed from favorites: {}", channel_id);
-            } else {
-                self.ui.favorites.insert(channel_id.clone());
-                log::info!("Added to favorites: {}", channel_id);
-            }
+        // 古くなったら最新以降の差分だけ取得し、受信済みのものと重複させずに先頭へ足す
+        let ttl = Duration::from_secs(app.config.message_cache_ttl_secs);
+        if let Some(at) = app.discord.messages_fetched_at.get_mut(GENERAL_ID) {
+            *at -= ttl;
         }
+        let commands = flatten(app.select_channel_commands(GENERAL_ID.to_string()));
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::LoadNewerMessages { channel_id, after }]
+                if channel_id == GENERAL_ID && after == "303"
+        ));
+        app.update(AppEvent::MessageCreate(message("304", GENERAL_ID, "bob", "a", "01:03")));
+        app.update(AppEvent::NewerMessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![
+                message("305", GENERAL_ID, "alice", "b", "01:04"),
+                message("304", GENERAL_ID, "bob", "a", "01:03"),
+            ],
+        });
+        let ids: Vec<&str> =
+            app.discord.messages[GENERAL_ID].iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["305", "304", "303", "302", "301"]);
     }
 
-    /// 検索モードを切り替え
-    pub fn toggle_search_mode(&mut self) {
-        self.ui.search_mode = !self.ui.search_mode;
+    #[test]
+    fn f5_refreshes_channels_messages_and_token() {
+        let mut app = with_messages();
+        let commands = press(&mut app, &[KeyCode::F(5)]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::ValidateToken, Command::LoadChannels, Command::LoadMessages(id)]
+                if id == GENERAL_ID
+        ));
+        assert_eq!(app.ui.status_message.as_deref(), Some("Refreshing…"));
 
-        if self.ui.search_mode {
-            // 検索モードに入る時はバッファをクリア
-            self.ui.search_buffer.clear();
-            log::debug!("Entered search mode");
-        } else {
-            // 検索モードを抜ける時はバッファをクリア
-            self.ui.search_buffer.clear();
-            log::debug!("Exited search mode");
-        }
+        app.update(AppEvent::ChannelsLoaded { complete: true });
+        assert_eq!(app.ui.status_message.as_deref(), Some("Refreshed"));
+        assert!(!app.ui.refreshing);
     }
 
-    /// 検索入力を追加
-    pub fn search_input(&mut self, c: char) {
-        if self.ui.search_mode {
-            self.ui.search_buffer.push(c);
-            log::debug!("Search query: {}", self.ui.search_buffer);
-        }
+    #[test]
+    fn startup_channel_setting_controls_the_first_selection() {
+        let mut app = AppState::new();
+        app.config.startup_channel = StartupChannel::None;
+        let commands = flatten(app.update(AppEvent::GatewayReady(ready())));
+        assert_eq!(app.ui.selected_channel, None);
+        assert!(!commands.iter().any(|c| matches!(c, Command::LoadMessages(_))));
+
+        let mut app = AppState::new();
+        app.config.startup_channel = StartupChannel::Last;
+        app.config.last_channel = Some("201".to_string());
+        app.update(AppEvent::GatewayReady(ready()));
+        assert_eq!(app.ui.selected_channel.as_deref(), Some("201"));
+        assert_eq!(app.config_to_save().last_channel.as_deref(), Some("201"));
+
+        // 見つからないチャンネルを指定していれば従来どおり先頭を開く
+        let mut app = AppState::new();
+        app.config.startup_channel = StartupChannel::Pinned;
+        app.config.startup_channel_id = Some("999".to_string());
+        app.update(AppEvent::GatewayReady(ready()));
+        assert_eq!(app.ui.selected_channel.as_deref(), Some("200"));
     }
 
-    /// 検索入力をバックスペース
-    pub fn search_backspace(&mut self) {
-        if self.ui.search_mode {
-            self.ui.search_buffer.pop();
-            log::debug!("Search query: {}", self.ui.search_buffer);
+    #[test]
+    fn nsfw_channel_is_not_loaded_or_acked_until_confirmed() {
+        let mut app = connected_app();
+        if let Some(channel) = app.discord.channels.get_mut(RANDOM_ID) {
+            channel.nsfw = true;
+            channel.last_message_id = Some("900".to_string());
         }
+        app.ui.selected_channel = Some(RANDOM_ID.to_string());
+        assert!(flatten(app.select_channel_commands(RANDOM_ID.to_string())).is_empty());
+        assert!(!app.discord.read_states.contains_key(RANDOM_ID));
+        // 再読み込みでも確認前は読み込まない
+        assert!(!flatten(app.refresh())
+            .iter()
+            .any(|c| matches!(c, Command::LoadMessages(_))));
+
+        let commands = press(&mut app, &[KeyCode::Enter]);
+        assert!(commands
+            .iter()
+            .any(|c| matches!(c, Command::LoadMessages(id) if id == RANDOM_ID)));
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            Command::AckChannel { channel_id, message_id }
+                if channel_id == RANDOM_ID && message_id == "900"
+        )));
     }
 
-    /// 現在選択中のチャンネルのメッセージリストを取得
-    pub fn get_current_messages(&self) -> Vec<&Message> {
-        if let Some(channel_id) = &self.ui.selected_channel {
-            if let Some(messages) = self.discord.messages.get(channel_id) {
-                return messages.iter().collect();
-            }
+    #[test]
+    fn nsfw_channel_needs_confirmation_once_per_session() {
+        let mut app = with_messages();
+        if let Some(channel) = app.discord.channels.get_mut(GENERAL_ID) {
+            channel.nsfw = true;
         }
-        Vec::new()
+        assert!(app.nsfw_gate_active());
+        press(&mut app, &[KeyCode::Enter]);
+        assert!(!app.nsfw_gate_active());
+
+        app.ui.nsfw_acknowledged.clear();
+        app.config.nsfw_acknowledged = true;
+        assert!(!app.nsfw_gate_active());
     }
-}
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn quit_key_follows_config() {
+        let mut app = connected_app();
+        assert!(matches!(app.update(AppEvent::KeyPress(KeyCode::Char('q'))), Command::Quit));
+
+        app.config.quit_key = QuitKey::Confirm;
+        assert!(press(&mut app, &[KeyCode::Char('q'), KeyCode::Char('n')]).is_empty());
+        assert!(!app.ui.quit_confirm);
+        app.update(AppEvent::KeyPress(KeyCode::Char('q')));
+        assert!(matches!(app.update(AppEvent::KeyPress(KeyCode::Char('y'))), Command::Quit));
+
+        app.config.quit_key = QuitKey::Disabled;
+        assert!(press(&mut app, &[KeyCode::Char('q')]).is_empty());
+        app.ui.input_mode = InputMode::Editing;
+        assert!(press(&mut app, &[KeyCode::Char('q')]).is_empty());
+        assert_eq!(app.ui.input_buffer, "q");
     }
-}
 
-/// 画像/絵文字のダウンロード Command を必要に応じて Batch にまとめる
-fn batch_commands(
-    images: Vec<(String, String)>,
-    emojis: Vec<(String, String)>,
-) -> Command {
-    let mut cmds: Vec<Command> = Vec::new();
-    if !images.is_empty() {
-        cmds.push(Command::DownloadImages(images));
+    #[test]
+    fn quick_send_targets_another_channel_by_name() {
+        let mut app = connected_app();
+        app.ui.input_mode = InputMode::Editing;
+        app.ui.input_buffer = "/to #Random  see you there".to_string();
+        let cmds = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            cmds.as_slice(),
+            [Command::SendMessage { channel_id, content, reply_to: None, .. }]
+                if channel_id == RANDOM_ID && content == "see you there"
+        ));
+        // 開いているチャンネルは変わらない
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(GENERAL_ID));
+        assert!(app.ui.input_buffer.is_empty());
+
+        // 送信が完了しても、開いているチャンネルの表示や送り先の未読はそのまま
+        let earlier = message("50", RANDOM_ID, "bob", "earlier", "01:00");
+        app.discord.messages.insert(RANDOM_ID.to_string(), vec![earlier]);
+        app.discord.session_unread.insert(RANDOM_ID.to_string());
+        app.ui.message_scroll_offset = 4;
+        app.ui.scroll_to_unread = false;
+        let sent = message("60", RANDOM_ID, "me", "see you there", "01:05");
+        assert!(flatten(app.update(AppEvent::MessageSent(sent))).is_empty());
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(GENERAL_ID));
+        assert_eq!(app.ui.message_scroll_offset, 4);
+        assert!(!app.ui.scroll_to_unread);
+        assert!(app.discord.session_unread.contains(RANDOM_ID));
+        let ids: Vec<&str> =
+            app.discord.messages[RANDOM_ID].iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["60", "50"]);
+        assert!(app.ui.outgoing_sends.is_empty());
+
+        // 見つからなければ送らずに入力を残す
+        app.ui.input_buffer = "/to #missing hello".to_string();
+        assert!(press(&mut app, &[KeyCode::Enter]).is_empty());
+        assert_eq!(app.ui.input_buffer, "/to #missing hello");
+        assert!(app.ui.status_message.as_deref().unwrap().contains("missing"));
     }
-    if !emojis.is_empty() {
-        cmds.push(Command::DownloadEmojis(emojis));
+
+    #[test]
+    fn conservative_mode_disables_bulk_delete() {
+        let mut app = connected_app();
+        press(&mut app, &[KeyCode::Char('X')]);
+        assert_eq!(app.ui.input_mode, InputMode::PurgeConfirm);
+        press(&mut app, &[KeyCode::Esc]);
+
+        app.config.conservative_mode = true;
+        press(&mut app, &[KeyCode::Char('X')]);
+        assert_eq!(app.ui.input_mode, InputMode::Normal);
+        assert_eq!(
+            app.ui.status_message.as_deref(),
+            Some("Conservative mode: bulk delete is disabled")
+        );
     }
-    match cmds.len() {
-        0 => Command::None,
-        1 => cmds.into_iter().next().unwrap(),
-        _ => Command::Batch(cmds),
+
+    #[test]
+    fn jump_keys_reach_the_oldest_and_newest_messages() {
+        let mut app = with_messages();
+        app.ui.cached_max_scroll_offset = 5;
+        app.ui.auto_follow = false;
+
+        // gg / Home は読み込み済みの最古へ移動し、その前を読み込む
+        let commands = press(&mut app, &[KeyCode::Char('g'), KeyCode::Char('g')]);
+        assert_eq!(app.ui.message_scroll_offset, 5);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::LoadOlderMessages { channel_id, before }]
+                if channel_id == GENERAL_ID && before == "301"
+        ));
+        app.update(AppEvent::OlderMessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![message("300", GENERAL_ID, "carol", "first", "00:30")],
+        });
+        app.ui.message_scroll_offset = 0;
+        let commands = press(&mut app, &[KeyCode::Home]);
+        assert_eq!(app.ui.message_scroll_offset, 5);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::LoadOlderMessages { before, .. }] if before == "300"
+        ));
+
+        // G / End は最新へ戻り、自動追従を再開する
+        press(&mut app, &[KeyCode::Char('G')]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
+        assert!(app.ui.auto_follow);
+        app.ui.message_scroll_offset = 3;
+        app.ui.auto_follow = false;
+        press(&mut app, &[KeyCode::End]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
+        assert!(app.ui.auto_follow);
+
+        // g の後に別のキーを挟むと gg にならない
+        press(&mut app, &[KeyCode::Char('g'), KeyCode::Char('j'), KeyCode::Char('g')]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
     }
-}
 
-/// Discord snowflake ID (数値文字列) の大小比較。a > b なら true
-fn snowflake_gt(a: &str, b: &str) -> bool {
-    match a.len().cmp(&b.len()) {
-        std::cmp::Ordering::Greater => true,
-        std::cmp::Ordering::Less => false,
-        std::cmp::Ordering::Equal => a > b,
+    #[test]
+    fn scroll_keys_follow_the_message_order() {
+        let mut app = with_messages();
+        app.ui.cached_max_scroll_offset = 5;
+        press(&mut app, &[KeyCode::Char('e')]);
+        assert_eq!(app.ui.message_scroll_offset, 1);
+
+        // newest-first では下が古い側
+        app.config.message_order = MessageOrder::NewestFirst;
+        press(&mut app, &[KeyCode::Char('e')]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
+        press(&mut app, &[KeyCode::Char('d'), KeyCode::Char('d')]);
+        assert_eq!(app.ui.message_scroll_offset, 2);
+        press(&mut app, &[KeyCode::Char('g'), KeyCode::Char('g')]);
+        assert_eq!(app.ui.message_scroll_offset, 0);
+        press(&mut app, &[KeyCode::Char('G')]);
+        assert_eq!(app.ui.message_scroll_offset, 5);
+    }
+
+    #[test]
+    fn text_only_toggle_hides_other_channel_types() {
+        let mut app = connected_app();
+        let voice: Channel = serde_json::from_value(serde_json::json!({
+            "id": "250", "type": 2, "guild_id": "100", "name": "voice-room",
+        }))
+        .unwrap();
+        app.discord.channels.insert(voice.id.clone(), voice);
+        assert!(app.search_channels("room").iter().any(|ch| ch.id == "250"));
+
+        let commands = press(&mut app, &[KeyCode::Char('T')]);
+        assert!(app.config.text_channels_only);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SaveConfig(config)] if config.text_channels_only
+        ));
+        assert!(app.search_channels("room").is_empty());
+        assert!(app.get_channel_list().iter().all(|ch| ch.id != "250"));
+
+        press(&mut app, &[KeyCode::Char('T')]);
+        assert!(app.get_channel_list().iter().any(|ch| ch.id == "250"));
+    }
+
+    #[test]
+    fn failed_sends_stay_visible_and_r_resends_them() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('o'), KeyCode::Enter, KeyCode::Esc]);
+        assert_eq!(app.current_outgoing_sends()[0].status, SendStatus::Sending);
+
+        app.update(AppEvent::SendFailed {
+            channel_id: GENERAL_ID.to_string(),
+            error: "HTTP 500 - oops".to_string(),
+        });
+        let failed = SendStatus::Failed("HTTP 500 - oops".to_string());
+        assert_eq!(app.current_outgoing_sends()[0].status, failed);
+
+        // R は返信より先に、失敗したメッセージを同じ内容で送り直す
+        let commands = press(&mut app, &[KeyCode::Char('R')]);
+        assert!(matches!(
+            commands.as_slice(),
+            [Command::SendMessage { channel_id, content, .. }]
+                if channel_id == GENERAL_ID && content == "o"
+        ));
+        assert!(app.ui.reply_to.is_none());
+        assert_eq!(app.current_outgoing_sends()[0].status, SendStatus::Sending);
+
+        let sent = message("304", GENERAL_ID, "me", "o", "01:03");
+        app.update(AppEvent::MessageSent(sent));
+        assert!(app.current_outgoing_sends().is_empty());
+    }
+
+    #[test]
+    fn pasted_lines_stay_in_one_message() {
+        let mut app = connected_app();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('>')]);
+        assert!(flatten(app.update(AppEvent::Paste("first\r\nsecond\n".to_string()))).is_empty());
+        assert_eq!(app.ui.input_buffer, ">first\nsecond\n");
+
+        let cmds = press(&mut app, &[KeyCode::Enter]);
+        assert!(matches!(
+            cmds.as_slice(),
+            [Command::SendMessage { content, .. }] if content == ">first\nsecond\n"
+        ));
     }
 }
@@ -6,13 +6,27 @@ use rsa::{pkcs8::EncodePublicKey, Oaep, RsaPrivateKey, RsaPublicKey};
 use serde::Deserialize;
 use serde_json::json;
 use sha2::Sha256;
+use std::path::Path;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
+use crate::clipboard;
+use crate::config::NetworkTimeouts;
 use crate::token_store;
 
 const REMOTE_AUTH_URL: &str = "wss://remote-auth-gateway.discord.gg/?v=2";
+/// hello に timeout_ms が無い場合の QR 有効期限 (Discord の既定は約 2 分)
+const DEFAULT_AUTH_TIMEOUT_MS: u64 = 120_000;
 const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
 
+/// QR コード認証でのログイン URL の提示方法
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QrOptions {
+    /// QR コードを描画せず URL のみ表示する
+    pub link_only: bool,
+    /// URL を OSC 52 でクリップボードへコピーする
+    pub copy_link: bool,
+}
+
 /// Remote Auth WebSocketメッセージ
 #[derive(Debug, Deserialize)]
 struct RemoteAuthMessage {
@@ -30,7 +44,10 @@ struct RemoteAuthMessage {
 /// 4. QRコードを生成してターミナルに表示
 /// 5. ユーザーがモバイルアプリでスキャン・承認
 /// 6. トークンを取得
-pub async fn authenticate_with_qr() -> Result<String> {
+pub async fn authenticate_with_qr(
+    timeouts: &NetworkTimeouts,
+    qr_options: QrOptions,
+) -> Result<String> {
     log::info!("Starting QR code authentication...");
 
     // WebSocket接続（必要なヘッダーを追加）
@@ -54,22 +71,45 @@ pub async fn authenticate_with_qr() -> Result<String> {
             .context("Failed to parse User-Agent header")?
     );
 
-    let (ws_stream, _) = connect_async(request)
+    let (ws_stream, _) = tokio::time::timeout(timeouts.ws_handshake, connect_async(request))
         .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Remote Auth handshake timed out after {:?}",
+                timeouts.ws_handshake
+            )
+        })?
         .context("Failed to connect to Discord Remote Auth")?;
     log::info!("Connected to Remote Auth server");
 
     let (mut write, mut read) = ws_stream.split();
 
-    // Hello メッセージを待機
-    let hello_msg = read
-        .next()
-        .await
-        .context("No hello message received")?
-        .context("WebSocket error")?;
+    // Hello メッセージを待機 (Text 以外の制御フレームは読み飛ばす)
+    let hello_text = loop {
+        let msg = read
+            .next()
+            .await
+            .context("No hello message received")?
+            .context("WebSocket error")?;
+        match msg {
+            Message::Text(text) => break text,
+            Message::Ping(payload) => {
+                write
+                    .send(Message::Pong(payload))
+                    .await
+                    .context("Failed to send pong")?;
+            }
+            Message::Close(frame) => {
+                anyhow::bail!("Remote Auth server closed the connection before hello: {:?}", frame);
+            }
+            other => {
+                log::debug!("Ignoring non-text frame before hello: {:?}", other);
+            }
+        }
+    };
 
     let hello: RemoteAuthMessage =
-        serde_json::from_str(&hello_msg.to_string()).context("Failed to parse hello")?;
+        serde_json::from_str(&hello_text).context("Failed to parse hello")?;
 
     if hello.op != "hello" {
         anyhow::bail!("Expected hello, got: {}", hello.op);
@@ -84,6 +124,15 @@ pub async fn authenticate_with_qr() -> Result<String> {
     let mut heartbeat_timer = tokio::time::interval(tokio::time::Duration::from_millis(heartbeat_interval));
     heartbeat_timer.tick().await; // 最初のtickをスキップ
 
+    // QR の有効期限 (hello の timeout_ms)。期限切れ後はサーバーが切断するので、
+    // 無言で待ち続けないよう手元でも期限を監視する
+    let timeout_ms = hello.data["timeout_ms"].as_u64().unwrap_or(DEFAULT_AUTH_TIMEOUT_MS);
+    log::debug!("Remote auth timeout: {}ms", timeout_ms);
+    let session_deadline = tokio::time::sleep(tokio::time::Duration::from_millis(timeout_ms));
+    tokio::pin!(session_deadline);
+    // 直前のハートビートに ACK が返ってきたか (返らないまま次の送信時刻が来たら接続断とみなす)
+    let mut heartbeat_acked = true;
+
     // RSA鍵ペアを生成（2048ビット）
     log::debug!("Generating RSA key pair...");
     let mut rng = rand::thread_rng();
@@ -114,12 +163,21 @@ pub async fn authenticate_with_qr() -> Result<String> {
         tokio::select! {
             // ハートビート送信
             _ = heartbeat_timer.tick() => {
-                let heartbeat = json!({"op": "heartbeat"}).to_string();
-                if let Err(e) = write.send(Message::Text(heartbeat)).await {
-                    log::error!("Failed to send heartbeat: {}", e);
-                    break;
+                if !heartbeat_acked {
+                    anyhow::bail!(
+                        "Remote Auth server stopped responding (heartbeat not acknowledged). \
+                         Check your network connection and restart to get a new QR code."
+                    );
                 }
-                log::debug!("Sent heartbeat");
+                send_auth_heartbeat(&mut write).await?;
+                heartbeat_acked = false;
+            }
+            // QR の有効期限切れ
+            _ = &mut session_deadline => {
+                anyhow::bail!(
+                    "QR code expired before login was approved ({}s). Restart hakuhyo to get a new QR code.",
+                    timeout_ms / 1000
+                );
             }
             // メッセージ受信
             msg_result = read.next() => {
@@ -129,11 +187,39 @@ pub async fn authenticate_with_qr() -> Result<String> {
                         anyhow::bail!("WebSocket error: {}", e);
                     }
                     None => {
-                        anyhow::bail!("WebSocket connection closed");
+                        anyhow::bail!(
+                            "Remote Auth connection closed unexpectedly. Restart hakuhyo to get a new QR code."
+                        );
+                    }
+                };
+
+                // JSON として解釈するのは Text フレームのみ。制御フレーム/バイナリは個別に処理
+                let text = match msg {
+                    Message::Text(text) => text,
+                    Message::Ping(payload) => {
+                        write
+                            .send(Message::Pong(payload))
+                            .await
+                            .context("Failed to send pong")?;
+                        continue;
+                    }
+                    Message::Close(frame) => {
+                        let reason = frame
+                            .map(|f| format!("{} ({})", f.reason, f.code))
+                            .unwrap_or_else(|| "no reason given".to_string());
+                        anyhow::bail!(
+                            "Remote Auth server closed the connection: {}. Please restart and try again.",
+                            reason
+                        );
+                    }
+                    other => {
+                        log::debug!("Ignoring non-text frame: {:?}", other);
+                        continue;
                     }
                 };
 
-                let data: RemoteAuthMessage = serde_json::from_str(&msg.to_string())?;
+                let data: RemoteAuthMessage = serde_json::from_str(&text)
+                    .with_context(|| format!("Failed to parse Remote Auth message: {}", text))?;
                 log::debug!("Received op: {}", data.op);
 
                 match data.op.as_str() {
@@ -182,13 +268,24 @@ pub async fn authenticate_with_qr() -> Result<String> {
                         println!("\n╔══════════════════════════════════════╗");
                         println!("║      Discord QRコードログイン        ║");
                         println!("╚══════════════════════════════════════╝");
-                        println!("\nモバイルのDiscordアプリで以下のQRコードをスキャンしてください：\n");
 
-                        // QRコードを表示（エラーが発生した場合はURLを表示）
-                        if let Err(e) = print_qr(&qr_url) {
-                            log::warn!("Failed to display QR code: {}. Showing URL instead.", e);
-                            println!("QRコード表示エラー。以下のURLをブラウザで開いてください：");
+                        if qr_options.link_only {
+                            println!("\nDiscordアプリにログイン済みのスマートフォンで以下のURLを開いてください：\n");
                             println!("{}", qr_url);
+                        } else {
+                            println!("\nモバイルのDiscordアプリで以下のQRコードをスキャンしてください：\n");
+
+                            // QRコードを表示（エラーが発生した場合はURLを表示）
+                            if let Err(e) = print_qr(&qr_url) {
+                                log::warn!("Failed to display QR code: {}. Showing URL instead.", e);
+                                println!("QRコード表示エラー。以下のURLをブラウザで開いてください：");
+                                println!("{}", qr_url);
+                            }
+                        }
+
+                        if qr_options.copy_link {
+                            clipboard::copy(&qr_url);
+                            println!("\n(URLをクリップボードにコピーしました)");
                         }
 
                         println!("\n認証を待っています...");
@@ -208,7 +305,7 @@ pub async fn authenticate_with_qr() -> Result<String> {
                         log::debug!("Got ticket, exchanging for token...");
 
                         // トークン取得API呼び出し
-                        let client = reqwest::Client::new();
+                        let client = timeouts.http_client();
                         let token_response = client
                             .post("https://discord.com/api/v9/users/@me/remote-auth/login")
                             .json(&json!({"ticket": ticket}))
@@ -246,8 +343,15 @@ pub async fn authenticate_with_qr() -> Result<String> {
                     "cancel" => {
                         anyhow::bail!("Authentication was cancelled");
                     }
+                    "heartbeat" => {
+                        // サーバーからのハートビート要求には即座に応答し、タイマーも仕切り直す
+                        log::debug!("Server requested heartbeat");
+                        send_auth_heartbeat(&mut write).await?;
+                        heartbeat_acked = false;
+                        heartbeat_timer.reset();
+                    }
                     "heartbeat_ack" => {
-                        // ハートビートACKは無視
+                        heartbeat_acked = true;
                     }
                     _ => {
                         log::debug!("Ignoring unknown op: {}", data.op);
@@ -261,6 +365,11 @@ pub async fn authenticate_with_qr() -> Result<String> {
         }
     }
 
+    // 認証ソケットを正常 close してから抜ける
+    if let Err(e) = write.send(Message::Close(None)).await {
+        log::debug!("Failed to close Remote Auth socket: {}", e);
+    }
+
     if token.is_empty() {
         anyhow::bail!("Failed to get token");
     }
@@ -268,13 +377,74 @@ pub async fn authenticate_with_qr() -> Result<String> {
     Ok(token)
 }
 
+/// Remote Auth のハートビートを 1 回送信
+async fn send_auth_heartbeat<S>(write: &mut S) -> Result<()>
+where
+    S: futures::Sink<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let heartbeat = json!({"op": "heartbeat"}).to_string();
+    write
+        .send(Message::Text(heartbeat))
+        .await
+        .context("Failed to send heartbeat to Remote Auth server (connection lost?)")?;
+    log::debug!("Sent heartbeat");
+    Ok(())
+}
+
+/// トークンの前後の空白・引用符を取り除き、明らかに壊れているものだけを弾く。
+///
+/// 空のもの・空白や base64 と `.` 以外の文字を含むもの (別の値を貼り付けたもの) は
+/// API を呼ぶまでもなく使えないのでエラーにする。区切りの数など形の確認は
+/// [`token_format_warning`] に任せ、ここでは拒否しない (値そのものはログに出さない)
+pub fn normalize_token(raw: &str) -> Result<String> {
+    let token = raw
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .trim()
+        .to_string();
+    log::debug!("Checking token format (length {})", token.len());
+    if token.is_empty() {
+        anyhow::bail!("the token is empty");
+    }
+    let is_base64 = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '/' | '=');
+    if let Some(c) = token.chars().find(|&c| c != '.' && !is_base64(c)) {
+        let kind = if c.is_whitespace() { "whitespace" } else { "unexpected characters" };
+        anyhow::bail!("the token contains {} (length {})", kind, token.len());
+    }
+    Ok(token)
+}
+
+/// 見慣れない形のトークンなら警告文を返す。
+///
+/// Discord のユーザートークンは `base64.base64.base64` の 3 つに分かれた形か、
+/// 古い 2 段階認証アカウントの `mfa.<...>` の形をしている。形式は変わることがあるので
+/// 合わなくても拒否はせず、`/users/@me` で確かめる前の手がかりとしてログに残すだけ
+pub fn token_format_warning(token: &str) -> Option<String> {
+    if let Some(rest) = token.strip_prefix("mfa.") {
+        if !rest.is_empty() {
+            return None;
+        }
+    }
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() == 3 && parts.iter().all(|p| !p.is_empty()) {
+        return None;
+    }
+    Some(format!(
+        "the token does not look like a Discord token: expected 3 dot-separated parts, \
+         got {} (length {}). It may have been truncated when pasting",
+        parts.iter().filter(|p| !p.is_empty()).count(),
+        token.len()
+    ))
+}
+
 /// 保存されたトークンを検証
 ///
 /// Discord APIの `/users/@me` エンドポイントを使用してトークンの有効性を確認
-async fn validate_stored_token(token: &str) -> bool {
+pub async fn validate_stored_token(token: &str, timeouts: &NetworkTimeouts) -> bool {
     log::debug!("Validating stored token...");
 
-    let client = reqwest::Client::new();
+    let client = timeouts.http_client();
     let response = client
         .get("https://discord.com/api/v10/users/@me")
         .header("Authorization", token)
@@ -306,29 +476,57 @@ async fn validate_stored_token(token: &str) -> bool {
 ///
 /// # エラー
 /// - 全ての認証方法が失敗した場合
-pub async fn get_or_authenticate_token() -> Result<String> {
+pub async fn get_or_authenticate_token(
+    profile_dir: &Path,
+    timeouts: &NetworkTimeouts,
+    qr_options: QrOptions,
+) -> Result<String> {
     // 1. キーチェーンから取得を試行
-    if let Ok(token) = tokio::task::spawn_blocking(|| token_store::load_token()).await? {
+    let dir = profile_dir.to_path_buf();
+    if let Ok(stored) = tokio::task::spawn_blocking(move || token_store::load_token(&dir)).await? {
         log::info!("Token found in keyring, validating...");
-        if validate_stored_token(&token).await {
+        // 空や不正な文字を含むものだけは問い合わせずに作り直す。
+        // 見慣れない形 (mfa. トークンや将来の形式) は警告だけ出して API に確かめる
+        let valid = match normalize_token(&stored) {
+            Ok(token) => {
+                if let Some(warning) = token_format_warning(&token) {
+                    log::warn!("Stored token has an unexpected format: {}", warning);
+                }
+                validate_stored_token(&token, timeouts).await.then_some(token)
+            }
+            Err(e) => {
+                log::warn!("Stored token is malformed: {}", e);
+                None
+            }
+        };
+        if let Some(token) = valid {
             return Ok(token);
-        } else {
-            log::warn!("Stored token is invalid, will re-authenticate");
-            // 無効なトークンは削除
-            let _ = tokio::task::spawn_blocking(|| token_store::delete_token()).await;
         }
+        log::warn!("Stored token is invalid, will re-authenticate");
+        // 無効なトークンは削除
+        let dir = profile_dir.to_path_buf();
+        let _ = tokio::task::spawn_blocking(move || token_store::delete_token(&dir)).await;
     } else {
         log::debug!("No token found in keyring");
     }
 
     // 2. QRコード認証を実行
     log::info!("Starting QR code authentication...");
-    let token = authenticate_with_qr().await?;
+    let token = authenticate_with_qr(timeouts, qr_options).await?;
+    // Discord から受け取ったトークンは形式が変わっていても使う (警告だけ残す)
+    let token = normalize_token(&token).unwrap_or_else(|e| {
+        log::warn!("Token from QR login has an unexpected format: {}", e);
+        token
+    });
+    if let Some(warning) = token_format_warning(&token) {
+        log::warn!("Token from QR login has an unexpected format: {}", warning);
+    }
 
     // 3. 取得したトークンをキーチェーンに保存
     let token_clone = token.clone();
+    let dir = profile_dir.to_path_buf();
     tokio::task::spawn_blocking(move || {
-        if let Err(e) = token_store::save_token(&token_clone) {
+        if let Err(e) = token_store::save_token(&dir, &token_clone) {
             log::error!("Failed to save token to keyring: {}", e);
         }
     })
@@ -336,3 +534,39 @@ pub async fn get_or_authenticate_token() -> Result<String> {
 
     Ok(token)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_trimmed_and_gross_errors_rejected() {
+        let token = "MTIzNDU2Nzg5MDEyMzQ1Njc4.GaBcDe.abc-DEF_ghi";
+        assert_eq!(normalize_token(&format!("  \"{}\"\n", token)).unwrap(), token);
+
+        // 空白や別の値を含むもの・空のものは API を呼ぶ前に弾く
+        assert!(normalize_token("MTIz.Ga BcDe.abc").is_err());
+        assert!(normalize_token("Authorization: MTIz.GaBcDe.abc").is_err());
+        assert!(normalize_token("  ").is_err());
+        // エラーメッセージに値そのものは含めない
+        let error = normalize_token("secret part").unwrap_err().to_string();
+        assert!(!error.contains("secret"));
+    }
+
+    #[test]
+    fn unusual_token_shapes_are_only_warned_about() {
+        // 旧形式の mfa. トークンや区切りの数が違うものも拒否はしない
+        let mfa = "mfa.VkO_2G4Qv3T-abcdefghijklmnopqrstuvwxyz0123456789";
+        assert_eq!(normalize_token(mfa).unwrap(), mfa);
+        assert_eq!(token_format_warning(mfa), None);
+        assert_eq!(token_format_warning("MTIzNDU2Nzg5MDEyMzQ1Njc4.GaBcDe.abc-DEF_ghi"), None);
+
+        let truncated = normalize_token("MTIzNDU2Nzg5MDEyMzQ1Njc4.GaBcDe").unwrap();
+        assert!(token_format_warning(&truncated).is_some());
+        assert!(token_format_warning("MTIz.GaBcDe.").is_some());
+        assert!(token_format_warning("mfa.").is_some());
+        // 警告文にも値そのものは含めない
+        let warning = token_format_warning("secretpart.only").unwrap();
+        assert!(!warning.contains("secretpart"));
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use crate::discord::{Channel, DiscordRestClient, Guild};
+
+/// チャンネル指定を解決できなかった理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// 一致するチャンネルが無い
+    NotFound(String),
+    /// 複数のサーバーに同名のチャンネルがある (`サーバー名/チャンネル名` の候補一覧)
+    Ambiguous(Vec<String>),
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::NotFound(query) => write!(f, "No channel matches '{}'", query),
+            ResolveError::Ambiguous(candidates) => write!(
+                f,
+                "Ambiguous channel, qualify it with the server: {}",
+                candidates.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// `#name` / `name` / `サーバー名/チャンネル名` / `@ユーザー名` (DM) / チャンネル ID を
+/// 読み込み済みのチャンネルから探す。名前の比較は大文字小文字を区別しない
+pub fn resolve_channel<'a>(
+    query: &str,
+    channels: &'a HashMap<String, Channel>,
+    guilds: &HashMap<String, Guild>,
+) -> Result<&'a Channel, ResolveError> {
+    let query = query.trim();
+    if let Some(channel) = channels.get(query).filter(|ch| ch.is_messageable()) {
+        return Ok(channel);
+    }
+    let (guild_part, name) = match query.split_once('/') {
+        Some((guild, name)) => (Some(guild), name),
+        None => (None, query),
+    };
+    let dm = name.starts_with('@');
+    let name = name.trim_start_matches(['#', '@']);
+
+    let guild_name = |ch: &Channel| {
+        ch.guild_id
+            .as_ref()
+            .and_then(|id| guilds.get(id))
+            .map(|g| g.name.as_str())
+    };
+    let mut matches: Vec<&Channel> = channels
+        .values()
+        .filter(|ch| ch.is_messageable())
+        .filter(|ch| {
+            // `@name` は DM / グループ DM、それ以外はサーバーのチャンネルから探す
+            if dm {
+                ch.guild_id.is_none()
+            } else {
+                ch.guild_id.is_some()
+            }
+        })
+        .filter(|ch| ch.display_name().eq_ignore_ascii_case(name))
+        .filter(|ch| match guild_part {
+            Some(guild) => {
+                ch.guild_id.as_deref() == Some(guild)
+                    || guild_name(ch).is_some_and(|n| n.eq_ignore_ascii_case(guild))
+            }
+            None => true,
+        })
+        .collect();
+
+    match matches.len() {
+        0 => Err(ResolveError::NotFound(query.to_string())),
+        1 => Ok(matches.remove(0)),
+        _ => {
+            let mut candidates: Vec<String> = matches
+                .iter()
+                .map(|ch| {
+                    let guild = guild_name(ch).unwrap_or("?");
+                    format!("{}/{}", guild, ch.display_name())
+                })
+                .collect();
+            candidates.sort();
+            Err(ResolveError::Ambiguous(candidates))
+        }
+    }
+}
+
+/// REST でチャンネル一覧を取得して `target` を解決し、`content` を送信する (CLI の send 用)。
+/// `サーバー名/チャンネル名` の形ならそのサーバーのチャンネルだけ取得する
+pub async fn send_to_channel(
+    rest: &DiscordRestClient,
+    target: &str,
+    content: &str,
+) -> Result<Channel> {
+    let mut channels: HashMap<String, Channel> = HashMap::new();
+    let mut guilds: HashMap<String, Guild> = HashMap::new();
+
+    for channel in rest.get_dm_channels().await? {
+        channels.insert(channel.id.clone(), channel);
+    }
+    let guild_filter = target.split_once('/').map(|(guild, _)| guild);
+    for guild in rest.get_guilds().await? {
+        if guild_filter.is_some_and(|g| g != guild.id && !g.eq_ignore_ascii_case(&guild.name)) {
+            continue;
+        }
+        let guild_channels = rest
+            .get_guild_channels(&guild.id)
+            .await
+            .with_context(|| format!("Failed to load channels for {}", guild.name))?;
+        channels.extend(guild_channels.into_iter().map(|ch| (ch.id.clone(), ch)));
+        guilds.insert(guild.id.clone(), guild);
+    }
+
+    let channel = resolve_channel(target, &channels, &guilds)?.clone();
+    rest.send_message(&channel.id, content, None, true)
+        .await
+        .with_context(|| format!("Failed to send to {}", channel.display_name()))?;
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixtures() -> (HashMap<String, Channel>, HashMap<String, Guild>) {
+        let channels = [
+            json!({ "id": "1", "type": 0, "name": "general", "guild_id": "10" }),
+            json!({ "id": "2", "type": 0, "name": "general", "guild_id": "20" }),
+            json!({ "id": "3", "type": 0, "name": "random", "guild_id": "10" }),
+            json!({ "id": "4", "type": 4, "name": "Info", "guild_id": "10" }),
+            json!({
+                "id": "5",
+                "type": 1,
+                "recipients": [{ "id": "9", "username": "alice", "discriminator": "0" }],
+            }),
+        ]
+        .into_iter()
+        .map(|v| serde_json::from_value::<Channel>(v).unwrap())
+        .map(|ch| (ch.id.clone(), ch))
+        .collect();
+        let guilds = [json!({ "id": "10", "name": "Dev" }), json!({ "id": "20", "name": "Games" })]
+            .into_iter()
+            .map(|v| serde_json::from_value::<Guild>(v).unwrap())
+            .map(|g| (g.id.clone(), g))
+            .collect();
+        (channels, guilds)
+    }
+
+    #[test]
+    fn resolves_names_ids_and_dms() {
+        let (channels, guilds) = fixtures();
+        let id = |query: &str| resolve_channel(query, &channels, &guilds).map(|ch| ch.id.as_str());
+        assert_eq!(id("#random"), Ok("3"));
+        assert_eq!(id("dev/General"), Ok("1"));
+        assert_eq!(id("20/#general"), Ok("2"));
+        assert_eq!(id("@alice"), Ok("5"));
+        assert_eq!(id("3"), Ok("3"));
+        // カテゴリはメッセージを送れないので対象外
+        assert_eq!(id("info"), Err(ResolveError::NotFound("info".to_string())));
+    }
+
+    #[test]
+    fn same_name_in_several_servers_needs_qualification() {
+        let (channels, guilds) = fixtures();
+        let result = resolve_channel("#general", &channels, &guilds);
+        assert_eq!(
+            result.map(|ch| ch.id.as_str()),
+            Err(ResolveError::Ambiguous(vec![
+                "Dev/general".to_string(),
+                "Games/general".to_string()
+            ]))
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+use crate::export::ExportFormat;
+
+/// コマンドライン引数の解析結果
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    /// サブコマンド (None なら TUI を起動)
+    pub command: Option<CliCommand>,
+    /// 絵文字を使わない ASCII 記号で描画する (`--ascii`)
+    pub ascii: bool,
+    /// 読み取り専用モードで起動する (`--read-only`)
+    pub read_only: bool,
+    /// QR 認証で QR コードを描画せず URL のみ表示する (`--qr-link`)
+    pub qr_link: bool,
+    /// QR 認証のログイン URL をクリップボードへコピーしない (`--no-copy-link`、設定より優先)
+    pub no_copy_link: bool,
+    /// 使用するプロファイル名 (`--profile <name>`、None なら default)
+    pub profile: Option<String>,
+    /// 認証・通信をせず、組み込みのデモデータで TUI を起動する (`--demo` / `HAKUHYO_DEMO=1`)
+    pub demo: bool,
+    /// Gateway に接続せず、REST のポーリングで新着を取得する (`--no-gateway`)
+    pub no_gateway: bool,
+}
+
+/// TUI 以外で実行するサブコマンド
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    /// チャンネル履歴をファイルに書き出す
+    Export {
+        channel_id: String,
+        limit: usize,
+        format: ExportFormat,
+        output: PathBuf,
+    },
+    /// チャンネルを名前で指定してメッセージを送信する
+    Send {
+        /// `#name` / `サーバー名/チャンネル名` / `@ユーザー名` / チャンネル ID
+        target: String,
+        /// 本文 (None なら標準入力から読む)
+        content: Option<String>,
+    },
+    /// 使い方を表示
+    Help,
+}
+
+/// export のデフォルト取得件数
+const DEFAULT_EXPORT_LIMIT: usize = 500;
+
+/// 使い方テキスト
+pub const USAGE: &str = "\
+Usage:
+  hakuhyo [--profile NAME] [--ascii] [--read-only] [--qr-link] [--no-copy-link] [--demo]
+          [--no-gateway]
+                                TUI を起動 (--profile: アカウントごとのトークン・設定を使う,
+                                --ascii: 記号を ASCII のみで描画,
+                                --read-only: 送信・既読化・削除を行わない,
+                                --qr-link: QR 認証で QR の代わりに URL を表示,
+                                --no-copy-link: QR 認証の URL をクリップボードへコピーしない,
+                                --demo: ログインせずデモデータで起動。通信しない,
+                                --no-gateway: WebSocket を使わず REST で新着を定期取得)
+  hakuhyo [--profile NAME] export <channel_id> [--limit N] [--format json|text] [--output PATH]
+                                チャンネル履歴をファイルに書き出す
+  hakuhyo [--profile NAME] send <#channel|server/channel|@user|channel_id> [MESSAGE...]
+                                メッセージを送信 (MESSAGE 省略時は標準入力から読む)
+  hakuhyo --help                このヘルプを表示";
+
+/// `std::env::args()` を解析する
+pub fn parse_args() -> Result<CliArgs> {
+    parse_from(std::env::args().skip(1).collect())
+}
+
+/// 引数列 (プログラム名を除く) を解析する
+fn parse_from(args: Vec<String>) -> Result<CliArgs> {
+    let mut iter = args.into_iter().peekable();
+    let mut ascii = false;
+    let mut read_only = false;
+    let mut qr_link = false;
+    let mut no_copy_link = false;
+    let mut profile: Option<String> = None;
+    let mut demo = std::env::var("HAKUHYO_DEMO").is_ok_and(|v| v == "1");
+    let mut no_gateway = false;
+    // サブコマンドより前のグローバルフラグ
+    while let Some(flag) = iter.next_if(|a| {
+        matches!(
+            a.as_str(),
+            "--ascii"
+                | "--read-only"
+                | "--qr-link"
+                | "--no-copy-link"
+                | "--profile"
+                | "--demo"
+                | "--no-gateway"
+        )
+    }) {
+        match flag.as_str() {
+            "--ascii" => ascii = true,
+            "--read-only" => read_only = true,
+            "--demo" => demo = true,
+            "--no-gateway" => no_gateway = true,
+            "--no-copy-link" => no_copy_link = true,
+            "--profile" => {
+                let v = iter.next().context("--profile requires a name")?;
+                crate::profile::validate_name(&v)?;
+                profile = Some(v);
+            }
+            _ => qr_link = true,
+        }
+    }
+    let Some(first) = iter.next() else {
+        return Ok(CliArgs {
+            command: None,
+            ascii,
+            read_only,
+            qr_link,
+            no_copy_link,
+            profile,
+            demo,
+            no_gateway,
+        });
+    };
+
+    let command = match first.as_str() {
+        "-h" | "--help" | "help" => CliCommand::Help,
+        "export" => {
+            let mut channel_id: Option<String> = None;
+            let mut limit = DEFAULT_EXPORT_LIMIT;
+            let mut format = ExportFormat::Text;
+            let mut output: Option<PathBuf> = None;
+
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "--limit" | "-n" => {
+                        let v = iter.next().context("--limit requires a value")?;
+                        limit = v
+                            .parse()
+                            .with_context(|| format!("Invalid --limit value: {}", v))?;
+                    }
+                    "--format" | "-f" => {
+                        let v = iter.next().context("--format requires a value")?;
+                        format = match v.as_str() {
+                            "json" => ExportFormat::Json,
+                            "text" | "txt" => ExportFormat::Text,
+                            _ => anyhow::bail!("Unknown --format: {} (json|text)", v),
+                        };
+                    }
+                    "--output" | "-o" => {
+                        let v = iter.next().context("--output requires a value")?;
+                        output = Some(PathBuf::from(v));
+                    }
+                    other if other.starts_with('-') => {
+                        anyhow::bail!("Unknown option for export: {}", other)
+                    }
+                    other => {
+                        if channel_id.is_some() {
+                            anyhow::bail!("Unexpected argument: {}", other);
+                        }
+                        channel_id = Some(other.to_string());
+                    }
+                }
+            }
+
+            let channel_id = channel_id.context("export requires <channel_id>")?;
+            // 出力先未指定なら カレントディレクトリに <channel_id>.<ext>
+            let output = output.unwrap_or_else(|| {
+                PathBuf::from(format!("{}.{}", channel_id, format.extension()))
+            });
+            CliCommand::Export {
+                channel_id,
+                limit,
+                format,
+                output,
+            }
+        }
+        "send" => {
+            let target = iter.next().context("send requires a channel")?;
+            let words: Vec<String> = iter.collect();
+            CliCommand::Send {
+                target,
+                content: (!words.is_empty()).then(|| words.join(" ")),
+            }
+        }
+        other => anyhow::bail!("Unknown argument: {}\n\n{}", other, USAGE),
+    };
+
+    Ok(CliArgs {
+        command: Some(command),
+        ascii,
+        read_only,
+        qr_link,
+        no_copy_link,
+        profile,
+        demo,
+        no_gateway,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_copy_link_is_a_global_flag() {
+        let args = |list: &[&str]| parse_from(list.iter().map(|a| a.to_string()).collect());
+        let parsed = args(&["--qr-link", "--no-copy-link"]).unwrap();
+        assert!(parsed.qr_link && parsed.no_copy_link && parsed.command.is_none());
+        assert!(!args(&[]).unwrap().no_copy_link);
+        // サブコマンドより後ろはサブコマンドの引数として扱う
+        let send = args(&["send", "#general", "--no-copy-link"]).unwrap();
+        assert!(!send.no_copy_link);
+        assert!(matches!(
+            send.command,
+            Some(CliCommand::Send { content: Some(c), .. }) if c == "--no-copy-link"
+        ));
+    }
+}
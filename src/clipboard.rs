@@ -0,0 +1,25 @@
+use base64::{engine::general_purpose, Engine as _};
+use std::io::Write;
+
+/// OSC 52 エスケープシーケンスでクリップボードへコピーする
+/// (SSH 越しでも手元の端末のクリップボードに届く。非対応端末では無視される)
+pub fn copy(text: &str) {
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "{}", osc52_sequence(text));
+    let _ = stdout.flush();
+}
+
+/// `text` をクリップボードに置く OSC 52 シーケンス
+fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", general_purpose::STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_sent_base64_encoded() {
+        assert_eq!(osc52_sequence("こんにちは"), "\x1b]52;c;44GT44KT44Gr44Gh44Gv\x07");
+    }
+}
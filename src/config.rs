@@ -1,45 +1,663 @@
+use crate::discord::{Activity, ActivityType, Presence, PresenceStatus, RetryPolicy};
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// アプリケーション設定
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// お気に入りチャンネルID一覧
-    pub favorites: HashSet<String>,
+    /// お気に入りチャンネルID一覧 (favorite_order が manual のときはこの順で表示)
+    pub favorites: Vec<String>,
+    /// チャンネル一覧・お気に入り・検索結果の並び順
+    #[serde(default, deserialize_with = "lenient")]
+    pub channel_order: ChannelOrder,
+    /// お気に入りの並び順 ("sorted": channel_order に従う / "manual": 登録順・手動並べ替え)
+    #[serde(default, deserialize_with = "lenient")]
+    pub favorite_order: FavoriteOrder,
+    /// メッセージ作者・DM の横にアバター画像を表示する (画像プロトコル対応端末のみ、重いので既定 off)
+    #[serde(default)]
+    pub show_avatars: bool,
+    /// サイドバーのサーバー名の前に頭文字のバッジを付ける (show_avatars が有効な端末ではサーバーアイコン)
+    #[serde(default = "default_true")]
+    pub guild_badges: bool,
+    /// チャンネル一覧・検索・未読一覧をテキストチャンネル (と DM・スレッド) だけにする。
+    /// アナウンス・ボイス・フォーラムを隠す (`T` で切り替え。お気に入りは常に表示)
+    #[serde(default)]
+    pub text_channels_only: bool,
+    /// 作者名をユーザーごとの色で表示する (ロールの色があればそちらを優先。false なら緑で統一)
+    #[serde(default = "default_true")]
+    pub author_colors: bool,
+    /// 画像添付のプレビューを自動で取得・表示する (false ならメッセージごとに `v` で表示したときだけ取得)
+    #[serde(default)]
+    pub image_previews: bool,
+    /// 1 メッセージに表示する添付ファイル (画像プレビュー含む) の上限。超えた分は `+N more` に
+    /// まとめる (0 なら制限しない。`E` でチャンネルごとにすべて表示)
+    #[serde(default = "default_max_attachments_per_message")]
+    pub max_attachments_per_message: usize,
+    /// 1 メッセージに表示する埋め込みの上限 (0 なら制限しない。`E` でチャンネルごとにすべて表示)
+    #[serde(default = "default_max_embeds_per_message")]
+    pub max_embeds_per_message: usize,
+    /// 同じ作者の連続メッセージをまとめて表示する (2 件目以降は作者名を省略)
+    #[serde(default)]
+    pub group_messages: bool,
+    /// まとめ表示の対象とする直前メッセージからの経過秒数
+    #[serde(default = "default_group_window_secs")]
+    pub group_window_secs: u64,
+    /// 読み込み済みのメッセージをこの秒数は再取得せずに表示する。
+    /// 過ぎていれば最新のメッセージ以降の差分だけ取得する (0 なら開くたびに全件取得)
+    #[serde(default = "default_message_cache_ttl_secs")]
+    pub message_cache_ttl_secs: u64,
+    /// 日付をまたぐメッセージの間に "── March 3 ──" の区切り線を入れる (日本時間で判定)
+    #[serde(default)]
+    pub date_separators: bool,
+    /// 年齢制限 (NSFW) チャンネルを開いたとき、警告を出さずにそのまま表示する
+    #[serde(default)]
+    pub nsfw_acknowledged: bool,
+    /// 起動時の自動追従 (最下部表示中に新着が来たら最新へ追従する) の初期値
+    #[serde(default = "default_true")]
+    pub auto_follow: bool,
+    /// 時刻を 24 時間表記 (`%H:%M`) で表示する。false なら 12 時間表記 (`%I:%M %p`)
+    #[serde(default = "default_true")]
+    pub clock_24h: bool,
+    /// メッセージ時刻の表示位置 ("inline": 行頭に `[HH:MM]` / "gutter": 左端の固定幅の列 / "hidden": 非表示)
+    #[serde(default, deserialize_with = "lenient")]
+    pub timestamp_layout: TimestampLayout,
+    /// メッセージの表示密度 ("compact": 1 メッセージ 1 行 / "comfortable": 作者名の行の下に本文)
+    #[serde(default, deserialize_with = "lenient")]
+    pub density: Density,
+    /// メッセージの並び順 ("oldest_first": 最新が下 / "newest_first": 最新が上)
+    #[serde(default, deserialize_with = "lenient")]
+    pub message_order: MessageOrder,
+    /// 読み取り専用モード (送信・既読化・削除を行わない)。`--read-only` でも有効化できる
+    #[serde(default)]
+    pub read_only: bool,
+    /// 控えめモード: 送信・リアクション・削除の間隔を空け、一括削除を無効にする
+    /// (自動操作らしい挙動を減らしてアカウントが制限されるリスクを下げる。保証ではない)
+    #[serde(default)]
+    pub conservative_mode: bool,
+    /// Gateway (WebSocket) に接続せず、REST のポーリングで新着を取得する
+    /// (WebSocket を通さないネットワーク向け)。`--no-gateway` でも有効化できる
+    #[serde(default)]
+    pub no_gateway: bool,
+    /// ポーリングで新着を確認する間隔 (秒、5〜300)
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 初回起動時の利用規約リスクの確認を済ませたか
+    #[serde(default)]
+    pub tos_acknowledged: bool,
+    /// REST リクエスト全体のタイムアウト秒数
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// TCP/TLS 接続確立のタイムアウト秒数 (REST)
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// WebSocket (Gateway / QR 認証) ハンドシェイクのタイムアウト秒数
+    #[serde(default = "default_ws_handshake_timeout_secs")]
+    pub ws_handshake_timeout_secs: u64,
+    /// キー入力・REST 応答・画像取得などのイベントキューの容量 (Gateway のイベントは別キュー)
+    #[serde(default = "default_event_queue_capacity")]
+    pub event_queue_capacity: usize,
+    /// 同時に送る REST リクエストの上限 (チャンネル・メッセージの読み込みや送信をまとめて制限する)
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// タイムアウト・接続エラー・5xx のときに REST リクエストを再試行する回数 (0〜10)
+    #[serde(default = "default_request_retries")]
+    pub request_retries: u32,
+    /// 1 回目の再試行までの待ち時間 (ミリ秒。以降は倍々に伸ばす)
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+    /// 再試行の待ち時間の上限 (ミリ秒)
+    #[serde(default = "default_retry_max_delay_ms")]
+    pub retry_max_delay_ms: u64,
+    /// REST リクエストが連続でこの回数失敗したら、しばらくリクエストを止める (0 なら止めない)
+    #[serde(default = "default_circuit_breaker_threshold")]
+    pub circuit_breaker_threshold: u32,
+    /// リクエストを止めておく秒数
+    #[serde(default = "default_circuit_breaker_cooldown_secs")]
+    pub circuit_breaker_cooldown_secs: u64,
+    /// QR 認証時に QR コードを描画せず、ログイン用 URL のみ表示する (SSH 越し等で QR が崩れる場合向け)
+    #[serde(default)]
+    pub qr_link_only: bool,
+    /// QR 認証時にログイン用 URL をクリップボードへコピーする (OSC 52 対応端末のみ)
+    #[serde(default)]
+    pub qr_copy_link: bool,
+    /// 記号を ASCII のみで描画する (`--ascii` と同じ。絵文字が豆腐になる端末向け)
+    #[serde(default)]
+    pub ascii_mode: bool,
+    /// お気に入りマーク (未指定なら "⭐ "、`--ascii` 時は "* ")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favorite_marker: Option<String>,
+    /// リスト選択行の記号 (未指定なら ">> "、`--ascii` 時は "> ")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlight_symbol: Option<String>,
+    /// 接続時のオンライン状態 ("online" / "idle" / "dnd" / "invisible")。`p` キーで切り替えた値も保存される
+    #[serde(default, deserialize_with = "lenient")]
+    pub status: PresenceStatus,
+    /// カスタムステータスの文言
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_status: Option<String>,
+    /// アクティビティ表示 (例: `{"type": "playing", "name": "Vim"}`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<ActivityConfig>,
+    /// 本文に含まれていたら強調表示するキーワード (大文字小文字を区別しない)
+    #[serde(default)]
+    pub highlight_keywords: Vec<String>,
+    /// キーワードを含む新着を、開いていないチャンネルでもメンションと同様に通知する
+    #[serde(default)]
+    pub highlight_notify: bool,
+    /// 送信するメッセージの @everyone / @here / ロールへのメンションで実際に通知するか
+    /// (入力中に Shift+Tab でそのメッセージだけ切り替えられる)
+    #[serde(default = "default_true")]
+    pub allow_mass_mentions: bool,
+    /// フレンドでない相手からの DM の扱い ("show": 通常の DM と同じ / "notify": ステータスバーで
+    /// 知らせる / "request": 未読一覧に "request" の印を付ける / "hide": 未読一覧・検索に出さない)
+    #[serde(default, deserialize_with = "lenient")]
+    pub non_friend_dm: NonFriendDm,
+    /// 起動時に開くチャンネル ("first": お気に入り/一覧の先頭 / "none": 何も開かない /
+    /// "last": 前回最後に開いていたチャンネル / "pinned": `startup_channel_id` のチャンネル)
+    #[serde(default, deserialize_with = "lenient")]
+    pub startup_channel: StartupChannel,
+    /// `startup_channel` が "pinned" のときに開くチャンネル ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_channel_id: Option<String>,
+    /// 前回終了時に開いていたチャンネル ID (`startup_channel` が "last" のときに使う)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_channel: Option<String>,
+    /// channel_id -> そのチャンネルで最後に画面に表示した最新のメッセージ ID。
+    /// 次に開いたときはここを既読位置として、続きから読めるようにスクロールする
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub last_viewed: HashMap<String, String>,
+    /// `q` キーの動作 ("quit": すぐ終了 / "confirm": 確認してから終了 / "disabled": 何もしない)
+    #[serde(default, deserialize_with = "lenient")]
+    pub quit_key: QuitKey,
+    /// 検索中のサイドバー ("hide": 空にする / "show": そのまま表示 / "dim": 淡く表示)
+    #[serde(default, deserialize_with = "lenient")]
+    pub search_sidebar: SearchSidebar,
+    /// 読み込むサーバー (ギルド ID) の一覧。空ならすべて読み込む。参加しているサーバーが
+    /// 多いときに使うものだけに絞ると、起動時の処理とメモリを減らせる (DM は常に読み込む)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guild_allowlist: Vec<String>,
+    /// 最近使ったリアクション (Unicode 絵文字 or `name:id`、新しい順)
+    #[serde(default)]
+    pub recent_reactions: Vec<String>,
+    /// 旧形式 (順序を持たない集合) で保存されたお気に入りを読み込んだか。
+    /// チャンネル情報が揃った時点で表示順に並べ直して順序付きの形式へ移行する
+    #[serde(skip)]
+    pub legacy_favorites: bool,
+}
+
+/// 設定ファイル上のアクティビティ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityConfig {
+    /// "playing" / "streaming" / "listening" / "watching" / "competing"
+    #[serde(rename = "type", default = "default_activity_type")]
+    pub kind: String,
+    pub name: String,
+}
+
+/// チャンネルの並び順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelOrder {
+    /// チャンネル種別 → 名前 (テキスト・DM・ボイス… の順)
+    #[default]
+    Type,
+    /// DM / グループ DM を先に、その後サーバーのチャンネル
+    DmsFirst,
+    /// サーバーのチャンネルを先に、その後 DM / グループ DM
+    GuildsFirst,
+    /// 種別に関係なく名前順
+    Alphabetical,
+}
+
+/// メッセージ時刻の表示位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampLayout {
+    /// 行頭に `[HH:MM] ` を付ける
+    #[default]
+    Inline,
+    /// 左端に時刻の列を確保し、全メッセージの時刻と本文の開始位置を揃える
+    Gutter,
+    /// 時刻を表示しない
+    Hidden,
+}
+
+/// メッセージ一覧の表示密度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Density {
+    /// 1 メッセージ 1 行 (`[時刻] 作者: 本文`)。多くのチャンネルを眺める用
+    #[default]
+    Compact,
+    /// 作者名 (と時刻) の行の下に本文を字下げして表示。じっくり読む用
+    Comfortable,
+}
+
+/// メッセージ一覧の並び順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageOrder {
+    /// 古いものが上、最新が下 (チャット風)
+    #[default]
+    OldestFirst,
+    /// 最新が上、古いものが下 (フィード風)
+    NewestFirst,
+}
+
+/// フレンドでない相手からの DM (メッセージリクエスト) の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonFriendDm {
+    /// 通常の DM と同じく未読一覧に出す
+    #[default]
+    Show,
+    /// 未読一覧に出し、新着をステータスバーで知らせる
+    Notify,
+    /// 未読一覧に "request" の印を付けて出す
+    Request,
+    /// 未読一覧・チャンネル検索に出さない (お気に入りに入れたものは出す)
+    Hide,
+}
+
+/// 起動時に開くチャンネル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupChannel {
+    /// お気に入りの先頭 (無ければチャンネル一覧の先頭)
+    #[default]
+    First,
+    /// 何も開かない (メッセージ取得もしない)
+    None,
+    /// 前回最後に開いていたチャンネル
+    Last,
+    /// `startup_channel_id` で指定したチャンネル
+    Pinned,
+}
+
+/// `q` キーを押したときの動作 (Ctrl+C は常にすぐ終了する)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuitKey {
+    /// すぐに終了する
+    #[default]
+    Quit,
+    /// 確認のオーバーレイを出し、`y` で終了する
+    Confirm,
+    /// 何もしない (打ち間違いで終了しないように)
+    Disabled,
+}
+
+/// 検索オーバーレイを開いている間のサイドバー (お気に入り・未読) の表示
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSidebar {
+    /// 枠だけ残して中身を消す
+    #[default]
+    Hide,
+    /// 普段通り表示する
+    Show,
+    /// 灰色で淡く表示する
+    Dim,
+}
+
+/// お気に入りの並び順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FavoriteOrder {
+    /// channel_order に従って自動で並べる
+    #[default]
+    Sorted,
+    /// 登録順 (`K` / `J` で並べ替えた順)
+    Manual,
+}
+
+/// 不正な値を既定値に置き換えて読み込む (enum の typo で設定ファイル全体が
+/// 読めなくなり、お気に入りが失われるのを防ぐ)
+fn lenient<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned + Default,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+        log::warn!("Invalid config value {}: {}, using default", value, e);
+        T::default()
+    }))
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_activity_type() -> String {
+    "playing".to_string()
+}
+
+fn default_group_window_secs() -> u64 {
+    420
+}
+
+fn default_message_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_request_timeout_secs() -> u64 {
+    10
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_ws_handshake_timeout_secs() -> u64 {
+    15
+}
+
+fn default_event_queue_capacity() -> usize {
+    1024
+}
+
+fn default_max_attachments_per_message() -> usize {
+    3
+}
+
+fn default_max_embeds_per_message() -> usize {
+    1
+}
+
+fn default_max_concurrent_requests() -> usize {
+    DEFAULT_MAX_CONCURRENT_REQUESTS
+}
+
+fn default_request_retries() -> u32 {
+    2
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_retry_max_delay_ms() -> u64 {
+    8000
+}
+
+fn default_circuit_breaker_threshold() -> u32 {
+    5
+}
+
+fn default_circuit_breaker_cooldown_secs() -> u64 {
+    30
+}
+
+fn default_poll_interval_secs() -> u64 {
+    15
+}
+
+/// イベントキュー容量として受け付ける範囲
+const EVENT_QUEUE_CAPACITY_RANGE: std::ops::RangeInclusive<usize> = 16..=65536;
+
+/// 控えめモードで送信・リアクション・削除の間に空ける最小間隔
+const CONSERVATIVE_WRITE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 同時に送る REST リクエスト数の既定の上限 (設定を読む前の REST クライアントにも使う)
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// REST の同時リクエスト数として受け付ける範囲
+const MAX_CONCURRENT_REQUESTS_RANGE: std::ops::RangeInclusive<usize> = 1..=16;
+
+/// REST リクエストの再試行回数として受け付ける上限
+const MAX_REQUEST_RETRIES: u32 = 10;
+
+/// ポーリング間隔として受け付ける秒数の範囲
+const POLL_INTERVAL_SECS_RANGE: std::ops::RangeInclusive<u64> = 5..=300;
+
+/// タイムアウト設定として受け付ける秒数の範囲
+const TIMEOUT_SECS_RANGE: std::ops::RangeInclusive<u64> = 1..=300;
+
+/// ネットワーク関連のタイムアウト (設定値を検証済み)
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkTimeouts {
+    /// REST リクエスト全体
+    pub request: Duration,
+    /// TCP/TLS 接続確立
+    pub connect: Duration,
+    /// WebSocket ハンドシェイク
+    pub ws_handshake: Duration,
+}
+
+impl Default for NetworkTimeouts {
+    fn default() -> Self {
+        Config::default().network_timeouts()
+    }
+}
+
+impl Config {
+    /// 設定中のプレゼンス (IDENTIFY / PRESENCE_UPDATE 用)
+    pub fn presence(&self) -> Presence {
+        Presence {
+            status: self.status,
+            custom_status: self.custom_status.clone(),
+            activity: self.activity(),
+        }
+    }
+
+    /// アクティビティ設定を検証して変換する。種類が不正 / 名前が空なら警告して無視する
+    pub fn activity(&self) -> Option<Activity> {
+        let config = self.activity.as_ref()?;
+        let Some(kind) = ActivityType::from_name(&config.kind) else {
+            log::warn!(
+                "Invalid activity type {:?} (allowed: playing, streaming, listening, watching, competing), ignoring activity",
+                config.kind
+            );
+            return None;
+        };
+        if config.name.trim().is_empty() {
+            log::warn!("Activity name is empty, ignoring activity");
+            return None;
+        }
+        Some(Activity {
+            kind,
+            name: config.name.clone(),
+        })
+    }
+
+    /// タイムアウト設定を検証して Duration に変換する。
+    /// 範囲外 (1〜300 秒) の値は既定値に戻して警告する
+    pub fn network_timeouts(&self) -> NetworkTimeouts {
+        let validate = |name: &str, secs: u64, default: u64| -> Duration {
+            if TIMEOUT_SECS_RANGE.contains(&secs) {
+                Duration::from_secs(secs)
+            } else {
+                log::warn!(
+                    "Invalid {} = {} (allowed: {}..={}), using default {}",
+                    name,
+                    secs,
+                    TIMEOUT_SECS_RANGE.start(),
+                    TIMEOUT_SECS_RANGE.end(),
+                    default
+                );
+                Duration::from_secs(default)
+            }
+        };
+        NetworkTimeouts {
+            request: validate(
+                "request_timeout_secs",
+                self.request_timeout_secs,
+                default_request_timeout_secs(),
+            ),
+            connect: validate(
+                "connect_timeout_secs",
+                self.connect_timeout_secs,
+                default_connect_timeout_secs(),
+            ),
+            ws_handshake: validate(
+                "ws_handshake_timeout_secs",
+                self.ws_handshake_timeout_secs,
+                default_ws_handshake_timeout_secs(),
+            ),
+        }
+    }
+
+    /// イベントキューの容量。範囲外 (16〜65536) の値は既定値に戻して警告する
+    pub fn event_queue_capacity(&self) -> usize {
+        if EVENT_QUEUE_CAPACITY_RANGE.contains(&self.event_queue_capacity) {
+            return self.event_queue_capacity;
+        }
+        log::warn!(
+            "Invalid event_queue_capacity = {} (allowed: {}..={}), using default {}",
+            self.event_queue_capacity,
+            EVENT_QUEUE_CAPACITY_RANGE.start(),
+            EVENT_QUEUE_CAPACITY_RANGE.end(),
+            default_event_queue_capacity()
+        );
+        default_event_queue_capacity()
+    }
+
+    /// 書き込み系のリクエスト (送信・リアクション・削除) の最小間隔 (控えめモードのときのみ)
+    pub fn min_write_interval(&self) -> Option<Duration> {
+        self.conservative_mode.then_some(CONSERVATIVE_WRITE_INTERVAL)
+    }
+
+    /// REST の同時リクエスト数の上限。範囲外 (1〜16) の値は既定値に戻して警告する
+    pub fn max_concurrent_requests(&self) -> usize {
+        if MAX_CONCURRENT_REQUESTS_RANGE.contains(&self.max_concurrent_requests) {
+            return self.max_concurrent_requests;
+        }
+        log::warn!(
+            "Invalid max_concurrent_requests = {} (allowed: {}..={}), using default {}",
+            self.max_concurrent_requests,
+            MAX_CONCURRENT_REQUESTS_RANGE.start(),
+            MAX_CONCURRENT_REQUESTS_RANGE.end(),
+            default_max_concurrent_requests()
+        );
+        default_max_concurrent_requests()
+    }
+
+    /// ポーリングの間隔。範囲外 (5〜300 秒) の値は既定値に戻して警告する
+    pub fn poll_interval(&self) -> Duration {
+        if POLL_INTERVAL_SECS_RANGE.contains(&self.poll_interval_secs) {
+            return Duration::from_secs(self.poll_interval_secs);
+        }
+        log::warn!(
+            "Invalid poll_interval_secs = {} (allowed: {}..={}), using default {}",
+            self.poll_interval_secs,
+            POLL_INTERVAL_SECS_RANGE.start(),
+            POLL_INTERVAL_SECS_RANGE.end(),
+            default_poll_interval_secs()
+        );
+        Duration::from_secs(default_poll_interval_secs())
+    }
+
+    /// REST の再試行とサーキットブレーカーの設定。再試行回数は上限 (10) に丸め、
+    /// 待ち時間の上限が 1 回目より短ければ 1 回目に揃える
+    pub fn retry_policy(&self) -> RetryPolicy {
+        if self.request_retries > MAX_REQUEST_RETRIES {
+            log::warn!(
+                "Invalid request_retries = {} (allowed: 0..={}), using {}",
+                self.request_retries,
+                MAX_REQUEST_RETRIES,
+                MAX_REQUEST_RETRIES
+            );
+        }
+        let base_delay = Duration::from_millis(self.retry_base_delay_ms);
+        RetryPolicy {
+            max_retries: self.request_retries.min(MAX_REQUEST_RETRIES),
+            base_delay,
+            max_delay: Duration::from_millis(self.retry_max_delay_ms).max(base_delay),
+            breaker_threshold: self.circuit_breaker_threshold,
+            breaker_cooldown: Duration::from_secs(self.circuit_breaker_cooldown_secs.max(1)),
+        }
+    }
+}
+
+impl NetworkTimeouts {
+    /// タイムアウトを適用した HTTP クライアントを作成
+    pub fn http_client(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(self.request)
+            .connect_timeout(self.connect)
+            .build()
+            .expect("Failed to create HTTP client")
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            favorites: HashSet::new(),
+            favorites: Vec::new(),
+            channel_order: ChannelOrder::default(),
+            favorite_order: FavoriteOrder::default(),
+            show_avatars: false,
+            guild_badges: true,
+            author_colors: true,
+            text_channels_only: false,
+            image_previews: false,
+            max_attachments_per_message: default_max_attachments_per_message(),
+            max_embeds_per_message: default_max_embeds_per_message(),
+            group_messages: false,
+            group_window_secs: default_group_window_secs(),
+            message_cache_ttl_secs: default_message_cache_ttl_secs(),
+            nsfw_acknowledged: false,
+            date_separators: false,
+            auto_follow: true,
+            clock_24h: true,
+            timestamp_layout: TimestampLayout::default(),
+            density: Density::default(),
+            message_order: MessageOrder::default(),
+            read_only: false,
+            conservative_mode: false,
+            no_gateway: false,
+            poll_interval_secs: default_poll_interval_secs(),
+            tos_acknowledged: false,
+            request_timeout_secs: default_request_timeout_secs(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            ws_handshake_timeout_secs: default_ws_handshake_timeout_secs(),
+            event_queue_capacity: default_event_queue_capacity(),
+            max_concurrent_requests: default_max_concurrent_requests(),
+            request_retries: default_request_retries(),
+            retry_base_delay_ms: default_retry_base_delay_ms(),
+            retry_max_delay_ms: default_retry_max_delay_ms(),
+            circuit_breaker_threshold: default_circuit_breaker_threshold(),
+            circuit_breaker_cooldown_secs: default_circuit_breaker_cooldown_secs(),
+            qr_link_only: false,
+            qr_copy_link: false,
+            favorite_marker: None,
+            highlight_symbol: None,
+            ascii_mode: false,
+            status: PresenceStatus::default(),
+            custom_status: None,
+            activity: None,
+            highlight_keywords: Vec::new(),
+            guild_allowlist: Vec::new(),
+            highlight_notify: false,
+            allow_mass_mentions: true,
+            non_friend_dm: NonFriendDm::default(),
+            startup_channel: StartupChannel::default(),
+            startup_channel_id: None,
+            last_channel: None,
+            last_viewed: HashMap::new(),
+            quit_key: QuitKey::default(),
+            search_sidebar: SearchSidebar::default(),
+            recent_reactions: Vec::new(),
+            legacy_favorites: false,
         }
     }
 }
 
 /// 設定ファイルのパスを取得
 ///
-/// `~/.config/hakuhyo/favorites.json`
-fn get_config_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .context("Failed to get config directory")?
-        .join("hakuhyo");
-
-    // ディレクトリが存在しない場合は作成
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir)
-            .context("Failed to create config directory")?;
-        log::info!("Created config directory: {:?}", config_dir);
-    }
-
-    Ok(config_dir.join("favorites.json"))
+/// `<プロファイルのディレクトリ>/favorites.json`
+fn get_config_path(dir: &Path) -> PathBuf {
+    dir.join("favorites.json")
 }
 
 /// 設定ファイルを読み込み
-pub fn load_config() -> Result<Config> {
-    let config_path = get_config_path()?;
+pub fn load_config(dir: &Path) -> Result<Config> {
+    let config_path = get_config_path(dir);
 
     if !config_path.exists() {
         log::info!("Config file not found, using default config");
@@ -49,8 +667,15 @@ pub fn load_config() -> Result<Config> {
     let content = fs::read_to_string(&config_path)
         .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
 
-    let config: Config = serde_json::from_str(&content)
+    let raw: serde_json::Value = serde_json::from_str(&content)
         .with_context(|| format!("Failed to parse config file: {:?}", config_path))?;
+    let mut config: Config = serde_json::from_value(raw.clone())
+        .with_context(|| format!("Failed to parse config file: {:?}", config_path))?;
+    // favorite_order が無いのは集合 (HashSet) で保存していた旧バージョンのファイル
+    if raw.get("favorite_order").is_none() && !config.favorites.is_empty() {
+        log::info!("Favorites were saved in the legacy unordered format, migrating");
+        config.legacy_favorites = true;
+    }
 
     log::info!("✓ Loaded config from {:?}", config_path);
     log::debug!("Favorites count: {}", config.favorites.len());
@@ -59,8 +684,8 @@ pub fn load_config() -> Result<Config> {
 }
 
 /// 設定ファイルに保存
-pub fn save_config(config: &Config) -> Result<()> {
-    let config_path = get_config_path()?;
+pub fn save_config(dir: &Path, config: &Config) -> Result<()> {
+    let config_path = get_config_path(dir);
 
     let content = serde_json::to_string_pretty(config)
         .context("Failed to serialize config")?;
@@ -73,3 +698,55 @@ pub fn save_config(config: &Config) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_interval_outside_the_range_falls_back_to_the_default() {
+        let with_interval = |secs| Config {
+            poll_interval_secs: secs,
+            ..Config::default()
+        };
+        assert_eq!(with_interval(30).poll_interval(), Duration::from_secs(30));
+        assert_eq!(with_interval(5).poll_interval(), Duration::from_secs(5));
+        let default = Duration::from_secs(default_poll_interval_secs());
+        for secs in [0, 4, 301] {
+            assert_eq!(with_interval(secs).poll_interval(), default);
+        }
+    }
+
+    #[test]
+    fn event_queue_capacity_outside_the_range_falls_back_to_the_default() {
+        let with_capacity = |capacity| Config {
+            event_queue_capacity: capacity,
+            ..Config::default()
+        };
+        assert_eq!(with_capacity(16).event_queue_capacity(), 16);
+        assert_eq!(with_capacity(65536).event_queue_capacity(), 65536);
+        let default = default_event_queue_capacity();
+        for capacity in [0, 15, 65537] {
+            assert_eq!(with_capacity(capacity).event_queue_capacity(), default);
+        }
+    }
+
+    #[test]
+    fn activity_with_an_unknown_type_or_empty_name_is_ignored() {
+        let with_activity = |kind: &str, name: &str| Config {
+            activity: Some(ActivityConfig {
+                kind: kind.to_string(),
+                name: name.to_string(),
+            }),
+            ..Config::default()
+        };
+        let expected = Activity {
+            kind: ActivityType::Listening,
+            name: "lofi".to_string(),
+        };
+        assert_eq!(with_activity("Listening", "lofi").activity(), Some(expected));
+        assert_eq!(with_activity("dancing", "lofi").activity(), None);
+        assert_eq!(with_activity("playing", "  ").activity(), None);
+        assert_eq!(Config::default().activity(), None);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::discord::{Channel, DiscordApi, DiscordError, Guild, Message, MessageReply, User};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// デモのサーバー / チャンネル / ユーザーの ID
+const GUILD_ID: &str = "10";
+const GENERAL_ID: &str = "11";
+const RANDOM_ID: &str = "12";
+const NEWS_ID: &str = "13";
+const LOUNGE_ID: &str = "14";
+const DM_ID: &str = "20";
+const ME_ID: &str = "1";
+
+/// Discord の snowflake の基準時刻 (2015-01-01T00:00:00Z のミリ秒)
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// (ID, ユーザー名, 表示名)
+const USERS: [(&str, &str, &str); 4] = [
+    (ME_ID, "demo", "Demo User"),
+    ("2", "alice", "Alice"),
+    ("3", "bob", "Bob"),
+    ("4", "carol", "Carol"),
+];
+
+/// (チャンネル ID, 作者 ID, 何分前か, 本文)。チャンネルごとに古い順
+const MESSAGES: &[(&str, &str, i64, &str)] = &[
+    (GENERAL_ID, "2", 95, "おはようございます！hakuhyo のデモへようこそ"),
+    (GENERAL_ID, "3", 90, "Discord をターミナルから読み書きできる TUI クライアントです"),
+    (GENERAL_ID, "2", 60, "リポジトリはこちら: https://github.com/void2610/hakuhyo"),
+    (GENERAL_ID, ME_ID, 58, "ありがとう、見てみます"),
+    (GENERAL_ID, "4", 30, "キーボードだけで操作できるのがいいですね"),
+    (GENERAL_ID, "3", 5, "/ でチャンネル検索、i で入力モード、Enter で送信です"),
+    (RANDOM_ID, "4", 200, "ランチどこ行く？"),
+    (RANDOM_ID, "2", 190, "ラーメンで 🍜"),
+    (NEWS_ID, "3", 1440, "v1.0 をリリースしました 🎉"),
+    (DM_ID, "2", 15, "デモ見てる？"),
+    (DM_ID, ME_ID, 14, "見てるよ"),
+];
+
+/// `at` に作られた `seq` 番目のものとしての snowflake ID
+fn snowflake(at: DateTime<Utc>, seq: u64) -> String {
+    let ms = (at.timestamp_millis() - DISCORD_EPOCH_MS).max(0) as u64;
+    ((ms << 22) | (seq & 0xfff)).to_string()
+}
+
+fn user_json(id: &str) -> Value {
+    let (id, username, global_name) = USERS
+        .iter()
+        .find(|(user_id, _, _)| *user_id == id)
+        .copied()
+        .unwrap_or((id, "unknown", "Unknown"));
+    json!({ "id": id, "username": username, "global_name": global_name, "discriminator": "0" })
+}
+
+fn message_json(
+    id: String,
+    channel_id: &str,
+    author_id: &str,
+    at: DateTime<Utc>,
+    content: &str,
+) -> Value {
+    json!({
+        "id": id,
+        "channel_id": channel_id,
+        "author": user_json(author_id),
+        "content": content,
+        "timestamp": at.to_rfc3339(),
+    })
+}
+
+/// フィクスチャのメッセージをチャンネルごとに新しい順で返す (REST の応答と同じ順)
+fn fixture_messages(now: DateTime<Utc>) -> HashMap<String, Vec<Message>> {
+    let mut messages: HashMap<String, Vec<Message>> = HashMap::new();
+    for (seq, (channel_id, author_id, minutes_ago, content)) in MESSAGES.iter().enumerate() {
+        let at = now - Duration::minutes(*minutes_ago);
+        let value = message_json(snowflake(at, seq as u64), channel_id, author_id, at, content);
+        match serde_json::from_value::<Message>(value) {
+            Ok(message) => messages.entry(channel_id.to_string()).or_default().insert(0, message),
+            Err(e) => log::warn!("Invalid demo message fixture: {}", e),
+        }
+    }
+    messages
+}
+
+/// デモ用の READY (サーバー 1 つ・DM 1 つ)。#general は途中まで既読にしてあり、
+/// 開くと未読の境目が出る
+pub fn ready_payload() -> Value {
+    let messages = fixture_messages(Utc::now());
+    let last_id = |channel_id: &str| {
+        messages
+            .get(channel_id)
+            .and_then(|list| list.first())
+            .map(|m| m.id.clone())
+    };
+    let general_read = messages.get(GENERAL_ID).and_then(|list| list.get(2)).map(|m| m.id.clone());
+    json!({
+        "session_id": "demo",
+        "user": user_json(ME_ID),
+        "users": USERS.iter().map(|(id, _, _)| user_json(id)).collect::<Vec<_>>(),
+        "guilds": [{
+            "id": GUILD_ID,
+            "properties": { "name": "Hakuhyo Demo", "owner_id": ME_ID },
+            "channels": [
+                { "id": GENERAL_ID, "type": 0, "name": "general", "position": 0,
+                  "last_message_id": last_id(GENERAL_ID) },
+                { "id": RANDOM_ID, "type": 0, "name": "random", "position": 1,
+                  "last_message_id": last_id(RANDOM_ID) },
+                { "id": NEWS_ID, "type": 5, "name": "announcements", "position": 2,
+                  "last_message_id": last_id(NEWS_ID) },
+                { "id": LOUNGE_ID, "type": 2, "name": "lounge", "position": 3 },
+            ],
+        }],
+        "private_channels": [{
+            "id": DM_ID,
+            "type": 1,
+            "recipient_ids": ["2"],
+            "last_message_id": last_id(DM_ID),
+        }],
+        "read_state": { "entries": [
+            { "id": GENERAL_ID, "last_message_id": general_read },
+            { "id": RANDOM_ID, "last_message_id": last_id(RANDOM_ID) },
+            { "id": DM_ID, "last_message_id": last_id(DM_ID) },
+        ] },
+    })
+}
+
+/// ネットワークを使わない `DiscordApi` (`--demo` 用)。フィクスチャのメッセージを返し、
+/// 送信・削除はメモリ上のメッセージに反映する
+pub struct DemoApi {
+    /// channel_id -> メッセージ (新しい順)
+    messages: Mutex<HashMap<String, Vec<Message>>>,
+    /// 送信したメッセージの ID の通し番号
+    next_seq: AtomicU64,
+}
+
+impl Default for DemoApi {
+    fn default() -> Self {
+        Self {
+            messages: Mutex::new(fixture_messages(Utc::now())),
+            next_seq: AtomicU64::new(MESSAGES.len() as u64),
+        }
+    }
+}
+
+impl DemoApi {
+    fn channel_messages(&self, channel_id: &str) -> Vec<Message> {
+        let messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        messages.get(channel_id).cloned().unwrap_or_default()
+    }
+}
+
+impl DiscordApi for DemoApi {
+    fn get_messages<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        before: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        let messages = self.channel_messages(channel_id);
+        let start = before
+            .and_then(|b| messages.iter().position(|m| m.id == b))
+            .map_or(0, |i| i + 1);
+        let page = messages.into_iter().skip(start).take(limit as usize).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn get_messages_after<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        after: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        let messages = self.channel_messages(channel_id);
+        let end = messages.iter().position(|m| m.id == after).unwrap_or(messages.len());
+        let page = messages.into_iter().take(end.min(limit as usize)).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn get_messages_around<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        around: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        let messages = self.channel_messages(channel_id);
+        let center = messages.iter().position(|m| m.id == around).unwrap_or(0);
+        let start = center.saturating_sub(limit as usize / 2);
+        let page = messages.into_iter().skip(start).take(limit as usize).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn send_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        content: &'a str,
+        _reply_to: Option<&'a MessageReply>,
+        _mass_mentions: bool,
+    ) -> BoxFuture<'a, Result<Message, DiscordError>> {
+        let now = Utc::now();
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let value = message_json(snowflake(now, seq), channel_id, ME_ID, now, content);
+        let result = serde_json::from_value::<Message>(value)
+            .map_err(DiscordError::from);
+        if let Ok(message) = &result {
+            let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+            messages.entry(channel_id.to_string()).or_default().insert(0, message.clone());
+        }
+        Box::pin(async move { result })
+    }
+
+    fn edit_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        content: &'a str,
+    ) -> BoxFuture<'a, Result<Message, DiscordError>> {
+        let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        let edited = messages
+            .get_mut(channel_id)
+            .and_then(|list| list.iter_mut().find(|m| m.id == message_id))
+            .map(|message| {
+                message.content = content.to_string();
+                message.edited_timestamp = Some(Utc::now().to_rfc3339());
+                message.clone()
+            })
+            .ok_or(DiscordError::Http {
+                status: 404,
+                body: "Unknown Message".to_string(),
+            });
+        Box::pin(async move { edited })
+    }
+
+    fn delete_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        let mut messages = self.messages.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(list) = messages.get_mut(channel_id) {
+            list.retain(|m| m.id != message_id);
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn add_reaction<'a>(
+        &'a self,
+        _channel_id: &'a str,
+        _message_id: &'a str,
+        _emoji: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn ack_message<'a>(
+        &'a self,
+        _channel_id: &'a str,
+        _message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn refresh_attachment_urls<'a>(
+        &'a self,
+        urls: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, DiscordError>> {
+        let unchanged = urls.iter().map(|u| (u.clone(), u.clone())).collect();
+        Box::pin(async move { Ok(unchanged) })
+    }
+
+    // チャンネル一覧は READY で渡すので、REST での取り直し (F5) では何も足さない
+    fn get_guilds(&self) -> BoxFuture<'_, Result<Vec<Guild>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_guild_channels<'a>(
+        &'a self,
+        _guild_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_active_threads<'a>(
+        &'a self,
+        _guild_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_user<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, Result<User, DiscordError>> {
+        let user = serde_json::from_value(user_json(user_id)).map_err(DiscordError::from);
+        Box::pin(async move { user })
+    }
+
+    fn get_dm_channels(&self) -> BoxFuture<'_, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_channel<'a>(&'a self, channel_id: &'a str) -> BoxFuture<'a, Result<Channel, DiscordError>> {
+        let error = DiscordError::Http {
+            status: 404,
+            body: format!("Unknown channel {}", channel_id),
+        };
+        Box::pin(async move { Err(error) })
+    }
+
+    fn get_current_user(&self) -> BoxFuture<'_, Result<User, DiscordError>> {
+        let user = serde_json::from_value(user_json(ME_ID))
+            .map_err(DiscordError::from);
+        Box::pin(async move { user })
+    }
+
+    fn get_gateway_url(&self) -> BoxFuture<'_, Result<String, DiscordError>> {
+        Box::pin(async { Ok(String::new()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::AppState;
+    use crate::events::AppEvent;
+
+    #[test]
+    fn demo_ready_populates_the_app_without_network() {
+        let mut app = AppState::new();
+        app.update(AppEvent::GatewayReady(ready_payload()));
+        assert!(app.discord.connected);
+        assert_eq!(app.discord.guilds[GUILD_ID].name, "Hakuhyo Demo");
+        assert_eq!(app.discord.channels[DM_ID].display_name(), "alice");
+        // フィクスチャのメッセージは REST と同じく新しい順で、チャンネルの最新 ID と揃っている
+        let messages = fixture_messages(Utc::now());
+        let general = &messages[GENERAL_ID];
+        assert!(general.windows(2).all(|pair| pair[0].id > pair[1].id));
+        assert_eq!(general.len(), 6);
+    }
+}
@@ -0,0 +1,220 @@
+use super::models::{Channel, Guild, Message, MessageReply, User};
+use super::error::Result;
+use super::rest::DiscordRestClient;
+use futures::future::BoxFuture;
+
+/// コマンド処理から使う Discord REST API。
+/// 実装は `DiscordRestClient` だが、テストではネットワークを使わない実装に差し替えられる
+pub trait DiscordApi: Send + Sync {
+    /// チャンネルのメッセージを取得 (`before` より古いもの)
+    fn get_messages<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        before: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<Message>>>;
+
+    /// `after` より新しいメッセージを取得
+    fn get_messages_after<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        after: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>>>;
+
+    /// `around` の前後のメッセージを取得
+    fn get_messages_around<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        around: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>>>;
+
+    /// メッセージを送信 (`reply_to` があれば返信。`mass_mentions` が false なら
+    /// @everyone / @here / ロールへのメンションで通知しない)
+    fn send_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        content: &'a str,
+        reply_to: Option<&'a MessageReply>,
+        mass_mentions: bool,
+    ) -> BoxFuture<'a, Result<Message>>;
+
+    /// 自分のメッセージの本文を編集
+    fn edit_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        content: &'a str,
+    ) -> BoxFuture<'a, Result<Message>>;
+
+    /// メッセージを削除
+    fn delete_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// リアクションを付ける
+    fn add_reaction<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        emoji: &'a str,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// チャンネルを既読にする
+    fn ack_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// 期限切れの署名付き添付 URL を再署名する ((元の URL, 新しい URL) の組)
+    fn refresh_attachment_urls<'a>(
+        &'a self,
+        urls: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>>;
+
+    /// 参加しているギルド一覧
+    fn get_guilds(&self) -> BoxFuture<'_, Result<Vec<Guild>>>;
+
+    /// ギルドのチャンネル一覧
+    fn get_guild_channels<'a>(&'a self, guild_id: &'a str) -> BoxFuture<'a, Result<Vec<Channel>>>;
+
+    /// ギルド内のアクティブなスレッド
+    fn get_active_threads<'a>(&'a self, guild_id: &'a str) -> BoxFuture<'a, Result<Vec<Channel>>>;
+
+    /// ユーザー情報
+    fn get_user<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, Result<User>>;
+
+    /// DM / グループ DM 一覧
+    fn get_dm_channels(&self) -> BoxFuture<'_, Result<Vec<Channel>>>;
+
+    /// チャンネルを 1 つ取得 (一覧に無いチャンネルへの新着の解決用)
+    fn get_channel<'a>(&'a self, channel_id: &'a str) -> BoxFuture<'a, Result<Channel>>;
+
+    /// ログイン中のユーザー (トークンの有効性確認用)
+    fn get_current_user(&self) -> BoxFuture<'_, Result<User>>;
+
+    /// Gateway の URL
+    fn get_gateway_url(&self) -> BoxFuture<'_, Result<String>>;
+}
+
+impl DiscordApi for DiscordRestClient {
+    fn get_messages<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        before: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<Message>>> {
+        Box::pin(DiscordRestClient::get_messages(self, channel_id, limit, before))
+    }
+
+    fn get_messages_after<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        after: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>>> {
+        Box::pin(DiscordRestClient::get_messages_after(self, channel_id, limit, after))
+    }
+
+    fn get_messages_around<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        around: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>>> {
+        Box::pin(DiscordRestClient::get_messages_around(self, channel_id, limit, around))
+    }
+
+    fn send_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        content: &'a str,
+        reply_to: Option<&'a MessageReply>,
+        mass_mentions: bool,
+    ) -> BoxFuture<'a, Result<Message>> {
+        Box::pin(DiscordRestClient::send_message(
+            self,
+            channel_id,
+            content,
+            reply_to,
+            mass_mentions,
+        ))
+    }
+
+    fn edit_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        content: &'a str,
+    ) -> BoxFuture<'a, Result<Message>> {
+        Box::pin(DiscordRestClient::edit_message(self, channel_id, message_id, content))
+    }
+
+    fn delete_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordRestClient::delete_message(self, channel_id, message_id))
+    }
+
+    fn add_reaction<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        emoji: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordRestClient::add_reaction(self, channel_id, message_id, emoji))
+    }
+
+    fn ack_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(DiscordRestClient::ack_message(self, channel_id, message_id))
+    }
+
+    fn refresh_attachment_urls<'a>(
+        &'a self,
+        urls: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>>> {
+        Box::pin(DiscordRestClient::refresh_attachment_urls(self, urls))
+    }
+
+    fn get_guilds(&self) -> BoxFuture<'_, Result<Vec<Guild>>> {
+        Box::pin(DiscordRestClient::get_guilds(self))
+    }
+
+    fn get_guild_channels<'a>(&'a self, guild_id: &'a str) -> BoxFuture<'a, Result<Vec<Channel>>> {
+        Box::pin(DiscordRestClient::get_guild_channels(self, guild_id))
+    }
+
+    fn get_active_threads<'a>(&'a self, guild_id: &'a str) -> BoxFuture<'a, Result<Vec<Channel>>> {
+        Box::pin(DiscordRestClient::get_active_threads(self, guild_id))
+    }
+
+    fn get_user<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, Result<User>> {
+        Box::pin(DiscordRestClient::get_user(self, user_id))
+    }
+
+    fn get_dm_channels(&self) -> BoxFuture<'_, Result<Vec<Channel>>> {
+        Box::pin(DiscordRestClient::get_dm_channels(self))
+    }
+
+    fn get_channel<'a>(&'a self, channel_id: &'a str) -> BoxFuture<'a, Result<Channel>> {
+        Box::pin(DiscordRestClient::get_channel(self, channel_id))
+    }
+
+    fn get_current_user(&self) -> BoxFuture<'_, Result<User>> {
+        Box::pin(DiscordRestClient::get_current_user(self))
+    }
+
+    fn get_gateway_url(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(DiscordRestClient::get_gateway_url(self))
+    }
+}
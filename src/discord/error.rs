@@ -0,0 +1,113 @@
+use std::time::Duration;
+use thiserror::Error;
+
+/// discord モジュール (REST / Gateway) のエラー。呼び出し側で原因を区別できるようにする
+/// (例: `Unauthorized` なら再ログインを促す、`RateLimited` なら待って再送する)
+#[derive(Debug, Error)]
+pub enum DiscordError {
+    /// HTTP 応答エラー (401 / 429 以外の 4xx / 5xx)
+    #[error("HTTP {status} - {body}")]
+    Http { status: u16, body: String },
+    /// 429。`body` は Discord の応答 (`retry_after` と slowmode のエラーコードを含む)
+    #[error("rate limited - {body}")]
+    RateLimited { body: String },
+    /// 401 / Gateway の 4004。トークンが無効か失効している
+    #[error("the token was rejected by Discord")]
+    Unauthorized,
+    /// 送信失敗やネットワークエラー等
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+    /// 失敗が続いたため、サーキットブレーカーでリクエストを止めている
+    #[error(
+        "Discord appears to be down, pausing requests for {}s",
+        retry_in.as_secs().max(1)
+    )]
+    Unavailable { retry_in: Duration },
+    /// Gateway の接続・ハンドシェイクの失敗
+    #[error("gateway error: {0}")]
+    Gateway(String),
+    /// 応答の JSON を解釈できない
+    #[error("failed to parse response: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// リクエスト URL を組み立てられない
+    #[error("invalid request URL: {0}")]
+    InvalidUrl(String),
+}
+
+pub type Result<T> = std::result::Result<T, DiscordError>;
+
+/// slowmode によるレート制限を表す Discord のエラーコード
+const SLOWMODE_ERROR_CODE: u64 = 20016;
+
+impl DiscordError {
+    /// 成功以外の HTTP 応答を原因ごとのエラーにする
+    pub fn from_status(status: u16, body: String) -> Self {
+        match status {
+            401 => DiscordError::Unauthorized,
+            429 => DiscordError::RateLimited { body },
+            _ => DiscordError::Http { status, body },
+        }
+    }
+
+    /// 再試行しても結果が変わらない失敗か (429 を除く 4xx)
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            DiscordError::Unauthorized => true,
+            DiscordError::Http { status, .. } => (400..500).contains(status),
+            _ => false,
+        }
+    }
+
+    /// 429 の場合、応答 body の `retry_after` (秒) を返す
+    pub fn retry_after(&self) -> Option<f64> {
+        match self {
+            DiscordError::RateLimited { body } => Some(parse_retry_after(body).unwrap_or(1.0)),
+            _ => None,
+        }
+    }
+
+    /// slowmode (チャンネルの rate_limit_per_user) による 429 か
+    pub fn is_slowmode(&self) -> bool {
+        match self {
+            DiscordError::RateLimited { body } => {
+                serde_json::from_str::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v.get("code").and_then(|c| c.as_u64()))
+                    == Some(SLOWMODE_ERROR_CODE)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 429 応答 body から `retry_after` (秒) を取り出す
+fn parse_retry_after(body: &str) -> Option<f64> {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("retry_after").and_then(|r| r.as_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_map_to_the_cause() {
+        assert!(matches!(DiscordError::from_status(401, String::new()), DiscordError::Unauthorized));
+        let not_found = DiscordError::from_status(404, "Unknown Channel".to_string());
+        assert!(matches!(not_found, DiscordError::Http { status: 404, .. }));
+        assert!(not_found.is_permanent());
+        assert!(DiscordError::Unauthorized.is_permanent());
+        assert!(!DiscordError::from_status(503, String::new()).is_permanent());
+
+        // 429 は恒久的なエラーではなく、待ち時間と slowmode かどうかを取り出せる
+        let body = r#"{"retry_after": 2.5, "code": 20016}"#.to_string();
+        let slowmode = DiscordError::from_status(429, body);
+        assert!(!slowmode.is_permanent());
+        assert_eq!(slowmode.retry_after(), Some(2.5));
+        assert!(slowmode.is_slowmode());
+        let global = DiscordError::from_status(429, "rate limited".into());
+        assert_eq!(global.retry_after(), Some(1.0));
+        assert!(!global.is_slowmode());
+    }
+}
@@ -1,26 +1,53 @@
+use super::error::{DiscordError, Result};
 use super::models::{self, *};
-use anyhow::{Context, Result};
-use futures::stream::{SplitSink, SplitStream};
-use futures::{SinkExt, StreamExt};
+use futures::stream::SplitStream;
+use futures::{Sink, SinkExt, StreamExt};
 use serde_json::json;
 use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
 use tokio::time::{interval, Duration};
 use tokio_tungstenite::{
-    connect_async, tungstenite::protocol::Message as WsMessage, MaybeTlsStream, WebSocketStream,
+    connect_async,
+    tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message as WsMessage},
+    MaybeTlsStream, WebSocketStream,
 };
 
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
-type WsWrite = SplitSink<WsStream, WsMessage>;
 type WsRead = SplitStream<WsStream>;
 
+/// 接続失敗時の再試行間隔の初期値
+const BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+/// 接続失敗時の再試行間隔の上限
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// 一度も接続できていない状態で諦めるまでの連続失敗回数
+pub const INITIAL_CONNECT_MAX_ATTEMPTS: u32 = 6;
+/// トークンが無効なときに Gateway が接続を閉じる close code
+const AUTHENTICATION_FAILED: u16 = 4004;
+
+/// `attempt` 回目 (1 始まり) の失敗後に待つ時間 (指数バックオフ、上限 BACKOFF_MAX)
+pub fn backoff_delay(attempt: u32) -> Duration {
+    let factor = 1u32 << attempt.saturating_sub(1).min(16);
+    BACKOFF_INITIAL.saturating_mul(factor).min(BACKOFF_MAX)
+}
+
+/// GatewaySender から書き込みタスクへ渡す送信要求
+enum Outgoing {
+    Payload(serde_json::Value),
+    /// サーバーから要求された (op 1) ハートビートをタイマーを待たずに送る
+    Heartbeat,
+    /// Close フレーム (1000) を送って接続を終える
+    Close,
+}
+
 /// 切断後の再接続方針
 enum ConnectionOutcome {
     /// 同一セッションで再接続（RESUME を試みる）
     Reconnect,
     /// セッション無効。resumable=false なら再 IDENTIFY
     InvalidSession { resumable: bool },
+    /// トークンが拒否された (4004)。再接続しても同じなので諦める
+    AuthenticationFailed,
 }
 
 /// メッセージ処理結果
@@ -31,6 +58,69 @@ enum MessageResult {
     Ignore,
 }
 
+/// 接続中の Gateway へペイロードを送るためのハンドル。
+/// `GatewayClient::run()` は self を消費するため、run 前に `sender()` で取得しておく
+#[derive(Clone)]
+pub struct GatewaySender {
+    presence: Arc<RwLock<Presence>>,
+    tx: mpsc::UnboundedSender<Outgoing>,
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl GatewaySender {
+    /// プレゼンスを変更する (PRESENCE_UPDATE, op 3)。
+    /// 再接続時の IDENTIFY にも反映されるよう保持値も更新する
+    pub async fn update_presence(&self, presence: Presence) {
+        let mut current = self.presence.write().await;
+        *current = presence;
+        self.send_presence(&current);
+    }
+
+    /// アクティビティだけを変更する (None で解除)。オンライン状態等はそのまま
+    pub async fn set_activity(&self, activity: Option<Activity>) {
+        let mut current = self.presence.write().await;
+        current.activity = activity;
+        self.send_presence(&current);
+    }
+
+    fn send_presence(&self, presence: &Presence) {
+        let payload = json!({
+            "op": opcodes::PRESENCE_UPDATE,
+            "d": presence.to_json()
+        });
+        if self.tx.send(Outgoing::Payload(payload)).is_err() {
+            log::warn!("Gateway is not running, presence update dropped");
+        }
+    }
+
+    /// サーバーメンバーを user_id 指定で要求する (REQUEST_GUILD_MEMBERS, op 8。
+    /// 1 回あたり最大 MEMBER_REQUEST_MAX_IDS 件)。結果は GUILD_MEMBERS_CHUNK で届く。レート制限が厳しいため呼び出し側で間隔を空けること
+    pub fn request_guild_members(&self, guild_id: &str, user_ids: Vec<String>) {
+        let d = json!({
+            "guild_id": guild_id,
+            "user_ids": user_ids,
+            "presences": false
+        });
+        let payload = json!({
+            "op": opcodes::REQUEST_GUILD_MEMBERS,
+            "d": d
+        });
+        if self.tx.send(Outgoing::Payload(payload)).is_err() {
+            log::warn!("Gateway is not running, member request dropped");
+        }
+    }
+
+    /// 終了時に Close フレームを送って切断し、以降の再接続を止める。
+    /// 正常 close (1000) でセッションが即座に破棄され、オフライン表示にも早く切り替わる
+    pub fn close(&self) {
+        self.shutdown.send_replace(true);
+        let _ = self.tx.send(Outgoing::Close);
+    }
+}
+
+/// REQUEST_GUILD_MEMBERS 1 回で指定できる user_id の上限
+pub const MEMBER_REQUEST_MAX_IDS: usize = 100;
+
 /// Gateway クライアント
 pub struct GatewayClient {
     token: String,
@@ -40,16 +130,32 @@ pub struct GatewayClient {
     last_sequence: Arc<RwLock<Option<u64>>>,
     session_id: Option<String>,
     resume_gateway_url: Option<String>,
+    /// WebSocket ハンドシェイクのタイムアウト
+    handshake_timeout: Duration,
+    /// IDENTIFY で送る自分のプレゼンス (GatewaySender から更新される)
+    presence: Arc<RwLock<Presence>>,
+    /// GatewaySender から送られる送信待ちペイロード
+    outgoing_tx: mpsc::UnboundedSender<Outgoing>,
+    outgoing_rx: Arc<Mutex<mpsc::UnboundedReceiver<Outgoing>>>,
+    /// GatewaySender::close() 済みなら true (再接続せず run() を終える)
+    shutdown: Arc<watch::Sender<bool>>,
 }
 
 impl GatewayClient {
     /// Gateway クライアントを初期化（実際の接続は run() 内で確立）
-    pub fn new(token: String, gateway_url: String) -> Self {
-        // インテント設定（ギルド、メッセージ、DM、メッセージ内容）
+    pub fn new(
+        token: String,
+        gateway_url: String,
+        handshake_timeout: Duration,
+        presence: Presence,
+    ) -> Self {
+        // インテント設定（ギルド、ボイス接続状況、メッセージ、DM、メッセージ内容）
         let intents = intents::GUILDS
+            | intents::GUILD_VOICE_STATES
             | intents::GUILD_MESSAGES
             | intents::DIRECT_MESSAGES
             | intents::MESSAGE_CONTENT;
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
 
         Self {
             token,
@@ -58,34 +164,88 @@ impl GatewayClient {
             last_sequence: Arc::new(RwLock::new(None)),
             session_id: None,
             resume_gateway_url: None,
+            handshake_timeout,
+            presence: Arc::new(RwLock::new(presence)),
+            outgoing_tx,
+            outgoing_rx: Arc::new(Mutex::new(outgoing_rx)),
+            shutdown: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// 実行中の Gateway へ送信するためのハンドルを取得
+    pub fn sender(&self) -> GatewaySender {
+        GatewaySender {
+            presence: self.presence.clone(),
+            tx: self.outgoing_tx.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 
-    /// Gateway イベントループを開始（切断時は自動で再接続・RESUME）
+    /// Gateway イベントループを開始（切断時は自動で再接続・RESUME）。
+    /// 接続失敗は指数バックオフで再試行し、起動後一度も接続できないまま
+    /// INITIAL_CONNECT_MAX_ATTEMPTS 回失敗した場合のみエラーを返す。
+    /// トークンが拒否された場合は `DiscordError::Unauthorized` を返す。
+    /// GatewaySender::close() で切断した場合は Ok で終わる
     pub async fn run<F>(mut self, mut event_handler: F) -> Result<()>
     where
         F: FnMut(GatewayEvent) + Send + 'static,
     {
+        let mut ever_connected = false;
+        let mut failures: u32 = 0;
         loop {
+            if self.is_shut_down() {
+                log::info!("Gateway closed");
+                return Ok(());
+            }
+
             // 有効なセッションがあれば resume_gateway_url で RESUME を試みる
             let (url, resume) = match (&self.resume_gateway_url, &self.session_id) {
                 (Some(u), Some(_)) => (u.clone(), true),
                 _ => (self.gateway_url.clone(), false),
             };
 
-            let ws_stream = match Self::establish(&url).await {
-                Ok(s) => s,
+            let ws_stream = match Self::establish(&url, self.handshake_timeout).await {
+                Ok(s) => {
+                    ever_connected = true;
+                    failures = 0;
+                    s
+                }
                 Err(e) => {
-                    log::error!("Failed to connect to Gateway: {:?}, retrying in 5s", e);
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    failures += 1;
+                    if !ever_connected && failures >= INITIAL_CONNECT_MAX_ATTEMPTS {
+                        return Err(DiscordError::Gateway(format!(
+                            "Could not connect to the Discord Gateway after {} attempts ({}). \
+                             Check your network connection and try again",
+                            failures, e
+                        )));
+                    }
+                    // 起動直後の接続失敗も、切断後と同じく再接続中として表示させる
+                    // (一度接続した後の失敗は切断時に通知済み)
+                    if !ever_connected && failures == 1 {
+                        event_handler(GatewayEvent::Disconnected);
+                    }
+                    let delay = backoff_delay(failures);
+                    log::error!(
+                        "Failed to connect to Gateway (attempt {}): {:?}, retrying in {:?}",
+                        failures,
+                        e,
+                        delay
+                    );
+                    self.sleep_unless_shut_down(delay).await;
                     continue;
                 }
             };
 
-            match self.connection_loop(ws_stream, resume, &mut event_handler).await {
+            let outcome = self.connection_loop(ws_stream, resume, &mut event_handler).await;
+            if self.is_shut_down() {
+                log::info!("Gateway closed");
+                return Ok(());
+            }
+            event_handler(GatewayEvent::Disconnected);
+            match outcome {
                 ConnectionOutcome::Reconnect => {
                     log::warn!("Gateway disconnected, reconnecting...");
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    self.sleep_unless_shut_down(Duration::from_secs(1)).await;
                 }
                 ConnectionOutcome::InvalidSession { resumable } => {
                     if !resumable {
@@ -95,20 +255,45 @@ impl GatewayClient {
                         self.resume_gateway_url = None;
                         *self.last_sequence.write().await = None;
                     }
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    self.sleep_unless_shut_down(Duration::from_secs(2)).await;
+                }
+                ConnectionOutcome::AuthenticationFailed => {
+                    log::error!("Gateway rejected the token");
+                    return Err(DiscordError::Unauthorized);
                 }
             }
         }
     }
 
+    /// GatewaySender::close() 済みか
+    fn is_shut_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// 再接続までの待ち。待っている間に GatewaySender::close() されたらすぐに戻る
+    /// (戻った後のループ先頭で終了する)
+    async fn sleep_unless_shut_down(&self, delay: Duration) {
+        let mut shutdown = self.shutdown.subscribe();
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.wait_for(|closed| *closed) => {}
+        }
+    }
+
     /// WebSocket 接続を1つ確立
-    async fn establish(url: &str) -> Result<WsStream> {
+    async fn establish(url: &str, handshake_timeout: Duration) -> Result<WsStream> {
         let ws_url = format!("{}/?v=10&encoding=json", url);
         log::info!("Connecting to Gateway: {}", ws_url);
 
-        let (ws_stream, _) = connect_async(&ws_url)
+        let (ws_stream, _) = tokio::time::timeout(handshake_timeout, connect_async(&ws_url))
             .await
-            .context("Failed to connect to Gateway")?;
+            .map_err(|_| {
+                DiscordError::Gateway(format!(
+                    "handshake timed out after {:?}",
+                    handshake_timeout
+                ))
+            })?
+            .map_err(|e| DiscordError::Gateway(format!("failed to connect: {}", e)))?;
 
         log::info!("Connected to Gateway");
         Ok(ws_stream)
@@ -124,7 +309,7 @@ impl GatewayClient {
     where
         F: FnMut(GatewayEvent) + Send + 'static,
     {
-        let (mut write, mut read) = ws_stream.split();
+        let (write, mut read) = ws_stream.split();
 
         // Hello を受信してハートビート間隔を取得
         let heartbeat_interval = match Self::wait_for_hello(&mut read).await {
@@ -136,30 +321,38 @@ impl GatewayClient {
         };
         log::info!("Received Hello, heartbeat interval: {}ms", heartbeat_interval);
 
-        // RESUME 可能なら RESUME、そうでなければ IDENTIFY
-        let send_result = if resume {
+        // RESUME 可能なら RESUME、そうでなければ IDENTIFY。切断中に積まれた送信待ち
+        // (プレゼンスの変更など) より必ず先に届くよう、書き込みタスクに最初に送らせる
+        let handshake = if resume {
+            // 前の接続の書き込みタスクは停止済みなので、ここで読んだ seq 以降は動かない
             let seq = *self.last_sequence.read().await;
             let session_id = self.session_id.clone().unwrap_or_default();
             log::info!("Resuming session {} (seq={:?})", session_id, seq);
-            Self::send_resume(&mut write, &self.token, &session_id, seq).await
+            Self::resume_payload(&self.token, &session_id, seq)
         } else {
             log::info!("Sending Identify");
-            Self::send_identify(&mut write, &self.token).await
+            let presence = self.presence.read().await.clone();
+            Self::identify_payload(&self.token, &presence)
         };
-        if let Err(e) = send_result {
-            log::error!("Failed to send Identify/Resume: {:?}", e);
-            return ConnectionOutcome::Reconnect;
-        }
 
-        // ハートビートタスクを開始（write を move）
+        // 書き込みタスクを開始 (write を move)。以降の送信はすべて outgoing キューを通る
         let hb_seq = self.last_sequence.clone();
-        let hb_handle = tokio::spawn(async move {
-            Self::heartbeat_loop(&mut write, heartbeat_interval, hb_seq).await;
+        let outgoing = self.outgoing_rx.clone();
+        let mut writer = tokio::spawn(async move {
+            Self::writer_loop(write, handshake, heartbeat_interval, hb_seq, outgoing).await;
         });
 
         // イベント受信ループ
         let outcome = loop {
-            match read.next().await {
+            let message = tokio::select! {
+                message = read.next() => message,
+                // 書き込みに失敗した (or Close を送った) ら、応答を待たずにこの接続を終える
+                _ = &mut writer => {
+                    log::warn!("Gateway writer stopped");
+                    break ConnectionOutcome::Reconnect;
+                }
+            };
+            match message {
                 Some(Ok(WsMessage::Text(text))) => {
                     log::debug!("Received: {}", text);
                     match Self::handle_message(&text, self).await {
@@ -173,6 +366,9 @@ impl GatewayClient {
                 }
                 Some(Ok(WsMessage::Close(frame))) => {
                     log::warn!("Gateway connection closed: {:?}", frame);
+                    if frame.is_some_and(|f| u16::from(f.code) == AUTHENTICATION_FAILED) {
+                        break ConnectionOutcome::AuthenticationFailed;
+                    }
                     break ConnectionOutcome::Reconnect;
                 }
                 Some(Err(e)) => {
@@ -187,8 +383,8 @@ impl GatewayClient {
             }
         };
 
-        // ハートビートタスクを停止
-        hb_handle.abort();
+        // 書き込みタスクを停止
+        writer.abort();
         outcome
     }
 
@@ -196,28 +392,27 @@ impl GatewayClient {
     async fn wait_for_hello(read: &mut WsRead) -> Result<u64> {
         while let Some(msg) = read.next().await {
             if let Ok(WsMessage::Text(text)) = msg {
-                let payload: GatewayPayload = serde_json::from_str(&text)
-                    .context("Failed to parse Hello payload")?;
+                let payload: GatewayPayload = serde_json::from_str(&text)?;
 
                 if payload.op == opcodes::HELLO {
-                    let data: HelloData = serde_json::from_value(
-                        payload.d.context("Hello payload missing data")?,
-                    )
-                    .context("Failed to parse Hello data")?;
+                    let data = payload
+                        .d
+                        .ok_or_else(|| DiscordError::Gateway("Hello payload missing data".into()))?;
+                    let data: HelloData = serde_json::from_value(data)?;
 
                     return Ok(data.heartbeat_interval);
                 }
             }
         }
 
-        anyhow::bail!("Failed to receive Hello from Gateway")
+        Err(DiscordError::Gateway("connection closed before Hello".into()))
     }
 
-    /// Identify を送信
-    async fn send_identify(write: &mut WsWrite, token: &str) -> Result<()> {
+    /// Identify ペイロード
+    fn identify_payload(token: &str, presence: &Presence) -> serde_json::Value {
         // ユーザーアカウント認証用の詳細なproperties
         // 実際のDiscordクライアントを模倣
-        let identify_payload = json!({
+        json!({
             "op": opcodes::IDENTIFY,
             "d": {
                 "token": token,
@@ -238,12 +433,7 @@ impl GatewayClient {
                     "client_build_number": 261053,
                     "client_event_source": serde_json::Value::Null
                 },
-                "presence": {
-                    "status": "online",
-                    "since": 0,
-                    "activities": [],
-                    "afk": false
-                },
+                "presence": presence.to_json(),
                 "compress": false,
                 "client_state": {
                     "guild_versions": {},
@@ -255,81 +445,114 @@ impl GatewayClient {
                     "api_code_version": 0
                 }
             }
-        });
-
-        let payload_text = serde_json::to_string(&identify_payload)?;
-        log::debug!("Identify payload: {}", payload_text);
-        write
-            .send(WsMessage::Text(payload_text))
-            .await
-            .context("Failed to send Identify")?;
-
-        Ok(())
+        })
     }
 
-    /// Resume を送信（切断したセッションの再開）
-    async fn send_resume(
-        write: &mut WsWrite,
-        token: &str,
-        session_id: &str,
-        seq: Option<u64>,
-    ) -> Result<()> {
-        let resume_payload = json!({
+    /// Resume ペイロード。seq は切断前に最後に受け取ったシーケンス番号
+    fn resume_payload(token: &str, session_id: &str, seq: Option<u64>) -> serde_json::Value {
+        json!({
             "op": opcodes::RESUME,
             "d": {
                 "token": token,
                 "session_id": session_id,
                 "seq": seq
             }
-        });
-
-        let payload_text = serde_json::to_string(&resume_payload)?;
-        write
-            .send(WsMessage::Text(payload_text))
-            .await
-            .context("Failed to send Resume")?;
+        })
+    }
 
-        Ok(())
+    /// ハートビートペイロード（s と t フィールドを含めない）
+    fn heartbeat_payload(seq: Option<u64>) -> serde_json::Value {
+        json!({
+            "op": opcodes::HEARTBEAT,
+            "d": seq
+        })
     }
 
-    /// ハートビートループ
-    async fn heartbeat_loop(
-        write: &mut WsWrite,
+    /// 書き込みループ。最初に handshake (IDENTIFY / RESUME) を送り、以降は定期的なハートビートと
+    /// GatewaySender からの送信待ちペイロードを順に書き込む
+    /// (Gateway への書き込みはすべてこのタスクが行い、write を他と共有しない)
+    async fn writer_loop<W>(
+        mut write: W,
+        handshake: serde_json::Value,
         interval_ms: u64,
         last_sequence: Arc<RwLock<Option<u64>>>,
-    ) {
+        outgoing: Arc<Mutex<mpsc::UnboundedReceiver<Outgoing>>>,
+    ) where
+        W: Sink<WsMessage> + Unpin,
+        W::Error: std::fmt::Display,
+    {
         let mut ticker = interval(Duration::from_millis(interval_ms));
+        // 接続中はこのタスクが受信側を専有する (タスク abort 時にロック解放)
+        let mut outgoing = outgoing.lock().await;
+        if !Self::write_payload(&mut write, &handshake).await {
+            return;
+        }
 
         loop {
-            ticker.tick().await;
-
-            let seq = *last_sequence.read().await;
-            // ハートビートペイロードを直接構築（s と t フィールドを含めない）
-            let heartbeat = json!({
-                "op": opcodes::HEARTBEAT,
-                "d": seq
-            });
-
-            if let Ok(payload_text) = serde_json::to_string(&heartbeat) {
-                if write.send(WsMessage::Text(payload_text)).await.is_err() {
-                    log::error!("Failed to send heartbeat");
-                    break;
-                }
+            let payload = tokio::select! {
+                _ = ticker.tick() => Self::heartbeat_payload(*last_sequence.read().await),
+                Some(outgoing) = outgoing.recv() => match outgoing {
+                    Outgoing::Payload(payload) => payload,
+                    Outgoing::Heartbeat => {
+                        // 送った直後に定期分が重ならないよう、次の定期送信は 1 間隔後にする
+                        ticker.reset();
+                        Self::heartbeat_payload(*last_sequence.read().await)
+                    }
+                    Outgoing::Close => {
+                        let frame = CloseFrame {
+                            code: CloseCode::Normal,
+                            reason: "Client quit".into(),
+                        };
+                        if let Err(e) = write.send(WsMessage::Close(Some(frame))).await {
+                            log::warn!("Failed to send close frame: {}", e);
+                        }
+                        break;
+                    }
+                },
+            };
+
+            if !Self::write_payload(&mut write, &payload).await {
+                break;
+            }
+        }
+    }
+
+    /// ペイロードを 1 つ書き込む。失敗したら false
+    async fn write_payload<W>(write: &mut W, payload: &serde_json::Value) -> bool
+    where
+        W: Sink<WsMessage> + Unpin,
+        W::Error: std::fmt::Display,
+    {
+        match write.send(WsMessage::Text(payload.to_string())).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Failed to send payload (op {}): {}", payload["op"], e);
+                false
             }
         }
     }
 
     /// メッセージを処理
     async fn handle_message(text: &str, client: &mut GatewayClient) -> MessageResult {
-        let payload: GatewayPayload = match serde_json::from_str(text) {
-            Ok(p) => p,
-            Err(_) => return MessageResult::Ignore,
+        // ログに残すのはフレームの先頭だけ (READY などは巨大なため)
+        let head = || text.chars().take(120).collect::<String>();
+        let value: serde_json::Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Ignoring Gateway frame that is not JSON ({}): {}", e, head());
+                return MessageResult::Ignore;
+            }
         };
 
-        // シーケンス番号を更新
-        if let Some(seq) = payload.s {
-            *client.last_sequence.write().await = Some(seq);
+        // シーケンス番号は d の中身に関係なく先に記録する
+        // (1 つのイベントの形が想定外でも、ハートビートや RESUME がずれないように)
+        if let Some(seq) = value.get("s").and_then(|s| s.as_u64()) {
+            client.record_sequence(seq).await;
         }
+        let Some(payload) = GatewayPayload::from_envelope(value) else {
+            log::warn!("Ignoring Gateway frame without a valid op: {}", head());
+            return MessageResult::Ignore;
+        };
 
         match payload.op {
             opcodes::DISPATCH => Self::handle_dispatch(payload, client),
@@ -344,11 +567,29 @@ impl GatewayClient {
                 log::warn!("Invalid session (op 9), resumable={}", resumable);
                 MessageResult::InvalidSession { resumable }
             }
+            opcodes::HEARTBEAT => {
+                // サーバーからの即時ハートビート要求。送信は書き込みタスクに任せる
+                log::debug!("Gateway requested heartbeat (op 1)");
+                if client.outgoing_tx.send(Outgoing::Heartbeat).is_err() {
+                    log::warn!("Failed to queue requested heartbeat");
+                }
+                MessageResult::Ignore
+            }
             opcodes::HEARTBEAT_ACK => MessageResult::Ignore,
             _ => MessageResult::Ignore,
         }
     }
 
+    /// 受け取ったシーケンス番号を記録する。
+    /// RESUME 後はサーバーが取りこぼしたイベントを元の番号のまま再送するため、
+    /// 既に記録した番号より古いものでは巻き戻さない
+    async fn record_sequence(&self, seq: u64) {
+        let mut last = self.last_sequence.write().await;
+        if *last < Some(seq) {
+            *last = Some(seq);
+        }
+    }
+
     /// DISPATCH イベントを処理
     fn handle_dispatch(payload: GatewayPayload, client: &mut GatewayClient) -> MessageResult {
         let event_type = match payload.t.as_deref() {
@@ -357,7 +598,7 @@ impl GatewayClient {
         };
         let data = match payload.d {
             Some(d) => d,
-            None => return MessageResult::Ignore,
+            None => return malformed(event_type, "missing d"),
         };
 
         match event_type {
@@ -387,9 +628,19 @@ impl GatewayClient {
 
                 MessageResult::Event(GatewayEvent::Ready(data))
             }
+            "READY_SUPPLEMENTAL" => {
+                // ユーザーアカウントでは READY の直後にメンバー・プレゼンスの補足が届く
+                let supplemental = models::ReadySupplemental::parse(&data);
+                log::info!(
+                    "READY_SUPPLEMENTAL contains {} member lists and {} presences",
+                    supplemental.members.len(),
+                    supplemental.presences.len()
+                );
+                MessageResult::Event(GatewayEvent::ReadySupplemental(supplemental))
+            }
             "RESUMED" => {
                 log::info!("Gateway session resumed successfully");
-                MessageResult::Ignore
+                MessageResult::Event(GatewayEvent::Resumed)
             }
             "GUILD_CREATE" => {
                 // ギルド情報を抽出
@@ -404,6 +655,9 @@ impl GatewayClient {
                         name: guild_name,
                         icon,
                         owner_id,
+                        emojis: models::GuildEmoji::parse_list(data.get("emojis")),
+                        roles: models::GuildRole::parse_list(data.get("roles")),
+                        voice_states: models::VoiceState::parse_list(data.get("voice_states")),
                     };
 
                     log::info!("GUILD_CREATE: {} ({})", guild.name, guild.id);
@@ -441,7 +695,7 @@ impl GatewayClient {
 
                 match result {
                     Some(event) => MessageResult::Event(event),
-                    None => MessageResult::Ignore,
+                    None => malformed(event_type, "missing fields"),
                 }
             }
             "THREAD_CREATE" | "THREAD_UPDATE" => {
@@ -453,7 +707,8 @@ impl GatewayClient {
                         );
                         MessageResult::Event(GatewayEvent::ThreadUpsert(channel))
                     }
-                    _ => MessageResult::Ignore,
+                    Ok(_) => MessageResult::Ignore,
+                    Err(e) => malformed(event_type, &e.to_string()),
                 }
             }
             "THREAD_DELETE" => {
@@ -463,19 +718,72 @@ impl GatewayClient {
                 })();
                 match result {
                     Some(event) => MessageResult::Event(event),
-                    None => MessageResult::Ignore,
+                    None => malformed(event_type, "missing fields"),
                 }
             }
-            "MESSAGE_CREATE" => match serde_json::from_value::<models::Message>(data) {
-                Ok(message) => MessageResult::Event(GatewayEvent::MessageCreate(message)),
+            "CHANNEL_DELETE" => {
+                let result = (|| {
+                    let id = data.get("id")?.as_str()?.to_string();
+                    Some(GatewayEvent::ChannelDelete { id })
+                })();
+                match result {
+                    Some(event) => MessageResult::Event(event),
+                    None => malformed(event_type, "missing fields"),
+                }
+            }
+            "VOICE_STATE_UPDATE" => match serde_json::from_value::<models::VoiceState>(data) {
+                Ok(state) => MessageResult::Event(GatewayEvent::VoiceStateUpdate(state)),
                 Err(e) => {
-                    log::warn!("Failed to parse MESSAGE_CREATE: {}", e);
+                    log::debug!("Failed to parse VOICE_STATE_UPDATE: {}", e);
                     MessageResult::Ignore
                 }
             },
+            "PRESENCE_UPDATE" => {
+                // READY_SUPPLEMENTAL 以降のオンライン状態の変化
+                let result = (|| {
+                    let user_id = data.get("user")?.get("id")?.as_str()?.to_string();
+                    let status = serde_json::from_value(data.get("status")?.clone()).ok()?;
+                    Some(GatewayEvent::PresenceUpdate { user_id, status })
+                })();
+                match result {
+                    Some(event) => MessageResult::Event(event),
+                    None => malformed(event_type, "missing fields"),
+                }
+            }
+            "RELATIONSHIP_ADD" => match serde_json::from_value::<models::Relationship>(data) {
+                Ok(relationship) => {
+                    MessageResult::Event(GatewayEvent::RelationshipAdd(relationship))
+                }
+                Err(e) => malformed(event_type, &e.to_string()),
+            },
+            "RELATIONSHIP_REMOVE" => {
+                let result = (|| {
+                    let id = data.get("id")?.as_str()?.to_string();
+                    Some(GatewayEvent::RelationshipRemove { id })
+                })();
+                match result {
+                    Some(event) => MessageResult::Event(event),
+                    None => malformed(event_type, "missing fields"),
+                }
+            }
+            "GUILD_MEMBERS_CHUNK" => {
+                let result = (|| {
+                    let guild_id = data.get("guild_id")?.as_str()?.to_string();
+                    let members = GuildMember::parse_list(data.get("members"));
+                    Some(GatewayEvent::GuildMembersChunk { guild_id, members })
+                })();
+                match result {
+                    Some(event) => MessageResult::Event(event),
+                    None => malformed(event_type, "missing fields"),
+                }
+            }
+            "MESSAGE_CREATE" => match serde_json::from_value::<models::Message>(data) {
+                Ok(message) => MessageResult::Event(GatewayEvent::MessageCreate(message)),
+                Err(e) => malformed(event_type, &e.to_string()),
+            },
             "MESSAGE_UPDATE" => match serde_json::from_value::<models::Message>(data) {
                 Ok(message) => MessageResult::Event(GatewayEvent::MessageUpdate(message)),
-                Err(_) => MessageResult::Ignore,
+                Err(e) => malformed(event_type, &e.to_string()),
             },
             "MESSAGE_DELETE" => {
                 let result = (|| {
@@ -485,7 +793,23 @@ impl GatewayClient {
                 })();
                 match result {
                     Some(event) => MessageResult::Event(event),
-                    None => MessageResult::Ignore,
+                    None => malformed(event_type, "missing fields"),
+                }
+            }
+            "MESSAGE_DELETE_BULK" => {
+                let result = (|| {
+                    let channel_id = data.get("channel_id")?.as_str()?.to_string();
+                    let ids = data
+                        .get("ids")?
+                        .as_array()?
+                        .iter()
+                        .filter_map(|id| id.as_str().map(str::to_string))
+                        .collect();
+                    Some(GatewayEvent::MessageDeleteBulk { ids, channel_id })
+                })();
+                match result {
+                    Some(event) => MessageResult::Event(event),
+                    None => malformed(event_type, "missing fields"),
                 }
             }
             _ => MessageResult::Ignore,
@@ -493,14 +817,281 @@ impl GatewayClient {
     }
 }
 
+/// 形が想定外の DISPATCH を記録して捨てる (どのイベントで起きたか後から調べられるように)
+fn malformed(event_type: &str, reason: &str) -> MessageResult {
+    log::warn!("Ignoring malformed {} payload: {}", event_type, reason);
+    MessageResult::Ignore
+}
+
 /// Gateway イベント
 #[derive(Debug, Clone)]
 pub enum GatewayEvent {
     Ready(serde_json::Value),  // READY イベント全体（ギルド情報含む）
+    ReadySupplemental(models::ReadySupplemental), // READY を補うメンバー・プレゼンス
+    Resumed,                   // 切断後の RESUME が完了した
+    Disconnected,              // 接続が切れた (このあと自動で再接続する)
     GuildCreate { guild: models::Guild, channels: Vec<models::Channel> },
     ThreadUpsert(models::Channel),
     ThreadDelete { id: String },
+    ChannelDelete { id: String },
     MessageCreate(models::Message),
     MessageUpdate(models::Message),
     MessageDelete { id: String, channel_id: String },
+    MessageDeleteBulk { ids: Vec<String>, channel_id: String },
+    GuildMembersChunk { guild_id: String, members: Vec<models::GuildMember> },
+    VoiceStateUpdate(models::VoiceState),
+    PresenceUpdate { user_id: String, status: models::UserStatus },
+    RelationshipAdd(models::Relationship),
+    RelationshipRemove { id: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> GatewayClient {
+        GatewayClient::new(
+            "token".to_string(),
+            "wss://gateway.example".to_string(),
+            Duration::from_secs(1),
+            Presence::default(),
+        )
+    }
+
+    async fn dispatch(client: &mut GatewayClient, seq: u64, event: &str, data: serde_json::Value) {
+        let text = json!({ "op": opcodes::DISPATCH, "t": event, "s": seq, "d": data }).to_string();
+        GatewayClient::handle_message(&text, client).await;
+    }
+
+    #[tokio::test]
+    async fn heartbeat_continues_from_replayed_sequence_after_resume() {
+        let mut client = client();
+        let ready = json!({ "session_id": "abc", "resume_gateway_url": "wss://resume.example" });
+        dispatch(&mut client, 1, "READY", ready).await;
+        dispatch(&mut client, 2, "TYPING_START", json!({})).await;
+        dispatch(&mut client, 3, "TYPING_START", json!({})).await;
+
+        // 切断後の RESUME は最後に受け取った seq を送る
+        let seq = *client.last_sequence.read().await;
+        let session_id = client.session_id.clone().unwrap();
+        let resume = GatewayClient::resume_payload(&client.token, &session_id, seq);
+        assert_eq!(resume["op"], opcodes::RESUME);
+        assert_eq!(resume["d"]["session_id"], "abc");
+        assert_eq!(resume["d"]["seq"], 3);
+
+        // サーバーは取りこぼしたイベントを元の番号で再送し、続けて RESUMED を送る
+        dispatch(&mut client, 4, "TYPING_START", json!({})).await;
+        dispatch(&mut client, 5, "TYPING_START", json!({})).await;
+        dispatch(&mut client, 6, "RESUMED", json!({})).await;
+
+        let heartbeat = GatewayClient::heartbeat_payload(*client.last_sequence.read().await);
+        assert_eq!(heartbeat, json!({ "op": opcodes::HEARTBEAT, "d": 6 }));
+    }
+
+    /// 接続を拒否されるアドレス (空きポート) に向けたクライアント
+    fn unreachable_client() -> GatewayClient {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        GatewayClient::new(
+            "token".to_string(),
+            format!("ws://127.0.0.1:{}", port),
+            Duration::from_secs(1),
+            Presence::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn closing_during_the_reconnect_wait_stops_right_away() {
+        // 1 回目の失敗後の待ちに入らせる
+        let client = unreachable_client();
+        let sender = client.sender();
+        let run = tokio::spawn(client.run(|_| {}));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // 待ち (BACKOFF_INITIAL) の途中でも閉じたらすぐに終わる
+        sender.close();
+        let result = tokio::time::timeout(Duration::from_millis(500), run).await;
+        assert!(matches!(result, Ok(Ok(Ok(())))));
+    }
+
+    #[tokio::test]
+    async fn a_failed_first_connect_reports_the_disconnect() {
+        let client = unreachable_client();
+        let sender = client.sender();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let run = tokio::spawn(client.run(move |event| seen.lock().unwrap().push(event)));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // 接続できないまま再試行を待っている間も、再接続中と表示できる
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [GatewayEvent::Disconnected]
+        ));
+        sender.close();
+        let _ = run.await;
+    }
+
+    #[tokio::test]
+    async fn older_sequence_does_not_rewind() {
+        let client = client();
+        client.record_sequence(10).await;
+        client.record_sequence(7).await;
+        assert_eq!(*client.last_sequence.read().await, Some(10));
+    }
+
+    #[tokio::test]
+    async fn malformed_frames_still_advance_the_sequence() {
+        let mut client = client();
+        // d の形が想定外のイベント
+        dispatch(&mut client, 4, "MESSAGE_CREATE", json!("not a message")).await;
+        assert_eq!(*client.last_sequence.read().await, Some(4));
+        // t の型が想定外でも s は読む
+        let text = json!({ "op": opcodes::DISPATCH, "t": 12, "s": 5, "d": {} }).to_string();
+        let result = GatewayClient::handle_message(&text, &mut client).await;
+        assert!(matches!(result, MessageResult::Ignore));
+        assert_eq!(*client.last_sequence.read().await, Some(5));
+        // op が読めないフレーム・JSON でないフレームは捨てる
+        let text = json!({ "op": "zero", "s": 6 }).to_string();
+        GatewayClient::handle_message(&text, &mut client).await;
+        assert_eq!(*client.last_sequence.read().await, Some(6));
+        GatewayClient::handle_message("{\"op\": 0, \"s\": 7", &mut client).await;
+        assert_eq!(*client.last_sequence.read().await, Some(6));
+    }
+
+    #[tokio::test]
+    async fn bulk_delete_carries_all_ids() {
+        let mut client = client();
+        let data = json!({ "ids": ["1", "2", "3"], "channel_id": "10", "guild_id": "100" });
+        let text = json!({ "op": opcodes::DISPATCH, "t": "MESSAGE_DELETE_BULK", "s": 1, "d": data })
+            .to_string();
+        match GatewayClient::handle_message(&text, &mut client).await {
+            MessageResult::Event(GatewayEvent::MessageDeleteBulk { ids, channel_id }) => {
+                assert_eq!(ids, vec!["1", "2", "3"]);
+                assert_eq!(channel_id, "10");
+            }
+            _ => panic!("MESSAGE_DELETE_BULK was not parsed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_heartbeat_request_is_answered_immediately() {
+        let mut client = client();
+        dispatch(&mut client, 3, "TYPING_START", json!({})).await;
+        let text = json!({ "op": opcodes::HEARTBEAT, "d": null }).to_string();
+        GatewayClient::handle_message(&text, &mut client).await;
+
+        let queued = client.outgoing_rx.lock().await.try_recv();
+        assert!(matches!(queued, Ok(Outgoing::Heartbeat)));
+        // 送るときは最新のシーケンス番号を読む
+        let heartbeat = GatewayClient::heartbeat_payload(*client.last_sequence.read().await);
+        assert_eq!(heartbeat, json!({ "op": opcodes::HEARTBEAT, "d": 3 }));
+    }
+
+    #[tokio::test]
+    async fn writer_sends_the_handshake_before_payloads_queued_while_disconnected() {
+        let client = client();
+        let sender = client.sender();
+        sender.update_presence(Presence::default()).await;
+        sender.close();
+
+        let (write, written) = futures::channel::mpsc::unbounded();
+        let handshake = GatewayClient::resume_payload("token", "abc", Some(3));
+        let outgoing = client.outgoing_rx.clone();
+        GatewayClient::writer_loop(write, handshake, 60_000, client.last_sequence.clone(), outgoing)
+            .await;
+
+        let frames: Vec<WsMessage> = written.collect().await;
+        let ops: Vec<u64> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                WsMessage::Text(text) => serde_json::from_str::<serde_json::Value>(text).ok(),
+                _ => None,
+            })
+            .filter_map(|payload| payload["op"].as_u64())
+            .collect();
+        assert_eq!(ops.first(), Some(&(opcodes::RESUME as u64)));
+        assert!(ops.contains(&(opcodes::PRESENCE_UPDATE as u64)));
+        assert!(matches!(frames.last(), Some(WsMessage::Close(_))));
+    }
+
+    #[test]
+    fn heartbeat_before_any_dispatch_sends_null() {
+        assert_eq!(
+            GatewayClient::heartbeat_payload(None),
+            json!({ "op": opcodes::HEARTBEAT, "d": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn ready_supplemental_aligns_members_with_guilds() {
+        let mut client = client();
+        let data = json!({
+            "guilds": [{ "id": "100" }, { "id": "101" }],
+            "merged_members": [
+                [{ "user_id": "7", "nick": "Seven", "roles": ["r1"] }],
+                [{ "user_id": "8", "roles": [] }],
+            ],
+            "merged_presences": {
+                "friends": [{ "user_id": "7", "status": "idle" }],
+                "guilds": [[{ "user_id": "8", "status": "invisible" }], []],
+            },
+        });
+        let text = json!({ "op": opcodes::DISPATCH, "t": "READY_SUPPLEMENTAL", "s": 2, "d": data })
+            .to_string();
+        match GatewayClient::handle_message(&text, &mut client).await {
+            MessageResult::Event(GatewayEvent::ReadySupplemental(supplemental)) => {
+                let (guild_id, members) = &supplemental.members[1];
+                assert_eq!(guild_id, "101");
+                assert!(members.contains_key("8"));
+                let (_, members) = &supplemental.members[0];
+                assert_eq!(members["7"].nick.as_deref(), Some("Seven"));
+                assert_eq!(supplemental.presences["7"], models::UserStatus::Idle);
+                assert_eq!(supplemental.presences["8"], models::UserStatus::Offline);
+            }
+            _ => panic!("READY_SUPPLEMENTAL was not parsed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn presence_update_carries_the_new_status() {
+        let mut client = client();
+        let data = json!({ "user": { "id": "7" }, "status": "dnd", "guild_id": "100" });
+        let text = json!({ "op": opcodes::DISPATCH, "t": "PRESENCE_UPDATE", "s": 3, "d": data })
+            .to_string();
+        match GatewayClient::handle_message(&text, &mut client).await {
+            MessageResult::Event(GatewayEvent::PresenceUpdate { user_id, status }) => {
+                assert_eq!(user_id, "7");
+                assert_eq!(status, models::UserStatus::Dnd);
+            }
+            _ => panic!("PRESENCE_UPDATE was not parsed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn relationship_changes_are_forwarded() {
+        let mut client = client();
+        let user = json!({ "id": "20", "username": "friend", "discriminator": "0" });
+        let data = json!({ "id": "20", "type": 1, "user": user });
+        let text = json!({ "op": opcodes::DISPATCH, "t": "RELATIONSHIP_ADD", "s": 4, "d": data })
+            .to_string();
+        match GatewayClient::handle_message(&text, &mut client).await {
+            MessageResult::Event(GatewayEvent::RelationshipAdd(relationship)) => {
+                assert_eq!(relationship.id, "20");
+                assert!(relationship.is_friend());
+            }
+            _ => panic!("RELATIONSHIP_ADD was not parsed"),
+        }
+
+        let data = json!({ "id": "20", "type": 1 });
+        let text = json!({ "op": opcodes::DISPATCH, "t": "RELATIONSHIP_REMOVE", "s": 5, "d": data })
+            .to_string();
+        assert!(matches!(
+            GatewayClient::handle_message(&text, &mut client).await,
+            MessageResult::Event(GatewayEvent::RelationshipRemove { id }) if id == "20"
+        ));
+    }
 }
@@ -1,10 +1,20 @@
 // Discord APIモジュール
 
+pub mod api;
+pub mod error;
 pub mod models;
+pub mod rate_limit;
 pub mod rest;
+pub mod retry;
 pub mod gateway;
 
 // 再エクスポートして使いやすくする
+pub use api::DiscordApi;
+pub use error::DiscordError;
 pub use models::*;
-pub use rest::{DiscordRestClient, RestError};
-pub use gateway::{GatewayClient, GatewayEvent};
+pub use rest::DiscordRestClient;
+pub use retry::RetryPolicy;
+pub use gateway::{
+    backoff_delay, GatewayClient, GatewayEvent, GatewaySender,
+    INITIAL_CONNECT_MAX_ATTEMPTS, MEMBER_REQUEST_MAX_IDS,
+};
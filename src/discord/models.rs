@@ -1,4 +1,7 @@
+use crate::glyphs::Glyphs;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthChar;
 
 /// ユーザー情報
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -13,6 +16,18 @@ pub struct User {
     pub global_name: Option<String>,
 }
 
+impl User {
+    /// アバター画像の CDN URL (未設定ユーザーは None)
+    pub fn avatar_url(&self) -> Option<String> {
+        self.avatar.as_ref().map(|hash| {
+            format!(
+                "https://cdn.discordapp.com/avatars/{}/{}.png?size=64",
+                self.id, hash
+            )
+        })
+    }
+}
+
 /// 添付ファイル情報
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Attachment {
@@ -30,7 +45,35 @@ pub struct Attachment {
     pub height: Option<u32>,
 }
 
+/// 署名付き CDN URL (`?ex=<16 進の UNIX 秒>&is=...&hm=...`) の有効期限 (UNIX 秒)。
+/// 署名の無い URL (絵文字・アバター等) は None
+pub fn signed_url_expiry(url: &str) -> Option<i64> {
+    let query = url.split_once('?')?.1;
+    let query = query.split('#').next().unwrap_or(query);
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("ex="))
+        .and_then(|hex| i64::from_str_radix(hex, 16).ok())
+}
+
+/// 署名付き CDN URL が `now` (UNIX 秒) の時点で期限切れか (署名が無ければ false)
+pub fn signed_url_expired(url: &str, now: i64) -> bool {
+    signed_url_expiry(url).is_some_and(|expiry| expiry <= now)
+}
+
 impl Attachment {
+    /// スポイラー指定された添付か (Discord はファイル名の `SPOILER_` 接頭辞で表す)
+    pub fn is_spoiler(&self) -> bool {
+        self.filename.starts_with("SPOILER_")
+    }
+
+    /// 画像の添付か (プレビューの対象)
+    pub fn is_image(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|ct| ct.starts_with("image/"))
+    }
+
     /// 添付ファイルの表示用テキストを取得
     pub fn display_text(&self) -> String {
         if let Some(content_type) = &self.content_type {
@@ -49,6 +92,23 @@ impl Attachment {
     }
 }
 
+/// メッセージに付与されたスタンプ (sticker item)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Sticker {
+    pub id: String,
+    pub name: String,
+    /// 1: PNG, 2: APNG, 3: Lottie, 4: GIF
+    #[serde(default)]
+    pub format_type: u8,
+}
+
+impl Sticker {
+    /// スタンプの表示用テキストを取得
+    pub fn display_text(&self) -> String {
+        format!("[Sticker: {}]", self.name)
+    }
+}
+
 /// メッセージ情報
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Message {
@@ -61,9 +121,33 @@ pub struct Message {
     pub edited_timestamp: Option<String>,
     #[serde(default)]
     pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub sticker_items: Vec<Sticker>,
     /// ギルドメンバー情報 (サーバー内発言時にニックネームを含む)
     #[serde(default)]
     pub member: Option<MessageMember>,
+    /// 埋め込み (リンクのプレビュー等)
+    #[serde(default)]
+    pub embeds: Vec<Embed>,
+    /// メッセージフラグのビットフィールド (`message_flags` 参照)
+    #[serde(default)]
+    pub flags: u32,
+    /// メッセージの種類 (`message_types` 参照。参加・ピン留め等のシステムメッセージの判別用)
+    #[serde(rename = "type", default)]
+    pub message_type: u8,
+    /// 本文でメンションされたユーザー (グループへの追加などのシステムメッセージの表示に使う)
+    #[serde(default)]
+    pub mentions: Vec<User>,
+    /// 送信先のサーバー (Gateway の MESSAGE_CREATE にのみ含まれる。DM・REST では None)
+    #[serde(default)]
+    pub guild_id: Option<String>,
+}
+
+/// メッセージの埋め込み (表示に使うタイトルのみ)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Embed {
+    #[serde(default)]
+    pub title: Option<String>,
 }
 
 /// メッセージに付与される partial guild member
@@ -71,6 +155,119 @@ pub struct Message {
 pub struct MessageMember {
     #[serde(default)]
     pub nick: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// サーバーメンバー (GUILD_MEMBERS_CHUNK の members 要素)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuildMember {
+    #[serde(default)]
+    pub user: Option<User>,
+    #[serde(default)]
+    pub nick: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl GuildMember {
+    /// GUILD_MEMBERS_CHUNK の `members` 配列をパース (不正な要素は読み飛ばす)
+    pub fn parse_list(value: Option<&serde_json::Value>) -> Vec<GuildMember> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|m| serde_json::from_value::<GuildMember>(m.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// メッセージに付ける partial member へ変換
+    pub fn to_message_member(&self) -> MessageMember {
+        MessageMember {
+            nick: self.nick.clone(),
+            roles: self.roles.clone(),
+        }
+    }
+}
+
+/// 他ユーザーのオンライン状態 (READY_SUPPLEMENTAL の merged_presences)。
+/// invisible など未知の値はオフライン扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStatus {
+    Online,
+    Idle,
+    Dnd,
+    #[serde(other)]
+    Offline,
+}
+
+/// READY_SUPPLEMENTAL (ユーザーアカウントで READY の直後に届く補足情報)
+#[derive(Debug, Clone, Default)]
+pub struct ReadySupplemental {
+    /// (guild_id, user_id -> member)。merged_members は guilds と同じ順に並ぶ
+    pub members: Vec<(String, HashMap<String, GuildMember>)>,
+    /// user_id -> オンライン状態 (フレンドとギルドメンバーをまとめたもの)
+    pub presences: HashMap<String, UserStatus>,
+}
+
+impl ReadySupplemental {
+    /// dispatch の d をパース (不正な要素は読み飛ばす)
+    pub fn parse(data: &serde_json::Value) -> Self {
+        let guild_ids = data
+            .get("guilds")
+            .and_then(|v| v.as_array())
+            .map(|guilds| guilds.iter().map(|g| g.get("id").and_then(|id| id.as_str())))
+            .into_iter()
+            .flatten();
+        let merged_members = data
+            .get("merged_members")
+            .and_then(|v| v.as_array())
+            .map(|list| list.iter())
+            .into_iter()
+            .flatten();
+        let members = guild_ids
+            .zip(merged_members)
+            .filter_map(|(guild_id, list)| {
+                let members = list
+                    .as_array()?
+                    .iter()
+                    .filter_map(|m| {
+                        let user_id = m.get("user_id")?.as_str()?.to_string();
+                        let member = serde_json::from_value::<GuildMember>(m.clone()).ok()?;
+                        Some((user_id, member))
+                    })
+                    .collect();
+                Some((guild_id?.to_string(), members))
+            })
+            .collect();
+
+        let merged_presences = data.get("merged_presences");
+        let friends = merged_presences
+            .and_then(|p| p.get("friends"))
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten();
+        let guild_presences = merged_presences
+            .and_then(|p| p.get("guilds"))
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|list| list.as_array())
+            .flatten();
+        let presences = friends
+            .chain(guild_presences)
+            .filter_map(|p| {
+                let user_id = p.get("user_id")?.as_str()?.to_string();
+                let status = serde_json::from_value(p.get("status")?.clone()).ok()?;
+                Some((user_id, status))
+            })
+            .collect();
+
+        Self { members, presences }
+    }
 }
 
 /// READY イベント内 read_state エントリ (チャンネル毎の既読状態)
@@ -93,6 +290,24 @@ pub struct UserGuildSettingsEntry {
     pub channel_overrides: Vec<ChannelOverride>,
 }
 
+/// READY イベント内 relationships エントリ (フレンド・ブロック・申請中のユーザー)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Relationship {
+    /// 相手の user_id
+    pub id: String,
+    /// 1: フレンド / 2: ブロック / 3: 受け取った申請 / 4: 送った申請
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub user: Option<User>,
+}
+
+impl Relationship {
+    pub fn is_friend(&self) -> bool {
+        self.kind == 1
+    }
+}
+
 /// チャンネル単位の通知 override
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ChannelOverride {
@@ -102,6 +317,89 @@ pub struct ChannelOverride {
 }
 
 impl Message {
+    /// フォローしているアナウンスチャンネルから転送されたメッセージか
+    pub fn is_crosspost(&self) -> bool {
+        self.flags & message_flags::IS_CROSSPOST != 0
+    }
+
+    /// 作者が埋め込みを非表示にしたか
+    pub fn embeds_suppressed(&self) -> bool {
+        self.flags & message_flags::SUPPRESS_EMBEDS != 0
+    }
+
+    /// 自分にだけ表示されている (インタラクションの) メッセージか
+    pub fn is_ephemeral(&self) -> bool {
+        self.flags & message_flags::EPHEMERAL != 0
+    }
+
+    /// 遅延応答のインタラクションで、ボットがまだ考え中か
+    pub fn is_loading(&self) -> bool {
+        self.flags & message_flags::LOADING != 0
+    }
+
+    /// 通常の発言 (返信・スラッシュコマンドの応答を含む) か
+    pub fn is_regular(&self) -> bool {
+        matches!(
+            self.message_type,
+            message_types::DEFAULT
+                | message_types::REPLY
+                | message_types::CHAT_INPUT_COMMAND
+                | message_types::CONTEXT_MENU_COMMAND
+        )
+    }
+
+    /// システムメッセージ (参加・ピン留め・ブースト等) なら表示用の文章を返す
+    pub fn system_text(&self) -> Option<String> {
+        if self.is_regular() {
+            return None;
+        }
+        let author = self.author_display_name();
+        let target = self
+            .mentions
+            .first()
+            .map(|u| u.global_name.as_deref().unwrap_or(&u.username).to_string())
+            .unwrap_or_else(|| "someone".to_string());
+        let text = match self.message_type {
+            message_types::RECIPIENT_ADD => format!("{} added {} to the group", author, target),
+            message_types::RECIPIENT_REMOVE
+                if self.mentions.iter().all(|u| u.id == self.author.id) =>
+            {
+                format!("{} left the group", author)
+            }
+            message_types::RECIPIENT_REMOVE => {
+                format!("{} removed {} from the group", author, target)
+            }
+            message_types::CALL => format!("{} started a call", author),
+            message_types::CHANNEL_NAME_CHANGE => {
+                format!("{} changed the channel name: {}", author, self.content)
+            }
+            message_types::CHANNEL_ICON_CHANGE => format!("{} changed the channel icon", author),
+            message_types::CHANNEL_PINNED_MESSAGE => {
+                format!("{} pinned a message to this channel", author)
+            }
+            message_types::USER_JOIN => format!("{} joined the server", author),
+            message_types::GUILD_BOOST => format!("{} boosted the server", author),
+            level @ message_types::GUILD_BOOST_TIER_1..=message_types::GUILD_BOOST_TIER_3 => {
+                format!(
+                    "{} boosted the server! The server has reached Level {}",
+                    author,
+                    level - message_types::GUILD_BOOST
+                )
+            }
+            message_types::CHANNEL_FOLLOW_ADD => {
+                format!("{} added {} to this channel", author, self.content)
+            }
+            message_types::THREAD_CREATED => {
+                format!("{} started a thread: {}", author, self.content)
+            }
+            message_types::THREAD_STARTER_MESSAGE => "Thread started from a message".to_string(),
+            message_types::AUTO_MODERATION_ACTION => "AutoMod blocked a message".to_string(),
+            message_types::POLL_RESULT => "A poll has closed".to_string(),
+            other => format!("System message (type {})", other),
+        };
+        Some(text)
+    }
+
     /// 表示用の作者名を取得 (サーバーニックネーム → global_name → username の優先順)
     pub fn author_display_name(&self) -> &str {
         if let Some(nick) = self.member.as_ref().and_then(|m| m.nick.as_deref()) {
@@ -138,6 +436,10 @@ pub struct Channel {
     pub parent_id: Option<String>, // スレッドの親チャンネル / カテゴリ
     #[serde(default)]
     pub last_message_id: Option<String>, // 直近メッセージ ID (未読判定用)
+    #[serde(default)]
+    pub rate_limit_per_user: Option<u32>, // slowmode の秒数 (0 / None なら無効)
+    #[serde(default)]
+    pub nsfw: bool, // 年齢制限チャンネル
 }
 
 impl Channel {
@@ -156,19 +458,9 @@ impl Channel {
         }
     }
 
-    /// チャンネルタイプのプレフィックスを取得
+    /// チャンネルタイプのプレフィックスを取得 (既定の記号。画面では `AppState::channel_prefix`)
     pub fn type_prefix(&self) -> &str {
-        match self.channel_type {
-            0 => "# ",        // テキストチャンネル
-            1 => "@ ",        // DM
-            2 => "🔊 ",       // ボイスチャンネル
-            3 => "@@ ",       // グループ DM
-            5 => "📢 ",       // アナウンスチャンネル
-            10 | 11 | 12 => "🧵 ", // スレッド
-            15 => "📋 ",      // フォーラム
-            16 => "🖼️ ",     // メディアチャンネル
-            _ => "? ",
-        }
+        Glyphs::UNICODE.channel_prefix(self.channel_type)
     }
 
     /// メッセージのやり取りが可能なチャンネルかどうか
@@ -176,6 +468,34 @@ impl Channel {
     pub fn is_messageable(&self) -> bool {
         matches!(self.channel_type, 0 | 1 | 3 | 5 | 10 | 11 | 12)
     }
+
+    /// ボイスチャンネルかどうか
+    pub fn is_voice(&self) -> bool {
+        self.channel_type == 2
+    }
+
+    /// slowmode が有効ならその秒数
+    pub fn slowmode_secs(&self) -> Option<u32> {
+        self.rate_limit_per_user.filter(|secs| *secs > 0)
+    }
+
+    /// フォーラム / メディアチャンネルかどうか
+    /// (直接メッセージは持たず、投稿 = スレッドの一覧を持つ)
+    pub fn is_forum(&self) -> bool {
+        matches!(self.channel_type, 15 | 16)
+    }
+
+    /// 通常のテキストのやり取りをするチャンネルかどうか
+    /// (テキスト/DM/グループDM/テキストチャンネルのスレッド。アナウンス・ボイス・フォーラムは除く)
+    pub fn is_plain_text(&self) -> bool {
+        matches!(self.channel_type, 0 | 1 | 3 | 11 | 12)
+    }
+
+    /// サイドバー・検索に表示するチャンネルかどうか
+    /// (メッセージ可能 + フォーラム + 接続人数を表示するボイスチャンネル)
+    pub fn is_listable(&self) -> bool {
+        self.is_messageable() || self.is_forum() || self.is_voice()
+    }
 }
 
 /// ギルド（サーバー）情報
@@ -185,7 +505,149 @@ pub struct Guild {
     pub name: String,
     #[serde(default)]
     pub icon: Option<String>,
+    /// `GET /users/@me/guilds` の partial guild には含まれないため default 許容
+    #[serde(default)]
     pub owner_id: String,
+    /// サーバーのカスタム絵文字 (リアクション用)
+    #[serde(default)]
+    pub emojis: Vec<GuildEmoji>,
+    /// サーバーのロール (作者名の色付け用)。REST の partial guild には含まれない
+    #[serde(default)]
+    pub roles: Vec<GuildRole>,
+    /// GUILD_CREATE / READY 時点のボイスチャンネルの接続状況 (状態に取り込んだら空にする)
+    #[serde(skip)]
+    pub voice_states: Vec<VoiceState>,
+}
+
+impl Guild {
+    /// サーバーアイコンの URL (アイコン未設定なら None)
+    pub fn icon_url(&self) -> Option<String> {
+        self.icon.as_ref().map(|hash| {
+            format!(
+                "https://cdn.discordapp.com/icons/{}/{}.png?size=64",
+                self.id, hash
+            )
+        })
+    }
+
+    /// アイコンの代わりに表示する頭文字 (単語ごとの先頭の文字、表示幅 2 まで)。
+    /// "Hakuhyo Dev" なら "HD"、"rust" なら "R"
+    pub fn initials(&self) -> String {
+        let mut initials = String::new();
+        let mut width = 0;
+        for c in self.name.split_whitespace().filter_map(|word| word.chars().next()) {
+            let w = c.width().unwrap_or(0);
+            if width + w > 2 {
+                break;
+            }
+            width += w;
+            initials.extend(c.to_uppercase());
+        }
+        initials
+    }
+}
+
+/// ボイスチャンネルへの接続状況 (VOICE_STATE_UPDATE / GUILD_CREATE の `voice_states`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoiceState {
+    pub user_id: String,
+    /// 接続中のチャンネル (切断したら None)
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    /// GUILD_CREATE の `voice_states` には含まれない
+    #[serde(default)]
+    pub guild_id: Option<String>,
+}
+
+impl VoiceState {
+    /// READY / GUILD_CREATE の `voice_states` 配列をパース (不正な要素は読み飛ばす)
+    pub fn parse_list(value: Option<&serde_json::Value>) -> Vec<VoiceState> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|v| serde_json::from_value::<VoiceState>(v.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// サーバーのカスタム絵文字
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuildEmoji {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub animated: bool,
+    /// ブーストの失効等で使えなくなった絵文字は false
+    #[serde(default = "default_true")]
+    pub available: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl GuildEmoji {
+    /// READY / GUILD_CREATE の `emojis` 配列をパース (不正な要素は読み飛ばす)
+    pub fn parse_list(value: Option<&serde_json::Value>) -> Vec<GuildEmoji> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|e| serde_json::from_value::<GuildEmoji>(e.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// サーバーのロール
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuildRole {
+    pub id: String,
+    /// 表示色 (0xRRGGBB。0 なら色なし)
+    #[serde(default)]
+    pub color: u32,
+    /// 並び順 (大きいほど上位)
+    #[serde(default)]
+    pub position: i64,
+}
+
+impl GuildRole {
+    /// READY / GUILD_CREATE の `roles` 配列をパース (不正な要素は読み飛ばす)
+    pub fn parse_list(value: Option<&serde_json::Value>) -> Vec<GuildRole> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|r| serde_json::from_value::<GuildRole>(r.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// `GET /guilds/{id}/threads/active` のレスポンス
+#[derive(Debug, Deserialize)]
+pub struct ActiveThreadsResponse {
+    #[serde(default)]
+    pub threads: Vec<Channel>,
+}
+
+/// `POST /attachments/refresh-urls` のレスポンス
+#[derive(Debug, Deserialize)]
+pub struct RefreshedUrlsResponse {
+    #[serde(default)]
+    pub refreshed_urls: Vec<RefreshedUrl>,
+}
+
+/// 再署名された添付ファイル URL
+#[derive(Debug, Deserialize)]
+pub struct RefreshedUrl {
+    pub original: String,
+    pub refreshed: String,
 }
 
 /// Gateway URL レスポンス
@@ -206,6 +668,20 @@ pub struct GatewayPayload {
     pub t: Option<String>,
 }
 
+impl GatewayPayload {
+    /// 受信したフレームの外側 (op / s / t) を寛容に読む。d はそのまま持ち、
+    /// s / t の型が想定外なら無いものとして扱う。op が読めなければ None
+    pub fn from_envelope(mut value: serde_json::Value) -> Option<Self> {
+        let op = value.get("op")?.as_u64().and_then(|op| u8::try_from(op).ok())?;
+        Some(Self {
+            op,
+            d: value.get_mut("d").map(serde_json::Value::take).filter(|d| !d.is_null()),
+            s: value.get("s").and_then(|s| s.as_u64()),
+            t: value.get("t").and_then(|t| t.as_str()).map(str::to_string),
+        })
+    }
+}
+
 /// Hello ペイロードのデータ部分
 #[derive(Debug, Deserialize)]
 pub struct HelloData {
@@ -229,28 +705,326 @@ pub struct IdentifyProperties {
     pub device: String,
 }
 
+/// オンライン状態 (IDENTIFY / PRESENCE_UPDATE の status)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    #[default]
+    Online,
+    Idle,
+    Dnd,
+    Invisible,
+}
+
+impl PresenceStatus {
+    /// Discord API 上の文字列表現
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Idle => "idle",
+            PresenceStatus::Dnd => "dnd",
+            PresenceStatus::Invisible => "invisible",
+        }
+    }
+
+    /// キー操作で切り替える次の状態 (online → idle → dnd → invisible → online)
+    pub fn next(&self) -> Self {
+        match self {
+            PresenceStatus::Online => PresenceStatus::Idle,
+            PresenceStatus::Idle => PresenceStatus::Dnd,
+            PresenceStatus::Dnd => PresenceStatus::Invisible,
+            PresenceStatus::Invisible => PresenceStatus::Online,
+        }
+    }
+}
+
+/// アクティビティの種類 (Custom Status の 4 は Presence::custom_status 側で扱う)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityType {
+    Playing,
+    Streaming,
+    Listening,
+    Watching,
+    Competing,
+}
+
+impl ActivityType {
+    /// 設定ファイル上の名前から変換 (不正な名前なら None)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "playing" => Some(ActivityType::Playing),
+            "streaming" => Some(ActivityType::Streaming),
+            "listening" => Some(ActivityType::Listening),
+            "watching" => Some(ActivityType::Watching),
+            "competing" => Some(ActivityType::Competing),
+            _ => None,
+        }
+    }
+
+    /// Discord API 上の type 値
+    pub fn code(&self) -> u8 {
+        match self {
+            ActivityType::Playing => 0,
+            ActivityType::Streaming => 1,
+            ActivityType::Listening => 2,
+            ActivityType::Watching => 3,
+            ActivityType::Competing => 5,
+        }
+    }
+}
+
+/// アクティビティ ("Playing X" 等)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub kind: ActivityType,
+    pub name: String,
+}
+
+/// 自分のプレゼンス (オンライン状態 + カスタムステータス + アクティビティ)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Presence {
+    pub status: PresenceStatus,
+    /// カスタムステータスの文言 (None / 空文字なら設定しない)
+    pub custom_status: Option<String>,
+    pub activity: Option<Activity>,
+}
+
+impl Presence {
+    /// IDENTIFY の presence / PRESENCE_UPDATE の d に使う JSON を構築
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut activities = Vec::new();
+        if let Some(text) = self.custom_status.as_deref().filter(|t| !t.is_empty()) {
+            // カスタムステータスは type 4 (Custom) の activity として送る
+            activities.push(serde_json::json!({
+                "type": 4,
+                "name": "Custom Status",
+                "state": text
+            }));
+        }
+        if let Some(activity) = &self.activity {
+            activities.push(serde_json::json!({
+                "type": activity.kind.code(),
+                "name": activity.name
+            }));
+        }
+        serde_json::json!({
+            "status": self.status.as_str(),
+            "since": 0,
+            "activities": activities,
+            "afk": false
+        })
+    }
+}
+
 /// メッセージ作成リクエストのペイロード
 #[derive(Debug, Serialize)]
 pub struct CreateMessagePayload {
     pub content: String,
+    /// 返信先 (返信でなければ省略)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_reference: Option<MessageReference>,
+    /// メンションの通知先の制限 (省略時は Discord の既定どおり全て通知)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentions>,
+}
+
+/// メッセージ送信時の返信先
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageReply {
+    pub message_id: String,
+    /// 返信先の作者にメンション (通知) するか
+    pub mention: bool,
+}
+
+/// 本文中のメンションのうち実際に通知するもの
+#[derive(Debug, Clone, Serialize)]
+pub struct AllowedMentions {
+    /// 本文から解析して通知するメンションの種類 ("users" / "roles" / "everyone")
+    pub parse: Vec<&'static str>,
+    /// 返信先の作者に通知するか
+    pub replied_user: bool,
+}
+
+impl AllowedMentions {
+    /// 送信時の通知の制限。全て通知する (Discord の既定どおり) なら None。
+    /// `mass_mentions` が false なら @everyone / @here / ロールへのメンションで通知しない
+    pub fn for_send(reply_to: Option<&MessageReply>, mass_mentions: bool) -> Option<Self> {
+        let replied_user = reply_to.filter(|reply| !reply.mention).is_none();
+        if replied_user && mass_mentions {
+            return None;
+        }
+        // parse を省くと本文中のユーザーへのメンションも通知されなくなる
+        let mut parse = vec!["users"];
+        if mass_mentions {
+            parse.extend(["roles", "everyone"]);
+        }
+        Some(Self {
+            parse,
+            replied_user,
+        })
+    }
+}
+
+/// 返信先メッセージへの参照
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageReference {
+    pub message_id: String,
+    pub channel_id: String,
+    /// false なら返信先が削除済みでも通常のメッセージとして送信される
+    pub fail_if_not_exists: bool,
 }
 
 /// Gateway インテント定数
 pub mod intents {
     pub const GUILDS: u32 = 1 << 0;
+    pub const GUILD_VOICE_STATES: u32 = 1 << 7;
     pub const GUILD_MESSAGES: u32 = 1 << 9;
     pub const DIRECT_MESSAGES: u32 = 1 << 12;
     pub const MESSAGE_CONTENT: u32 = 1 << 15;
 }
 
+/// メッセージフラグ定数 (表示に使うもののみ)
+pub mod message_flags {
+    pub const IS_CROSSPOST: u32 = 1 << 1;
+    pub const SUPPRESS_EMBEDS: u32 = 1 << 2;
+    pub const EPHEMERAL: u32 = 1 << 6;
+    pub const LOADING: u32 = 1 << 7;
+}
+
+/// メッセージの種類 (Message.type) の定数
+pub mod message_types {
+    pub const DEFAULT: u8 = 0;
+    pub const RECIPIENT_ADD: u8 = 1;
+    pub const RECIPIENT_REMOVE: u8 = 2;
+    pub const CALL: u8 = 3;
+    pub const CHANNEL_NAME_CHANGE: u8 = 4;
+    pub const CHANNEL_ICON_CHANGE: u8 = 5;
+    pub const CHANNEL_PINNED_MESSAGE: u8 = 6;
+    pub const USER_JOIN: u8 = 7;
+    pub const GUILD_BOOST: u8 = 8;
+    pub const GUILD_BOOST_TIER_1: u8 = 9;
+    pub const GUILD_BOOST_TIER_3: u8 = 11;
+    pub const CHANNEL_FOLLOW_ADD: u8 = 12;
+    pub const THREAD_CREATED: u8 = 18;
+    pub const REPLY: u8 = 19;
+    pub const CHAT_INPUT_COMMAND: u8 = 20;
+    pub const THREAD_STARTER_MESSAGE: u8 = 21;
+    pub const CONTEXT_MENU_COMMAND: u8 = 23;
+    pub const AUTO_MODERATION_ACTION: u8 = 24;
+    pub const POLL_RESULT: u8 = 46;
+}
+
 /// Gateway Opcode 定数
 pub mod opcodes {
     pub const DISPATCH: u8 = 0;
     pub const HEARTBEAT: u8 = 1;
     pub const IDENTIFY: u8 = 2;
+    pub const PRESENCE_UPDATE: u8 = 3;
     pub const RESUME: u8 = 6;
     pub const RECONNECT: u8 = 7;
+    pub const REQUEST_GUILD_MEMBERS: u8 = 8;
     pub const INVALID_SESSION: u8 = 9;
     pub const HELLO: u8 = 10;
     pub const HEARTBEAT_ACK: u8 = 11;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNED: &str = "https://cdn.discordapp.com/attachments/1/2/a.png?ex=65f0a1b2&is=65de2cb2&hm=abcdef&";
+
+    #[test]
+    fn parses_expiry_of_signed_url() {
+        assert_eq!(signed_url_expiry(SIGNED), Some(0x65f0a1b2));
+        assert!(signed_url_expired(SIGNED, 0x65f0a1b2));
+        assert!(!signed_url_expired(SIGNED, 0x65f0a1b1));
+    }
+
+    #[test]
+    fn guild_initials_take_the_first_letter_of_each_word() {
+        let guild = |name: &str| -> Guild {
+            serde_json::from_value(serde_json::json!({ "id": "1", "name": name })).unwrap()
+        };
+        assert_eq!(guild("Hakuhyo Dev").initials(), "HD");
+        assert_eq!(guild("rust  lang users").initials(), "RL");
+        assert_eq!(guild("rust").initials(), "R");
+        // 全角文字は 1 文字で幅 2 になる
+        assert_eq!(guild("白氷 サーバー").initials(), "白");
+        assert_eq!(guild("").initials(), "");
+    }
+
+    #[test]
+    fn unsigned_url_never_expires() {
+        let url = "https://cdn.discordapp.com/emojis/123.png?size=48";
+        assert_eq!(signed_url_expiry(url), None);
+        assert!(!signed_url_expired(url, i64::MAX));
+    }
+
+    #[test]
+    fn interprets_message_flags() {
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "author": { "id": "3", "username": "bot", "discriminator": "0" },
+            "content": "",
+            "timestamp": "2024-01-01T00:00:00+00:00",
+            "flags": message_flags::SUPPRESS_EMBEDS | message_flags::LOADING,
+        }))
+        .unwrap();
+        assert!(message.embeds_suppressed());
+        assert!(message.is_loading());
+        assert!(!message.is_crosspost());
+        assert!(!message.is_ephemeral());
+    }
+
+    #[test]
+    fn allowed_mentions_only_restricts_what_was_turned_off() {
+        let quiet_reply = MessageReply {
+            message_id: "1".to_string(),
+            mention: false,
+        };
+        assert!(AllowedMentions::for_send(None, true).is_none());
+        let value = serde_json::to_value(AllowedMentions::for_send(Some(&quiet_reply), true));
+        assert_eq!(
+            value.unwrap(),
+            serde_json::json!({ "parse": ["users", "roles", "everyone"], "replied_user": false })
+        );
+        let value = serde_json::to_value(AllowedMentions::for_send(None, false));
+        assert_eq!(
+            value.unwrap(),
+            serde_json::json!({ "parse": ["users"], "replied_user": true })
+        );
+    }
+
+    #[test]
+    fn presence_sends_custom_status_and_activity_as_activities() {
+        let presence = Presence {
+            status: PresenceStatus::Idle,
+            custom_status: Some("working".to_string()),
+            activity: Some(Activity {
+                kind: ActivityType::Competing,
+                name: "Advent of Code".to_string(),
+            }),
+        };
+        assert_eq!(
+            presence.to_json(),
+            serde_json::json!({
+                "status": "idle",
+                "since": 0,
+                "activities": [
+                    { "type": 4, "name": "Custom Status", "state": "working" },
+                    { "type": 5, "name": "Advent of Code" },
+                ],
+                "afk": false
+            })
+        );
+
+        // 空のカスタムステータスは送らない
+        let presence = Presence {
+            custom_status: Some(String::new()),
+            ..Presence::default()
+        };
+        assert_eq!(presence.to_json()["activities"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,204 @@
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Discord のグローバルレート制限 (1 秒あたりのリクエスト数)
+const GLOBAL_PER_SEC: f64 = 50.0;
+/// ルート (チャンネル / サーバー単位) ごとに連続で送れる数
+const ROUTE_BURST: f64 = 5.0;
+/// ルートごとの 1 秒あたりの補充数
+const ROUTE_PER_SEC: f64 = 5.0;
+
+/// トークンバケット。トークンが足りない分は前借りし、補充されるまでの待ち時間を返す
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    capacity: f64,
+    per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            per_sec,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// 予算を使い切っていないか (新しく作ったバケットと同じ状態か)
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// 1 トークンを予約し、使えるようになるまでの待ち時間を返す (足りていれば 0)
+    fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_sec)
+        }
+    }
+}
+
+/// REST リクエストのレート制限。全体のバケットと、主要パラメータ
+/// (チャンネル / サーバー ID) ごとのバケットの両方に空きが出るまで待たせる
+#[derive(Debug)]
+pub struct RateLimiter {
+    global: Mutex<TokenBucket>,
+    routes: Mutex<HashMap<String, TokenBucket>>,
+    route_burst: f64,
+    route_per_sec: f64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(GLOBAL_PER_SEC, ROUTE_BURST, ROUTE_PER_SEC)
+    }
+}
+
+impl RateLimiter {
+    /// 全体は `global_per_sec` 件 / 秒 (同数まで連続可)、ルートごとは
+    /// `route_burst` 件まで連続で送れて `route_per_sec` 件 / 秒で補充される
+    pub fn new(global_per_sec: f64, route_burst: f64, route_per_sec: f64) -> Self {
+        Self {
+            global: Mutex::new(TokenBucket::new(global_per_sec, global_per_sec, Instant::now())),
+            routes: Mutex::new(HashMap::new()),
+            route_burst,
+            route_per_sec,
+        }
+    }
+
+    /// `url` へのリクエストを送ってよくなるまで待つ
+    pub async fn acquire(&self, url: &str) {
+        let wait = self.reserve(&route_key(url), Instant::now());
+        if !wait.is_zero() {
+            log::debug!("Rate limiter: waiting {:?} before {}", wait, url);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// `now` 時点でルート `route` への 1 リクエスト分を予約し、送れるまでの待ち時間を返す
+    fn reserve(&self, route: &str, now: Instant) -> Duration {
+        let mut global = self.global.lock().unwrap_or_else(|e| e.into_inner());
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        // 満タンまで補充されたバケットは作り直しても同じなので捨て、開いたチャンネルの数だけ
+        // バケットが増え続けないようにする
+        routes.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+        let bucket = routes
+            .entry(route.to_string())
+            .or_insert_with(|| TokenBucket::new(self.route_burst, self.route_per_sec, now));
+        // どちらのバケットも今の時点で消費する (遅い方に合わせて送るので、安全側に倒れる)
+        bucket.reserve(now).max(global.reserve(now))
+    }
+}
+
+/// 429 応答のヘッダーから再送まで待つ時間を取り出す。
+/// 小数で返る `X-RateLimit-Reset-After` を優先し、無ければ `Retry-After` (秒) を使う
+pub fn retry_after_header(headers: &HeaderMap) -> Option<Duration> {
+    ["x-ratelimit-reset-after", "retry-after"]
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok()?.trim().parse::<f64>().ok())
+        .find(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// レート制限のルート。Discord と同じく主要パラメータ (チャンネル / サーバー / webhook の ID)
+/// ごとに分け、それ以外はパスの先頭の区切りでまとめる
+fn route_key(url: &str) -> String {
+    let path = url.split_once("/api/v10/").map_or(url, |(_, path)| path);
+    let path = path.split('?').next().unwrap_or(path);
+    let mut segments = path.split('/');
+    match (segments.next(), segments.next()) {
+        (Some(major @ ("channels" | "guilds" | "webhooks")), Some(id)) => {
+            format!("{}/{}", major, id)
+        }
+        (Some(first), _) => first.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_is_spread_to_the_configured_rate() {
+        let limiter = RateLimiter::new(50.0, 5.0, 5.0);
+        let now = Instant::now();
+        let waits: Vec<Duration> = (0..10).map(|_| limiter.reserve("channels/1", now)).collect();
+        // 最初の 5 件はすぐ送れ、以降は 1 秒あたり 5 件に抑えられる
+        assert!(waits[..5].iter().all(|w| w.is_zero()));
+        for (i, wait) in waits[5..].iter().enumerate() {
+            let expected = (i + 1) as f64 / 5.0;
+            assert!((wait.as_secs_f64() - expected).abs() < 1e-6, "{:?}", waits);
+        }
+        // 別のチャンネルはルートの予算が別なので待たない
+        assert!(limiter.reserve("channels/2", now).is_zero());
+        // 時間が経てば補充される
+        assert!(limiter.reserve("channels/1", now + Duration::from_secs(3)).is_zero());
+    }
+
+    #[test]
+    fn idle_route_buckets_are_dropped() {
+        let limiter = RateLimiter::new(1000.0, 5.0, 5.0);
+        let now = Instant::now();
+        for i in 0..100 {
+            limiter.reserve(&format!("channels/{}", i), now);
+        }
+        assert_eq!(limiter.routes.lock().unwrap().len(), 100);
+        // 補充が済んだルートは次の予約のときに消え、使ったルートだけが残る
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.reserve("channels/1", later).is_zero());
+        assert_eq!(limiter.routes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn global_budget_bounds_requests_across_routes() {
+        let limiter = RateLimiter::new(10.0, 5.0, 5.0);
+        let now = Instant::now();
+        let waits: Vec<Duration> = (0..20)
+            .map(|i| limiter.reserve(&format!("channels/{}", i), now))
+            .collect();
+        // ルートはすべて別でも、全体で 1 秒あたり 10 件に抑えられる
+        assert!(waits[..10].iter().all(|w| w.is_zero()));
+        assert!((waits[10].as_secs_f64() - 0.1).abs() < 1e-6);
+        assert!((waits[19].as_secs_f64() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn retry_after_prefers_the_precise_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after_header(&headers), None);
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after_header(&headers), Some(Duration::from_secs(2)));
+        headers.insert("x-ratelimit-reset-after", "1.250".parse().unwrap());
+        assert_eq!(retry_after_header(&headers), Some(Duration::from_millis(1250)));
+        // 読めない値は飛ばして次のヘッダーを使う
+        headers.insert("x-ratelimit-reset-after", "soon".parse().unwrap());
+        assert_eq!(retry_after_header(&headers), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn routes_are_keyed_by_major_parameter() {
+        let base = "https://discord.com/api/v10";
+        assert_eq!(route_key(&format!("{}/channels/1/messages?limit=50", base)), "channels/1");
+        assert_eq!(route_key(&format!("{}/channels/1/messages/2/ack", base)), "channels/1");
+        assert_eq!(route_key(&format!("{}/guilds/9/channels", base)), "guilds/9");
+        assert_eq!(route_key(&format!("{}/users/@me/guilds?limit=200", base)), "users");
+    }
+}
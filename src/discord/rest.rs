@@ -1,52 +1,224 @@
+use super::error::{DiscordError, Result};
 use super::models::*;
-use anyhow::{Context, Result};
-use reqwest::Client;
-use std::time::Duration;
-
-/// `get_messages` 用のエラー型。HTTP status を取り出して呼び出し側で
-/// 一時エラーと永続エラーを区別できるようにする。
-#[derive(Debug)]
-pub enum RestError {
-    /// HTTP 応答エラー (4xx / 5xx)
-    Http { status: u16, body: String },
-    /// 送信失敗やネットワークエラー等
-    Network(anyhow::Error),
-}
-
-impl std::fmt::Display for RestError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            RestError::Http { status, body } => {
-                write!(f, "HTTP {} - {}", status, body)
-            }
-            RestError::Network(e) => write!(f, "network error: {}", e),
-        }
-    }
-}
-
-impl std::error::Error for RestError {}
+use super::rate_limit::{retry_after_header, RateLimiter};
+use super::retry::{is_transient_status, CircuitBreaker, RetryPolicy};
+use crate::config::{NetworkTimeouts, DEFAULT_MAX_CONCURRENT_REQUESTS};
+use reqwest::{Client, RequestBuilder, Response};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
 
 const API_BASE: &str = "https://discord.com/api/v10";
 
-/// Discord REST API クライアント
+/// 429 になったリクエストを待って再送する最大回数 (送信は呼び出し側で再送する)
+const RATE_LIMIT_MAX_RETRIES: u32 = 3;
+
+/// Discord REST API クライアント。
+/// clone したクライアント同士で同時リクエスト数の上限を共有する
 #[derive(Clone)]
 pub struct DiscordRestClient {
     client: Client,
     token: String,
+    permits: Arc<Semaphore>,
+    limiter: Arc<RateLimiter>,
+    /// 書き込み系のリクエストの最小間隔 (控えめモード)
+    min_write_interval: Option<Duration>,
+    /// 最後に書き込み系のリクエストを送った時刻
+    last_write: Arc<tokio::sync::Mutex<Option<Instant>>>,
+    /// 一時的な失敗の再試行回数と待ち時間
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+    /// サーキットブレーカーでリクエストを止めている間 true
+    outage: Arc<watch::Sender<bool>>,
+    /// 429 で待っている間、再送する時刻 (待っていなければ None)
+    rate_limited: Arc<watch::Sender<Option<Instant>>>,
 }
 
 impl DiscordRestClient {
-    /// 新しいREST APIクライアントを作成
-    pub fn new(token: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
+    /// 新しいREST APIクライアントを作成 (タイムアウトは設定値を使用)
+    pub fn new(token: String, timeouts: &NetworkTimeouts) -> Self {
+        let client = timeouts.http_client();
+        let retry = RetryPolicy::default();
+
+        Self {
+            client,
+            token,
+            permits: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_REQUESTS)),
+            limiter: Arc::new(RateLimiter::default()),
+            min_write_interval: None,
+            last_write: Arc::new(tokio::sync::Mutex::new(None)),
+            breaker: Arc::new(CircuitBreaker::new(retry.breaker_threshold, retry.breaker_cooldown)),
+            retry,
+            outage: Arc::new(watch::channel(false).0),
+            rate_limited: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    /// 一時的な失敗の再試行とサーキットブレーカーの設定を変える
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(
+            policy.breaker_threshold,
+            policy.breaker_cooldown,
+        ));
+        self.retry = policy;
+        self
+    }
+
+    /// サーキットブレーカーでリクエストを止めているか (true) の変化を受け取る
+    pub fn outage_updates(&self) -> watch::Receiver<bool> {
+        self.outage.subscribe()
+    }
+
+    /// 429 で待っている間の再送時刻 (None なら待っていない) の変化を受け取る
+    pub fn rate_limit_updates(&self) -> watch::Receiver<Option<Instant>> {
+        self.rate_limited.subscribe()
+    }
+
+    /// 同時に送る REST リクエスト数の上限を変える
+    pub fn with_max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(limit.max(1)));
+        self
+    }
+
+    /// 送信・リアクション・削除の間に最低 `interval` 空ける (控えめモード、None なら制限しない)
+    pub fn with_min_write_interval(mut self, interval: Option<Duration>) -> Self {
+        self.min_write_interval = interval;
+        self
+    }
+
+    /// 書き込み系のリクエストの前に呼ぶ。前回から最小間隔が経つまで待つ
+    /// (待っている間は後続の書き込みも順番待ちになる)
+    async fn pace_write(&self) {
+        let Some(interval) = self.min_write_interval else {
+            return;
+        };
+        let mut last = self.last_write.lock().await;
+        if let Some(at) = *last {
+            tokio::time::sleep_until((at + interval).into()).await;
+        }
+        *last = Some(Instant::now());
+    }
+
+    /// リクエストを送る前に呼ぶ。レート制限の予算を超えている場合は補充されるまで待ち、
+    /// それから同時リクエスト数の上限に空きが出るまで待つ (予算切れのルートが待っている間に
+    /// 枠を塞いで、他のルートのリクエストを止めないようにする)。
+    /// 返した permit を drop するまで枠を使い続ける
+    async fn throttle(&self, url: &str) -> SemaphorePermit<'_> {
+        self.limiter.acquire(url).await;
+        self.permits
+            .acquire()
+            .await
+            .expect("REST semaphore is never closed")
+    }
 
-        Self { client, token }
+    /// 認証ヘッダーを付けてリクエストを送る。タイムアウト・接続エラー・5xx は
+    /// 指数バックオフで再試行し、失敗が続いたらサーキットブレーカーでしばらく送らない。
+    /// `idempotent` が false (メッセージ送信) なら二重投稿を避けるため、届いていないと
+    /// 分かる接続エラーだけ再試行する。5xx のまま諦めたときは最後の応答を返す。
+    /// 429 は送信以外なら指定の時間だけ待って再送する (送信は呼び出し側で待って再送する)
+    async fn execute(
+        &self,
+        url: &str,
+        idempotent: bool,
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<Response> {
+        let mut attempt = 0;
+        let mut rate_limit_retries = 0;
+        loop {
+            if let Err(retry_in) = self.breaker.check(Instant::now()) {
+                return Err(DiscordError::Unavailable { retry_in });
+            }
+            let permit = self.throttle(url).await;
+            let result = build()
+                .header("Authorization", self.token.clone())
+                .header("User-Agent", "Hakuhyo/1.0")
+                .send()
+                .await;
+            // 待っている間は他のリクエストに枠を譲る
+            drop(permit);
+            match result {
+                // 429 は障害ではないので、ブレーカーには数えずに指定の時間だけ待って再送する
+                Ok(response)
+                    if response.status().as_u16() == 429
+                        && idempotent
+                        && rate_limit_retries < RATE_LIMIT_MAX_RETRIES =>
+                {
+                    // 応答は返っているので、お試しのリクエストなら回復とみなす
+                    self.record_success();
+                    rate_limit_retries += 1;
+                    let wait = retry_after_header(response.headers())
+                        .unwrap_or(Duration::from_secs(1));
+                    log::warn!("Rate limited on {}, retrying after {:?}", url, wait);
+                    self.wait_rate_limit(wait).await;
+                    continue;
+                }
+                Ok(response) if !is_transient_status(response.status().as_u16()) => {
+                    self.record_success();
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    self.record_failure();
+                    if !idempotent || attempt >= self.retry.max_retries {
+                        return Ok(response);
+                    }
+                    log::warn!("{} returned {}, retrying", url, response.status());
+                }
+                Err(e) => {
+                    self.record_failure();
+                    let retryable =
+                        e.is_connect() || (idempotent && (e.is_timeout() || e.is_request()));
+                    if !retryable || attempt >= self.retry.max_retries {
+                        return Err(DiscordError::Network(e));
+                    }
+                    log::warn!("Request to {} failed ({}), retrying", url, e);
+                }
+            }
+            attempt += 1;
+            tokio::time::sleep(self.retry.delay(attempt)).await;
+        }
     }
 
-    /// チャンネルのメッセージを取得。失敗時は HTTP status を含む構造化エラーを返す
+    /// 429 の指定どおり `wait` だけ待つ。待っている間は再送時刻を知らせ、
+    /// 他に待っているリクエストが無くなったら None に戻す
+    async fn wait_rate_limit(&self, wait: Duration) {
+        let until = Instant::now() + wait;
+        self.rate_limited.send_if_modified(|current| match current {
+            Some(later) if *later >= until => false,
+            _ => {
+                *current = Some(until);
+                true
+            }
+        });
+        tokio::time::sleep(wait).await;
+        self.rate_limited.send_if_modified(|current| match current {
+            Some(at) if *at <= Instant::now() => {
+                *current = None;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// 応答が返ったことをサーキットブレーカーに記録し、止めていたなら再開を知らせる
+    fn record_success(&self) {
+        if self.breaker.record_success() {
+            log::info!("Discord is reachable again, resuming requests");
+            self.outage.send_replace(false);
+        }
+    }
+
+    /// 一時的な失敗をサーキットブレーカーに記録し、止めることになったら知らせる
+    fn record_failure(&self) {
+        if self.breaker.record_failure(Instant::now()) {
+            log::warn!(
+                "Discord appears to be down, pausing requests for {:?}",
+                self.retry.breaker_cooldown
+            );
+            self.outage.send_replace(true);
+        }
+    }
+
+    /// チャンネルのメッセージを取得。失敗時は原因ごとの `DiscordError` を返す
     /// (呼び出し側で 4xx/5xx/ネットワークの違いを判別するため)。
     /// `before` を指定すると、その message_id より古いものを返す
     pub async fn get_messages(
@@ -54,7 +226,7 @@ impl DiscordRestClient {
         channel_id: &str,
         limit: u8,
         before: Option<&str>,
-    ) -> std::result::Result<Vec<Message>, RestError> {
+    ) -> Result<Vec<Message>> {
         let mut url = format!(
             "{}/channels/{}/messages?limit={}",
             API_BASE,
@@ -64,37 +236,129 @@ impl DiscordRestClient {
         if let Some(before_id) = before {
             url.push_str(&format!("&before={}", before_id));
         }
-        // レート制限対策: 最小間隔を設ける
-        tokio::time::sleep(Duration::from_millis(20)).await;
-        let response = self
-            .client
-            .get(&url)
-            .header("Authorization", self.token.clone())
-            .header("User-Agent", "Hakuhyo/1.0")
-            .send()
-            .await
-            .map_err(|e| RestError::Network(anyhow::Error::new(e)))?;
-        let status = response.status();
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(RestError::Http {
-                status: status.as_u16(),
-                body,
-            });
-        }
-        response
-            .json::<Vec<Message>>()
-            .await
-            .map_err(|e| RestError::Network(anyhow::Error::new(e).context("Failed to parse messages JSON")))
+        self.get(&url).await
     }
 
-    /// メッセージを送信
-    pub async fn send_message(&self, channel_id: &str, content: &str) -> Result<Message> {
+    /// `after` より新しいメッセージを取得 (キャッシュの差分更新用)
+    pub async fn get_messages_after(
+        &self,
+        channel_id: &str,
+        limit: u8,
+        after: &str,
+    ) -> Result<Vec<Message>> {
+        let url = format!(
+            "{}/channels/{}/messages?limit={}&after={}",
+            API_BASE,
+            channel_id,
+            limit.min(100),
+            after
+        );
+        self.get(&url).await
+    }
+
+    /// `around` の前後のメッセージを取得 (リンクからのジャンプ用。`around` 自体も含む)
+    pub async fn get_messages_around(
+        &self,
+        channel_id: &str,
+        limit: u8,
+        around: &str,
+    ) -> Result<Vec<Message>> {
+        let url = format!(
+            "{}/channels/{}/messages?limit={}&around={}",
+            API_BASE,
+            channel_id,
+            limit.min(100),
+            around
+        );
+        self.get(&url).await
+    }
+
+    /// ログイン中のユーザー (`GET /users/@me`)。トークンが無効なら `DiscordError::Unauthorized`
+    pub async fn get_current_user(&self) -> Result<User> {
+        self.get(&format!("{}/users/@me", API_BASE)).await
+    }
+
+    /// メッセージを送信。`reply_to` を指定すると同じチャンネルのそのメッセージへの返信になる
+    /// (`mention` が false なら返信先の作者に通知しない)。`mass_mentions` が false なら
+    /// @everyone / @here / ロールへのメンションで通知しない。
+    /// 429 (slowmode 含む) は呼び出し側で待機・再送できるよう
+    /// `DiscordError::RateLimited` として返す
+    pub async fn send_message(
+        &self,
+        channel_id: &str,
+        content: &str,
+        reply_to: Option<&MessageReply>,
+        mass_mentions: bool,
+    ) -> Result<Message> {
         let url = format!("{}/channels/{}/messages", API_BASE, channel_id);
         let payload = CreateMessagePayload {
             content: content.to_string(),
+            message_reference: reply_to.map(|reply| MessageReference {
+                message_id: reply.message_id.clone(),
+                channel_id: channel_id.to_string(),
+                fail_if_not_exists: false,
+            }),
+            allowed_mentions: AllowedMentions::for_send(reply_to, mass_mentions),
         };
-        self.post(&url, &payload).await
+        self.pace_write().await;
+        let response = self
+            .execute(&url, false, || self.client.post(&url).json(&payload))
+            .await?;
+        parse_json(check_status(response).await?).await
+    }
+
+    /// 自分のメッセージの本文を書き換える。同じ内容の再送は無害なので再試行してよい
+    pub async fn edit_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        content: &str,
+    ) -> Result<Message> {
+        let url = format!(
+            "{}/channels/{}/messages/{}",
+            API_BASE, channel_id, message_id
+        );
+        let payload = serde_json::json!({ "content": content });
+        self.pace_write().await;
+        let response = self
+            .execute(&url, true, || self.client.patch(&url).json(&payload))
+            .await?;
+        parse_json(check_status(response).await?).await
+    }
+
+    /// メッセージを削除する。429 の場合は指定の時間だけ待って再試行する
+    pub async fn delete_message(&self, channel_id: &str, message_id: &str) -> Result<()> {
+        let url = format!(
+            "{}/channels/{}/messages/{}",
+            API_BASE, channel_id, message_id
+        );
+        self.pace_write().await;
+        let response = self
+            .execute(&url, true, || self.client.delete(&url))
+            .await?;
+        check_status(response).await?;
+        Ok(())
+    }
+
+    /// メッセージに自分のリアクションを付ける。
+    /// `emoji` は Unicode 絵文字そのもの、またはカスタム絵文字の `name:id`
+    pub async fn add_reaction(&self, channel_id: &str, message_id: &str, emoji: &str) -> Result<()> {
+        // 絵文字はパスセグメントとしてパーセントエンコードする
+        let mut url =
+            reqwest::Url::parse(API_BASE).map_err(|e| DiscordError::InvalidUrl(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|()| DiscordError::InvalidUrl(API_BASE.to_string()))?
+            .extend([
+                "channels", channel_id, "messages", message_id, "reactions", emoji, "@me",
+            ]);
+        self.pace_write().await;
+        let response = self
+            .execute(url.as_str(), true, || {
+                self.client.put(url.clone()).header("Content-Length", "0")
+            })
+            .await?;
+        check_status(response).await?;
+        Ok(())
     }
 
     /// メッセージを既読としてマークする (ユーザーアカウント用)
@@ -105,27 +369,92 @@ impl DiscordRestClient {
             API_BASE, channel_id, message_id
         );
         let payload = serde_json::json!({ "token": serde_json::Value::Null });
-        tokio::time::sleep(Duration::from_millis(20)).await;
         let response = self
-            .client
-            .post(&url)
-            .header("Authorization", self.token.clone())
-            .header("User-Agent", "Hakuhyo/1.0")
-            .json(&payload)
-            .send()
-            .await
-            .context("Failed to send ack request")?;
-        let status = response.status();
-        if !status.is_success() {
-            let text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Ack failed with status {}: {}", status, text);
-        }
+            .execute(&url, true, || self.client.post(&url).json(&payload))
+            .await?;
+        check_status(response).await?;
         Ok(())
     }
 
+    /// 期限付きの添付ファイル URL を再署名する (`POST /attachments/refresh-urls`)。
+    /// 返り値は (元の URL, 新しい URL) の組
+    pub async fn refresh_attachment_urls(&self, urls: &[String]) -> Result<Vec<(String, String)>> {
+        let url = format!("{}/attachments/refresh-urls", API_BASE);
+        let payload = serde_json::json!({ "attachment_urls": urls });
+        let response = self
+            .execute(&url, true, || self.client.post(&url).json(&payload))
+            .await?;
+        let body: RefreshedUrlsResponse = parse_json(check_status(response).await?).await?;
+        Ok(body
+            .refreshed_urls
+            .into_iter()
+            .map(|r| (r.original, r.refreshed))
+            .collect())
+    }
+
+    /// 参加しているギルド一覧を取得 (`GET /users/@me/guilds`)。
+    /// 1 ページ最大 200 件なので `after` カーソルで全件取得する
+    pub async fn get_guilds(&self) -> Result<Vec<Guild>> {
+        const PAGE_LIMIT: usize = 200;
+        let mut guilds: Vec<Guild> = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut url = format!("{}/users/@me/guilds?limit={}", API_BASE, PAGE_LIMIT);
+            if let Some(after) = &after {
+                url.push_str(&format!("&after={}", after));
+            }
+            let (page, next) = parse_guilds_page(self.get(&url).await?, PAGE_LIMIT)?;
+            guilds.extend(page);
+            match next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        Ok(guilds)
+    }
+
+    /// ギルドのチャンネル一覧を取得 (`GET /guilds/{id}/channels`)。
+    /// guild_id が欠けている場合に備えて呼び出し元のギルド ID で補完する
+    pub async fn get_guild_channels(&self, guild_id: &str) -> Result<Vec<Channel>> {
+        let url = format!("{}/guilds/{}/channels", API_BASE, guild_id);
+        parse_guild_channels(self.get(&url).await?, guild_id)
+    }
+
+    /// ギルド内のアクティブなスレッド (フォーラム投稿を含む) を取得
+    /// (`GET /guilds/{id}/threads/active`)
+    pub async fn get_active_threads(&self, guild_id: &str) -> Result<Vec<Channel>> {
+        let url = format!("{}/guilds/{}/threads/active", API_BASE, guild_id);
+        let response: ActiveThreadsResponse = self.get(&url).await?;
+        let mut threads = response.threads;
+        for thread in threads.iter_mut() {
+            if thread.guild_id.is_none() {
+                thread.guild_id = Some(guild_id.to_string());
+            }
+        }
+        Ok(threads)
+    }
+
+    /// ユーザー情報を取得 (`GET /users/{id}`)
+    pub async fn get_user(&self, user_id: &str) -> Result<User> {
+        let url = format!("{}/users/{}", API_BASE, user_id);
+        self.get(&url).await
+    }
+
+    /// DM / グループ DM 一覧を取得 (`GET /users/@me/channels`)。
+    /// READY の private_channels と形を揃えるため recipient_ids も埋める
+    pub async fn get_dm_channels(&self) -> Result<Vec<Channel>> {
+        let url = format!("{}/users/@me/channels", API_BASE);
+        parse_dm_channels(self.get(&url).await?)
+    }
+
+    /// チャンネルを 1 つ取得 (`GET /channels/{id}`)。DM なら recipient_ids も埋める
+    pub async fn get_channel(&self, channel_id: &str) -> Result<Channel> {
+        let url = format!("{}/channels/{}", API_BASE, channel_id);
+        let mut channel: Channel = self.get(&url).await?;
+        fill_recipient_ids(&mut channel);
+        Ok(channel)
+    }
+
     /// Gateway URLを取得
     pub async fn get_gateway_url(&self) -> Result<String> {
         // ユーザーアカウント認証対応: /gateway エンドポイントを使用
@@ -136,74 +465,150 @@ impl DiscordRestClient {
 
     /// GETリクエストを送信
     async fn get<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
-        // レート制限対策: 最小間隔を設ける
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        // 同時リクエスト数・頻度の制限と一時的な失敗の再試行は execute が行う
+        let response = self.execute(url, true, || self.client.get(url)).await?;
+        parse_json(check_status(response).await?).await
+    }
+}
 
-        // トークンをそのまま使用（ユーザーアカウント認証対応）
-        let auth_header = self.token.clone();
+/// 成功以外の応答を、status ごとの `DiscordError` にする
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(DiscordError::from_status(status.as_u16(), body))
+}
 
-        let response = self
-            .client
-            .get(url)
-            .header("Authorization", auth_header)
-            .header("User-Agent", "Hakuhyo/1.0")
-            .send()
-            .await
-            .context("Failed to send GET request")?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Request failed with status {}: {}", status, error_text);
+/// 応答 body を JSON として読む (読み込みの失敗は Network、解釈の失敗は Parse)
+async fn parse_json<T: serde::de::DeserializeOwned>(response: Response) -> Result<T> {
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// REST の DM は recipients だけを返すので、READY と同じく recipient_ids を埋める
+/// `GET /users/@me/guilds` の 1 ページを解釈し、続きがあれば次の `after` カーソルも返す
+/// (`limit` 件ちょうど返ってきたら、最後のギルド ID の次から取得する)
+fn parse_guilds_page(
+    page: serde_json::Value,
+    limit: usize,
+) -> Result<(Vec<Guild>, Option<String>)> {
+    let guilds: Vec<Guild> = serde_json::from_value(page)?;
+    let next = if guilds.len() < limit {
+        None
+    } else {
+        guilds.last().map(|g| g.id.clone())
+    };
+    Ok((guilds, next))
+}
+
+/// `GET /guilds/{id}/channels` の応答を解釈し、欠けている guild_id を補う
+fn parse_guild_channels(value: serde_json::Value, guild_id: &str) -> Result<Vec<Channel>> {
+    let mut channels: Vec<Channel> = serde_json::from_value(value)?;
+    for channel in channels.iter_mut() {
+        if channel.guild_id.is_none() {
+            channel.guild_id = Some(guild_id.to_string());
         }
+    }
+    Ok(channels)
+}
 
-        let data = response
-            .json::<T>()
-            .await
-            .context("Failed to parse JSON response")?;
+/// `GET /users/@me/channels` の応答を解釈し、READY と同じく recipient_ids も埋める
+fn parse_dm_channels(value: serde_json::Value) -> Result<Vec<Channel>> {
+    let mut channels: Vec<Channel> = serde_json::from_value(value)?;
+    channels.iter_mut().for_each(fill_recipient_ids);
+    Ok(channels)
+}
 
-        Ok(data)
+fn fill_recipient_ids(channel: &mut Channel) {
+    if channel.recipient_ids.is_none() {
+        channel.recipient_ids = channel
+            .recipients
+            .as_ref()
+            .map(|users| users.iter().map(|u| u.id.clone()).collect());
     }
+}
 
-    /// POSTリクエストを送信
-    async fn post<T: serde::Serialize, R: serde::de::DeserializeOwned>(
-        &self,
-        url: &str,
-        payload: &T,
-    ) -> Result<R> {
-        // レート制限対策: 最小間隔を設ける
-        tokio::time::sleep(Duration::from_millis(20)).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // トークンをそのまま使用（ユーザーアカウント認証対応）
-        let auth_header = self.token.clone();
+    fn fixture(json: &str) -> serde_json::Value {
+        serde_json::from_str(json).unwrap()
+    }
 
-        let response = self
-            .client
-            .post(url)
-            .header("Authorization", auth_header)
-            .header("User-Agent", "Hakuhyo/1.0")
-            .json(payload)
-            .send()
-            .await
-            .context("Failed to send POST request")?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Request failed with status {}: {}", status, error_text);
-        }
+    #[test]
+    fn guild_pages_continue_after_the_last_id_until_a_short_page() {
+        let page1 = fixture(include_str!("fixtures/guilds_page1.json"));
+        let (guilds, next) = parse_guilds_page(page1, 2).unwrap();
+        let names: Vec<&str> = guilds.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["Discord API", "Hakuhyo Dev"]);
+        assert_eq!(guilds[0].icon.as_deref(), Some("a363a84e969bcbe1353eb2fdfb2e50e6"));
+        assert_eq!(next.as_deref(), Some("613425648685547541"));
 
-        let data = response
-            .json::<R>()
-            .await
-            .context("Failed to parse JSON response")?;
+        let page2 = fixture(include_str!("fixtures/guilds_page2.json"));
+        let (guilds, next) = parse_guilds_page(page2, 2).unwrap();
+        assert_eq!(guilds[0].name, "Rust 日本語");
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn guild_channels_get_the_requested_guild_id_when_missing() {
+        let value = fixture(include_str!("fixtures/guild_channels.json"));
+        let channels = parse_guild_channels(value, "613425648685547541").unwrap();
+        assert_eq!(channels.len(), 3);
+        assert!(channels
+            .iter()
+            .all(|c| c.guild_id.as_deref() == Some("613425648685547541")));
+        let general = &channels[1];
+        assert_eq!(general.name.as_deref(), Some("general"));
+        assert_eq!(general.parent_id.as_deref(), Some("613425648685547542"));
+        assert_eq!(general.last_message_id.as_deref(), Some("1251123456789012345"));
+        let forum = &channels[2];
+        assert!(forum.is_forum() && forum.nsfw);
+        assert_eq!(forum.rate_limit_per_user, Some(10));
+    }
+
+    #[test]
+    fn dm_channels_fill_recipient_ids_like_ready() {
+        let value = fixture(include_str!("fixtures/dm_channels.json"));
+        let channels = parse_dm_channels(value).unwrap();
+        assert_eq!(channels[0].display_name(), "alice");
+        assert_eq!(channels[0].recipient_ids, Some(vec!["82198898841029460".to_string()]));
+        assert_eq!(channels[1].display_name(), "weekend plans");
+        assert_eq!(
+            channels[1].recipient_ids,
+            Some(vec!["82198898841029460".to_string(), "82198898841029461".to_string()])
+        );
+        assert!(parse_dm_channels(serde_json::json!({ "message": "oops" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn writes_are_spaced_by_the_minimum_interval() {
+        let interval = Duration::from_millis(50);
+        let timeouts = crate::config::Config::default().network_timeouts();
+        let client = DiscordRestClient::new(String::new(), &timeouts)
+            .with_min_write_interval(Some(interval));
+        let start = Instant::now();
+        client.pace_write().await;
+        // 初回は待たない
+        assert!(start.elapsed() < interval);
+        client.pace_write().await;
+        assert!(start.elapsed() >= interval);
+    }
+
+    #[test]
+    fn concurrent_requests_are_capped_by_the_configured_limit() {
+        let config = crate::config::Config::default();
+        let timeouts = config.network_timeouts();
+        let client = DiscordRestClient::new(String::new(), &timeouts);
+        assert_eq!(client.permits.available_permits(), config.max_concurrent_requests());
 
-        Ok(data)
+        let client = client.with_max_concurrent_requests(2);
+        assert_eq!(client.permits.available_permits(), 2);
+        // 0 では何も送れなくなるので 1 に切り上げる
+        let client = client.with_max_concurrent_requests(0);
+        assert_eq!(client.permits.available_permits(), 1);
     }
 }
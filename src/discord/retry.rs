@@ -0,0 +1,176 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 一時的な失敗 (タイムアウト・接続エラー・5xx) の再試行とサーキットブレーカーの設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 1 リクエストあたりの再試行回数 (0 なら再試行しない)
+    pub max_retries: u32,
+    /// 1 回目の再試行までの待ち時間 (以降は倍々に伸ばす)
+    pub base_delay: Duration,
+    /// 再試行の待ち時間の上限
+    pub max_delay: Duration,
+    /// 連続でこの回数失敗したらリクエストを止める (0 なら止めない)
+    pub breaker_threshold: u32,
+    /// リクエストを止めておく時間
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// 設定ファイルの既定値と同じ設定
+    fn default() -> Self {
+        crate::config::Config::default().retry_policy()
+    }
+}
+
+impl RetryPolicy {
+    /// `attempt` 回目 (1 始まり) の再試行の前に待つ時間 (指数バックオフ、上限 max_delay)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// 一時的な失敗として再試行してよい HTTP status か (429 は別に扱う)
+pub fn is_transient_status(status: u16) -> bool {
+    matches!(status, 500 | 502 | 503 | 504)
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// 止めている時間が過ぎた後、お試しの 1 件を通した時刻 (結果が出るまで他は止める)
+    trial_since: Option<Instant>,
+}
+
+/// 連続して失敗したら一定時間リクエストを止めるサーキットブレーカー。
+/// Discord 側の障害中に再試行を重ねて負荷をかけないようにする
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// `now` 時点でリクエストを送ってよいか。止めている間は再開までの残り時間を返す。
+    /// 止めている時間が過ぎたら、お試しの 1 件だけを通し (半開状態)、その結果が
+    /// 記録されるまで他のリクエストは止めたままにする。お試しが結果を残さずに
+    /// (中断などで) cooldown が過ぎたら、次の 1 件をお試しとして通す
+    pub fn check(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.open_until {
+            Some(until) if until > now => Err(until - now),
+            Some(_) => {
+                if let Some(stale_at) = state.trial_since.map(|since| since + self.cooldown) {
+                    if stale_at > now {
+                        return Err(stale_at - now);
+                    }
+                }
+                state.trial_since = Some(now);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// 成功を記録する。止めていた状態から回復したら true
+    pub fn record_success(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.consecutive_failures = 0;
+        state.trial_since = None;
+        state.open_until.take().is_some()
+    }
+
+    /// 一時的な失敗を記録する。これでリクエストを止めることになったら true
+    pub fn record_failure(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.consecutive_failures += 1;
+        state.trial_since = None;
+        if self.threshold == 0 || state.consecutive_failures < self.threshold {
+            return false;
+        }
+        // 再開後のお試しリクエストが失敗したら、また同じ時間止める
+        let newly_open = state.open_until.is_none();
+        state.open_until = Some(now + self.cooldown);
+        newly_open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u64> = (1..=6).map(|n| policy.delay(n).as_millis() as u64).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 8000]);
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures_and_recovers() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        let now = Instant::now();
+        assert!(!breaker.record_failure(now));
+        assert!(!breaker.record_failure(now));
+        // 成功を挟むと数え直す
+        assert!(!breaker.record_success());
+        assert!(!breaker.record_failure(now));
+        assert!(!breaker.record_failure(now));
+        assert!(breaker.check(now).is_ok());
+        assert!(breaker.record_failure(now));
+        assert_eq!(breaker.check(now), Err(Duration::from_secs(30)));
+
+        // 止めている時間が過ぎたら 1 件通し、失敗すればまた止める (通知は最初の 1 回だけ)
+        let later = now + Duration::from_secs(31);
+        assert!(breaker.check(later).is_ok());
+        assert!(!breaker.record_failure(later));
+        assert!(breaker.check(later).is_err());
+
+        // お試しの 1 件が成功するまで、同時に来た他のリクエストは通さない
+        let retry_at = later + Duration::from_secs(31);
+        assert!(breaker.check(retry_at).is_ok());
+        assert!(breaker.check(retry_at).is_err());
+        assert!(breaker.check(retry_at + Duration::from_secs(1)).is_err());
+        assert!(breaker.record_success());
+        assert!(breaker.check(retry_at).is_ok());
+        assert!(breaker.check(retry_at).is_ok());
+    }
+
+    #[test]
+    fn abandoned_trial_request_does_not_block_forever() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(30));
+        let now = Instant::now();
+        assert!(breaker.record_failure(now));
+        let later = now + Duration::from_secs(30);
+        assert!(breaker.check(later).is_ok());
+        // お試しが結果を残さないまま cooldown が過ぎたら、次の 1 件を通す
+        assert_eq!(breaker.check(later + Duration::from_secs(10)), Err(Duration::from_secs(20)));
+        assert!(breaker.check(later + Duration::from_secs(30)).is_ok());
+        assert!(breaker.check(later + Duration::from_secs(30)).is_err());
+    }
+
+    #[test]
+    fn default_policy_matches_the_config_defaults() {
+        assert_eq!(RetryPolicy::default(), crate::config::Config::default().retry_policy());
+        assert_eq!(RetryPolicy::default().max_retries, 2);
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        let now = Instant::now();
+        assert!((0..100).all(|_| !breaker.record_failure(now)));
+        assert!(breaker.check(now).is_ok());
+    }
+}
@@ -3,7 +3,6 @@
 pub enum MessageSegment {
     Text(String),
     Emoji {
-        #[allow(dead_code)]
         name: String,
         id: String,
         #[allow(dead_code)]
@@ -73,7 +72,8 @@ fn try_parse_emoji(s: &str) -> Option<MessageSegment> {
     })
 }
 
-/// 絵文字画像の取得 URL を構築
+/// 絵文字画像の取得 URL を構築。
+/// アニメーション絵文字 (`<a:...>`) も png 指定で静止画 (先頭フレーム) が返る
 pub fn emoji_cdn_url(id: &str) -> String {
     format!("https://cdn.discordapp.com/emojis/{}.png?size=64", id)
 }
@@ -117,3 +117,124 @@ fn flatten_alpha(img: &image::DynamicImage, bg: [u8; 3]) -> image::DynamicImage
     });
     image::DynamicImage::ImageRgba8(composited)
 }
+
+/// リアクションピッカーの候補 (Unicode 絵文字 or サーバーのカスタム絵文字)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickableEmoji {
+    /// ショートコード名 (`thumbsup`) / カスタム絵文字名
+    pub name: String,
+    /// Unicode 絵文字本体 (カスタム絵文字なら None)
+    pub unicode: Option<String>,
+    /// カスタム絵文字の ID
+    pub id: Option<String>,
+}
+
+impl PickableEmoji {
+    pub fn unicode(name: &str, emoji: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            unicode: Some(emoji.to_string()),
+            id: None,
+        }
+    }
+
+    pub fn custom(name: &str, id: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            unicode: None,
+            id: Some(id.to_string()),
+        }
+    }
+
+    /// リアクション API に渡す値 (Unicode 絵文字そのもの / `name:id`)。
+    /// 最近使った絵文字の保存キーにも使う
+    pub fn reaction_key(&self) -> String {
+        match (&self.id, &self.unicode) {
+            (Some(id), _) => format!("{}:{}", self.name, id),
+            (None, Some(emoji)) => emoji.clone(),
+            (None, None) => self.name.clone(),
+        }
+    }
+
+    /// ピッカーの一覧表示用テキスト
+    pub fn label(&self) -> String {
+        match &self.unicode {
+            Some(emoji) => format!("{}  :{}:", emoji, self.name),
+            None => format!("[custom]  :{}:", self.name),
+        }
+    }
+}
+
+/// ピッカーに常に並べる Unicode 絵文字 (ショートコード, 絵文字)
+pub const COMMON_EMOJI: &[(&str, &str)] = &[
+    ("thumbsup", "👍"),
+    ("thumbsdown", "👎"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("rofl", "🤣"),
+    ("smile", "😄"),
+    ("grin", "😁"),
+    ("sweat_smile", "😅"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("heart_eyes", "😍"),
+    ("thinking", "🤔"),
+    ("eyes", "👀"),
+    ("sob", "😭"),
+    ("cry", "😢"),
+    ("scream", "😱"),
+    ("rage", "😡"),
+    ("open_mouth", "😮"),
+    ("neutral_face", "😐"),
+    ("sunglasses", "😎"),
+    ("pray", "🙏"),
+    ("clap", "👏"),
+    ("ok_hand", "👌"),
+    ("wave", "👋"),
+    ("muscle", "💪"),
+    ("raised_hands", "🙌"),
+    ("point_up", "☝️"),
+    ("fire", "🔥"),
+    ("tada", "🎉"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("100", "💯"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("warning", "⚠️"),
+    ("question", "❓"),
+    ("exclamation", "❗"),
+    ("rocket", "🚀"),
+    ("bug", "🐛"),
+    ("zap", "⚡"),
+    ("bulb", "💡"),
+    ("memo", "📝"),
+    ("pushpin", "📌"),
+    ("coffee", "☕"),
+    ("beer", "🍺"),
+    ("pizza", "🍕"),
+    ("cat", "🐱"),
+    ("dog", "🐶"),
+    ("skull", "💀"),
+    ("ghost", "👻"),
+    ("broken_heart", "💔"),
+    ("sweat", "😓"),
+    ("sleeping", "😴"),
+    ("innocent", "😇"),
+    ("upside_down", "🙃"),
+    ("facepalm", "🤦"),
+    ("shrug", "🤷"),
+    ("saluting_face", "🫡"),
+    ("plus1", "➕"),
+    ("arrow_up", "⬆️"),
+];
+
+/// 候補を名前 (ショートコード) で絞り込む。大文字小文字は区別せず部分一致。
+/// 候補の並び (最近使ったもの → サーバー → 標準) は保ったまま返す
+pub fn filter_emoji<'a>(candidates: &'a [PickableEmoji], query: &str) -> Vec<&'a PickableEmoji> {
+    let query = query.trim().trim_matches(':').to_lowercase();
+    candidates
+        .iter()
+        .filter(|e| query.is_empty() || e.name.to_lowercase().contains(&query))
+        .collect()
+}
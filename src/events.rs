@@ -1,5 +1,9 @@
-use crate::discord::{Channel, Guild, Message};
+use crate::discord::{
+    Channel, Guild, GuildMember, Message, ReadySupplemental, Relationship, User, UserStatus,
+    VoiceState,
+};
 use crossterm::event::KeyCode;
+use std::time::Instant;
 
 /// アプリケーションイベント
 #[derive(Debug, Clone)]
@@ -8,27 +12,82 @@ pub enum AppEvent {
     // UI イベント
     /// キー入力
     KeyPress(KeyCode),
+    /// 貼り付けたテキスト (bracketed paste。改行を含めて 1 つの入力として扱う)
+    Paste(String),
     /// 文字入力（編集モード時）
     #[allow(dead_code)]
     Input(char),
+    /// 描画で画面に出た、開いているチャンネルの最新のメッセージ (既読位置として記録する)
+    MessageViewed { channel_id: String, message_id: String },
 
     // Discord イベント（Gateway）
     /// Gateway接続完了（READY イベント全体）
     GatewayReady(serde_json::Value),
-    /// ギルド作成（READY後の新規ギルド参加用）
-    GuildCreate { guild: Guild, channels: Vec<Channel> },
+    /// READY の補足 (メンバー・プレゼンス。ユーザーアカウントのみ)
+    ReadySupplemental(ReadySupplemental),
+    /// 他ユーザーのオンライン状態の変化 (PRESENCE_UPDATE)
+    PresenceUpdate { user_id: String, status: UserStatus },
+    /// フレンド・ブロック・申請の追加と変更 (RELATIONSHIP_ADD)
+    RelationshipAdd(Relationship),
+    /// フレンド・ブロック・申請の解除 (RELATIONSHIP_REMOVE)
+    RelationshipRemove { id: String },
+    /// 切断後の RESUME が完了した
+    GatewayResumed,
+    /// Gateway の接続が切れた (自動で再接続中)
+    GatewayDisconnected,
+    /// ギルドとそのチャンネルの追加 (GUILD_CREATE / REST での一覧取得)。
+    /// 起動直後に続けて届く分は 1 つにまとめて処理する
+    GuildsCreated(Vec<(Guild, Vec<Channel>)>),
+    /// ボイスチャンネルへの接続・切断・移動
+    VoiceStateUpdate(VoiceState),
     /// スレッド作成 / 更新（フォーラム投稿含む）
     ThreadUpsert(Channel),
     /// スレッド削除 / アーカイブ
     ThreadDelete { id: String },
+    /// チャンネル削除 (サーバーのチャンネル・DM を閉じた場合)
+    ChannelDelete { id: String },
     /// 新規メッセージ
     MessageCreate(Message),
     /// メッセージ更新
     MessageUpdate(Message),
     /// メッセージ削除
     MessageDelete { id: String, channel_id: String },
+    /// メッセージ一括削除 (MESSAGE_DELETE_BULK)
+    MessageDeleteBulk { ids: Vec<String>, channel_id: String },
+    /// REQUEST_GUILD_MEMBERS (op 8) の応答
+    GuildMembersChunk {
+        guild_id: String,
+        members: Vec<GuildMember>,
+    },
+
+    /// `GET /users/@me` が 401 になった (トークンが失効している)
+    TokenInvalid,
+
+    /// Gateway に接続できず再試行を諦めた (アプリを終了する)
+    GatewayFailed(String),
+    /// REST の失敗が続いてリクエストを止めた (true) / 再開した (false)
+    RestOutage(bool),
+    /// REST が 429 で待っている間の再送時刻 (待ち終わったら None)
+    RestRateLimited(Option<Instant>),
 
     // コマンド完了イベント（REST API の結果）
+    /// REST で取得したユーザー情報 (DM 相手の補完用)
+    UsersResolved(Vec<User>),
+    /// DM チャンネル一覧の読み込み完了
+    DmChannelsLoaded(Vec<Channel>),
+    /// 一覧に無かったチャンネルを REST で取得した (新着の届いた知らない DM など)
+    ChannelFetched(Channel),
+    /// REST でのチャンネル取得に失敗した。`gone` は 404 / 403 (削除済み・アクセス不可) のとき
+    ChannelFetchFailed { channel_id: String, gone: bool },
+    /// トークンを保存済みのプロファイル一覧 (アカウント切り替え用)
+    ProfilesListed(Vec<String>),
+    /// REST での DM / ギルドチャンネル一覧の読み込み完了 (complete: 全ギルドの取得に成功したか)
+    ChannelsLoaded { complete: bool },
+    /// フォーラムの投稿 (スレッド) 一覧の読み込み完了
+    ForumPostsLoaded {
+        forum_id: String,
+        threads: Vec<Channel>,
+    },
     /// メッセージ一覧読み込み完了
     MessagesLoaded {
         channel_id: String,
@@ -36,6 +95,32 @@ pub enum AppEvent {
     },
     /// メッセージ送信完了
     MessageSent(Message),
+    /// 送信に失敗した (再送の上限を超えた 429 を含む)
+    SendFailed { channel_id: String, error: String },
+    /// 編集・削除などの操作が失敗した (内容をステータスバーに表示する)
+    ActionFailed(String),
+    /// 送信が 429 になり、retry_after 秒後に再送する (slowmode なら slowmode = true)
+    SendRateLimited {
+        channel_id: String,
+        retry_after: f64,
+        slowmode: bool,
+    },
+    /// キャッシュより新しいメッセージの差分取得完了
+    NewerMessagesLoaded {
+        channel_id: String,
+        messages: Vec<Message>,
+    },
+    /// ポーリング (`--no-gateway`) で取得した新着。失敗時は空
+    ChannelPolled {
+        channel_id: String,
+        messages: Vec<Message>,
+    },
+    /// リンクで指定したメッセージの前後を取得完了
+    MessagesAroundLoaded {
+        channel_id: String,
+        message_id: String,
+        messages: Vec<Message>,
+    },
     /// 過去のメッセージを追加で読み込み完了
     OlderMessagesLoaded {
         channel_id: String,
@@ -48,6 +133,19 @@ pub enum AppEvent {
         channel_id: String,
         permanent: bool,
     },
+    /// 自分のメッセージ一括削除の進捗 (1 件削除するごと)
+    PurgeProgress {
+        channel_id: String,
+        message_id: String,
+        deleted: usize,
+        total: usize,
+    },
+    /// 自分のメッセージ一括削除が終了 (完了 / キャンセル / エラー)
+    PurgeFinished {
+        deleted: usize,
+        cancelled: bool,
+        error: Option<String>,
+    },
     /// メッセージリストを行単位でスクロール (正: 古い側へ / 負: 新しい側へ)
     ScrollMessages(i32),
     /// 画像添付ファイルのデコード完了 (DynamicImage は重いので Box で包む)
@@ -57,6 +155,8 @@ pub enum AppEvent {
     },
     /// 画像添付ファイルのダウンロード/デコード失敗 (再試行可能にするためロック解除用)
     AttachmentImageFailed { attachment_id: String },
+    /// 期限切れの添付ファイル URL を再署名した
+    AttachmentUrlRefreshed { attachment_id: String, url: String },
     /// カスタム絵文字のデコード完了
     EmojiImageLoaded {
         emoji_id: String,
@@ -64,10 +164,26 @@ pub enum AppEvent {
     },
     /// カスタム絵文字のダウンロード失敗 (ロック解除用)
     EmojiImageFailed { emoji_id: String },
+    /// ユーザーアバターのデコード完了
+    AvatarImageLoaded {
+        user_id: String,
+        image: Box<image::DynamicImage>,
+    },
+    /// ユーザーアバターのダウンロード失敗
+    AvatarImageFailed { user_id: String },
+    /// サーバーアイコンのデコード完了
+    GuildIconLoaded {
+        guild_id: String,
+        image: Box<image::DynamicImage>,
+    },
+    /// サーバーアイコンのダウンロード失敗
+    GuildIconFailed { guild_id: String },
 
     // システムイベント
     /// 定期的な描画更新
     Tick,
+    /// ポーリング (`--no-gateway`) で新着を確認する時刻になった
+    PollTick,
     /// アプリケーション終了
     Quit,
 }
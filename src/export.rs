@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::discord::{DiscordRestClient, Message};
+use crate::render;
+
+/// 書き出し形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Text,
+}
+
+impl ExportFormat {
+    /// デフォルトの出力ファイル拡張子
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Text => "txt",
+        }
+    }
+}
+
+/// JSON 書き出し用の 1 メッセージ分のレコード
+#[derive(Debug, Serialize)]
+struct ExportEntry<'a> {
+    id: &'a str,
+    author: &'a str,
+    author_id: &'a str,
+    timestamp: &'a str,
+    content: &'a str,
+    attachments: Vec<&'a str>,
+}
+
+/// 1 リクエストあたりの最大取得件数 (Discord API の上限)
+const PAGE_SIZE: usize = 100;
+
+/// チャンネルの履歴を新しい順に最大 `limit` 件取得し、古い順に並べてファイルへ書き出す。
+/// 返り値は書き出したメッセージ数。
+pub async fn export_channel(
+    rest: &DiscordRestClient,
+    channel_id: &str,
+    limit: usize,
+    format: ExportFormat,
+    output: &Path,
+) -> Result<usize> {
+    let mut messages: Vec<Message> = Vec::new();
+    let mut before: Option<String> = None;
+
+    // before カーソルでページングしながら遡る
+    while messages.len() < limit {
+        let page_size = (limit - messages.len()).min(PAGE_SIZE) as u8;
+        let page = rest
+            .get_messages(channel_id, page_size, before.as_deref())
+            .await
+            .with_context(|| format!("Failed to fetch messages for channel {}", channel_id))?;
+        let Some(oldest) = page.last() else {
+            break;
+        };
+        before = Some(oldest.id.clone());
+        let fetched = page.len();
+        messages.extend(page);
+        log::info!("Export: fetched {} messages (total {})", fetched, messages.len());
+        println!("  {} messages fetched...", messages.len());
+        if fetched < page_size as usize {
+            // 最古まで到達
+            break;
+        }
+    }
+
+    // REST は新→古順なので、トランスクリプトとして読みやすい古→新順に並べ替える
+    messages.reverse();
+
+    let content = match format {
+        ExportFormat::Json => render_json(&messages)?,
+        ExportFormat::Text => render_text(&messages),
+    };
+    fs::write(output, content)
+        .with_context(|| format!("Failed to write export file: {:?}", output))?;
+
+    log::info!("✓ Exported {} messages to {:?}", messages.len(), output);
+    Ok(messages.len())
+}
+
+/// JSON 配列として整形
+fn render_json(messages: &[Message]) -> Result<String> {
+    let entries: Vec<ExportEntry> = messages
+        .iter()
+        .map(|m| ExportEntry {
+            id: &m.id,
+            author: m.author_display_name(),
+            author_id: &m.author.id,
+            timestamp: &m.timestamp,
+            content: &m.content,
+            attachments: m
+                .attachments
+                .iter()
+                .filter_map(|a| a.url.as_deref())
+                .collect(),
+        })
+        .collect();
+    serde_json::to_string_pretty(&entries).context("Failed to serialize export")
+}
+
+/// `[YYYY-MM-DD HH:MM:SS] author: content` 形式のプレーンテキストとして整形
+/// (時刻は画面表示と同じく日本時間)
+fn render_text(messages: &[Message]) -> String {
+    let mut out = String::new();
+    for m in messages {
+        let time = render::to_local(&m.timestamp)
+            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| m.timestamp.clone());
+        out.push_str(&format!(
+            "[{}] {}: {}\n",
+            time,
+            m.author_display_name(),
+            m.content
+        ));
+        for att in &m.attachments {
+            let url = att.url.as_deref().unwrap_or("");
+            out.push_str(&format!("    [{}] {}\n", att.filename, url));
+        }
+        for sticker in &m.sticker_items {
+            out.push_str(&format!("    {}\n", sticker.display_text()));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 古い順に並べた 2 件 (表示名付きの作者・添付ファイル付き)
+    fn transcript() -> Vec<Message> {
+        [
+            json!({
+                "id": "301",
+                "channel_id": "200",
+                "author": {
+                    "id": "10",
+                    "username": "alice",
+                    "global_name": "Alice",
+                    "discriminator": "0",
+                },
+                "content": "hello",
+                "timestamp": "2024-01-01T15:30:00+00:00",
+                "attachments": [{
+                    "id": "900",
+                    "filename": "cat.png",
+                    "content_type": "image/png",
+                    "url": "https://cdn.example/cat.png",
+                }],
+            }),
+            json!({
+                "id": "302",
+                "channel_id": "200",
+                "author": { "id": "11", "username": "bob", "discriminator": "0" },
+                "content": "nice",
+                "timestamp": "2024-01-01T15:31:05.123000+00:00",
+            }),
+        ]
+        .into_iter()
+        .map(|value| serde_json::from_value(value).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn text_transcript_uses_display_names_and_japan_time() {
+        // UTC 15:30 は日本時間で翌日の 00:30
+        assert_eq!(
+            render_text(&transcript()),
+            concat!(
+                "[2024-01-02 00:30:00] Alice: hello\n",
+                "    [cat.png] https://cdn.example/cat.png\n",
+                "[2024-01-02 00:31:05] bob: nice\n",
+            )
+        );
+    }
+
+    #[test]
+    fn json_transcript_keeps_ids_timestamps_and_attachment_urls() {
+        let value: serde_json::Value =
+            serde_json::from_str(&render_json(&transcript()).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!([
+                {
+                    "id": "301",
+                    "author": "Alice",
+                    "author_id": "10",
+                    "timestamp": "2024-01-01T15:30:00+00:00",
+                    "content": "hello",
+                    "attachments": ["https://cdn.example/cat.png"],
+                },
+                {
+                    "id": "302",
+                    "author": "bob",
+                    "author_id": "11",
+                    "timestamp": "2024-01-01T15:31:05.123000+00:00",
+                    "content": "nice",
+                    "attachments": [],
+                },
+            ])
+        );
+    }
+}
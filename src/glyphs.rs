@@ -0,0 +1,131 @@
+/// 画面の装飾に使う記号の表。描画側はここから引き、`--ascii` / `ascii_mode` では
+/// 絵文字や罫線を使わない `Glyphs::ASCII` に丸ごと切り替える
+#[derive(Debug)]
+pub struct Glyphs {
+    /// お気に入りマーク (`favorite_marker` 未設定時)
+    pub favorite: &'static str,
+    /// リストの選択行の記号 (`highlight_symbol` 未設定時)
+    pub highlight: &'static str,
+    /// オンライン状態 (オンライン / 退席中 / 取り込み中)
+    pub online: &'static str,
+    /// オンライン状態 (オフライン)
+    pub offline: &'static str,
+    /// Unread リストの未読マーク
+    pub unread: &'static str,
+    /// Unread リストの、このセッションで既読にしたマーク
+    pub acked: &'static str,
+    /// フォーラム投稿一覧の新着マーク
+    pub new_post: &'static str,
+    /// 時刻の gutter と本文の区切り
+    pub gutter: &'static str,
+    /// 未読境界線・日付区切りの罫線
+    pub rule: &'static str,
+    /// システムメッセージの行頭
+    pub system: &'static str,
+    /// キーワードを含むメッセージの行頭
+    pub keyword: &'static str,
+    /// 転送されたアナウンスの印
+    pub crosspost: &'static str,
+    /// 処理中を表す省略記号
+    pub ellipsis: &'static str,
+    /// 送信中のメッセージの印
+    pub sending: &'static str,
+    /// 送信に失敗したメッセージの印
+    pub send_failed: &'static str,
+    /// 読み込み中のスピナー (Tick ごとに次のコマに進める)
+    pub spinner: &'static [&'static str],
+    /// チャンネル種別のプレフィックス (`channel_prefix` で引く)
+    text: &'static str,
+    dm: &'static str,
+    voice: &'static str,
+    group_dm: &'static str,
+    announcement: &'static str,
+    thread: &'static str,
+    forum: &'static str,
+    media: &'static str,
+    unknown: &'static str,
+}
+
+impl Glyphs {
+    /// 既定の記号 (絵文字・罫線を使う)
+    pub const UNICODE: Glyphs = Glyphs {
+        favorite: "⭐ ",
+        highlight: ">> ",
+        online: " ●",
+        offline: " ○",
+        unread: "• ",
+        acked: "✓ ",
+        new_post: " ●",
+        gutter: "│",
+        rule: "─",
+        system: "→ ",
+        keyword: "» ",
+        crosspost: "↗ ",
+        ellipsis: "…",
+        sending: "🕒 ",
+        send_failed: "✗ ",
+        spinner: &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"],
+        text: "# ",
+        dm: "@ ",
+        voice: "🔊 ",
+        group_dm: "@@ ",
+        announcement: "📢 ",
+        thread: "🧵 ",
+        forum: "📋 ",
+        media: "🖼️ ",
+        unknown: "? ",
+    };
+
+    /// ASCII のみの記号 (絵文字フォントが無い端末や SSH 越し向け)
+    pub const ASCII: Glyphs = Glyphs {
+        favorite: "* ",
+        highlight: "> ",
+        online: " *",
+        offline: " o",
+        unread: "- ",
+        acked: "v ",
+        new_post: " *",
+        gutter: "|",
+        rule: "-",
+        system: "-> ",
+        keyword: ">> ",
+        crosspost: "^ ",
+        ellipsis: "...",
+        sending: "~ ",
+        send_failed: "x ",
+        spinner: &["|", "/", "-", "\\"],
+        text: "# ",
+        dm: "@ ",
+        voice: "v ",
+        group_dm: "@@ ",
+        announcement: "! ",
+        thread: "+ ",
+        forum: "f ",
+        media: "m ",
+        unknown: "? ",
+    };
+
+    /// `ascii` なら ASCII のみの表を返す
+    pub fn for_mode(ascii: bool) -> &'static Glyphs {
+        if ascii {
+            &Glyphs::ASCII
+        } else {
+            &Glyphs::UNICODE
+        }
+    }
+
+    /// チャンネル種別のプレフィックス
+    pub fn channel_prefix(&self, channel_type: u8) -> &'static str {
+        match channel_type {
+            0 => self.text,
+            1 => self.dm,
+            2 => self.voice, // ボイスチャンネル
+            3 => self.group_dm,
+            5 => self.announcement,
+            10..=12 => self.thread,
+            15 => self.forum,
+            16 => self.media,
+            _ => self.unknown,
+        }
+    }
+}
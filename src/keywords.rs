@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+/// `text` 内でキーワードに一致する範囲 (バイト位置) を先頭から順に返す。
+///
+/// 大文字小文字を区別せず、英数字で始まる/終わるキーワードは単語の境界でのみ一致する
+/// (`bob` は `Bob!` に一致し `bobby` には一致しない)。
+/// 日本語など空白で区切らない言語のキーワードは境界を問わず一致する
+pub fn find_keywords(text: &str, keywords: &[String]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut start = 0;
+    while start < text.len() {
+        let rest = &text[start..];
+        let matched = keywords
+            .iter()
+            .filter_map(|keyword| match_keyword(rest, keyword, prev))
+            .max();
+        match matched {
+            Some(len) => {
+                ranges.push(start..start + len);
+                prev = text[..start + len].chars().next_back();
+                start += len;
+            }
+            None => {
+                let c = rest.chars().next().unwrap();
+                prev = Some(c);
+                start += c.len_utf8();
+            }
+        }
+    }
+    ranges
+}
+
+/// `text` 内にキーワードが 1 つでも含まれるか
+pub fn contains_keyword(text: &str, keywords: &[String]) -> bool {
+    !keywords.is_empty() && !find_keywords(text, keywords).is_empty()
+}
+
+/// `text` の先頭が `keyword` に一致すればその長さ (バイト数) を返す
+fn match_keyword(text: &str, keyword: &str, prev: Option<char>) -> Option<usize> {
+    let first = keyword.chars().next()?;
+    let last = keyword.chars().next_back()?;
+    if is_word_char(first) && prev.is_some_and(is_word_char) {
+        return None;
+    }
+
+    let mut chars = text.char_indices();
+    let mut len = 0;
+    for k in keyword.chars() {
+        let (i, c) = chars.next()?;
+        if !c.to_lowercase().eq(k.to_lowercase()) {
+            return None;
+        }
+        len = i + c.len_utf8();
+    }
+
+    let next = chars.next().map(|(_, c)| c);
+    if is_word_char(last) && next.is_some_and(is_word_char) {
+        return None;
+    }
+    Some(len)
+}
+
+/// 単語の境界判定に使う文字 (ASCII の英数字と `_`)
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(list: &[&str]) -> Vec<String> {
+        list.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_case_insensitively_on_word_boundaries() {
+        let kw = keywords(&["bob", "rust"]);
+        let text = "Hey BOB, bobby likes Rust.";
+        let found: Vec<&str> = find_keywords(text, &kw).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(found, vec!["BOB", "Rust"]);
+    }
+
+    #[test]
+    fn non_ascii_keywords_ignore_boundaries() {
+        let kw = keywords(&["薄氷"]);
+        let text = "これは薄氷のテスト";
+        assert_eq!(find_keywords(text, &kw), vec![9..15]);
+        assert!(!contains_keyword(text, &[]));
+    }
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+/// URL の末尾にあっても URL の一部とみなさない文字 (文末の句読点など)
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ':', ';', '!', '?', '\'', '"', '*', '_', '~'];
+
+/// `text` 内の http(s) URL の範囲 (バイト位置) を先頭から順に返す。
+///
+/// 英数字の直後から始まるものは URL とみなさない。空白・`<>"|`・ASCII 以外の文字で
+/// URL を終え (日本語の文中に続けて書かれても本文を巻き込まない)、末尾の句読点と
+/// 対応する `(` の無い `)` / `]` は URL に含めない
+pub fn find_links(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = find_scheme(&text[start..]) {
+        let begin = start + offset;
+        let preceded_by_word = text[..begin]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric());
+        let len = text[begin..]
+            .find(|c: char| !c.is_ascii_graphic() || matches!(c, '<' | '>' | '"' | '|'))
+            .unwrap_or(text.len() - begin);
+        let end = begin + trim_trailing(&text[begin..begin + len]);
+        let has_host = text[begin..end]
+            .split_once("://")
+            .is_some_and(|(_, rest)| !rest.is_empty());
+        if !preceded_by_word && has_host {
+            ranges.push(begin..end);
+        }
+        start = begin + len.max(1);
+    }
+    ranges
+}
+
+/// `text` 内の http(s) URL を先頭から順に返す
+pub fn extract_links(text: &str) -> Vec<String> {
+    find_links(text)
+        .into_iter()
+        .map(|range| text[range].to_string())
+        .collect()
+}
+
+/// `text` 内で最初に `http://` / `https://` が現れる位置 (大文字小文字を区別しない)
+fn find_scheme(text: &str) -> Option<usize> {
+    let lower = text.to_ascii_lowercase();
+    match (lower.find("http://"), lower.find("https://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Discord のメッセージリンクが指すメッセージ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageLink {
+    /// サーバー ID (DM なら None)
+    pub guild_id: Option<String>,
+    pub channel_id: String,
+    pub message_id: String,
+}
+
+/// `https://discord.com/channels/{guild}/{channel}/{message}` 形式のリンクを解釈する。
+/// DM のリンクは guild が `@me`。ptb / canary と旧ドメインの discordapp.com も受け付ける
+pub fn parse_message_link(text: &str) -> Option<MessageLink> {
+    let url = text.trim().trim_start_matches('<').trim_end_matches('>');
+    let rest = url.get(..8).filter(|s| s.eq_ignore_ascii_case("https://")).map(|_| &url[8..])?;
+    let (host, path) = rest.split_once('/')?;
+    let host = host.to_ascii_lowercase();
+    let host = ["ptb.", "canary."]
+        .iter()
+        .find_map(|sub| host.strip_prefix(sub))
+        .unwrap_or(&host);
+    if !matches!(host, "discord.com" | "discordapp.com") {
+        return None;
+    }
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    let ["channels", guild, channel, message] = segments.as_slice() else {
+        return None;
+    };
+    let is_id = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_id(channel) || !is_id(message) || (*guild != "@me" && !is_id(guild)) {
+        return None;
+    }
+    Some(MessageLink {
+        guild_id: (*guild != "@me").then(|| guild.to_string()),
+        channel_id: channel.to_string(),
+        message_id: message.to_string(),
+    })
+}
+
+/// URL の候補から末尾の句読点と閉じ括弧を外した長さを返す
+fn trim_trailing(url: &str) -> usize {
+    let mut end = url.len();
+    loop {
+        let candidate = &url[..end];
+        let Some(last) = candidate.chars().next_back() else {
+            return end;
+        };
+        let unbalanced = |open: char, close: char| {
+            last == close && candidate.matches(close).count() > candidate.matches(open).count()
+        };
+        if TRAILING_PUNCTUATION.contains(&last) || unbalanced('(', ')') || unbalanced('[', ']') {
+            end -= last.len_utf8();
+        } else {
+            return end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(text: &str) -> Vec<String> {
+        extract_links(text)
+    }
+
+    #[test]
+    fn trailing_punctuation_and_brackets_are_left_out() {
+        assert_eq!(links("see https://example.com."), vec!["https://example.com"]);
+        assert_eq!(links("(https://example.com/a)"), vec!["https://example.com/a"]);
+        assert_eq!(
+            links("wiki: https://en.wikipedia.org/wiki/Rust_(language)!"),
+            vec!["https://en.wikipedia.org/wiki/Rust_(language)"]
+        );
+        assert_eq!(links("<https://example.com/x?a=1&b=2>"), vec!["https://example.com/x?a=1&b=2"]);
+        assert_eq!(links("||http://spoiler.example||"), vec!["http://spoiler.example"]);
+    }
+
+    #[test]
+    fn finds_several_links_and_skips_non_links() {
+        assert_eq!(
+            links("a https://one.example, b HTTP://two.example/p。次"),
+            vec!["https://one.example", "HTTP://two.example/p"]
+        );
+        // 英数字に続くもの・ホストが無いものは URL とみなさない
+        assert!(links("xhttps://example.com https:// http").is_empty());
+        assert_eq!(find_links("リンクhttps://a.example です"), vec![9..26]);
+    }
+
+    #[test]
+    fn message_links_are_parsed_for_guilds_and_dms() {
+        let link = parse_message_link(" https://discord.com/channels/1/2/3 ").unwrap();
+        assert_eq!(link.guild_id.as_deref(), Some("1"));
+        assert_eq!((link.channel_id.as_str(), link.message_id.as_str()), ("2", "3"));
+
+        let dm = parse_message_link("<https://ptb.discordapp.com/channels/@me/20/30?x=1>").unwrap();
+        assert_eq!(dm.guild_id, None);
+        assert_eq!((dm.channel_id.as_str(), dm.message_id.as_str()), ("20", "30"));
+
+        // チャンネルへのリンク・他のサイト・ID でないものは受け付けない
+        assert!(parse_message_link("https://discord.com/channels/1/2").is_none());
+        assert!(parse_message_link("https://example.com/channels/1/2/3").is_none());
+        assert!(parse_message_link("https://discord.com/channels/1/abc/3").is_none());
+        assert!(parse_message_link("http://discord.com/channels/1/2/3").is_none());
+    }
+}
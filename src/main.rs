@@ -1,31 +1,66 @@
 mod app;
 mod auth;
+mod channel_ref;
+mod cli;
+mod clipboard;
 mod config;
+mod demo;
 mod discord;
 mod emoji;
 mod events;
+mod export;
+mod glyphs;
+mod keywords;
+mod links;
+mod profile;
+mod render;
 mod term_bg;
+#[cfg(test)]
+mod test_fixtures;
 mod token_store;
 mod ui;
 
-use app::{AppState, Command};
+use anyhow::Context;
+use app::{AppState, Command, MESSAGE_DELTA_LIMIT};
 use auth::get_or_authenticate_token;
 use crossterm::{
-    event::{Event, EventStream, KeyCode, KeyModifiers},
+    event::{
+        DisableBracketedPaste, EnableBracketedPaste, Event, EventStream, KeyCode, KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use discord::{DiscordRestClient, GatewayClient, GatewayEvent, RestError};
+use discord::{
+    backoff_delay, signed_url_expired, Channel, DiscordApi, DiscordRestClient, GatewayClient,
+    DiscordError, GatewayEvent, GatewaySender, Guild, MessageReply, INITIAL_CONNECT_MAX_ATTEMPTS,
+};
 use events::AppEvent;
 use futures::StreamExt;
 use ratatui::{backend::CrosstermBackend, Terminal};
-use ratatui_image::picker::Picker;
+use ratatui_image::picker::{Picker, ProtocolType};
+use std::collections::HashMap;
 use std::io;
-use tokio::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
 use tokio::time::{interval, Duration};
 
+/// ギルドチャンネル取得の同時実行数
+const GUILD_CHANNEL_CONCURRENCY: usize = 4;
+/// DM 相手のユーザー取得の同時実行数
+const USER_LOOKUP_CONCURRENCY: usize = 2;
+/// 送信が 429 になったときに待機して再送する最大回数
+const SEND_MAX_RETRIES: usize = 3;
+/// 一括削除で履歴を遡る最大ページ数 (1 ページ 100 件)
+const PURGE_MAX_SCAN_PAGES: usize = 20;
+/// 一括削除で 1 件ごとに空ける間隔 (DELETE のレート制限は厳しめなので余裕を持たせる)
+const PURGE_DELETE_INTERVAL: Duration = Duration::from_millis(1200);
+/// 終了時に Gateway の切断完了を待つ上限
+const GATEWAY_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// ログを初期化（ファイルに出力）
-fn init_logger() {
+fn init_logger(path: &Path) {
     use env_logger::Builder;
     use log::LevelFilter;
     use std::fs::OpenOptions;
@@ -34,7 +69,7 @@ fn init_logger() {
     let log_file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open("hakuhyo.log")
+        .open(path)
         .expect("Failed to open log file");
 
     Builder::new()
@@ -52,21 +87,125 @@ fn init_logger() {
         .init();
 }
 
+/// 初回起動時に表示する利用規約リスクの説明
+const TOS_NOTICE: &str = "\
+hakuhyo signs in with your user account. Automating a user account (a \"selfbot\")
+is against Discord's Terms of Service and can get the account limited or banned.
+Use it at your own risk. Setting \"conservative_mode\": true in config.json spaces out
+sends, reactions and deletes and disables bulk delete to look less like a bot.";
+
+/// 利用規約リスクの確認が済んでいなければ説明を表示し、`yes` の入力で確認済みとして保存する。
+/// 端末から入力できない (パイプ等) 場合はエラーにする
+fn acknowledge_tos_risk(profile_dir: &Path, config: &mut config::Config) -> anyhow::Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if config.tos_acknowledged {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("Run hakuhyo once in a terminal to acknowledge the Terms of Service risk");
+    }
+    println!("{}\n", TOS_NOTICE);
+    print!("Type 'yes' to continue: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("yes") {
+        anyhow::bail!("Not acknowledged, exiting");
+    }
+    config.tos_acknowledged = true;
+    if let Err(e) = config::save_config(profile_dir, config) {
+        log::warn!("Failed to save TOS acknowledgment: {:#}", e);
+    }
+    Ok(())
+}
+
+/// 利用規約リスクの確認が必要な起動か (書き込みをしない read-only やデモでは不要)
+fn tos_ack_required(config: &config::Config, read_only_arg: bool, demo: bool) -> bool {
+    !(read_only_arg || config.read_only || demo)
+}
+
+/// アカウント切り替え先が利用規約リスクを確認済みか調べる。
+/// TUI の中では確認を求められないので、未確認なら端末で一度起動するよう案内する
+fn check_tos_for_switch(
+    profile: &str,
+    config: &config::Config,
+    read_only_arg: bool,
+) -> anyhow::Result<()> {
+    if tos_ack_required(config, read_only_arg, false) && !config.tos_acknowledged {
+        anyhow::bail!(
+            "Terms of Service risk not acknowledged, run `hakuhyo --profile {}` in a terminal",
+            profile
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logger();
-    log::info!("Hakuhyo starting...");
+    let mut args = cli::parse_args()?;
+
+    // プロファイルごとにトークン・設定・ログの保存先を分ける
+    let profile = args.profile.as_deref().unwrap_or(profile::DEFAULT_PROFILE);
+    let profile_dir = profile::profile_dir(profile)?;
+    init_logger(&profile::log_path(profile, &profile_dir));
+    log::info!("Hakuhyo starting (profile: {}, dir: {:?})", profile, profile_dir);
+
+    // 設定ファイルを読み込み (認証・REST のタイムアウトにも使うので最初に読む)
+    let mut config = match config::load_config(&profile_dir) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to load config, using default: {:#}", e);
+            config::Config::default()
+        }
+    };
+    let timeouts = config.network_timeouts();
+    log::info!(
+        "Network timeouts: request={:?}, connect={:?}, ws_handshake={:?}",
+        timeouts.request,
+        timeouts.connect,
+        timeouts.ws_handshake
+    );
+
+    let qr_options = auth::QrOptions {
+        link_only: args.qr_link || config.qr_link_only,
+        copy_link: config.qr_copy_link && !args.no_copy_link,
+    };
+
+    // ユーザーアカウントの自動操作はアカウント停止のリスクがあるので、初回だけ確認する
+    // (書き込みをしない read-only や export では不要)
+    let needs_ack = match &args.command {
+        Some(cli::CliCommand::Send { .. }) => true,
+        Some(_) => false,
+        None => tos_ack_required(&config, args.read_only, args.demo),
+    };
+    if needs_ack {
+        acknowledge_tos_risk(&profile_dir, &mut config)?;
+    }
 
-    // トークン取得（キーチェーン → 環境変数 → QRコード認証）
-    let token = get_or_authenticate_token().await?;
+    if let Some(command) = args.command.take() {
+        let read_only = args.read_only || config.read_only;
+        return run_cli_command(command, &profile_dir, &timeouts, qr_options, read_only).await;
+    }
+
+    // トークン取得（キーチェーン → 環境変数 → QRコード認証）。デモでは Discord に繋がない
+    let token = if args.demo {
+        log::info!("Demo mode: using built-in fixtures, no network access");
+        String::new()
+    } else {
+        get_or_authenticate_token(&profile_dir, &timeouts, qr_options).await?
+    };
 
     // ターミナル初期化（認証完了後）
     enable_raw_mode()?;
     // Picker は termios でフォントサイズを取得し、環境変数からプロトコルを推測
+    let mut graphics_supported = false;
     let picker = match Picker::from_termios() {
         Ok(mut p) => {
             let proto = p.guess_protocol();
             log::info!("Image picker initialized: protocol={:?}", proto);
+            // halfblocks はどの端末でも動くフォールバックなので「画像対応」とはみなさない
+            graphics_supported = !matches!(proto, ProtocolType::Halfblocks);
             Some(p)
         }
         Err(e) => {
@@ -78,16 +217,32 @@ async fn main() -> anyhow::Result<()> {
     let bg_color = term_bg::detect_background_color();
     log::info!("Detected terminal bg color: rgb({},{},{})", bg_color[0], bg_color[1], bg_color[2]);
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    // 貼り付けを 1 つのイベントで受け取る (改行が Enter として届いて行ごとに送信されないように)
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     // アプリケーションを実行し、終了するまで待機
-    let result = run_app(&mut terminal, token, picker, bg_color).await;
+    let session = Session {
+        profile: profile.to_string(),
+        dir: profile_dir,
+        config,
+        timeouts,
+        token,
+    };
+    let result = run_app(
+        &mut terminal,
+        session,
+        picker,
+        graphics_supported,
+        bg_color,
+        &args,
+    )
+    .await;
 
     // ターミナル復元
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), DisableBracketedPaste, LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
     if let Err(err) = result {
@@ -99,69 +254,230 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// TUI を起動せずにサブコマンドを実行
+async fn run_cli_command(
+    command: cli::CliCommand,
+    profile_dir: &Path,
+    timeouts: &config::NetworkTimeouts,
+    qr_options: auth::QrOptions,
+    read_only: bool,
+) -> anyhow::Result<()> {
+    match command {
+        cli::CliCommand::Help => {
+            println!("{}", cli::USAGE);
+        }
+        cli::CliCommand::Export {
+            channel_id,
+            limit,
+            format,
+            output,
+        } => {
+            let token = get_or_authenticate_token(profile_dir, timeouts, qr_options).await?;
+            let rest_client = DiscordRestClient::new(token, timeouts);
+            println!("Exporting up to {} messages from {}...", limit, channel_id);
+            let count =
+                export::export_channel(&rest_client, &channel_id, limit, format, &output).await?;
+            println!("✓ Exported {} messages to {}", count, output.display());
+        }
+        cli::CliCommand::Send { target, content } => {
+            if read_only {
+                anyhow::bail!("Read-only mode: sending is disabled");
+            }
+            let content = match content {
+                Some(content) => content,
+                None => io::read_to_string(io::stdin()).context("Failed to read stdin")?,
+            };
+            let content = content.trim_end();
+            if content.is_empty() {
+                anyhow::bail!("Nothing to send");
+            }
+            let token = get_or_authenticate_token(profile_dir, timeouts, qr_options).await?;
+            let rest_client = DiscordRestClient::new(token, timeouts);
+            let channel = channel_ref::send_to_channel(&rest_client, &target, content).await?;
+            println!("✓ Sent to {}{}", channel.type_prefix(), channel.display_name());
+        }
+    }
+    Ok(())
+}
+
+/// 1 アカウント分の接続に必要な情報
+struct Session {
+    /// プロファイル名
+    profile: String,
+    /// プロファイルの保存先ディレクトリ
+    dir: PathBuf,
+    config: config::Config,
+    /// `config` から求めたネットワークのタイムアウト
+    timeouts: config::NetworkTimeouts,
+    token: String,
+}
+
+/// セッション (1 アカウント分の接続) の終わり方
+enum SessionEnd {
+    /// ユーザーが終了した
+    Quit,
+    /// Gateway の再接続を諦めた
+    Failed(anyhow::Error),
+    /// 別のアカウントに切り替える
+    Switch(Box<Session>),
+}
+
 async fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    token: String,
-    picker: Option<Picker>,
+    mut session: Session,
+    mut picker: Option<Picker>,
+    graphics_supported: bool,
     bg_color: [u8; 3],
+    args: &cli::CliArgs,
 ) -> anyhow::Result<()> {
-    log::info!("Initializing application state");
+    loop {
+        log::info!("Initializing application state (profile: {})", session.profile);
+        let Session {
+            profile,
+            dir,
+            config,
+            timeouts,
+            token,
+        } = session;
 
-    let mut app = AppState::new();
-    app.set_picker(picker);
-    app.set_bg_color(bg_color);
+        // アカウントを切り替えるたびに状態を作り直す (Picker だけは引き継ぐ)
+        let mut app = AppState::new();
+        app.set_picker(picker);
+        app.set_graphics_supported(graphics_supported);
+        app.set_bg_color(bg_color);
+        app.set_ascii_mode(args.ascii || config.ascii_mode);
+        app.set_profile(profile);
 
-    // 設定ファイルを読み込み
-    if let Ok(config) = config::load_config() {
-        app.load_favorites(config.favorites);
-    } else {
-        log::warn!("Failed to load config, using default");
+        app.load_favorites(config.favorites.clone());
+        app.set_read_only(args.read_only || config.read_only);
+        // デモは通信しないので、ポーリングも使わない
+        app.set_polling(!args.demo && (args.no_gateway || config.no_gateway));
+        app.set_config(config);
+        if app.read_only {
+            log::info!("Read-only mode enabled");
+        }
+        if app.polling {
+            log::info!("Gateway disabled, polling for new messages via REST");
+        }
+
+        let end = run_session(terminal, &mut app, token, &timeouts, &dir, args).await;
+
+        // 終了・切り替え時に設定を保存 (デモでの操作は本来の設定に残さない)
+        if !args.demo {
+            log::info!("Saving configuration...");
+            if let Err(e) = config::save_config(&dir, &app.config_to_save()) {
+                log::error!("Failed to save config: {}", e);
+            }
+        }
+
+        match end? {
+            SessionEnd::Quit => return Ok(()),
+            SessionEnd::Failed(e) => return Err(e),
+            SessionEnd::Switch(next) => {
+                log::info!("Switched account to profile {}", next.profile);
+                picker = app.picker.take();
+                session = *next;
+            }
+        }
     }
+}
 
-    let (event_tx, mut event_rx) = mpsc::channel::<AppEvent>(100);
-    let rest_client = DiscordRestClient::new(token.clone());
+/// 切り替え先プロファイルの設定とトークンを読み込み、利用規約リスクの確認とトークンを検証する
+async fn load_session(profile: &str, read_only_arg: bool) -> anyhow::Result<Session> {
+    let dir = profile::profile_dir(profile)?;
+    let config = match config::load_config(&dir) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Failed to load config of {}, using default: {:#}", profile, e);
+            config::Config::default()
+        }
+    };
+    check_tos_for_switch(profile, &config, read_only_arg)?;
+    let timeouts = config.network_timeouts();
+    let token = auth::normalize_token(&token_store::load_token(&dir)?)
+        .with_context(|| format!("saved token of {} is malformed", profile))?;
+    if let Some(warning) = auth::token_format_warning(&token) {
+        log::warn!("Saved token of {} has an unexpected format: {}", profile, warning);
+    }
+    if !auth::validate_stored_token(&token, &timeouts).await {
+        anyhow::bail!("saved token is invalid, run `hakuhyo --profile {}` to log in", profile);
+    }
+    Ok(Session {
+        profile: profile.to_string(),
+        dir,
+        config,
+        timeouts,
+        token,
+    })
+}
 
-    let gateway_url = rest_client.get_gateway_url().await?;
-    log::info!("Gateway URL: {}", gateway_url);
-    let gateway_client = GatewayClient::new(token, gateway_url);
+/// 1 アカウント分の接続を張り、終了・切り替え・致命的エラーまでイベントループを回す。
+/// `--demo` なら接続せず、組み込みのデモデータ ([`demo`]) で動かす
+async fn run_session(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut AppState,
+    token: String,
+    timeouts: &config::NetworkTimeouts,
+    profile_dir: &Path,
+    args: &cli::CliArgs,
+) -> anyhow::Result<SessionEnd> {
+    let demo = args.demo;
+    let capacity = app.config.event_queue_capacity();
+    log::info!("Event queue capacity: {}", capacity);
+    let (event_tx, mut event_rx) = mpsc::channel::<AppEvent>(capacity);
+    let (rest_client, mut outage_rx, mut rate_limit_rx): (Arc<dyn DiscordApi>, _, _) = if demo {
+        let api = Arc::new(demo::DemoApi::default());
+        (api, watch::channel(false).1, watch::channel(None).1)
+    } else {
+        let rest = DiscordRestClient::new(token.clone(), timeouts)
+            .with_max_concurrent_requests(app.config.max_concurrent_requests())
+            .with_min_write_interval(app.config.min_write_interval())
+            .with_retry_policy(app.config.retry_policy());
+        let (outage_rx, rate_limit_rx) = (rest.outage_updates(), rest.rate_limit_updates());
+        (Arc::new(rest), outage_rx, rate_limit_rx)
+    };
 
-    // Gateway イベントハンドラ
-    let gateway_event_tx = event_tx.clone();
-    tokio::spawn(async move {
-        let result = gateway_client
-            .run(move |gateway_event| {
-                let tx = gateway_event_tx.clone();
-                tokio::spawn(async move {
-                    let app_event = match gateway_event {
-                        GatewayEvent::Ready(data) => AppEvent::GatewayReady(data),
-                        GatewayEvent::GuildCreate { guild, channels } => {
-                            // ギルド情報を登録（READY後の新規ギルド参加用）
-                            // 通常は READY イベントで既に全ギルドが登録されているため、
-                            // これは後から参加したギルドの処理となる
-                            log::info!("New guild joined: {} ({})", guild.name, guild.id);
-                            AppEvent::GuildCreate { guild, channels }
-                        }
-                        GatewayEvent::ThreadUpsert(channel) => AppEvent::ThreadUpsert(channel),
-                        GatewayEvent::ThreadDelete { id } => AppEvent::ThreadDelete { id },
-                        GatewayEvent::MessageCreate(msg) => AppEvent::MessageCreate(msg),
-                        GatewayEvent::MessageUpdate(msg) => AppEvent::MessageUpdate(msg),
-                        GatewayEvent::MessageDelete { id, channel_id } => {
-                            AppEvent::MessageDelete { id, channel_id }
-                        }
-                    };
-                    let _ = tx.send(app_event).await;
-                });
-            })
-            .await;
+    let polling = app.polling;
+    let gateway_url = if demo || polling {
+        String::new()
+    } else {
+        fetch_gateway_url(terminal, app, rest_client.as_ref()).await?
+    };
+    let gateway_client = GatewayClient::new(
+        token,
+        gateway_url,
+        timeouts.ws_handshake,
+        app.presence(),
+    );
+    let gateway_sender = gateway_client.sender();
 
-        if let Err(e) = result {
-            log::error!("Gateway error: {:?}", e);
+    // Gateway イベントハンドラ。
+    // 受信タスクを待たせたりイベントを捨てたりしないよう Gateway 用は無制限のキューで受け、
+    // 受信順のまま同期的に積む
+    let (gateway_tx, mut gateway_rx) = mpsc::unbounded_channel::<AppEvent>();
+    let gateway_failed_tx = gateway_tx.clone();
+    let gateway_task = if demo || polling {
+        // 接続はせず READY だけ流す (ポーリングではギルド・チャンネルを REST で読み込む)。
+        // Gateway への送信 (プレゼンス等) は捨てられるようクライアントをセッションの間だけ持っておく
+        let ready = if demo {
+            demo::ready_payload()
+        } else {
+            polling_ready_payload(rest_client.as_ref()).await?
+        };
+        if gateway_tx.send(AppEvent::GatewayReady(ready)).is_err() {
+            log::debug!("Event loop has stopped, dropping READY");
         }
-    });
+        tokio::spawn(async move {
+            let _idle = gateway_client;
+            futures::future::pending::<()>().await;
+        })
+    } else {
+        spawn_gateway(gateway_client, gateway_tx, gateway_failed_tx)
+    };
 
-    // UI イベントハンドラ
+    // UI イベントハンドラ (セッションごとに作り直すので終了時に止める)
     let ui_event_tx = event_tx.clone();
-    tokio::spawn(async move {
+    let ui_task = tokio::spawn(async move {
         let mut reader = EventStream::new();
         while let Some(Ok(event)) = reader.next().await {
             match event {
@@ -170,35 +486,26 @@ async fn run_app(
                     if key_event.code == KeyCode::Char('c')
                         && key_event.modifiers.contains(KeyModifiers::CONTROL)
                     {
-                        let _ = ui_event_tx.send(AppEvent::Quit).await;
+                        emit(&ui_event_tx, AppEvent::Quit).await;
                         break;
                     }
                     // Ctrl+U / Ctrl+D でメッセージを大きめにスクロール (行単位)
                     if key_event.modifiers.contains(KeyModifiers::CONTROL) {
                         match key_event.code {
                             KeyCode::Char('u') => {
-                                let _ = ui_event_tx
-                                    .send(AppEvent::ScrollMessages(10))
-                                    .await;
+                                emit(&ui_event_tx, AppEvent::ScrollMessages(10)).await;
                                 continue;
                             }
                             KeyCode::Char('d') => {
-                                let _ = ui_event_tx
-                                    .send(AppEvent::ScrollMessages(-10))
-                                    .await;
+                                emit(&ui_event_tx, AppEvent::ScrollMessages(-10)).await;
                                 continue;
                             }
                             _ => {}
                         }
                     }
-                    // 'q' で終了（Normal モード時のみ）
-                    if key_event.code == KeyCode::Char('q') {
-                        let _ = ui_event_tx.send(AppEvent::Quit).await;
-                        break;
-                    }
-
-                    let _ = ui_event_tx.send(AppEvent::KeyPress(key_event.code)).await;
+                    emit(&ui_event_tx, AppEvent::KeyPress(key_event.code)).await;
                 }
+                Event::Paste(text) => emit(&ui_event_tx, AppEvent::Paste(text)).await,
                 _ => {}
             }
         }
@@ -206,7 +513,7 @@ async fn run_app(
 
     // 描画タイマー (バッファ満杯時は drop して他イベントの処理遅延を防ぐ)
     let tick_tx = event_tx.clone();
-    tokio::spawn(async move {
+    let tick_task = tokio::spawn(async move {
         let mut tick_interval = interval(Duration::from_millis(100));
         loop {
             tick_interval.tick().await;
@@ -220,76 +527,601 @@ async fn run_app(
         }
     });
 
+    // サーキットブレーカーで REST リクエストを止めた / 再開したことを知らせる
+    let outage_tx = event_tx.clone();
+    let outage_task = tokio::spawn(async move {
+        while outage_rx.changed().await.is_ok() {
+            let down = *outage_rx.borrow_and_update();
+            emit(&outage_tx, AppEvent::RestOutage(down)).await;
+        }
+    });
+    // 429 で待っている間の再送時刻を知らせる (ステータスバーの表示用)
+    let rate_limit_tx = event_tx.clone();
+    let rate_limit_task = tokio::spawn(async move {
+        while rate_limit_rx.changed().await.is_ok() {
+            let until = *rate_limit_rx.borrow_and_update();
+            emit(&rate_limit_tx, AppEvent::RestRateLimited(until)).await;
+        }
+    });
+
+    // ポーリングで新着を確認する時刻を知らせる (Gateway を使わない場合のみ)
+    let poll_interval = app.config.poll_interval();
+    let poll_tx = event_tx.clone();
+    let poll_task = tokio::spawn(async move {
+        if !polling {
+            return;
+        }
+        let mut poll_interval = interval(poll_interval);
+        loop {
+            poll_interval.tick().await;
+            if poll_tx.send(AppEvent::PollTick).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // チャンネル毎の送信キューと一括削除
+    let mut tasks = SessionTasks::default();
+    // GUILD_CREATE をまとめる際に読み過ぎた Gateway イベント (次のループで処理する)
+    let mut deferred: Option<AppEvent> = None;
     // メインループ
-    loop {
+    let end = loop {
         // UI描画
-        terminal.draw(|f| ui::render(f, &mut app))?;
+        draw(terminal, app)?;
 
-        // イベント処理
-        if let Some(event) = event_rx.recv().await {
+        // イベント処理 (Gateway のイベントとそれ以外のどちらか届いた方)
+        let received = match deferred.take() {
+            Some(event) => Some(event),
+            None => tokio::select! {
+                Some(event) = gateway_rx.recv() => Some(event),
+                event = event_rx.recv() => event,
+            },
+        };
+        // 起動直後に続けて届く GUILD_CREATE は 1 回の更新にまとめる
+        let received = match received {
+            Some(AppEvent::GuildsCreated(guilds)) => Some(AppEvent::GuildsCreated(
+                drain_guild_creates(guilds, &mut gateway_rx, &mut deferred),
+            )),
+            other => other,
+        };
+        if let Some(event) = received {
             // Quit イベントでループ終了
             if matches!(event, AppEvent::Quit) {
-                break;
+                break SessionEnd::Quit;
+            }
+            // Gateway の再接続を諦めた場合はエラーとして終了
+            if let AppEvent::GatewayFailed(reason) = event {
+                break SessionEnd::Failed(anyhow::anyhow!(reason));
             }
 
             // 状態更新
             let command = app.update(event);
 
+            // q (quit_key の設定に従って app が判断する) による終了
+            if matches!(command, Command::Quit) {
+                break SessionEnd::Quit;
+            }
+
+            // アカウント切り替えは今の接続を閉じる前に切り替え先のトークンを確認する
+            if let Command::SwitchProfile(profile) = command {
+                if demo {
+                    app.ui.status_message =
+                        Some("Switching accounts is not available in demo mode".to_string());
+                    continue;
+                }
+                draw(terminal, app)?;
+                match load_session(&profile, args.read_only).await {
+                    Ok(next) => break SessionEnd::Switch(Box::new(next)),
+                    Err(e) => {
+                        log::warn!("Failed to switch to profile {}: {:#}", profile, e);
+                        app.ui.status_message =
+                            Some(format!("Cannot switch to {}: {:#}", profile, e));
+                        continue;
+                    }
+                }
+            }
+
             // コマンド実行 (Batch は flatten してから処理)
-            dispatch_command(command, &rest_client, &event_tx);
+            dispatch_command(
+                command,
+                &rest_client,
+                &gateway_sender,
+                &event_tx,
+                &mut tasks,
+                profile_dir,
+            );
         }
+    };
+
+    // Gateway に Close フレームを送り、セッションを即座に終了させる
+    // (放置するとサーバー側にセッションが残り、オンライン表示もしばらく消えない)
+    tick_task.abort();
+    outage_task.abort();
+    rate_limit_task.abort();
+    poll_task.abort();
+    ui_task.abort();
+    tasks.abort_all();
+    if demo || polling {
+        gateway_task.abort();
+    } else if !matches!(end, SessionEnd::Failed(_)) {
+        log::info!("Closing Gateway connection...");
+        gateway_sender.close();
+        if tokio::time::timeout(GATEWAY_CLOSE_TIMEOUT, gateway_task)
+            .await
+            .is_err()
+        {
+            log::warn!("Gateway did not close within {:?}", GATEWAY_CLOSE_TIMEOUT);
+        }
+    }
+
+    Ok(end)
+}
+
+/// 画面を描画し、描画して分かったこと (画面に出た最新のメッセージ) を状態へ反映する
+fn draw(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut AppState,
+) -> io::Result<()> {
+    let mut viewed = None;
+    terminal.draw(|f| viewed = ui::render(f, app))?;
+    if let Some(event) = viewed {
+        // 既読位置を記録するだけで、実行するコマンドは無い
+        app.update(event);
     }
+    Ok(())
+}
 
-    // 終了時に設定を保存
-    log::info!("Saving configuration...");
-    let config_to_save = config::Config {
-        favorites: app.get_favorites().clone(),
+/// Gateway の接続先を取得する。起動直後の一時的なネットワーク断で終了しないよう、
+/// バックオフ付きで再試行する
+async fn fetch_gateway_url(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut AppState,
+    rest_client: &dyn DiscordApi,
+) -> anyhow::Result<String> {
+    let mut attempt: u32 = 0;
+    let gateway_url = loop {
+        attempt += 1;
+        match rest_client.get_gateway_url().await {
+            Ok(url) => break url,
+            Err(e) if attempt < INITIAL_CONNECT_MAX_ATTEMPTS => {
+                let delay = backoff_delay(attempt);
+                log::warn!(
+                    "Failed to get Gateway URL (attempt {}): {:?}, retrying in {:?}",
+                    attempt,
+                    e,
+                    delay
+                );
+                app.ui.status_message = Some(format!(
+                    "Connecting to Discord failed (attempt {}/{}), retrying in {}s...",
+                    attempt,
+                    INITIAL_CONNECT_MAX_ATTEMPTS,
+                    delay.as_secs()
+                ));
+                draw(terminal, app)?;
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!(
+                    "Could not reach Discord after {} attempts. Check your network connection and try again",
+                    attempt
+                )));
+            }
+        }
     };
-    if let Err(e) = config::save_config(&config_to_save) {
-        log::error!("Failed to save config: {}", e);
+    app.ui.status_message = None;
+    log::info!("Gateway URL: {}", gateway_url);
+    Ok(gateway_url)
+}
+
+/// ポーリング (`--no-gateway`) で READY の代わりに流すペイロード。
+/// ログイン中のユーザーだけを REST で取得し、ギルドが空なのでチャンネルは REST で読み込まれる
+async fn polling_ready_payload(rest: &dyn DiscordApi) -> anyhow::Result<serde_json::Value> {
+    let user = rest
+        .get_current_user()
+        .await
+        .context("Could not reach Discord. Check your network connection and try again")?;
+    Ok(serde_json::json!({
+        "user": user,
+        "users": [],
+        "guilds": [],
+        "private_channels": [],
+    }))
+}
+
+/// Gateway の受信タスクを起動し、受け取ったイベントを `gateway_tx` に積む
+fn spawn_gateway(
+    gateway_client: GatewayClient,
+    gateway_tx: mpsc::UnboundedSender<AppEvent>,
+    gateway_failed_tx: mpsc::UnboundedSender<AppEvent>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let result = gateway_client
+            .run(move |gateway_event| {
+                let app_event = match gateway_event {
+                    GatewayEvent::Ready(data) => AppEvent::GatewayReady(data),
+                    GatewayEvent::ReadySupplemental(data) => AppEvent::ReadySupplemental(data),
+                    GatewayEvent::Resumed => AppEvent::GatewayResumed,
+                    GatewayEvent::Disconnected => AppEvent::GatewayDisconnected,
+                    GatewayEvent::GuildCreate { guild, channels } => {
+                        // READY で未取得だったギルド、または後から参加したギルド
+                        log::info!("Guild available: {} ({})", guild.name, guild.id);
+                        AppEvent::GuildsCreated(vec![(guild, channels)])
+                    }
+                    GatewayEvent::ThreadUpsert(channel) => AppEvent::ThreadUpsert(channel),
+                    GatewayEvent::ThreadDelete { id } => AppEvent::ThreadDelete { id },
+                    GatewayEvent::ChannelDelete { id } => AppEvent::ChannelDelete { id },
+                    GatewayEvent::MessageCreate(msg) => AppEvent::MessageCreate(msg),
+                    GatewayEvent::MessageUpdate(msg) => AppEvent::MessageUpdate(msg),
+                    GatewayEvent::MessageDelete { id, channel_id } => {
+                        AppEvent::MessageDelete { id, channel_id }
+                    }
+                    GatewayEvent::MessageDeleteBulk { ids, channel_id } => {
+                        AppEvent::MessageDeleteBulk { ids, channel_id }
+                    }
+                    GatewayEvent::GuildMembersChunk { guild_id, members } => {
+                        AppEvent::GuildMembersChunk { guild_id, members }
+                    }
+                    GatewayEvent::VoiceStateUpdate(state) => AppEvent::VoiceStateUpdate(state),
+                    GatewayEvent::PresenceUpdate { user_id, status } => {
+                        AppEvent::PresenceUpdate { user_id, status }
+                    }
+                    GatewayEvent::RelationshipAdd(relationship) => {
+                        AppEvent::RelationshipAdd(relationship)
+                    }
+                    GatewayEvent::RelationshipRemove { id } => AppEvent::RelationshipRemove { id },
+                };
+                if gateway_tx.send(app_event).is_err() {
+                    log::debug!("Event loop has stopped, dropping gateway event");
+                }
+            })
+            .await;
+
+        if let Err(e) = result {
+            log::error!("Gateway error: {:?}", e);
+            // トークンが拒否されたときは終了せず、再ログインを促す
+            let event = match e {
+                DiscordError::Unauthorized => AppEvent::TokenInvalid,
+                e => AppEvent::GatewayFailed(e.to_string()),
+            };
+            if gateway_failed_tx.send(event).is_err() {
+                log::debug!("Event loop has stopped, dropping gateway failure");
+            }
+        }
+    })
+}
+
+/// Gateway のキューに既に積まれている GUILD_CREATE を `guilds` にまとめる。
+/// 他のイベントに当たったら順序を保つためそこで止め、`deferred` に戻す
+fn drain_guild_creates(
+    mut guilds: Vec<(Guild, Vec<Channel>)>,
+    gateway_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
+    deferred: &mut Option<AppEvent>,
+) -> Vec<(Guild, Vec<Channel>)> {
+    while let Ok(event) = gateway_rx.try_recv() {
+        match event {
+            AppEvent::GuildsCreated(more) => guilds.extend(more),
+            other => {
+                *deferred = Some(other);
+                break;
+            }
+        }
     }
+    if guilds.len() > 1 {
+        log::debug!("Coalesced {} guild creates", guilds.len());
+    }
+    guilds
+}
 
-    Ok(())
+/// メインループへイベントを送る。ループが止まっている (終了・アカウント切り替え後の)
+/// 場合は捨てるが、黙って消えないようログに残す
+async fn emit(tx: &mpsc::Sender<AppEvent>, event: AppEvent) {
+    if tx.send(event).await.is_err() {
+        log::debug!("Event loop has stopped, dropping event");
+    }
+}
+
+/// メッセージ取得の失敗をイベントにする。401 はチャンネルの失敗ではなくトークンの失効なので
+/// 再ログインを促す。それ以外は 4xx (429 を除く) のみ恒久的エラー扱いにし、
+/// 429 / 5xx / ネットワークエラーは一時的なので inaccessible に入れない
+fn messages_load_failed(channel_id: String, error: DiscordError) -> AppEvent {
+    match error {
+        DiscordError::Unauthorized => AppEvent::TokenInvalid,
+        e => AppEvent::MessagesLoadFailed {
+            channel_id,
+            permanent: e.is_permanent(),
+        },
+    }
+}
+
+/// チャンネル毎の送信キューと、それを処理するタスク。同じチャンネルへの送信を 1 タスクで直列化し、
+/// 連続送信時に順序が入れ替わったり同時に 429 を踏んだりしないようにする
+type SendQueues =
+    HashMap<String, (mpsc::UnboundedSender<OutgoingMessage>, tokio::task::JoinHandle<()>)>;
+
+/// dispatch_command が起動した、セッションをまたいで動かしてはいけないタスク
+#[derive(Default)]
+struct SessionTasks {
+    send_queues: SendQueues,
+    /// 実行中の一括削除
+    purges: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl SessionTasks {
+    /// 未送信のメッセージと実行中の一括削除を破棄する。アカウントを切り替えた後に
+    /// 前のアカウントで送信・削除が続かないよう、セッションの終わりに呼ぶ
+    fn abort_all(&mut self) {
+        for (_, (_, task)) in self.send_queues.drain() {
+            task.abort();
+        }
+        for task in self.purges.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+/// 送信キューに積むメッセージ
+struct OutgoingMessage {
+    content: String,
+    /// 返信先
+    reply_to: Option<MessageReply>,
+    /// @everyone / @here / ロールへのメンションで通知するか
+    mass_mentions: bool,
 }
 
 fn dispatch_command(
     command: Command,
-    rest_client: &DiscordRestClient,
+    rest_client: &Arc<dyn DiscordApi>,
+    gateway: &GatewaySender,
     event_tx: &mpsc::Sender<AppEvent>,
+    tasks: &mut SessionTasks,
+    profile_dir: &Path,
 ) {
     let rest = rest_client.clone();
     let tx = event_tx.clone();
     match command {
         Command::Batch(cmds) => {
             for c in cmds {
-                dispatch_command(c, rest_client, event_tx);
+                dispatch_command(c, rest_client, gateway, event_tx, tasks, profile_dir);
             }
         }
+        Command::LoadChannels => {
+            tokio::spawn(async move {
+                // DM / ギルドのどれかの取得に失敗したら false (お気に入りの整理を見送る)
+                let mut complete = true;
+                match rest.get_dm_channels().await {
+                    Ok(channels) => {
+                        emit(&tx, AppEvent::DmChannelsLoaded(channels)).await;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to load DM channels: {}", e);
+                        complete = false;
+                    }
+                }
+                let guilds = match rest.get_guilds().await {
+                    Ok(g) => g,
+                    Err(e) => {
+                        log::error!("Failed to load guilds: {}", e);
+                        emit(&tx, AppEvent::ChannelsLoaded { complete: false }).await;
+                        return;
+                    }
+                };
+                log::info!("Loading channels for {} guilds", guilds.len());
+                // ギルド毎のチャンネル取得を並列化 (同時実行数はレート制限を考慮して抑える)。
+                // 完了したギルドから順に、同時に終わった分をまとめて GuildsCreated として流す
+                let mut results = futures::stream::iter(guilds.into_iter().map(|guild| {
+                    let rest = rest.clone();
+                    async move {
+                        let result = rest.get_guild_channels(&guild.id).await;
+                        (guild, result)
+                    }
+                }))
+                .buffer_unordered(GUILD_CHANNEL_CONCURRENCY)
+                .ready_chunks(GUILD_CHANNEL_CONCURRENCY);
+                while let Some(chunk) = results.next().await {
+                    let mut loaded = Vec::new();
+                    for (guild, result) in chunk {
+                        match result {
+                            Ok(channels) => {
+                                log::info!(
+                                    "Loaded {} channels for guild {}",
+                                    channels.len(),
+                                    guild.name
+                                );
+                                loaded.push((guild, channels));
+                            }
+                            Err(e) => {
+                                log::warn!("Failed to load channels for guild {}: {}", guild.id, e);
+                                complete = false;
+                            }
+                        }
+                    }
+                    if !loaded.is_empty() {
+                        emit(&tx, AppEvent::GuildsCreated(loaded)).await;
+                    }
+                }
+                emit(&tx, AppEvent::ChannelsLoaded { complete }).await;
+            });
+        }
+        Command::UpdatePresence(presence) => {
+            let gateway = gateway.clone();
+            tokio::spawn(async move {
+                gateway.update_presence(presence).await;
+            });
+        }
+        Command::RequestGuildMembers { guild_id, user_ids } => {
+            gateway.request_guild_members(&guild_id, user_ids);
+        }
+        Command::ListProfiles => {
+            let tx = event_tx.clone();
+            tokio::spawn(async move {
+                match tokio::task::spawn_blocking(profile::list_profiles).await {
+                    Ok(Ok(profiles)) => {
+                        emit(&tx, AppEvent::ProfilesListed(profiles)).await;
+                    }
+                    Ok(Err(e)) => log::warn!("Failed to list profiles: {:#}", e),
+                    Err(e) => log::warn!("Failed to list profiles: {}", e),
+                }
+            });
+        }
+        // run_session のメインループで処理する (接続の張り直しが必要なため)
+        Command::SwitchProfile(_) | Command::Quit => {}
+        Command::SaveConfig(config) => {
+            let dir = profile_dir.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = config::save_config(&dir, &config) {
+                    log::error!("Failed to save config: {}", e);
+                }
+            });
+        }
+        Command::SetActivity(activity) => {
+            let gateway = gateway.clone();
+            tokio::spawn(async move {
+                gateway.set_activity(activity).await;
+            });
+        }
+        Command::FetchUsers(user_ids) => {
+            tokio::spawn(async move {
+                // 同時実行数を抑えて取得し、結果は 1 イベントにまとめて送る
+                let users: Vec<_> = futures::stream::iter(user_ids.into_iter().map(|user_id| {
+                    let rest = rest.clone();
+                    async move {
+                        match rest.get_user(&user_id).await {
+                            Ok(user) => Some(user),
+                            Err(e) => {
+                                log::warn!("Failed to fetch user {}: {}", user_id, e);
+                                None
+                            }
+                        }
+                    }
+                }))
+                .buffer_unordered(USER_LOOKUP_CONCURRENCY)
+                .filter_map(|user| async move { user })
+                .collect()
+                .await;
+                log::info!("Resolved {} users via REST", users.len());
+                emit(&tx, AppEvent::UsersResolved(users)).await;
+            });
+        }
+        Command::FetchChannel(channel_id) => {
+            tokio::spawn(async move {
+                match rest.get_channel(&channel_id).await {
+                    Ok(channel) => emit(&tx, AppEvent::ChannelFetched(channel)).await,
+                    Err(e) => {
+                        log::warn!("Failed to fetch channel {}: {}", channel_id, e);
+                        let gone = matches!(e, DiscordError::Http { status: 403 | 404, .. });
+                        emit(&tx, AppEvent::ChannelFetchFailed { channel_id, gone }).await;
+                    }
+                }
+            });
+        }
         Command::LoadMessages(channel_id) => {
             tokio::spawn(async move {
                 match rest.get_messages(&channel_id, 50, None).await {
                     Ok(messages) => {
-                        let _ = tx
-                            .send(AppEvent::MessagesLoaded {
+                        emit(
+                            &tx,
+                            AppEvent::MessagesLoaded {
                                 channel_id,
                                 messages,
-                            })
-                            .await;
+                            },
+                        )
+                        .await;
                     }
                     Err(e) => {
                         log::warn!("LoadMessages failed for {}: {}", channel_id, e);
-                        // 4xx (429 を除く) のみ恒久的エラー扱い。429 / 5xx / ネットワーク
-                        // エラーは一時的なので inaccessible に入れない。
-                        let permanent = matches!(
-                            e,
-                            RestError::Http { status, .. } if (400..500).contains(&status) && status != 429
-                        );
-                        let _ = tx
-                            .send(AppEvent::MessagesLoadFailed {
+                        emit(&tx, messages_load_failed(channel_id, e)).await;
+                    }
+                }
+            });
+        }
+        Command::LoadMessagesAround {
+            channel_id,
+            message_id,
+        } => {
+            tokio::spawn(async move {
+                match rest.get_messages_around(&channel_id, 50, &message_id).await {
+                    Ok(messages) => {
+                        emit(
+                            &tx,
+                            AppEvent::MessagesAroundLoaded {
                                 channel_id,
-                                permanent,
-                            })
-                            .await;
+                                message_id,
+                                messages,
+                            },
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        log::warn!("LoadMessagesAround failed for {}: {}", channel_id, e);
+                        emit(&tx, messages_load_failed(channel_id, e)).await;
+                    }
+                }
+            });
+        }
+        Command::ValidateToken => {
+            tokio::spawn(async move {
+                match rest.get_current_user().await {
+                    Ok(user) => log::info!("Token is valid ({})", user.username),
+                    Err(DiscordError::Unauthorized) => {
+                        emit(&tx, AppEvent::TokenInvalid).await;
+                    }
+                    Err(e) => log::warn!("Could not validate token: {}", e),
+                }
+            });
+        }
+        Command::LoadNewerMessages { channel_id, after } => {
+            tokio::spawn(async move {
+                match rest.get_messages_after(&channel_id, MESSAGE_DELTA_LIMIT, &after).await {
+                    Ok(messages) => {
+                        emit(
+                            &tx,
+                            AppEvent::NewerMessagesLoaded {
+                                channel_id,
+                                messages,
+                            },
+                        )
+                        .await;
+                    }
+                    Err(e) => {
+                        // キャッシュはそのまま表示し、次に開いたときに再試行する
+                        log::warn!("Failed to load newer messages for {}: {}", channel_id, e);
+                    }
+                }
+            });
+        }
+        Command::PollChannel { channel_id, after } => {
+            tokio::spawn(async move {
+                let messages =
+                    match rest.get_messages_after(&channel_id, MESSAGE_DELTA_LIMIT, &after).await {
+                        Ok(messages) => messages,
+                        Err(e) => {
+                            // 次の周期で取り直す
+                            log::warn!("Failed to poll {}: {}", channel_id, e);
+                            Vec::new()
+                        }
+                    };
+                emit(
+                    &tx,
+                    AppEvent::ChannelPolled {
+                        channel_id,
+                        messages,
+                    },
+                )
+                .await;
+            });
+        }
+        Command::LoadForumPosts { guild_id, forum_id } => {
+            tokio::spawn(async move {
+                match rest.get_active_threads(&guild_id).await {
+                    Ok(threads) => {
+                        let threads: Vec<_> = threads
+                            .into_iter()
+                            .filter(|t| t.parent_id.as_deref() == Some(forum_id.as_str()))
+                            .collect();
+                        emit(&tx, AppEvent::ForumPostsLoaded { forum_id, threads }).await;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to load forum posts for {}: {}", forum_id, e);
                     }
                 }
             });
@@ -298,22 +1130,26 @@ fn dispatch_command(
             tokio::spawn(async move {
                 match rest.get_messages(&channel_id, 50, Some(&before)).await {
                     Ok(messages) => {
-                        let _ = tx
-                            .send(AppEvent::OlderMessagesLoaded {
+                        emit(
+                            &tx,
+                            AppEvent::OlderMessagesLoaded {
                                 channel_id,
                                 messages,
-                            })
-                            .await;
+                            },
+                        )
+                        .await;
                     }
                     Err(e) => {
                         log::warn!("Failed to load older messages: {}", e);
                         // 失敗時もロード中フラグを解除する (空の結果を送る)
-                        let _ = tx
-                            .send(AppEvent::OlderMessagesLoaded {
+                        emit(
+                            &tx,
+                            AppEvent::OlderMessagesLoaded {
                                 channel_id,
                                 messages: Vec::new(),
-                            })
-                            .await;
+                            },
+                        )
+                        .await;
                     }
                 }
             });
@@ -321,12 +1157,36 @@ fn dispatch_command(
         Command::SendMessage {
             channel_id,
             content,
+            reply_to,
+            mass_mentions,
         } => {
-            tokio::spawn(async move {
-                if let Ok(message) = rest.send_message(&channel_id, &content).await {
-                    let _ = tx.send(AppEvent::MessageSent(message)).await;
+            // 送り終えて終了したキューは片付ける
+            tasks.send_queues.retain(|_, (_, task)| !task.is_finished());
+            // 既存キューが生きていればそこへ積む。無い (or 閉じた) なら作り直す
+            let outgoing = OutgoingMessage {
+                content,
+                reply_to,
+                mass_mentions,
+            };
+            let outgoing = match tasks.send_queues.get(&channel_id) {
+                Some((queue, _)) => match queue.send(outgoing) {
+                    Ok(()) => return,
+                    Err(e) => e.0,
+                },
+                None => outgoing,
+            };
+            // 閉じた直後のキューがまだ最後の 1 件を送っていれば、それを待ってから送る
+            let previous = tasks.send_queues.remove(&channel_id).map(|(_, task)| task);
+            let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+            let _ = queue_tx.send(outgoing);
+            let channel_id_for_task = channel_id.clone();
+            let task = tokio::spawn(async move {
+                if let Some(previous) = previous {
+                    let _ = previous.await;
                 }
+                run_send_queue(rest, tx, channel_id_for_task, queue_rx).await;
             });
+            tasks.send_queues.insert(channel_id, (queue_tx, task));
         }
         Command::AckChannel {
             channel_id,
@@ -338,39 +1198,124 @@ fn dispatch_command(
                 }
             });
         }
+        Command::EditMessage {
+            channel_id,
+            message_id,
+            content,
+        } => {
+            tokio::spawn(async move {
+                match rest.edit_message(&channel_id, &message_id, &content).await {
+                    Ok(message) => emit(&tx, AppEvent::MessageUpdate(message)).await,
+                    Err(e) => {
+                        log::warn!("Edit message failed (message={}): {}", message_id, e);
+                        let error = format!("Failed to edit message: {}", e);
+                        emit(&tx, AppEvent::ActionFailed(error)).await;
+                    }
+                }
+            });
+        }
+        Command::DeleteMessage {
+            channel_id,
+            message_id,
+        } => {
+            tokio::spawn(async move {
+                match rest.delete_message(&channel_id, &message_id).await {
+                    Ok(()) => {
+                        let deleted = AppEvent::MessageDelete {
+                            id: message_id,
+                            channel_id,
+                        };
+                        emit(&tx, deleted).await;
+                    }
+                    Err(e) => {
+                        log::warn!("Delete message failed (message={}): {}", message_id, e);
+                        let error = format!("Failed to delete message: {}", e);
+                        emit(&tx, AppEvent::ActionFailed(error)).await;
+                    }
+                }
+            });
+        }
+        Command::CopyToClipboard(text) => clipboard::copy(&text),
+        Command::AddReaction {
+            channel_id,
+            message_id,
+            emoji,
+        } => {
+            tokio::spawn(async move {
+                if let Err(e) = rest.add_reaction(&channel_id, &message_id, &emoji).await {
+                    log::warn!("Add reaction failed (message={}): {}", message_id, e);
+                    let error = format!("Failed to add reaction: {}", e);
+                    emit(&tx, AppEvent::ActionFailed(error)).await;
+                }
+            });
+        }
         Command::DownloadEmojis(items) => {
             for (emoji_id, url) in items {
                 let tx2 = tx.clone();
                 tokio::spawn(async move {
                     log::debug!("Downloading emoji: id={}, url={}", emoji_id, url);
-                    let result: Result<image::DynamicImage, String> = match reqwest::get(&url).await
-                    {
-                        Ok(resp) => match resp.bytes().await {
-                            Ok(bytes) => match tokio::task::spawn_blocking(move || {
-                                image::load_from_memory(&bytes)
-                            })
-                            .await
-                            {
-                                Ok(Ok(img)) => Ok(img),
-                                Ok(Err(e)) => Err(format!("decode failed: {}", e)),
-                                Err(e) => Err(format!("decode task panic: {}", e)),
-                            },
-                            Err(e) => Err(format!("read bytes failed: {}", e)),
-                        },
-                        Err(e) => Err(format!("download failed: {}", e)),
-                    };
-                    match result {
+                    match fetch_image(&url).await {
                         Ok(img) => {
-                            let _ = tx2
-                                .send(AppEvent::EmojiImageLoaded {
+                            emit(
+                                &tx2,
+                                AppEvent::EmojiImageLoaded {
                                     emoji_id,
                                     image: Box::new(img),
-                                })
-                                .await;
+                                },
+                            )
+                            .await;
                         }
                         Err(e) => {
                             log::warn!("Emoji fetch error ({}): {}", emoji_id, e);
-                            let _ = tx2.send(AppEvent::EmojiImageFailed { emoji_id }).await;
+                            emit(&tx2, AppEvent::EmojiImageFailed { emoji_id }).await;
+                        }
+                    }
+                });
+            }
+        }
+        Command::DownloadAvatars(items) => {
+            for (user_id, url) in items {
+                let tx2 = tx.clone();
+                tokio::spawn(async move {
+                    log::debug!("Downloading avatar: user={}, url={}", user_id, url);
+                    match fetch_image(&url).await {
+                        Ok(img) => {
+                            emit(
+                                &tx2,
+                                AppEvent::AvatarImageLoaded {
+                                    user_id,
+                                    image: Box::new(img),
+                                },
+                            )
+                            .await;
+                        }
+                        Err(e) => {
+                            log::warn!("Avatar fetch error ({}): {}", user_id, e);
+                            emit(&tx2, AppEvent::AvatarImageFailed { user_id }).await;
+                        }
+                    }
+                });
+            }
+        }
+        Command::DownloadGuildIcons(items) => {
+            for (guild_id, url) in items {
+                let tx2 = tx.clone();
+                tokio::spawn(async move {
+                    log::debug!("Downloading guild icon: guild={}, url={}", guild_id, url);
+                    match fetch_image(&url).await {
+                        Ok(img) => {
+                            emit(
+                                &tx2,
+                                AppEvent::GuildIconLoaded {
+                                    guild_id,
+                                    image: Box::new(img),
+                                },
+                            )
+                            .await;
+                        }
+                        Err(e) => {
+                            log::warn!("Guild icon fetch error ({}): {}", guild_id, e);
+                            emit(&tx2, AppEvent::GuildIconFailed { guild_id }).await;
                         }
                     }
                 });
@@ -378,49 +1323,65 @@ fn dispatch_command(
         }
         Command::DownloadImages(items) => {
             for (att_id, url) in items {
+                let rest = rest.clone();
                 let tx2 = tx.clone();
                 tokio::spawn(async move {
                     log::debug!("Downloading image: id={}, url={}", att_id, url);
                     // 任意の段階で失敗したら Failed を送って image_downloading を必ず解除する
-                    let result: Result<image::DynamicImage, String> = match reqwest::get(&url).await
-                    {
-                        Ok(resp) => match resp.bytes().await {
-                            Ok(bytes) => {
-                                match tokio::task::spawn_blocking(move || {
-                                    image::load_from_memory(&bytes)
-                                })
-                                .await
-                                {
-                                    Ok(Ok(img)) => Ok(img),
-                                    Ok(Err(e)) => Err(format!("decode failed: {}", e)),
-                                    Err(e) => Err(format!("decode task panic: {}", e)),
-                                }
-                            }
-                            Err(e) => Err(format!("read bytes failed: {}", e)),
-                        },
-                        Err(e) => Err(format!("download failed: {}", e)),
-                    };
-                    match result {
+                    match fetch_attachment_image(rest.as_ref(), &tx2, &att_id, url).await {
                         Ok(img) => {
-                            let _ = tx2
-                                .send(AppEvent::AttachmentImageLoaded {
+                            emit(
+                                &tx2,
+                                AppEvent::AttachmentImageLoaded {
                                     attachment_id: att_id,
                                     image: Box::new(img),
-                                })
-                                .await;
+                                },
+                            )
+                            .await;
                         }
                         Err(e) => {
                             log::warn!("Image fetch error ({}): {}", att_id, e);
-                            let _ = tx2
-                                .send(AppEvent::AttachmentImageFailed {
+                            emit(
+                                &tx2,
+                                AppEvent::AttachmentImageFailed {
                                     attachment_id: att_id,
-                                })
-                                .await;
+                                },
+                            )
+                            .await;
                         }
                     }
                 });
             }
         }
+        Command::PurgeOwnMessages {
+            channel_id,
+            user_id,
+            count,
+            cancel,
+        } => {
+            tasks.purges.retain(|task| !task.is_finished());
+            let task = tokio::spawn(async move {
+                let (deleted, error) = purge_own_messages(
+                    rest.as_ref(),
+                    &tx,
+                    &channel_id,
+                    &user_id,
+                    count,
+                    &cancel,
+                )
+                .await;
+                emit(
+                    &tx,
+                    AppEvent::PurgeFinished {
+                        deleted,
+                        cancelled: cancel.load(Ordering::Relaxed),
+                        error: error.map(|e| e.to_string()),
+                    },
+                )
+                .await;
+            });
+            tasks.purges.push(task);
+        }
         Command::OpenInDiscord {
             guild_id,
             channel_id,
@@ -429,23 +1390,561 @@ fn dispatch_command(
             let guild_segment = guild_id.unwrap_or_else(|| "@me".to_string());
             let url = format!("discord://-/channels/{}/{}", guild_segment, channel_id);
             log::info!("Opening in Discord app: {}", url);
+            tokio::spawn(open_with_system(url));
+        }
+        Command::OpenUrl(url) => {
+            log::info!("Opening link: {}", url);
+            // 添付ファイルの署名付き URL は期限が切れていたら再署名してから開く
             tokio::spawn(async move {
-                let opener = if cfg!(target_os = "macos") {
-                    "open"
-                } else if cfg!(target_os = "windows") {
-                    "start"
-                } else {
-                    "xdg-open"
-                };
-                let result = tokio::process::Command::new(opener)
-                    .arg(&url)
-                    .status()
-                    .await;
-                if let Err(e) = result {
-                    log::error!("Failed to launch Discord ({}): {}", opener, e);
-                }
+                open_with_system(fresh_signed_url(rest.as_ref(), url).await).await;
             });
         }
         Command::None => {}
     }
 }
+
+/// URL (`discord://` を含む) を OS の既定のアプリで開く
+async fn open_with_system(url: String) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        // start は cmd の組み込みコマンドで実行ファイルが無い。cmd /C start 経由だと
+        // 署名付き URL の & がコマンド区切りとして解釈されるので explorer に渡す
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let result = tokio::process::Command::new(opener)
+        .arg(&url)
+        .status()
+        .await;
+    if let Err(e) = result {
+        log::error!("Failed to open {} ({}): {}", url, opener, e);
+    }
+}
+
+/// 1 チャンネル分の送信キューを処理し、積まれた分を送り終えたら終了する。
+/// 429 の場合は retry_after だけ待って再送し、待機中であることを UI に通知する
+async fn run_send_queue(
+    rest: Arc<dyn DiscordApi>,
+    tx: mpsc::Sender<AppEvent>,
+    channel_id: String,
+    mut queue: mpsc::UnboundedReceiver<OutgoingMessage>,
+) {
+    loop {
+        // 空になったらキューを閉じて終わる (以降の送信は新しいキューで受ける)。
+        // 閉じる前に積まれていた分は送ってから終える
+        let outgoing = match queue.try_recv() {
+            Ok(outgoing) => outgoing,
+            Err(_) => {
+                queue.close();
+                match queue.try_recv() {
+                    Ok(outgoing) => outgoing,
+                    Err(_) => break,
+                }
+            }
+        };
+        let mut retries = 0;
+        loop {
+            match rest
+                .send_message(
+                    &channel_id,
+                    &outgoing.content,
+                    outgoing.reply_to.as_ref(),
+                    outgoing.mass_mentions,
+                )
+                .await
+            {
+                Ok(message) => {
+                    emit(&tx, AppEvent::MessageSent(message)).await;
+                    break;
+                }
+                Err(e) => match e.retry_after() {
+                    Some(retry_after) if retries < SEND_MAX_RETRIES => {
+                        retries += 1;
+                        let slowmode = e.is_slowmode();
+                        log::warn!(
+                            "Send rate limited in {} (slowmode={}), retrying after {:.2}s",
+                            channel_id,
+                            slowmode,
+                            retry_after
+                        );
+                        emit(
+                            &tx,
+                            AppEvent::SendRateLimited {
+                                channel_id: channel_id.clone(),
+                                retry_after,
+                                slowmode,
+                            },
+                        )
+                        .await;
+                        tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+                    }
+                    _ => {
+                        log::error!("Failed to send message to {}: {}", channel_id, e);
+                        let failed = AppEvent::SendFailed {
+                            channel_id: channel_id.clone(),
+                            error: e.to_string(),
+                        };
+                        emit(&tx, failed).await;
+                        break;
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// 自分 (user_id) のメッセージを新しい順に最大 count 件削除する。
+/// 返り値は (削除件数, 途中で発生したエラー)
+async fn purge_own_messages(
+    rest: &dyn DiscordApi,
+    tx: &mpsc::Sender<AppEvent>,
+    channel_id: &str,
+    user_id: &str,
+    count: usize,
+    cancel: &Arc<AtomicBool>,
+) -> (usize, Option<DiscordError>) {
+    // 履歴を遡って対象 (author が自分のもの) を集める
+    let mut targets: Vec<String> = Vec::new();
+    let mut before: Option<String> = None;
+    for _ in 0..PURGE_MAX_SCAN_PAGES {
+        if cancel.load(Ordering::Relaxed) || targets.len() >= count {
+            break;
+        }
+        let page = match rest.get_messages(channel_id, 100, before.as_deref()).await {
+            Ok(page) => page,
+            Err(e) => return (0, Some(e)),
+        };
+        let Some(oldest) = page.last() else {
+            break;
+        };
+        before = Some(oldest.id.clone());
+        let fetched = page.len();
+        targets.extend(
+            page.into_iter()
+                .filter(|m| m.author.id == user_id)
+                .map(|m| m.id),
+        );
+        if fetched < 100 {
+            break;
+        }
+    }
+    targets.truncate(count);
+    let total = targets.len();
+    log::info!("Purge: {} own messages found in {}", total, channel_id);
+
+    let mut deleted = 0;
+    for message_id in targets {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        match rest.delete_message(channel_id, &message_id).await {
+            Ok(()) => {}
+            // 他の端末などで既に消されている。削除済みとして数えて続ける
+            Err(DiscordError::Http { status: 404, .. }) => {
+                log::debug!("Purge: message {} was already deleted", message_id);
+            }
+            Err(e) => return (deleted, Some(e)),
+        }
+        deleted += 1;
+        emit(
+            tx,
+            AppEvent::PurgeProgress {
+                channel_id: channel_id.to_string(),
+                message_id,
+                deleted,
+                total,
+            },
+        )
+        .await;
+        tokio::time::sleep(PURGE_DELETE_INTERVAL).await;
+    }
+    (deleted, None)
+}
+
+/// 画像取得の失敗理由
+#[derive(Debug)]
+enum FetchImageError {
+    /// 403 / 404 (署名付き CDN URL が期限切れなら再署名で取り直せる)
+    Denied(u16),
+    Other(String),
+}
+
+impl std::fmt::Display for FetchImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchImageError::Denied(status) => write!(f, "HTTP {}", status),
+            FetchImageError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// 画像をダウンロードしてデコードする (デコードは blocking スレッドで実行)
+async fn fetch_image(url: &str) -> Result<image::DynamicImage, FetchImageError> {
+    let resp = reqwest::get(url)
+        .await
+        .map_err(|e| FetchImageError::Other(format!("download failed: {}", e)))?;
+    let status = resp.status().as_u16();
+    if status == 403 || status == 404 {
+        return Err(FetchImageError::Denied(status));
+    }
+    let bytes = resp
+        .bytes()
+        .await
+        .map_err(|e| FetchImageError::Other(format!("read bytes failed: {}", e)))?;
+    match tokio::task::spawn_blocking(move || image::load_from_memory(&bytes)).await {
+        Ok(Ok(img)) => Ok(img),
+        Ok(Err(e)) => Err(FetchImageError::Other(format!("decode failed: {}", e))),
+        Err(e) => Err(FetchImageError::Other(format!("decode task panic: {}", e))),
+    }
+}
+
+/// 添付画像を取得する。署名付き URL の `ex` が過去なら再署名してから取得する。
+/// 取得中に期限が切れて 403 / 404 になった場合も 1 回だけ取り直す
+/// (期限内の URL の 404 は削除されたものとして諦める)
+async fn fetch_attachment_image(
+    rest: &dyn DiscordApi,
+    tx: &mpsc::Sender<AppEvent>,
+    attachment_id: &str,
+    url: String,
+) -> Result<image::DynamicImage, FetchImageError> {
+    let mut url = url;
+    let mut refreshed = false;
+    if signed_url_expired(&url, chrono::Utc::now().timestamp()) {
+        if let Some(fresh) = refresh_attachment_url(rest, tx, attachment_id, &url).await {
+            url = fresh;
+            refreshed = true;
+        }
+    }
+    match fetch_image(&url).await {
+        Err(FetchImageError::Denied(status))
+            if !refreshed && signed_url_expired(&url, chrono::Utc::now().timestamp()) =>
+        {
+            match refresh_attachment_url(rest, tx, attachment_id, &url).await {
+                Some(fresh) => fetch_image(&fresh).await,
+                None => Err(FetchImageError::Denied(status)),
+            }
+        }
+        result => result,
+    }
+}
+
+/// 期限切れの署名付き CDN URL なら再署名した URL を返す (それ以外・再署名に失敗したら
+/// そのまま返す)。添付ファイルのリンクをブラウザ等で開く前に使う
+async fn fresh_signed_url(rest: &dyn DiscordApi, url: String) -> String {
+    if !signed_url_expired(&url, chrono::Utc::now().timestamp()) {
+        return url;
+    }
+    match rest.refresh_attachment_urls(std::slice::from_ref(&url)).await {
+        Ok(pairs) => match pairs.into_iter().next() {
+            Some((_, fresh)) => {
+                log::debug!("Refreshed expired link before opening");
+                fresh
+            }
+            None => url,
+        },
+        Err(e) => {
+            log::warn!("Failed to refresh expired link, opening as is: {:#}", e);
+            url
+        }
+    }
+}
+
+/// 添付ファイルの URL を再署名し、新しい URL をアプリへ通知する (キャッシュ用)
+async fn refresh_attachment_url(
+    rest: &dyn DiscordApi,
+    tx: &mpsc::Sender<AppEvent>,
+    attachment_id: &str,
+    url: &str,
+) -> Option<String> {
+    let fresh = match rest.refresh_attachment_urls(&[url.to_string()]).await {
+        Ok(pairs) => pairs.into_iter().next().map(|(_, fresh)| fresh),
+        Err(e) => {
+            log::warn!("Failed to refresh attachment URL ({}): {:#}", attachment_id, e);
+            return None;
+        }
+    };
+    let Some(fresh) = fresh else {
+        log::warn!("Attachment URL was not refreshed ({})", attachment_id);
+        return None;
+    };
+    log::debug!("Refreshed attachment URL ({})", attachment_id);
+    emit(
+        tx,
+        AppEvent::AttachmentUrlRefreshed {
+            attachment_id: attachment_id.to_string(),
+            url: fresh.clone(),
+        },
+    )
+    .await;
+    Some(fresh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{message, with_messages, MockApi, GENERAL_ID, RANDOM_ID};
+
+    fn gateway_sender() -> GatewaySender {
+        GatewayClient::new(
+            "token".to_string(),
+            "wss://gateway.example".to_string(),
+            Duration::from_secs(1),
+            discord::Presence::default(),
+        )
+        .sender()
+    }
+
+    /// モックの API でコマンドを実行し、結果のイベントを 1 つ受け取る
+    async fn dispatch(api: Arc<dyn DiscordApi>, command: Command) -> AppEvent {
+        let (tx, mut rx) = mpsc::channel(16);
+        let mut tasks = SessionTasks::default();
+        dispatch_command(
+            command,
+            &api,
+            &gateway_sender(),
+            &tx,
+            &mut tasks,
+            Path::new("."),
+        );
+        rx.recv().await.expect("no event emitted")
+    }
+
+    #[tokio::test]
+    async fn load_messages_round_trips_through_the_app() {
+        let mut api = MockApi::default();
+        api.messages.insert(
+            GENERAL_ID.to_string(),
+            vec![message("310", GENERAL_ID, "carol", "from the api", "02:00")],
+        );
+        let event = dispatch(Arc::new(api), Command::LoadMessages(GENERAL_ID.to_string())).await;
+        assert!(matches!(&event, AppEvent::MessagesLoaded { messages, .. } if messages.len() == 1));
+
+        let mut app = with_messages();
+        app.update(event);
+        let contents: Vec<&str> = app
+            .get_current_messages()
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["from the api"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_token_while_loading_messages_asks_to_log_in_again() {
+        let api = MockApi::default();
+        api.message_errors.lock().unwrap().push_back(DiscordError::Unauthorized);
+        let event = dispatch(Arc::new(api), Command::LoadMessages(GENERAL_ID.to_string())).await;
+        assert!(matches!(event, AppEvent::TokenInvalid));
+
+        // チャンネルは開けないものとして記録しない
+        let mut app = with_messages();
+        app.update(event);
+        assert!(!app.discord.inaccessible_channels.contains(GENERAL_ID));
+        assert!(app.ui.status_message.is_some());
+
+        let forbidden = DiscordError::Http { status: 403, body: String::new() };
+        assert!(matches!(
+            messages_load_failed(GENERAL_ID.to_string(), forbidden),
+            AppEvent::MessagesLoadFailed { permanent: true, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_send_is_retried() {
+        let api = Arc::new(MockApi::default());
+        api.send_results.lock().unwrap().push_back(Err(DiscordError::RateLimited {
+            body: r#"{"retry_after": 0.0}"#.to_string(),
+        }));
+        let (tx, mut rx) = mpsc::channel(16);
+        let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+        queue_tx
+            .send(OutgoingMessage {
+                content: "hello".to_string(),
+                reply_to: Some(MessageReply {
+                    message_id: "303".to_string(),
+                    mention: false,
+                }),
+                mass_mentions: true,
+            })
+            .unwrap();
+        drop(queue_tx);
+        run_send_queue(api.clone(), tx, GENERAL_ID.to_string(), queue_rx).await;
+
+        assert!(matches!(rx.recv().await, Some(AppEvent::SendRateLimited { slowmode: false, .. })));
+        assert!(matches!(rx.recv().await, Some(AppEvent::MessageSent(m)) if m.content == "hello"));
+        let sent = api.sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].2.as_ref().map(|r| r.message_id.as_str()), Some("303"));
+    }
+
+    #[tokio::test]
+    async fn ending_a_session_drops_pending_sends() {
+        let api = Arc::new(MockApi::default());
+        api.send_results.lock().unwrap().push_back(Err(DiscordError::RateLimited {
+            body: r#"{"retry_after": 0.2}"#.to_string(),
+        }));
+        let rest: Arc<dyn DiscordApi> = api.clone();
+        let (tx, mut rx) = mpsc::channel(16);
+        let mut tasks = SessionTasks::default();
+        let send = Command::SendMessage {
+            channel_id: GENERAL_ID.to_string(),
+            content: "hello".to_string(),
+            reply_to: None,
+            mass_mentions: false,
+        };
+        dispatch_command(send, &rest, &gateway_sender(), &tx, &mut tasks, Path::new("."));
+        assert!(matches!(rx.recv().await, Some(AppEvent::SendRateLimited { .. })));
+
+        // アカウント切り替えで 429 の待機中の再送を取りやめる
+        tasks.abort_all();
+        assert!(tasks.send_queues.is_empty());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(api.sent.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn drained_send_queues_are_removed() {
+        let api = Arc::new(MockApi::default());
+        let rest: Arc<dyn DiscordApi> = api.clone();
+        let (tx, mut rx) = mpsc::channel(16);
+        let mut tasks = SessionTasks::default();
+        let send = |channel_id: &str| Command::SendMessage {
+            channel_id: channel_id.to_string(),
+            content: "hello".to_string(),
+            reply_to: None,
+            mass_mentions: false,
+        };
+        let sender = gateway_sender();
+        dispatch_command(send(GENERAL_ID), &rest, &sender, &tx, &mut tasks, Path::new("."));
+        assert!(matches!(rx.recv().await, Some(AppEvent::MessageSent(_))));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(tasks.send_queues[GENERAL_ID].1.is_finished());
+
+        // 次の送信のときに、送り終えたチャンネルのキューを片付ける
+        dispatch_command(send(RANDOM_ID), &rest, &sender, &tx, &mut tasks, Path::new("."));
+        assert!(!tasks.send_queues.contains_key(GENERAL_ID));
+        assert!(matches!(rx.recv().await, Some(AppEvent::MessageSent(_))));
+
+        // 閉じたキューのチャンネルへ送り直しても届く
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        dispatch_command(send(RANDOM_ID), &rest, &sender, &tx, &mut tasks, Path::new("."));
+        assert!(matches!(rx.recv().await, Some(AppEvent::MessageSent(_))));
+        assert_eq!(api.sent.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn purge_treats_already_deleted_messages_as_deleted() {
+        let mut api = MockApi::default();
+        api.messages.insert(
+            GENERAL_ID.to_string(),
+            vec![
+                message("303", GENERAL_ID, "me", "gone elsewhere", "01:02"),
+                message("302", GENERAL_ID, "bob", "not mine", "01:01"),
+                message("301", GENERAL_ID, "me", "still here", "01:00"),
+            ],
+        );
+        api.delete_results.lock().unwrap().push_back(Err(DiscordError::Http {
+            status: 404,
+            body: r#"{"message": "Unknown Message", "code": 10008}"#.to_string(),
+        }));
+        let (tx, mut rx) = mpsc::channel(16);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (deleted, error) =
+            purge_own_messages(&api, &tx, GENERAL_ID, "u-me", 10, &cancel).await;
+
+        // 404 で止まらずに次のメッセージへ進む
+        assert_eq!(deleted, 2);
+        assert!(error.is_none());
+        let requested: Vec<String> =
+            api.deleted.lock().unwrap().iter().map(|(_, id)| id.clone()).collect();
+        assert_eq!(requested, ["303", "301"]);
+        for expected in ["303", "301"] {
+            assert!(matches!(
+                rx.recv().await,
+                Some(AppEvent::PurgeProgress { message_id, total: 2, .. }) if message_id == expected
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn edits_and_deletes_report_their_outcome() {
+        let api = Arc::new(MockApi::default());
+        let edit = Command::EditMessage {
+            channel_id: GENERAL_ID.to_string(),
+            message_id: "302".to_string(),
+            content: "fixed typo".to_string(),
+        };
+        let event = dispatch(api.clone(), edit).await;
+        assert!(matches!(&event, AppEvent::MessageUpdate(m) if m.content == "fixed typo"));
+        assert_eq!(api.edited.lock().unwrap()[0].1, "302");
+
+        api.delete_results.lock().unwrap().push_back(Err(DiscordError::Http {
+            status: 403,
+            body: "Missing Permissions".to_string(),
+        }));
+        let delete = || Command::DeleteMessage {
+            channel_id: GENERAL_ID.to_string(),
+            message_id: "302".to_string(),
+        };
+        let event = dispatch(api.clone(), delete()).await;
+        assert!(matches!(&event, AppEvent::ActionFailed(e) if e.starts_with("Failed to delete")));
+        let event = dispatch(api.clone(), delete()).await;
+        assert!(matches!(event, AppEvent::MessageDelete { id, .. } if id == "302"));
+    }
+
+    #[tokio::test]
+    async fn failed_reactions_are_reported() {
+        let api = Arc::new(MockApi::default());
+        api.reaction_results.lock().unwrap().push_back(Err(DiscordError::Http {
+            status: 403,
+            body: "Missing Permissions".to_string(),
+        }));
+        let react = Command::AddReaction {
+            channel_id: GENERAL_ID.to_string(),
+            message_id: "302".to_string(),
+            emoji: "👍".to_string(),
+        };
+        let event = dispatch(api, react).await;
+        let AppEvent::ActionFailed(error) = event else {
+            panic!("expected a failure, got {:?}", event);
+        };
+        assert!(error.starts_with("Failed to add reaction"));
+
+        let mut app = with_messages();
+        app.update(AppEvent::ActionFailed(error.clone()));
+        assert_eq!(app.ui.status_message, Some(error));
+    }
+
+    #[tokio::test]
+    async fn expired_links_are_refreshed_before_opening() {
+        let api = MockApi::default();
+        let expired = "https://cdn.discordapp.com/attachments/1/2/a.png?ex=1&is=0&hm=ab";
+        assert_eq!(
+            fresh_signed_url(&api, expired.to_string()).await,
+            format!("{}&fresh", expired)
+        );
+        // 期限内・署名の無い URL はそのまま開く
+        let valid = format!(
+            "https://cdn.discordapp.com/attachments/1/2/a.png?ex={:x}&is=0&hm=ab",
+            chrono::Utc::now().timestamp() + 3600
+        );
+        assert_eq!(fresh_signed_url(&api, valid.clone()).await, valid);
+        let plain = "https://example.com/page";
+        assert_eq!(fresh_signed_url(&api, plain.to_string()).await, plain);
+    }
+
+    #[test]
+    fn switching_to_an_unacknowledged_profile_is_refused() {
+        let mut config = config::Config::default();
+        assert!(check_tos_for_switch("work", &config, false).is_err());
+        // read-only なら書き込まないので確認は要らない
+        assert!(check_tos_for_switch("work", &config, true).is_ok());
+        config.read_only = true;
+        assert!(check_tos_for_switch("work", &config, false).is_ok());
+        config.read_only = false;
+        config.tos_acknowledged = true;
+        assert!(check_tos_for_switch("work", &config, false).is_ok());
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `--profile` 未指定時のプロファイル名
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// プロファイル名の最大文字数
+const MAX_NAME_LEN: usize = 64;
+
+/// プロファイル名として使えるか (ディレクトリ名になるので英数字と `-` `_` のみ、
+/// MAX_NAME_LEN 文字まで)
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Invalid profile name: {:?} (use up to {} letters, digits, '-' and '_')",
+            name,
+            MAX_NAME_LEN
+        );
+    }
+    Ok(())
+}
+
+/// 全プロファイルの親ディレクトリ `~/.config/hakuhyo`
+fn base_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .context("Failed to get config directory")?
+        .join("hakuhyo"))
+}
+
+/// プロファイルの保存先ディレクトリ (トークン・設定) を取得し、無ければ作成する
+///
+/// - `default`: `~/.config/hakuhyo` (プロファイル導入前と同じ場所)
+/// - それ以外: `~/.config/hakuhyo/profiles/<name>`
+pub fn profile_dir(name: &str) -> Result<PathBuf> {
+    let base = base_dir()?;
+    let dir = if name == DEFAULT_PROFILE {
+        base
+    } else {
+        base.join("profiles").join(name)
+    };
+
+    // ディレクトリが存在しない場合は作成
+    if !dir.exists() {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create profile directory: {:?}", dir))?;
+    }
+
+    Ok(dir)
+}
+
+/// トークンを保存済み (ログイン済み) のプロファイル名を列挙する (default が先頭、以降は名前順)
+pub fn list_profiles() -> Result<Vec<String>> {
+    let base = base_dir()?;
+    let mut profiles = Vec::new();
+    if base.join("token.txt").exists() {
+        profiles.push(DEFAULT_PROFILE.to_string());
+    }
+    let mut named: Vec<String> = match fs::read_dir(base.join("profiles")) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("token.txt").exists())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| validate_name(name).is_ok() && name != DEFAULT_PROFILE)
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e).context("Failed to read profiles directory"),
+    };
+    named.sort();
+    profiles.extend(named);
+    Ok(profiles)
+}
+
+/// ログファイルのパス
+///
+/// `default` は従来どおりカレントディレクトリの `hakuhyo.log`、
+/// それ以外はプロファイルのディレクトリ内の `hakuhyo.log`
+pub fn log_path(name: &str, dir: &Path) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        PathBuf::from("hakuhyo.log")
+    } else {
+        dir.join("hakuhyo.log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_short_non_empty_and_path_safe() {
+        assert!(validate_name("work").is_ok());
+        assert!(validate_name("alt-2_b").is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+
+        assert!(validate_name("").is_err());
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name(" work ").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(validate_name("../etc").is_err());
+    }
+}
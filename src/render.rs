@@ -0,0 +1,815 @@
+use crate::app::{AppState, OutgoingSend, PendingSend, ReplyTarget, SendStatus};
+use crate::config::TimestampLayout;
+use crate::discord::Message;
+use crate::glyphs::Glyphs;
+use crate::keywords::{contains_keyword, find_keywords};
+use crate::links::find_links;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use unicode_width::UnicodeWidthStr;
+
+/// 未読/既読の境界線を表す 1 行を構築する (`rule` は罫線の文字)
+pub fn build_unread_separator_line(width: u16, rule: &str) -> Line<'static> {
+    let label = " New messages ";
+    let label_w = label.chars().count() as u16;
+    let total_dashes = width.saturating_sub(label_w);
+    let left = (total_dashes / 2) as usize;
+    let right = (total_dashes - total_dashes / 2) as usize;
+    let line = format!(
+        "{}{}{}",
+        rule.repeat(left),
+        label,
+        rule.repeat(right)
+    );
+    Line::from(Span::styled(
+        line,
+        Style::default()
+            .fg(Color::Red)
+            .add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// カスタム絵文字 (id, name) をテキストで描画する場合の表記を返す関数 (画像で描画するなら None)
+pub type EmojiText<'a> = &'a dyn Fn(&str, &str) -> Option<String>;
+
+/// 本文の描画設定
+#[derive(Clone, Copy)]
+pub struct BodyOptions<'a> {
+    /// 強調表示するキーワード
+    pub keywords: &'a [String],
+    /// `SPOILER_` で始まる添付を隠さずに表示するか
+    pub reveal_spoilers: bool,
+    /// 行頭の印などに使う記号
+    pub glyphs: &'static Glyphs,
+    /// `L` で次に開くリンク (本文中のリンクの何番目か。このメッセージが対象のときのみ)
+    pub active_link: Option<usize>,
+    /// 表示する添付ファイルの上限 (超えた分は `+N more`。None なら制限しない)
+    pub max_attachments: Option<usize>,
+    /// 表示する埋め込みの上限 (超えた分は `+N more`。None なら制限しない)
+    pub max_embeds: Option<usize>,
+}
+
+/// 1メッセージ分のテキスト行と、カスタム絵文字の (x cell オフセット, emoji_id) リスト、
+/// アバター描画位置 (x cell オフセット、`show_avatar` 時のみ) を構築
+pub fn build_message_line(
+    msg: &Message,
+    author_color: Color,
+    show_avatar: bool,
+    grouped: bool,
+    timestamp: TimestampFormat,
+    options: BodyOptions,
+    emoji_text: EmojiText,
+) -> (Line<'static>, Vec<(u16, String)>, Option<u16>) {
+    let time_str = timestamp.prefix(&msg.timestamp);
+    if let Some(text) = msg.system_text() {
+        // システムメッセージ: 作者名と本文の代わりに合成した文章を控えめな色で表示する
+        let spans = vec![
+            Span::styled(time_str, Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                format!("{}{}", options.glyphs.system, text),
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            ),
+        ];
+        return (Line::from(spans), Vec::new(), None);
+    }
+    let user_str = format!("{}: ", msg.author_display_name());
+
+    if grouped {
+        // まとめ表示: 作者名 (とアバター) の代わりに同じ幅の空白を入れて本文の位置を揃える
+        let avatar_w = if show_avatar { 3 } else { 0 };
+        let pad_w = avatar_w + user_str.as_str().width();
+        let indent = time_str.as_str().width() + pad_w;
+        let (body, emoji_positions) = build_message_body(msg, indent as u16, options, emoji_text);
+        let mut spans = vec![
+            Span::styled(time_str, Style::default().fg(Color::DarkGray)),
+            Span::raw(" ".repeat(pad_w)),
+        ];
+        spans.extend(body.spans);
+        return (Line::from(spans), emoji_positions, None);
+    }
+
+    let (mut spans, col_offset, avatar_x) =
+        author_spans(time_str, show_avatar, user_str, author_color);
+    let (body, emoji_positions) = build_message_body(msg, col_offset, options, emoji_text);
+    spans.extend(body.spans);
+
+    (Line::from(spans), emoji_positions, avatar_x)
+}
+
+/// Comfortable 表示で本文を字下げする幅 (時刻の後ろ)
+const COMFORTABLE_INDENT: usize = 2;
+
+/// Comfortable 表示の見出し行 (時刻 + アバター + 作者名) と、アバター描画位置を構築
+pub fn build_author_line(
+    msg: &Message,
+    author_color: Color,
+    show_avatar: bool,
+    timestamp: TimestampFormat,
+) -> (Line<'static>, Option<u16>) {
+    let time_str = timestamp.prefix(&msg.timestamp);
+    let name = msg.author_display_name().to_string();
+    let (spans, _, avatar_x) = author_spans(time_str, show_avatar, name, author_color);
+    (Line::from(spans), avatar_x)
+}
+
+/// Comfortable 表示の本文行 (時刻の幅 + COMFORTABLE_INDENT だけ字下げ) と、
+/// カスタム絵文字の (x cell オフセット, emoji_id) リストを構築
+pub fn build_indented_body_line(
+    msg: &Message,
+    timestamp: TimestampFormat,
+    options: BodyOptions,
+    emoji_text: EmojiText,
+) -> (Line<'static>, Vec<(u16, String)>) {
+    let indent = timestamp.prefix(&msg.timestamp).as_str().width() + COMFORTABLE_INDENT;
+    let (body, emoji_positions) = build_message_body(msg, indent as u16, options, emoji_text);
+    let mut spans = vec![Span::raw(" ".repeat(indent))];
+    spans.extend(body.spans);
+    (Line::from(spans), emoji_positions)
+}
+
+/// 時刻 + アバター用の空白 + 作者名の spans と、続く本文の開始列、アバター描画位置を返す
+fn author_spans(
+    time_str: String,
+    show_avatar: bool,
+    user_str: String,
+    author_color: Color,
+) -> (Vec<Span<'static>>, u16, Option<u16>) {
+    let mut col_offset: u16 = time_str.as_str().width() as u16;
+    let mut spans: Vec<Span<'static>> = vec![Span::styled(
+        time_str,
+        Style::default().fg(Color::DarkGray),
+    )];
+    // アバター用に 2 セル + 空白 1 セルを確保 (画像は描画側でオーバーレイ)
+    let avatar_x = if show_avatar {
+        let x = col_offset;
+        spans.push(Span::raw("   ".to_string()));
+        col_offset = col_offset.saturating_add(3);
+        Some(x)
+    } else {
+        None
+    };
+    col_offset = col_offset.saturating_add(user_str.as_str().width() as u16);
+    spans.push(Span::styled(
+        user_str,
+        Style::default()
+            .fg(author_color)
+            .add_modifier(Modifier::BOLD),
+    ));
+    (spans, col_offset, avatar_x)
+}
+
+/// ユーザー ID から選ぶ作者名の色。暗い背景で読みにくい青・灰色と、
+/// 未読やエラーの表示に使う赤は入れない
+const AUTHOR_PALETTE: [Color; 8] = [
+    Color::Green,
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::LightGreen,
+    Color::LightCyan,
+    Color::LightYellow,
+    Color::LightMagenta,
+];
+
+/// 作者名の色。サーバーで色付きのロールがあれば最上位のロールの色、無ければユーザー ID から
+/// 決まる色 (`author_colors` が false なら従来通りすべて緑)
+pub fn author_color(app: &AppState, msg: &Message) -> Color {
+    if !app.config.author_colors {
+        return Color::Green;
+    }
+    role_color(app, msg).unwrap_or_else(|| hashed_author_color(&msg.author.id))
+}
+
+/// 作者が持つロールのうち、色付きで最上位のものの色
+fn role_color(app: &AppState, msg: &Message) -> Option<Color> {
+    let member_roles = &msg.member.as_ref()?.roles;
+    let guild_id = app.discord.channels.get(&msg.channel_id)?.guild_id.as_ref()?;
+    let guild = app.discord.guilds.get(guild_id)?;
+    let role = guild
+        .roles
+        .iter()
+        .filter(|role| role.color != 0 && member_roles.contains(&role.id))
+        .max_by_key(|role| role.position)?;
+    let [_, r, g, b] = role.color.to_be_bytes();
+    Some(Color::Rgb(r, g, b))
+}
+
+/// ユーザー ID から決まる色 (FNV-1a。起動をまたいでも同じ人は同じ色になる)
+fn hashed_author_color(user_id: &str) -> Color {
+    let hash = user_id
+        .bytes()
+        .fold(0x811c_9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x0100_0193));
+    AUTHOR_PALETTE[hash as usize % AUTHOR_PALETTE.len()]
+}
+
+/// 本文 (テキスト + カスタム絵文字 + 添付) の spans を構築する。
+/// `col_offset` は本文の開始列で、絵文字オーバーレイ位置の計算に使う。
+/// キーワードに一致する箇所は強調し、一致したメッセージには先頭に印を付ける
+pub fn build_message_body(
+    msg: &Message,
+    mut col_offset: u16,
+    options: BodyOptions,
+    emoji_text: EmojiText,
+) -> (Line<'static>, Vec<(u16, String)>) {
+    let keywords = options.keywords;
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut emoji_positions: Vec<(u16, String)> = Vec::new();
+
+    if contains_keyword(&msg.content, keywords) {
+        let marker = options.glyphs.keyword.to_string();
+        col_offset = col_offset.saturating_add(marker.as_str().width() as u16);
+        spans.push(Span::styled(marker, keyword_style()));
+    }
+
+    // 転送されたアナウンスには転送元が別チャンネルであることを示す印を付ける
+    if msg.is_crosspost() {
+        let marker = options.glyphs.crosspost.to_string();
+        col_offset = col_offset.saturating_add(marker.as_str().width() as u16);
+        spans.push(Span::styled(marker, Style::default().fg(Color::Magenta)));
+    }
+
+    // 遅延応答のインタラクションは本文が届くまで "thinking…" を表示
+    if msg.is_loading() && msg.content.is_empty() {
+        spans.push(Span::styled(
+            format!("thinking{}", options.glyphs.ellipsis),
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+        ));
+    }
+
+    if !msg.content.is_empty() {
+        // 本文中のリンクの通し番号 (`active_link` と照らし合わせる)
+        let mut link_index = 0;
+        for seg in crate::emoji::parse_message_segments(&msg.content) {
+            match seg {
+                crate::emoji::MessageSegment::Text(t) => {
+                    let w = t.as_str().width() as u16;
+                    spans.extend(linkify(&t, keywords, options.active_link, &mut link_index));
+                    col_offset = col_offset.saturating_add(w);
+                }
+                crate::emoji::MessageSegment::Emoji { id, name, .. } => {
+                    if let Some(text) = emoji_text(&id, &name) {
+                        // 画像で描画できない場合は `:name:` で表示
+                        col_offset = col_offset.saturating_add(text.as_str().width() as u16);
+                        spans.push(Span::styled(text, Style::default().fg(Color::Yellow)));
+                    } else {
+                        // 2 セル幅占位 (画像オーバーレイ用)
+                        spans.push(Span::raw("  ".to_string()));
+                        emoji_positions.push((col_offset, id));
+                        col_offset = col_offset.saturating_add(2);
+                    }
+                }
+            }
+        }
+    }
+
+    let attachment_limit = options.max_attachments.unwrap_or(usize::MAX);
+    for (i, attachment) in msg.attachments.iter().take(attachment_limit).enumerate() {
+        if i > 0 || !msg.content.is_empty() {
+            spans.push(Span::raw(" ".to_string()));
+            col_offset = col_offset.saturating_add(1);
+        }
+        // スポイラーの添付は `s` で表示するまで内容 (ファイル名) も出さない
+        let (txt, color) = if attachment.is_spoiler() && !options.reveal_spoilers {
+            ("[Spoiler: hidden]".to_string(), Color::Magenta)
+        } else {
+            (attachment.display_text(), Color::Cyan)
+        };
+        col_offset = col_offset.saturating_add(txt.as_str().width() as u16);
+        spans.push(Span::styled(
+            txt,
+            Style::default().fg(color).add_modifier(Modifier::ITALIC),
+        ));
+    }
+
+    if let Some(more) = more_label(msg.attachments.len(), attachment_limit) {
+        spans.push(Span::styled(more, Style::default().fg(Color::DarkGray)));
+    }
+
+    // 埋め込みはタイトルのみ表示。作者が非表示にしたもの (SUPPRESS_EMBEDS) は出さない
+    if !msg.embeds_suppressed() {
+        let titles: Vec<&str> = msg.embeds.iter().filter_map(|e| e.title.as_deref()).collect();
+        let embed_limit = options.max_embeds.unwrap_or(usize::MAX);
+        for title in titles.iter().take(embed_limit) {
+            if !spans.is_empty() {
+                spans.push(Span::raw(" ".to_string()));
+            }
+            spans.push(Span::styled(
+                format!("[Embed: {}]", title),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::ITALIC),
+            ));
+        }
+        if let Some(more) = more_label(titles.len(), embed_limit) {
+            spans.push(Span::styled(more, Style::default().fg(Color::DarkGray)));
+        }
+    }
+
+    // スタンプは名前のみ表示 (Lottie 等は描画できないため)
+    for sticker in &msg.sticker_items {
+        if !spans.is_empty() {
+            spans.push(Span::raw(" ".to_string()));
+        }
+        spans.push(Span::styled(
+            sticker.display_text(),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::ITALIC),
+        ));
+    }
+
+    if msg.is_ephemeral() {
+        spans.push(Span::styled(
+            " (only you can see this)".to_string(),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    (Line::from(spans), emoji_positions)
+}
+
+/// `total` 件のうち `limit` 件だけ表示したときの省略表記 (省略が無ければ None)
+fn more_label(total: usize, limit: usize) -> Option<String> {
+    (total > limit).then(|| format!(" +{} more", total - limit))
+}
+
+/// キーワードの強調表示スタイル
+pub fn keyword_style() -> Style {
+    Style::default()
+        .fg(Color::Black)
+        .bg(Color::Yellow)
+        .add_modifier(Modifier::BOLD)
+}
+
+/// 本文中のリンクのスタイル
+pub fn link_style() -> Style {
+    Style::default()
+        .fg(Color::Blue)
+        .add_modifier(Modifier::UNDERLINED)
+}
+
+/// テキストをリンクとそれ以外に分け、リンクは下線付きで、それ以外はキーワードを強調して
+/// spans にする。`link_index` は本文中のリンクの通し番号で、`active` 番目のリンクは反転表示する
+fn linkify(
+    text: &str,
+    keywords: &[String],
+    active: Option<usize>,
+    link_index: &mut usize,
+) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in find_links(text) {
+        spans.extend(highlight_keywords(&text[pos..range.start], keywords));
+        let style = if active == Some(*link_index) {
+            link_style().add_modifier(Modifier::REVERSED)
+        } else {
+            link_style()
+        };
+        spans.push(Span::styled(text[range.clone()].to_string(), style));
+        *link_index += 1;
+        pos = range.end;
+    }
+    spans.extend(highlight_keywords(&text[pos..], keywords));
+    spans
+}
+
+/// テキストをキーワードに一致する部分とそれ以外の spans に分ける
+pub fn highlight_keywords(text: &str, keywords: &[String]) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in find_keywords(text, keywords) {
+        if range.start > pos {
+            spans.push(Span::raw(text[pos..range.start].to_string()));
+        }
+        spans.push(Span::styled(text[range.clone()].to_string(), keyword_style()));
+        pos = range.end;
+    }
+    if pos < text.len() {
+        spans.push(Span::raw(text[pos..].to_string()));
+    }
+    spans
+}
+
+/// `msg` が直前のメッセージ `prev` とまとめて表示できるか
+/// (同じ作者で、投稿間隔が window_secs 以内)
+pub fn is_grouped_with(prev: &Message, msg: &Message, window_secs: i64) -> bool {
+    if prev.author.id != msg.author.id || !prev.is_regular() || !msg.is_regular() {
+        return false;
+    }
+    match (
+        prev.timestamp.parse::<DateTime<Utc>>(),
+        msg.timestamp.parse::<DateTime<Utc>>(),
+    ) {
+        (Ok(prev_at), Ok(at)) => {
+            let elapsed = (at - prev_at).num_seconds();
+            (0..=window_secs).contains(&elapsed)
+        }
+        _ => false,
+    }
+}
+
+/// メッセージ行頭の時刻表示の設定
+#[derive(Clone, Copy)]
+pub struct TimestampFormat {
+    layout: TimestampLayout,
+    clock_24h: bool,
+    /// gutter と本文の区切り
+    separator: &'static str,
+}
+
+impl TimestampFormat {
+    pub fn new(app: &AppState) -> Self {
+        Self {
+            layout: app.config.timestamp_layout,
+            clock_24h: app.config.clock_24h,
+            separator: app.glyphs().gutter,
+        }
+    }
+
+    /// 行頭に付ける時刻部分 (本文との区切りの空白を含む)
+    pub fn prefix(&self, timestamp: &str) -> String {
+        match self.layout {
+            TimestampLayout::Inline => {
+                format!("[{}] ", format_timestamp(timestamp, self.clock_24h))
+            }
+            TimestampLayout::Gutter => {
+                // 列幅は時刻書式から求める (パース失敗時の "??:??" 等と同じ幅)
+                let width = format_timestamp("", self.clock_24h).width();
+                let time = format_timestamp(timestamp, self.clock_24h);
+                let pad = width.saturating_sub(time.as_str().width());
+                format!("{}{} {} ", " ".repeat(pad), time, self.separator)
+            }
+            TimestampLayout::Hidden => String::new(),
+        }
+    }
+}
+
+/// 日本時間 (UTC+9)
+fn jst() -> FixedOffset {
+    FixedOffset::east_opt(9 * 3600).unwrap()
+}
+
+/// タイムスタンプを日本時間 (UTC+9) に変換
+pub fn to_local(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    let dt = timestamp.parse::<DateTime<Utc>>().ok()?;
+    Some(dt.with_timezone(&jst()))
+}
+
+/// 日本時間での今年 (日付の区切り線で年を省くかの判定に使う)
+pub fn current_local_year() -> i32 {
+    local_year(Utc::now())
+}
+
+/// `now` の日本時間での年
+fn local_year(now: DateTime<Utc>) -> i32 {
+    now.with_timezone(&jst()).year()
+}
+
+/// タイムスタンプの日付 (日本時間)。パースできなければ None
+pub fn local_date(timestamp: &str) -> Option<NaiveDate> {
+    to_local(timestamp).map(|dt| dt.date_naive())
+}
+
+/// 日付の区切り線 ("── March 3 ──"、`rule` は罫線の文字)。今年以外の日付には年を付ける
+pub fn build_date_separator_line(
+    date: NaiveDate,
+    current_year: i32,
+    width: u16,
+    rule: &str,
+) -> Line<'static> {
+    let label = if date.year() == current_year {
+        format!(" {} ", date.format("%B %-d"))
+    } else {
+        format!(" {} ", date.format("%B %-d, %Y"))
+    };
+    let dashes = (width as usize).saturating_sub(label.width());
+    let line = format!("{}{}{}", rule.repeat(dashes / 2), label, rule.repeat(dashes - dashes / 2));
+    Line::from(Span::styled(line, Style::default().fg(Color::DarkGray)))
+}
+
+/// タイムスタンプを "HH:MM" (24 時間) または "hh:MM AM/PM" (12 時間) 形式に整形（日本時間）
+pub fn format_timestamp(timestamp: &str, clock_24h: bool) -> String {
+    if let Some(dt_jst) = to_local(timestamp) {
+        let format = if clock_24h { "%H:%M" } else { "%I:%M %p" };
+        dt_jst.format(format).to_string()
+    } else if clock_24h {
+        "??:??".to_string()
+    } else {
+        "??:?? ??".to_string()
+    }
+}
+
+/// 返信バナーの 1 行 ("Replying to @author: snippet")
+pub fn reply_banner_line(reply: &ReplyTarget) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(" Replying to ", Style::default().fg(Color::DarkGray)),
+        Span::styled(
+            format!("@{}", reply.author),
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!(": {}", reply.snippet),
+            Style::default().fg(Color::DarkGray),
+        ),
+        if reply.mention {
+            Span::styled("  @ON", Style::default().fg(Color::Cyan))
+        } else {
+            Span::styled("  @OFF", Style::default().fg(Color::DarkGray))
+        },
+        Span::styled("  (Tab: ping | Esc: cancel)", Style::default().fg(Color::DarkGray)),
+    ])
+}
+
+/// 送信待ちメッセージの 1 行 (本文は 1 行目のみ)
+pub fn pending_send_line(pending: &PendingSend) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(" pending ", Style::default().fg(Color::Black).bg(Color::Yellow)),
+        Span::styled(
+            format!(" {}", pending.content.lines().next().unwrap_or("")),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ])
+}
+
+/// 送信中・送信に失敗した自分のメッセージの仮の 1 行 (本文は 1 行目のみ)。
+/// 送信中は薄い時計の印、失敗したら赤で理由と再送のキーを出す
+pub fn outgoing_send_line(send: &OutgoingSend, author: &str, glyphs: &Glyphs) -> Line<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut spans = match &send.status {
+        SendStatus::Sending => vec![Span::styled(glyphs.sending.to_string(), dim)],
+        SendStatus::Failed(_) => vec![Span::styled(
+            format!("{}failed — press R to retry ", glyphs.send_failed),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )],
+    };
+    spans.push(Span::styled(
+        format!("{}: {}", author, send.content.lines().next().unwrap_or("")),
+        dim,
+    ));
+    if let SendStatus::Failed(error) = &send.status {
+        spans.push(Span::styled(format!(" ({})", error), Style::default().fg(Color::Red)));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INLINE_24H: TimestampFormat = TimestampFormat {
+        layout: TimestampLayout::Inline,
+        clock_24h: true,
+        separator: "│",
+    };
+
+    const PLAIN: BodyOptions = BodyOptions {
+        keywords: &[],
+        reveal_spoilers: false,
+        glyphs: &Glyphs::UNICODE,
+        active_link: None,
+        max_attachments: None,
+        max_embeds: None,
+    };
+
+    fn message(content: &str, extra: serde_json::Value) -> Message {
+        let mut value = serde_json::json!({
+            "id": "1",
+            "channel_id": "2",
+            "author": { "id": "3", "username": "alice", "discriminator": "0" },
+            "content": content,
+            "timestamp": "2024-01-01T00:00:00+00:00",
+        });
+        if let (Some(base), Some(extra)) = (value.as_object_mut(), extra.as_object()) {
+            base.extend(extra.clone());
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// アバター無し・既定の作者名の色で 1 行表示を構築する
+    fn compact_line(
+        msg: &Message,
+        grouped: bool,
+        emoji_text: EmojiText,
+    ) -> (Line<'static>, Vec<(u16, String)>, Option<u16>) {
+        build_message_line(msg, Color::Green, false, grouped, INLINE_24H, PLAIN, emoji_text)
+    }
+
+    fn text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    fn no_emoji_images(_: &str, name: &str) -> Option<String> {
+        Some(format!(":{}:", name))
+    }
+
+    #[test]
+    fn formats_timestamps_in_jst() {
+        let ts = "2024-01-01T06:05:00+00:00";
+        assert_eq!(format_timestamp(ts, true), "15:05");
+        assert_eq!(format_timestamp(ts, false), "03:05 PM");
+        assert_eq!(format_timestamp("broken", true), "??:??");
+    }
+
+    #[test]
+    fn gutter_pads_time_to_a_fixed_column() {
+        let gutter = TimestampFormat {
+            layout: TimestampLayout::Gutter,
+            ..INLINE_24H
+        };
+        assert_eq!(gutter.prefix("2024-01-01T00:00:00+00:00"), "09:00 │ ");
+        assert_eq!(gutter.prefix("broken"), "??:?? │ ");
+        let hidden = TimestampFormat {
+            layout: TimestampLayout::Hidden,
+            ..INLINE_24H
+        };
+        assert_eq!(hidden.prefix("2024-01-01T00:00:00+00:00"), "");
+    }
+
+    #[test]
+    fn system_messages_render_synthesized_text() {
+        let joined = message("", serde_json::json!({ "type": 7 }));
+        let (line, _, _) = compact_line(&joined, false, &no_emoji_images);
+        assert_eq!(text(&line), "[09:00] → alice joined the server");
+
+        let boost = message("", serde_json::json!({ "type": 10 }));
+        assert_eq!(
+            boost.system_text().as_deref(),
+            Some("alice boosted the server! The server has reached Level 2")
+        );
+        let reply = message("hi", serde_json::json!({ "type": 19 }));
+        assert_eq!(reply.system_text(), None);
+        assert!(!is_grouped_with(&reply, &joined, 300));
+    }
+
+    #[test]
+    fn grouped_line_aligns_body_with_author_line() {
+        let msg = message("hello", serde_json::json!({}));
+        let (line, _, _) = compact_line(&msg, false, &no_emoji_images);
+        assert_eq!(text(&line), "[09:00] alice: hello");
+        let (grouped, _, _) = compact_line(&msg, true, &no_emoji_images);
+        assert_eq!(text(&grouped), "[09:00]        hello");
+    }
+
+    #[test]
+    fn custom_emoji_reserves_cells_for_image_overlay() {
+        let msg = message("hi <:blob:42> there", serde_json::json!({}));
+        let (line, positions, _) = compact_line(&msg, false, &|_, _| None);
+        assert_eq!(text(&line), "[09:00] alice: hi    there");
+        assert_eq!(positions, vec![(18, "42".to_string())]);
+
+        let (line, positions, _) = compact_line(&msg, false, &no_emoji_images);
+        assert_eq!(text(&line), "[09:00] alice: hi :blob: there");
+        assert!(positions.is_empty());
+    }
+
+    #[test]
+    fn highlights_keywords_and_marks_the_message() {
+        let msg = message("ping Bob now", serde_json::json!({}));
+        let keywords = vec!["bob".to_string()];
+        let options = BodyOptions {
+            keywords: &keywords,
+            ..PLAIN
+        };
+        let (body, _) = build_message_body(&msg, 0, options, &no_emoji_images);
+        let contents: Vec<&str> = body.spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(contents, vec!["» ", "ping ", "Bob", " now"]);
+        assert_eq!(body.spans[2].style, keyword_style());
+    }
+
+    #[test]
+    fn links_are_styled_and_the_active_one_stands_out() {
+        let msg = message("see https://a.example/x. or (https://b.example)", serde_json::json!({}));
+        let options = BodyOptions {
+            active_link: Some(1),
+            ..PLAIN
+        };
+        let (body, _) = build_message_body(&msg, 0, options, &no_emoji_images);
+        let contents: Vec<&str> = body.spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(
+            contents,
+            vec!["see ", "https://a.example/x", ". or (", "https://b.example", ")"]
+        );
+        assert_eq!(body.spans[1].style, link_style());
+        assert_eq!(body.spans[3].style, link_style().add_modifier(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn suppressed_embeds_are_not_rendered() {
+        let embeds = serde_json::json!({ "embeds": [{ "title": "Docs" }] });
+        let (body, _) =
+            build_message_body(&message("link", embeds.clone()), 0, PLAIN, &no_emoji_images);
+        assert_eq!(text(&body), "link [Embed: Docs]");
+
+        let mut suppressed = embeds;
+        suppressed["flags"] = serde_json::json!(crate::discord::message_flags::SUPPRESS_EMBEDS);
+        let (body, _) =
+            build_message_body(&message("link", suppressed), 0, PLAIN, &no_emoji_images);
+        assert_eq!(text(&body), "link");
+    }
+
+    #[test]
+    fn unread_separator_fills_the_width() {
+        let line = build_unread_separator_line(30, "─");
+        assert_eq!(text(&line).chars().count(), 30);
+        assert!(text(&line).contains(" New messages "));
+    }
+
+    #[test]
+    fn date_separator_uses_japan_time_and_omits_current_year() {
+        // UTC 15:00 は日本時間で翌日
+        let date = local_date("2024-03-02T15:00:00+00:00").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2024, 3, 3).unwrap());
+        assert_eq!(text(&build_date_separator_line(date, 2024, 13, "─")), "── March 3 ──");
+        assert_eq!(
+            text(&build_date_separator_line(date, 2025, 0, "─")),
+            " March 3, 2024 "
+        );
+        assert_eq!(local_date("not a timestamp"), None);
+
+        // 今年も日本時間で判定する (UTC では大晦日でも日本時間では年が明けている)
+        let new_year = "2024-12-31T15:00:00+00:00".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(local_year(new_year), 2025);
+    }
+
+    #[test]
+    fn spoiler_attachments_stay_hidden_until_revealed() {
+        let attachments = serde_json::json!({ "attachments": [{
+            "id": "9",
+            "filename": "SPOILER_cat.png",
+            "content_type": "image/png",
+        }] });
+        let msg = message("", attachments);
+        let (hidden, _) = build_message_body(&msg, 0, PLAIN, &no_emoji_images);
+        assert_eq!(text(&hidden), "[Spoiler: hidden]");
+        let revealed = BodyOptions {
+            reveal_spoilers: true,
+            ..PLAIN
+        };
+        let (shown, _) = build_message_body(&msg, 0, revealed, &no_emoji_images);
+        assert_eq!(text(&shown), "[Image: SPOILER_cat.png]");
+    }
+
+    #[test]
+    fn attachments_and_embeds_beyond_the_cap_are_summarized() {
+        let files: Vec<serde_json::Value> = (1..=5)
+            .map(|i| serde_json::json!({ "id": i.to_string(), "filename": format!("{}.txt", i) }))
+            .collect();
+        let extra = serde_json::json!({
+            "attachments": files,
+            "embeds": [{ "title": "A" }, { "title": "B" }, { "title": "C" }],
+        });
+        let msg = message("", extra);
+        let capped = BodyOptions {
+            max_attachments: Some(2),
+            max_embeds: Some(1),
+            ..PLAIN
+        };
+        let (body, _) = build_message_body(&msg, 0, capped, &no_emoji_images);
+        assert_eq!(
+            text(&body),
+            "[File: 1.txt] [File: 2.txt] +3 more [Embed: A] +2 more"
+        );
+        let (body, _) = build_message_body(&msg, 0, PLAIN, &no_emoji_images);
+        assert!(text(&body).ends_with("[File: 5.txt] [Embed: A] [Embed: B] [Embed: C]"));
+    }
+
+    #[test]
+    fn author_colors_prefer_the_top_colored_role_over_the_hashed_color() {
+        use crate::test_fixtures::{connected_app, GENERAL_ID, GUILD_ID};
+        let mut app = connected_app();
+        app.discord.guilds.get_mut(GUILD_ID).unwrap().roles = serde_json::from_value(
+            serde_json::json!([
+                { "id": "r1", "color": 0x3498db, "position": 1 },
+                { "id": "r2", "color": 0xe91e63, "position": 5 },
+                { "id": "r3", "color": 0, "position": 9 },
+            ]),
+        )
+        .unwrap();
+        let plain = message("hi", serde_json::json!({ "channel_id": GENERAL_ID }));
+        let hashed = author_color(&app, &plain);
+        assert!(AUTHOR_PALETTE.contains(&hashed));
+        let hard_to_read = [Color::Blue, Color::LightBlue, Color::Red, Color::LightRed];
+        assert!(!AUTHOR_PALETTE.iter().any(|c| hard_to_read.contains(c)));
+        // 同じユーザーは常に同じ色
+        assert_eq!(hashed_author_color("3"), hashed);
+
+        let extra = serde_json::json!({
+            "channel_id": GENERAL_ID,
+            "member": { "roles": ["r1", "r2", "r3"] },
+        });
+        let with_roles = message("hi", extra);
+        assert_eq!(author_color(&app, &with_roles), Color::Rgb(0xe9, 0x1e, 0x63));
+
+        app.config.author_colors = false;
+        assert_eq!(author_color(&app, &with_roles), Color::Green);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::app::AppState;
+use crate::discord::{Channel, DiscordApi, DiscordError, Guild, Message, MessageReply, User};
+use futures::future::BoxFuture;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// フィクスチャのサーバー / チャンネル ID
+pub const GUILD_ID: &str = "100";
+pub const GENERAL_ID: &str = "200";
+pub const RANDOM_ID: &str = "201";
+
+/// ログイン中のユーザー
+pub fn me() -> User {
+    serde_json::from_value(json!({ "id": "1", "username": "me", "discriminator": "0" })).unwrap()
+}
+
+/// テキストチャンネル
+pub fn text_channel(id: &str, name: &str) -> Channel {
+    serde_json::from_value(json!({
+        "id": id,
+        "type": 0,
+        "guild_id": GUILD_ID,
+        "name": name,
+    }))
+    .unwrap()
+}
+
+/// `channel_id` への `author` のメッセージ (timestamp は UTC の "HH:MM")
+pub fn message(id: &str, channel_id: &str, author: &str, content: &str, utc_time: &str) -> Message {
+    serde_json::from_value(json!({
+        "id": id,
+        "channel_id": channel_id,
+        "author": { "id": format!("u-{}", author), "username": author, "discriminator": "0" },
+        "content": content,
+        "timestamp": format!("2024-01-01T{}:00+00:00", utc_time),
+    }))
+    .unwrap()
+}
+
+/// 接続済みで、サーバー 1 つ (#general をお気に入り登録・選択中, #random) を持つ状態。
+/// メッセージは空なので、必要に応じて `with_messages` で追加する
+pub fn connected_app() -> AppState {
+    let mut app = AppState::new();
+    app.discord.connected = true;
+    app.discord.current_user = Some(me());
+    let guild: Guild =
+        serde_json::from_value(json!({ "id": GUILD_ID, "name": "Hakuhyo Dev" })).unwrap();
+    app.discord.guilds.insert(GUILD_ID.to_string(), guild);
+    for channel in [
+        text_channel(GENERAL_ID, "general"),
+        text_channel(RANDOM_ID, "random"),
+    ] {
+        app.discord.channels.insert(channel.id.clone(), channel);
+    }
+    app.ui.favorites.push(GENERAL_ID.to_string());
+    app.ui.selected_channel = Some(GENERAL_ID.to_string());
+    app.ui.channel_list_state.select(Some(0));
+    app
+}
+
+/// `connected_app` の #general に会話を入れた状態 (REST と同じく新しい順に保持)
+pub fn with_messages() -> AppState {
+    let mut app = connected_app();
+    let messages = vec![
+        message("303", GENERAL_ID, "bob", "see you", "01:02"),
+        message("302", GENERAL_ID, "me", "hi alice", "01:01"),
+        message("301", GENERAL_ID, "alice", "hello", "01:00"),
+    ];
+    app.discord.messages.insert(GENERAL_ID.to_string(), messages);
+    app
+}
+
+/// ネットワークを使わない `DiscordApi`。送信は `send_results` を先頭から順に返し、
+/// 呼び出し内容を記録する。`messages` 以外の取得系は空を返す
+#[derive(Default)]
+pub struct MockApi {
+    /// channel_id -> get_messages で返すメッセージ (新しい順)
+    pub messages: HashMap<String, Vec<Message>>,
+    /// get_messages の失敗 (空になったら `messages` から返す)
+    pub message_errors: Mutex<VecDeque<DiscordError>>,
+    /// send_message の応答 (空になったら送った内容をそのまま返す)
+    pub send_results: Mutex<VecDeque<Result<Message, DiscordError>>>,
+    /// send_message で送られた (channel_id, content, reply_to)
+    pub sent: Mutex<Vec<(String, String, Option<MessageReply>)>>,
+    /// ack_message で既読にされた (channel_id, message_id)
+    pub acked: Mutex<Vec<(String, String)>>,
+    /// delete_message の応答 (空になったら成功を返す)
+    pub delete_results: Mutex<VecDeque<Result<(), DiscordError>>>,
+    /// delete_message で削除を要求された (channel_id, message_id)
+    pub deleted: Mutex<Vec<(String, String)>>,
+    /// edit_message で書き換えを要求された (channel_id, message_id, content)
+    pub edited: Mutex<Vec<(String, String, String)>>,
+    /// add_reaction の応答 (空になったら成功を返す)
+    pub reaction_results: Mutex<VecDeque<Result<(), DiscordError>>>,
+}
+
+impl DiscordApi for MockApi {
+    fn get_messages<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        before: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        if let Some(error) = self.message_errors.lock().unwrap().pop_front() {
+            return Box::pin(async move { Err(error) });
+        }
+        let messages = self.messages.get(channel_id).cloned().unwrap_or_default();
+        let start = before
+            .and_then(|b| messages.iter().position(|m| m.id == b))
+            .map_or(0, |i| i + 1);
+        let page = messages.into_iter().skip(start).take(limit as usize).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn get_messages_after<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        after: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        let messages = self.messages.get(channel_id).cloned().unwrap_or_default();
+        let end = messages.iter().position(|m| m.id == after).unwrap_or(messages.len());
+        let page = messages.into_iter().take(end.min(limit as usize)).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn get_messages_around<'a>(
+        &'a self,
+        channel_id: &'a str,
+        limit: u8,
+        around: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Message>, DiscordError>> {
+        let messages = self.messages.get(channel_id).cloned().unwrap_or_default();
+        let center = messages.iter().position(|m| m.id == around).unwrap_or(0);
+        let start = center.saturating_sub(limit as usize / 2);
+        let page = messages.into_iter().skip(start).take(limit as usize).collect();
+        Box::pin(async move { Ok(page) })
+    }
+
+    fn send_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        content: &'a str,
+        reply_to: Option<&'a MessageReply>,
+        _mass_mentions: bool,
+    ) -> BoxFuture<'a, Result<Message, DiscordError>> {
+        self.sent.lock().unwrap().push((
+            channel_id.to_string(),
+            content.to_string(),
+            reply_to.cloned(),
+        ));
+        let result = self
+            .send_results
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| Ok(message("9000", channel_id, "me", content, "12:00")));
+        Box::pin(async move { result })
+    }
+
+    fn edit_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+        content: &'a str,
+    ) -> BoxFuture<'a, Result<Message, DiscordError>> {
+        self.edited.lock().unwrap().push((
+            channel_id.to_string(),
+            message_id.to_string(),
+            content.to_string(),
+        ));
+        let edited = message(message_id, channel_id, "me", content, "12:00");
+        Box::pin(async move { Ok(edited) })
+    }
+
+    fn delete_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        self.deleted
+            .lock()
+            .unwrap()
+            .push((channel_id.to_string(), message_id.to_string()));
+        let result = self.delete_results.lock().unwrap().pop_front().unwrap_or(Ok(()));
+        Box::pin(async move { result })
+    }
+
+    fn add_reaction<'a>(
+        &'a self,
+        _channel_id: &'a str,
+        _message_id: &'a str,
+        _emoji: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        let result = self.reaction_results.lock().unwrap().pop_front().unwrap_or(Ok(()));
+        Box::pin(async move { result })
+    }
+
+    fn ack_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message_id: &'a str,
+    ) -> BoxFuture<'a, Result<(), DiscordError>> {
+        self.acked
+            .lock()
+            .unwrap()
+            .push((channel_id.to_string(), message_id.to_string()));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn refresh_attachment_urls<'a>(
+        &'a self,
+        urls: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<(String, String)>, DiscordError>> {
+        let refreshed = urls.iter().map(|u| (u.clone(), format!("{}&fresh", u))).collect();
+        Box::pin(async move { Ok(refreshed) })
+    }
+
+    fn get_guilds(&self) -> BoxFuture<'_, Result<Vec<Guild>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_guild_channels<'a>(
+        &'a self,
+        _guild_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_active_threads<'a>(
+        &'a self,
+        _guild_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_user<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, Result<User, DiscordError>> {
+        let user = serde_json::from_value(json!({
+            "id": user_id,
+            "username": format!("user{}", user_id),
+            "discriminator": "0",
+        }))
+        .map_err(DiscordError::from);
+        Box::pin(async move { user })
+    }
+
+    fn get_dm_channels(&self) -> BoxFuture<'_, Result<Vec<Channel>, DiscordError>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_channel<'a>(&'a self, channel_id: &'a str) -> BoxFuture<'a, Result<Channel, DiscordError>> {
+        let error = DiscordError::Http {
+            status: 404,
+            body: format!("Unknown channel {}", channel_id),
+        };
+        Box::pin(async move { Err(error) })
+    }
+
+    fn get_current_user(&self) -> BoxFuture<'_, Result<User, DiscordError>> {
+        Box::pin(async { Ok(me()) })
+    }
+
+    fn get_gateway_url(&self) -> BoxFuture<'_, Result<String, DiscordError>> {
+        Box::pin(async { Ok("wss://gateway.example".to_string()) })
+    }
+}
@@ -1,41 +1,31 @@
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
 /// トークンファイルのパスを取得
 ///
-/// `~/.config/hakuhyo/token.txt`
-fn get_token_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .context("Failed to get config directory")?
-        .join("hakuhyo");
-
-    // ディレクトリが存在しない場合は作成
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir)
-            .context("Failed to create config directory")?;
-        log::debug!("Created config directory: {:?}", config_dir);
-    }
-
-    Ok(config_dir.join("token.txt"))
+/// `<プロファイルのディレクトリ>/token.txt`
+fn get_token_path(dir: &Path) -> PathBuf {
+    dir.join("token.txt")
 }
 
 /// トークンをファイルに保存
 ///
 /// # セキュリティ
-/// - ファイルパーミッション: 0600（所有者のみ読み書き可能）
-/// - 保存先: ~/.config/hakuhyo/token.txt
-/// - ⚠️ 平文で保存されるため、バックアップやクラウド同期に注意
-pub fn save_token(token: &str) -> Result<()> {
+/// - Unix: ファイルパーミッション 0600（所有者のみ読み書き可能）で平文保存
+/// - Windows: DPAPI で暗号化して保存 (同じユーザーでログインしたときだけ復号できる)
+/// - 保存先: <プロファイルのディレクトリ>/token.txt (既定は ~/.config/hakuhyo/token.txt)
+/// - ⚠️ Unix では平文で保存されるため、バックアップやクラウド同期に注意
+pub fn save_token(dir: &Path, token: &str) -> Result<()> {
     log::debug!("Saving token to file...");
 
-    let token_path = get_token_path()?;
+    let token_path = get_token_path(dir);
 
     // トークンをファイルに書き込み
-    fs::write(&token_path, token)
+    fs::write(&token_path, encode_token(token)?)
         .with_context(|| format!("Failed to write token file: {:?}", token_path))?;
 
     // Unix系OSの場合、ファイルパーミッションを 0600 に設定（所有者のみ読み書き可能）
@@ -53,32 +43,147 @@ pub fn save_token(token: &str) -> Result<()> {
 }
 
 /// トークンをファイルから読み込み
-pub fn load_token() -> Result<String> {
+pub fn load_token(dir: &Path) -> Result<String> {
     log::debug!("Loading token from file...");
 
-    let token_path = get_token_path()?;
+    let token_path = get_token_path(dir);
 
     if !token_path.exists() {
         anyhow::bail!("Token file not found");
     }
 
-    let token = fs::read_to_string(&token_path)
+    let bytes = fs::read(&token_path)
         .with_context(|| format!("Failed to read token file: {:?}", token_path))?;
+    let token = decode_token(dir, &bytes)?;
 
     log::info!("✓ Token loaded from {:?}", token_path);
     Ok(token.trim().to_string())
 }
 
+/// ファイルに書き込む内容 (Unix は平文)
+#[cfg(not(windows))]
+fn encode_token(token: &str) -> Result<Vec<u8>> {
+    Ok(token.as_bytes().to_vec())
+}
+
+/// ファイルに書き込む内容 (Windows は DPAPI で暗号化)
+#[cfg(windows)]
+fn encode_token(token: &str) -> Result<Vec<u8>> {
+    dpapi::protect(token.as_bytes())
+}
+
+/// ファイルの内容からトークンを取り出す
+#[cfg(not(windows))]
+fn decode_token(_dir: &Path, bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).context("Token file is not valid UTF-8")
+}
+
+/// ファイルの内容からトークンを取り出す。
+/// 暗号化前の版で保存した平文のファイルなら読み込んだうえで暗号化して保存し直す
+#[cfg(windows)]
+fn decode_token(dir: &Path, bytes: &[u8]) -> Result<String> {
+    if let Ok(plain) = dpapi::unprotect(bytes) {
+        return String::from_utf8(plain).context("Decrypted token is not valid UTF-8");
+    }
+    let token = String::from_utf8(bytes.to_vec())
+        .context("Token file could not be decrypted for the current user")?;
+    log::info!("Migrating plaintext token file to DPAPI");
+    if let Err(e) = save_token(dir, token.trim()) {
+        log::warn!("Failed to re-save token with DPAPI: {:#}", e);
+    }
+    Ok(token)
+}
+
+/// Windows のデータ保護 API (DPAPI)。現在のユーザーの資格情報に紐づく鍵で暗号化する
+#[cfg(windows)]
+mod dpapi {
+    use anyhow::{bail, Result};
+    use std::ptr;
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    /// 現在のユーザーだけが復号できるよう暗号化
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = input_blob(data);
+        let mut output = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+        // SAFETY: input は呼び出し中有効な data を指す。output には成功時のみ
+        // LocalAlloc された領域が入り、take_output で解放する
+        let ok = unsafe {
+            CryptProtectData(
+                &input,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+        if ok == 0 {
+            bail!("CryptProtectData failed: {}", std::io::Error::last_os_error());
+        }
+        Ok(take_output(output))
+    }
+
+    /// protect で暗号化したデータを復号
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        let input = input_blob(data);
+        let mut output = CRYPT_INTEGER_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+        // SAFETY: protect と同じ
+        let ok = unsafe {
+            CryptUnprotectData(
+                &input,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+        if ok == 0 {
+            bail!("CryptUnprotectData failed: {}", std::io::Error::last_os_error());
+        }
+        Ok(take_output(output))
+    }
+
+    fn input_blob(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        }
+    }
+
+    /// DPAPI が確保した出力をコピーして解放する
+    fn take_output(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        // SAFETY: 成功した CryptProtectData / CryptUnprotectData の出力で、
+        // pbData から cbData バイトが有効。コピー後に一度だけ解放する
+        unsafe {
+            let bytes = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+            LocalFree(blob.pbData as _);
+            bytes
+        }
+    }
+}
+
 /// トークンをファイルから削除
 ///
 /// # 用途
 /// - 無効なトークンを削除する場合
 /// - ユーザーが明示的にログアウトする場合
 #[allow(dead_code)]
-pub fn delete_token() -> Result<()> {
+pub fn delete_token(dir: &Path) -> Result<()> {
     log::debug!("Deleting token file...");
 
-    let token_path = get_token_path()?;
+    let token_path = get_token_path(dir);
 
     if token_path.exists() {
         fs::remove_file(&token_path)
@@ -90,3 +195,55 @@ pub fn delete_token() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの一時ディレクトリ (前回の実行の残りは消しておく)
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hakuhyo-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn token_is_saved_as_owner_only_plaintext_outside_windows() {
+        let dir = scratch_dir("token-plain");
+        save_token(&dir, "secret-token").unwrap();
+        assert_eq!(fs::read(get_token_path(&dir)).unwrap(), b"secret-token");
+        #[cfg(unix)]
+        {
+            let mode = fs::metadata(get_token_path(&dir)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // 手で編集したファイルの改行は読み込み時に落とす
+        fs::write(get_token_path(&dir), "secret-token\n").unwrap();
+        assert_eq!(load_token(&dir).unwrap(), "secret-token");
+
+        delete_token(&dir).unwrap();
+        assert!(load_token(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn token_round_trips_through_dpapi() {
+        let dir = scratch_dir("token-dpapi");
+        save_token(&dir, "secret-token").unwrap();
+        let encrypted = fs::read(get_token_path(&dir)).unwrap();
+        assert_ne!(encrypted, b"secret-token");
+        assert_eq!(load_token(&dir).unwrap(), "secret-token");
+
+        // 暗号化前の平文のファイルは読めて、そのまま暗号化し直される
+        fs::write(get_token_path(&dir), "plain-token\n").unwrap();
+        assert_eq!(load_token(&dir).unwrap(), "plain-token");
+        let migrated = fs::read(get_token_path(&dir)).unwrap();
+        assert_ne!(migrated, b"plain-token");
+        assert_eq!(load_token(&dir).unwrap(), "plain-token");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
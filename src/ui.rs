@@ -1,18 +1,26 @@
 use crate::app::{AppState, InputMode, SidebarFocus};
-use crate::discord::Message;
-use chrono::{DateTime, Utc};
+use crate::config::{Density, QuitKey, SearchSidebar};
+use crate::discord::{Message, PresenceStatus, UserStatus};
+use crate::events::AppEvent;
+use crate::render::{
+    author_color, build_author_line, build_date_separator_line, build_indented_body_line,
+    build_message_line, build_unread_separator_line, current_local_year, is_grouped_with,
+    local_date, outgoing_send_line, pending_send_line, reply_banner_line, BodyOptions,
+    TimestampFormat,
+};
 use unicode_width::UnicodeWidthStr;
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 use ratatui_image::{CropOptions, Resize, StatefulImage};
 
-/// TUIを描画
-pub fn render(frame: &mut Frame, app: &mut AppState) {
+/// TUIを描画する。描画して初めて分かること (画面に出た最新のメッセージ) は
+/// イベントとして返すので、呼び出し側で `AppState::update` に渡す
+pub fn render(frame: &mut Frame, app: &mut AppState) -> Option<AppEvent> {
     // 未読リストキャッシュを (必要なら) 再計算してから描画
     app.refresh_unread_cache();
 
@@ -25,12 +33,18 @@ pub fn render(frame: &mut Frame, app: &mut AppState) {
         ])
         .split(frame.area());
 
-    // 右エリア: メッセージエリア | 入力エリア
+    // 右エリア: メッセージエリア | 送信待ち | 返信バナー | 入力エリア
+    let pending_height = app.current_pending_sends().len().min(PENDING_SENDS_MAX_LINES) as u16;
+    let reply_banner_height = if app.current_reply().is_some() { 1 } else { 0 };
+    // 複数行を貼り付けたら入力欄を広げる
+    let input_height = app.ui.input_buffer.split('\n').count().min(INPUT_MAX_LINES) as u16 + 2;
     let content_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(3),      // メッセージ
-            Constraint::Length(3),   // 入力
+            Constraint::Length(pending_height), // 送信待ち
+            Constraint::Length(reply_banner_height), // 返信バナー
+            Constraint::Length(input_height), // 入力
             Constraint::Length(1),   // ステータスバー
         ])
         .split(main_chunks[1]);
@@ -41,11 +55,19 @@ pub fn render(frame: &mut Frame, app: &mut AppState) {
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(main_chunks[0]);
 
-    if !app.ui.search_mode {
+    let search_sidebar = app.config.search_sidebar;
+    if !app.ui.search_mode || search_sidebar != SearchSidebar::Hide {
         render_channel_list(frame, app, sidebar_chunks[0]);
         render_unread_list(frame, app, sidebar_chunks[1]);
+        if app.ui.search_mode && search_sidebar == SearchSidebar::Dim {
+            // 選択中・未読の色や太字も含めて、サイドバー全体を灰色にする
+            let dim = Style::default()
+                .fg(Color::DarkGray)
+                .remove_modifier(Modifier::BOLD);
+            frame.buffer_mut().set_style(main_chunks[0], dim);
+        }
     } else {
-        // 検索モード時はサイドバーを淡く表示
+        // 検索モード時はサイドバーの中身を消して枠だけ表示
         let placeholder = List::new(Vec::<ListItem>::new()).block(
             Block::default()
                 .borders(Borders::ALL)
@@ -63,30 +85,49 @@ pub fn render(frame: &mut Frame, app: &mut AppState) {
     }
 
     // メッセージリストを描画
-    render_message_list(frame, app, content_chunks[0]);
+    let viewed = render_message_list(frame, app, content_chunks[0]);
 
     // 入力エリアを描画
-    render_input_area(frame, app, content_chunks[1]);
+    render_pending_sends(frame, app, content_chunks[1]);
+    render_reply_banner(frame, app, content_chunks[2]);
+    render_input_area(frame, app, content_chunks[3]);
 
     // ステータスバーを描画
-    render_status_bar(frame, app, content_chunks[2]);
+    render_status_bar(frame, app, content_chunks[4]);
 
     // 検索モードの場合、最後にオーバーレイを描画
     if app.ui.search_mode {
         render_search_overlay(frame, app);
     }
+    if app.ui.emoji_picker.is_some() {
+        render_emoji_picker(frame, app);
+    }
+    if app.ui.account_switcher.is_some() {
+        render_account_switcher(frame, app);
+    }
+    if app.ui.help_open {
+        render_help_overlay(frame, app);
+    }
+    if app.ui.quit_confirm {
+        render_quit_confirm(frame);
+    }
+    viewed
 }
 
 /// チャンネルリストを描画（お気に入り）
 fn render_channel_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layout::Rect) {
-    let focused = app.ui.sidebar_focus == SidebarFocus::Favorites;
+    // 検索中は選択位置 (channel_list_state) を検索結果が使うので、フォーカス無しとして描画する
+    let focused = app.ui.sidebar_focus == SidebarFocus::Favorites && !app.ui.search_mode;
+    let show_avatars = app.avatars_enabled();
     let favorites = app.get_favorite_channels();
+    let mut avatar_rows: Vec<(usize, ListImage, u16)> = Vec::new();
 
     let items: Vec<ListItem> = favorites
         .iter()
-        .map(|channel| {
-            let prefix = channel.type_prefix();
-            let name = channel.display_name();
+        .enumerate()
+        .map(|(idx, channel)| {
+            let prefix = dm_prefix_or_avatar(app, channel, show_avatars);
+            let name = format!("{}{}", channel.display_name(), voice_count_suffix(app, channel));
 
             let guild_name = if let Some(guild_id) = &channel.guild_id {
                 if let Some(guild) = app.discord.guilds.get(guild_id) {
@@ -105,9 +146,19 @@ fn render_channel_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
                 .map(|parent| format!("{} > ", parent.display_name()))
                 .unwrap_or_default();
 
-            let favorite_mark = "⭐ ";
-            let content =
-                format!("{}{}{}{}{}", favorite_mark, guild_name, parent_name, prefix, name);
+            let favorite_mark = app.favorite_marker();
+            let badge = guild_badge(app, channel, idx, favorite_mark.width(), &mut avatar_rows);
+            if let Some(user_id) = dm_avatar_user(channel, show_avatars) {
+                let x = favorite_mark.width()
+                    + badge_width(&badge)
+                    + guild_name.width()
+                    + parent_name.width();
+                avatar_rows.push((idx, ListImage::Avatar(user_id), x as u16));
+            }
+            let mut spans = vec![Span::raw(favorite_mark.to_string())];
+            spans.extend(badge);
+            spans.push(Span::raw(format!("{}{}{}{}", guild_name, parent_name, prefix, name)));
+            let content = with_dm_status(app, channel, spans);
 
             let style = if Some(&channel.id) == app.ui.selected_channel.as_ref() {
                 Style::default()
@@ -122,6 +173,7 @@ fn render_channel_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
         .collect();
 
     let border_color = if focused { Color::Cyan } else { Color::DarkGray };
+    let highlight_symbol = app.highlight_symbol().to_string();
     let list = List::new(items)
         .block(
             Block::default()
@@ -134,26 +186,35 @@ fn render_channel_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
                 .bg(Color::DarkGray)
                 .add_modifier(Modifier::BOLD),
         )
-        .highlight_symbol(">> ");
+        .highlight_symbol(&highlight_symbol);
 
     if focused {
         frame.render_stateful_widget(list, area, &mut app.ui.channel_list_state);
     } else {
         frame.render_widget(list, area);
     }
+    render_list_avatars(frame, app, area, focused, &avatar_rows);
 }
 
 /// 未読チャンネル一覧を描画
 fn render_unread_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layout::Rect) {
-    let focused = app.ui.sidebar_focus == SidebarFocus::Unread;
+    let focused = app.ui.sidebar_focus == SidebarFocus::Unread && !app.ui.search_mode;
+    let show_avatars = app.avatars_enabled();
     let unread = app.get_unread_channels();
     let title = format!("Unread ({})", unread.len());
+    let mut avatar_rows: Vec<(usize, ListImage, u16)> = Vec::new();
 
     let items: Vec<ListItem> = unread
         .iter()
-        .map(|channel| {
-            let prefix = channel.type_prefix();
-            let name = channel.display_name();
+        .enumerate()
+        .map(|(idx, channel)| {
+            let prefix = dm_prefix_or_avatar(app, channel, show_avatars);
+            let mut name =
+                format!("{}{}", channel.display_name(), voice_count_suffix(app, channel));
+            // フレンドでない相手からの DM (メッセージリクエスト)
+            if app.shows_request_marker(channel) {
+                name.push_str(" [request]");
+            }
 
             let guild_name = channel
                 .guild_id
@@ -170,8 +231,19 @@ fn render_unread_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layo
                 .unwrap_or_default();
 
             let acked = app.discord.acked_in_session.contains(&channel.id);
-            let mark = if acked { "✓ " } else { "• " };
-            let content = format!("{}{}{}{}{}", mark, guild_name, parent_name, prefix, name);
+            let mark = if acked { app.glyphs().acked } else { app.glyphs().unread };
+            let badge = guild_badge(app, channel, idx, mark.width(), &mut avatar_rows);
+            if let Some(user_id) = dm_avatar_user(channel, show_avatars) {
+                let x = mark.width()
+                    + badge_width(&badge)
+                    + guild_name.width()
+                    + parent_name.width();
+                avatar_rows.push((idx, ListImage::Avatar(user_id), x as u16));
+            }
+            let mut spans = vec![Span::raw(mark)];
+            spans.extend(badge);
+            spans.push(Span::raw(format!("{}{}{}{}", guild_name, parent_name, prefix, name)));
+            let content = with_dm_status(app, channel, spans);
 
             let style = if Some(&channel.id) == app.ui.selected_channel.as_ref() {
                 Style::default()
@@ -188,6 +260,7 @@ fn render_unread_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layo
         .collect();
 
     let border_color = if focused { Color::Magenta } else { Color::DarkGray };
+    let highlight_symbol = app.highlight_symbol().to_string();
     let list = List::new(items)
         .block(
             Block::default()
@@ -200,17 +273,227 @@ fn render_unread_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layo
                 .bg(Color::DarkGray)
                 .add_modifier(Modifier::BOLD),
         )
-        .highlight_symbol(">> ");
+        .highlight_symbol(&highlight_symbol);
 
     if focused {
         frame.render_stateful_widget(list, area, &mut app.ui.channel_list_state);
     } else {
         frame.render_widget(list, area);
     }
+    render_list_avatars(frame, app, area, focused, &avatar_rows);
+}
+
+/// 1:1 DM の行末に相手のオンライン状態を色付きで付ける
+fn with_dm_status(
+    app: &AppState,
+    channel: &crate::discord::Channel,
+    mut spans: Vec<Span<'static>>,
+) -> Line<'static> {
+    let Some(status) = app.dm_status(channel) else {
+        return Line::from(spans);
+    };
+    let color = match status {
+        UserStatus::Online => Color::Green,
+        UserStatus::Idle => Color::Yellow,
+        UserStatus::Dnd => Color::Red,
+        UserStatus::Offline => Color::DarkGray,
+    };
+    spans.push(Span::styled(app.status_marker(status), Style::default().fg(color)));
+    Line::from(spans)
+}
+
+/// サーバーの頭文字バッジの背景色 (サーバー ID から決める)
+const GUILD_BADGE_COLORS: [Color; 6] = [
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+];
+
+/// サーバーのチャンネルなら、サーバー名の前に付けるバッジ (2 セル + 空白) を返す。
+/// アイコン画像を取得済みなら空白にして、その位置を `rows` に追加する (画像は後でオーバーレイ)
+fn guild_badge(
+    app: &AppState,
+    channel: &crate::discord::Channel,
+    idx: usize,
+    x: usize,
+    rows: &mut Vec<(usize, ListImage, u16)>,
+) -> Vec<Span<'static>> {
+    if !app.config.guild_badges {
+        return Vec::new();
+    }
+    let Some(guild) = channel.guild_id.as_ref().and_then(|id| app.discord.guilds.get(id)) else {
+        return Vec::new();
+    };
+    if app.avatars_enabled() && app.discord.guild_icon_protocols.contains_key(&guild.id) {
+        rows.push((idx, ListImage::GuildIcon(guild.id.clone()), x as u16));
+        return vec![Span::raw("   ")];
+    }
+    let initials = guild.initials();
+    let label = format!("{}{}", initials, " ".repeat(2usize.saturating_sub(initials.width())));
+    let hash = guild
+        .id
+        .bytes()
+        .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+    let color = GUILD_BADGE_COLORS[hash % GUILD_BADGE_COLORS.len()];
+    vec![
+        Span::styled(label, Style::default().fg(Color::Black).bg(color)),
+        Span::raw(" "),
+    ]
+}
+
+/// バッジの表示幅 (セル数)
+fn badge_width(badge: &[Span]) -> usize {
+    badge.iter().map(|span| span.width()).sum()
+}
+
+/// ボイスチャンネルなら接続中の人数を ` (3)` の形で返す (誰も居なければ空)
+fn voice_count_suffix(app: &AppState, channel: &crate::discord::Channel) -> String {
+    if !channel.is_voice() {
+        return String::new();
+    }
+    match app.voice_member_count(channel) {
+        0 => String::new(),
+        count => format!(" ({})", count),
+    }
+}
+
+/// 1:1 DM のアバターを表示する場合、その相手の user_id を返す
+fn dm_avatar_user(channel: &crate::discord::Channel, show_avatars: bool) -> Option<String> {
+    if !show_avatars || channel.channel_type != 1 {
+        return None;
+    }
+    channel
+        .recipients
+        .as_ref()
+        .and_then(|r| r.first())
+        .map(|u| u.id.clone())
+}
+
+/// type prefix を返す。アバター表示対象の DM では画像用に同じ幅の空白を返す
+fn dm_prefix_or_avatar(
+    app: &AppState,
+    channel: &crate::discord::Channel,
+    show_avatars: bool,
+) -> &'static str {
+    if dm_avatar_user(channel, show_avatars).is_some() {
+        "   "
+    } else {
+        app.channel_prefix(channel)
+    }
+}
+
+/// サイドバーのリスト行に重ねる画像
+enum ListImage {
+    /// DM 相手のアバター (user_id)
+    Avatar(String),
+    /// サーバーアイコン (guild_id)
+    GuildIcon(String),
+}
+
+/// サイドバーのリスト行に DM 相手のアバター・サーバーアイコンをオーバーレイする。
+/// rows は (行 index, 画像, 行頭からの x cell オフセット)。
+fn render_list_avatars(
+    frame: &mut Frame,
+    app: &mut AppState,
+    area: Rect,
+    focused: bool,
+    rows: &[(usize, ListImage, u16)],
+) {
+    if rows.is_empty() {
+        return;
+    }
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    // フォーカス中 (stateful 描画) のみスクロール offset と highlight symbol 幅が付く
+    let (offset, symbol_w) = if focused && app.ui.channel_list_state.selected().is_some() {
+        (app.ui.channel_list_state.offset(), app.highlight_symbol().width() as u16)
+    } else {
+        (0, 0)
+    };
+    for (idx, image, x_off) in rows {
+        if *idx < offset {
+            continue;
+        }
+        let row = (*idx - offset) as u16;
+        if row >= inner.height {
+            continue;
+        }
+        let x = symbol_w + x_off;
+        if x + 2 > inner.width {
+            continue;
+        }
+        let protocol = match image {
+            ListImage::Avatar(user_id) => app.discord.avatar_protocols.get_mut(user_id),
+            ListImage::GuildIcon(guild_id) => app.discord.guild_icon_protocols.get_mut(guild_id),
+        };
+        if let Some(protocol) = protocol {
+            let avatar_area = Rect {
+                x: inner.x + x,
+                y: inner.y + row,
+                width: 2,
+                height: 1,
+            };
+            frame.render_stateful_widget(StatefulImage::new(None), avatar_area, protocol);
+        }
+    }
+}
+
+/// フォーラムの投稿一覧を描画 (e/d で選択、Enter で開く)
+fn render_forum_posts(frame: &mut Frame, app: &AppState, area: Rect) {
+    let posts = app.get_current_forum_posts();
+    if posts.is_empty() {
+        let placeholder = Paragraph::new("No active posts").alignment(Alignment::Center);
+        frame.render_widget(placeholder, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = posts
+        .iter()
+        .map(|post| {
+            let mut spans =
+                vec![Span::raw(app.channel_prefix(post)), Span::raw(post.display_name())];
+            if app.is_channel_unread(post) {
+                spans.push(Span::styled(app.glyphs().new_post, Style::default().fg(Color::Yellow)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let mut state = ListState::default();
+    state.select(Some(app.ui.forum_post_index.min(posts.len() - 1)));
+    let list = List::new(items)
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(app.highlight_symbol());
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
 /// メッセージリストを描画
-fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::layout::Rect) {
+/// メッセージ一覧の 1 件分のレイアウト
+struct MessageLayout<'a> {
+    msg: &'a Message,
+    /// 日付の区切り線・作者名の行・画像を含めた高さ
+    height: u16,
+    /// 表示する画像 (attachment_id, セル高さ)
+    images: Vec<(String, u16)>,
+    /// 上端に入れる日付の区切り線
+    date_break: Option<chrono::NaiveDate>,
+    /// 画面で上にあるメッセージとまとめて作者名を省略するか
+    grouped: bool,
+    /// Comfortable 表示で作者名の行を足すか
+    author_row: bool,
+}
+
+fn render_message_list(
+    frame: &mut Frame,
+    app: &mut AppState,
+    area: ratatui::layout::Rect,
+) -> Option<AppEvent> {
     // タイトル算出
     let title = if let Some(channel_id) = &app.ui.selected_channel {
         if let Some(channel) = app.discord.channels.get(channel_id) {
@@ -228,12 +511,16 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
                 .map(|p| format!("{} > ", p.display_name()))
                 .unwrap_or_default();
 
+            // フォーラムは操作体系が違うので明示する
+            let forum_label = if channel.is_forum() { " (Forum)" } else { "" };
+
             format!(
-                " {}{}{}{} ",
+                " {}{}{}{}{} ",
                 guild_name,
                 parent_name,
-                channel.type_prefix(),
-                channel.display_name()
+                app.channel_prefix(channel),
+                channel.display_name(),
+                forum_label
             )
         } else {
             "Messages".to_string()
@@ -249,17 +536,76 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
     let inner = block.inner(area);
     frame.render_widget(block, area);
 
-    // 借用衝突を避けるため、表示対象のメッセージを clone で抽出
-    let messages: Vec<Message> = app
-        .get_current_messages()
-        .iter()
-        .map(|m| (*m).clone())
-        .collect();
+    // 削除されたチャンネルは、空のチャンネルと区別できるように理由を出す
+    if app.selected_channel_unavailable() {
+        let notice = vec![
+            Line::from(Span::styled(
+                "This channel is no longer available",
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+            Line::from("It may have been deleted. Select another channel (j/k or /)"),
+        ];
+        let top = inner.height.saturating_sub(notice.len() as u16) / 2;
+        let area = Rect {
+            y: inner.y + top,
+            height: inner.height - top,
+            ..inner
+        };
+        frame.render_widget(Paragraph::new(notice).alignment(Alignment::Center), area);
+        return None;
+    }
+
+    // NSFW チャンネルは確認するまで中身を表示しない
+    if app.nsfw_gate_active() {
+        let warning = vec![
+            Line::from(Span::styled(
+                "This channel is marked NSFW (age-restricted)",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+            Line::from("Press Enter to show its messages"),
+            Line::from(Span::styled(
+                "(set \"nsfw_acknowledged\": true to skip this warning)",
+                Style::default().fg(Color::DarkGray),
+            )),
+        ];
+        let top = inner.height.saturating_sub(warning.len() as u16) / 2;
+        let area = Rect {
+            y: inner.y + top,
+            height: inner.height - top,
+            ..inner
+        };
+        frame.render_widget(Paragraph::new(warning).alignment(Alignment::Center), area);
+        return None;
+    }
+
+    // フォーラムはメッセージの代わりに投稿 (スレッド) 一覧を表示
+    if app.is_forum_selected() {
+        render_forum_posts(frame, app, inner);
+        return None;
+    }
+
+    // 送信中・送信に失敗した自分のメッセージは、最新側の端に仮の行として出す
+    let inner = render_outgoing_sends(frame, app, inner);
+
+    // 表示対象のメッセージ (新しい順)。キャッシュが大きくても毎フレーム全件を clone しない
+    // よう参照のまま扱う (以降の app への書き込みは別のフィールドなので借用は衝突しない)
+    let messages: &[Message] = match app.ui.selected_channel.as_ref() {
+        Some(channel_id) => app.discord.messages.get(channel_id).map_or(&[], Vec::as_slice),
+        None => &[],
+    };
 
     if messages.is_empty() {
-        let placeholder = Paragraph::new("No messages").alignment(Alignment::Center);
+        // 起動時にチャンネルを開かない設定では、選び方を案内する
+        let text = match app.loading_spinner() {
+            Some(spinner) => format!("{} Loading messages{}", spinner, app.glyphs().ellipsis),
+            None if app.ui.selected_channel.is_some() => "No messages".to_string(),
+            None => "Select a channel (j/k to move, / to search)".to_string(),
+        };
+        let placeholder = Paragraph::new(text).alignment(Alignment::Center);
         frame.render_widget(placeholder, inner);
-        return;
+        return None;
     }
 
     // 画像高さ計算用のセル寸法 (1セルあたりピクセル数)。Picker 未取得時は妥当なデフォルト
@@ -275,6 +621,7 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
     const IMAGE_FALLBACK_H: u16 = 10;
 
     let area_w = inner.width;
+    let show_avatars = app.avatars_enabled();
     let inner_top = inner.y as i32;
     let inner_bottom = inner_top + inner.height as i32;
 
@@ -304,47 +651,281 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
         Some((cells, target_w_px, target_h_px))
     };
 
-    // 全メッセージの (msg, 総高さ, 画像リスト) を最新→古い順で計算
-    type MessageImages = Vec<(String, u16)>;
-    let entries: Vec<(Message, u16, MessageImages)> = messages
-        .iter()
-        .map(|msg| {
-            let images: MessageImages = msg
-                .attachments
-                .iter()
-                .filter(|a| {
-                    a.content_type
-                        .as_deref()
-                        .is_some_and(|ct| ct.starts_with("image/"))
-                        && app.discord.image_sources.contains_key(&a.id)
-                })
-                .map(|a| {
-                    let (ow, oh) = if let Some(src) = app.discord.image_sources.get(&a.id) {
-                        (src.width(), src.height())
-                    } else {
-                        (a.width.unwrap_or(0), a.height.unwrap_or(0))
-                    };
-                    let cells = calc_dims(ow, oh)
-                        .map(|(c, _, _)| c)
-                        .unwrap_or(IMAGE_FALLBACK_H);
-                    (a.id.clone(), cells)
-                })
-                .collect();
-            // 画像が多数 or 高さが大きい場合に u16 がオーバーフローしないよう u32 で集計
-            let img_sum: u32 = images.iter().map(|(_, c)| *c as u32).sum();
-            let h: u16 = (1u32 + img_sum).min(u16::MAX as u32) as u16;
-            (msg.clone(), h, images)
-        })
-        .collect();
+    // 画面の上にあるメッセージ: oldest-first なら古い側の messages[i + 1]、
+    // newest-first なら新しい側の messages[i - 1]
+    let newest_first = app.newest_first();
+    let above = |i: usize| if newest_first { i.checked_sub(1) } else { Some(i + 1) };
+    let current_year = current_local_year();
 
-    // 画像キャッシュを area_w に合わせて準備 (アスペクトは保持してリサイズ)
+    // セパレータ挿入位置を判定。
+    // messages は新→古順なので、messages[i] が境界より新しく messages[i+1] が境界以前なら
+    // messages[i] と古い側のメッセージとの境目 (oldest-first なら直上、newest-first なら直下)
+    // にセパレータを描画する。
+    let boundary = app
+        .ui
+        .selected_channel
+        .as_deref()
+        .and_then(|cid| app.ui.unread_boundaries.get(cid).cloned());
+    let separator_at_index: Option<usize> = match &boundary {
+        Some(b) => {
+            let mut found = None;
+            for (i, msg) in messages.iter().enumerate() {
+                let is_newer = app.snowflake_gt(&msg.id, b.as_str());
+                if is_newer {
+                    let next_is_newer = messages
+                        .get(i + 1)
+                        .map(|next| app.snowflake_gt(&next.id, b.as_str()))
+                        .unwrap_or(true);
+                    if !next_is_newer {
+                        found = Some(i);
+                        break;
+                    }
+                }
+            }
+            found
+        }
+        None => None,
+    };
+
+    let group_window = app
+        .config
+        .group_messages
+        .then_some(app.config.group_window_secs as i64);
+
+    // messages[i] のレイアウト。画像の寸法・日付・まとめ表示から高さを求めるのは
+    // キャッシュ全体に対して毎フレーム行うと重いので、必要な分だけ最新側から順に求める
+    let measure = |i: usize| {
+        let msg = &messages[i];
+        // 上限を超えた添付は本文に `+N more` と出すだけで、画像も表示しない
+        let limit = app.attachment_limit(&msg.channel_id).unwrap_or(usize::MAX);
+        let images: Vec<(String, u16)> = msg
+            .attachments
+            .iter()
+            .take(limit)
+            .filter(|a| {
+                a.is_image()
+                    && app.discord.image_sources.contains_key(&a.id)
+                    && app.image_previews_enabled(&msg.id)
+                    && (!a.is_spoiler() || app.spoilers_revealed(&msg.channel_id))
+            })
+            .map(|a| {
+                let (ow, oh) = if let Some(src) = app.discord.image_sources.get(&a.id) {
+                    (src.width(), src.height())
+                } else {
+                    (a.width.unwrap_or(0), a.height.unwrap_or(0))
+                };
+                let cells = calc_dims(ow, oh)
+                    .map(|(c, _, _)| c)
+                    .unwrap_or(IMAGE_FALLBACK_H);
+                (a.id.clone(), cells)
+            })
+            .collect();
+        // 画像が多数 or 高さが大きい場合に u16 がオーバーフローしないよう u32 で集計
+        let img_sum: u32 = images.iter().map(|(_, c)| *c as u32).sum();
+        let mut height: u16 = (1u32 + img_sum).min(u16::MAX as u32) as u16;
+
+        let neighbour = above(i).and_then(|j| Some((j, messages.get(j)?)));
+        // 日付の区切り線: 画面で上にあるメッセージと日付 (日本時間) が違えば、
+        // 上端に 1 行入れる (高さに含める)
+        let date_break = (|| {
+            if !app.config.date_separators {
+                return None;
+            }
+            let date = local_date(&msg.timestamp)?;
+            let prev = local_date(&neighbour?.1.timestamp)?;
+            (date != prev).then_some(date)
+        })();
+
+        // まとめ表示: 画面で上にあるメッセージと作者が同じで時間が近ければ作者名を省略。
+        // 未読セパレータを挟む場合はまとめない
+        let grouped = match (group_window, neighbour) {
+            (Some(window), Some((j, neighbour))) => {
+                // 古い方・新しい方の順に渡す
+                let (older, newer) = if newest_first {
+                    (msg, neighbour)
+                } else {
+                    (neighbour, msg)
+                };
+                // セパレータは新しい側 (= min(i, j)) のメッセージに付いている
+                Some(i.min(j)) != separator_at_index
+                    && date_break.is_none()
+                    && is_grouped_with(older, newer, window)
+            }
+            _ => false,
+        };
+
+        // Comfortable 表示では、まとめていない通常のメッセージの上に作者名の行を足す
+        let author_row =
+            app.config.density == Density::Comfortable && !grouped && msg.is_regular();
+        if date_break.is_some() {
+            height = height.saturating_add(1);
+        }
+        if author_row {
+            height = height.saturating_add(1);
+        }
+        MessageLayout {
+            msg,
+            height,
+            images,
+            date_break,
+            grouped,
+            author_row,
+        }
+    };
+    // laid[..end] の高さの合計 (未読セパレータを除く)
+    let height_until = |laid: &[MessageLayout], end: usize| -> u32 {
+        laid[..end].iter().map(|e| e.height as u32).sum()
+    };
+
+    // スクロール位置の調整に高さが要るメッセージ
+    let mut offset = app.ui.message_scroll_offset;
+    let prev_newest_index = app
+        .ui
+        .newest_rendered_message
+        .as_deref()
+        .filter(|_| offset > 0 || !app.ui.auto_follow)
+        .and_then(|prev| messages.iter().position(|m| m.id == prev));
+    let scroll_target_index = app
+        .ui
+        .scroll_to_message
+        .as_deref()
+        .and_then(|target| messages.iter().position(|m| m.id == target));
+    let scroll_to_unread = app.ui.scroll_to_unread;
+    let needed = [
+        prev_newest_index,
+        separator_at_index.filter(|_| scroll_to_unread).map(|sep| sep + 1),
+        scroll_target_index.map(|idx| idx + 1),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(0);
+
+    // 最新側から順に並べる。laid_height は並べた分の高さ (未読セパレータを含む)
+    let mut laid: Vec<MessageLayout> = Vec::new();
+    let mut laid_height: u32 = 0;
+    while laid.len() < needed {
+        let entry = measure(laid.len());
+        laid_height += entry.height as u32 + u32::from(separator_at_index == Some(laid.len()));
+        laid.push(entry);
+    }
+
+    // スクロール中 (offset > 0) に新着が届いた場合、offset は最新基準なので
+    // そのままだと表示位置がずれる。前回描画時の最新メッセージより新しい分の高さだけ
+    // offset を増やして、読んでいる位置を固定する
+    // (最下部にいて自動追従が ON のときは従来通り最新へ追従)
+    if let Some(pos) = prev_newest_index {
+        offset += height_until(&laid, pos) as usize;
+    }
+
+    // チャンネルを開いた直後は、未読セパレータ (前回読んだ位置) が画面の端に来るようにして
+    // 続きから読めるようにする。既読位置が読み込んだ範囲より古ければ最古までスクロールする
+    let mut unread_notice = None;
+    if scroll_to_unread {
+        let older_than_loaded = boundary.as_deref().is_some_and(|b| {
+            messages
+                .last()
+                .is_some_and(|oldest| app.snowflake_gt(&oldest.id, b))
+        });
+        if let Some(sep) = separator_at_index {
+            let unread_height = height_until(&laid, sep + 1);
+            offset = (unread_height + 1).saturating_sub(inner.height as u32) as usize;
+        } else if older_than_loaded {
+            offset = usize::MAX;
+            unread_notice = Some("Last read message is older than the loaded history".to_string());
+        }
+    }
+
+    // リンクからジャンプしたときは、対象のメッセージが画面の中央に来るようにする
+    if let Some(idx) = scroll_target_index {
+        let before = height_until(&laid, idx)
+            + separator_at_index.map_or(0, |sep| u32::from(sep < idx));
+        let center = before + laid[idx].height as u32 / 2;
+        offset = center.saturating_sub(inner.height as u32 / 2) as usize;
+    }
+
+    // 画面と、画像を前もって用意する前後 1 画面分が埋まったら並べるのをやめる
+    let fill = (offset as u64).saturating_add(2 * inner.height as u64);
+    while laid.len() < messages.len() && (laid_height as u64) <= fill {
+        let entry = measure(laid.len());
+        laid_height += entry.height as u32 + u32::from(separator_at_index == Some(laid.len()));
+        laid.push(entry);
+    }
+
+    // 最古まで並べたときだけ全体高さからスクロール offset の上限が決まるのでクランプする。
+    // 途中でやめた場合は offset より古い側にまだメッセージがある
+    let max_offset = if laid.len() == messages.len() {
+        laid_height.saturating_sub(inner.height as u32) as usize
+    } else {
+        usize::MAX
+    };
+    let scroll_offset = offset.min(max_offset);
+    app.ui.message_scroll_offset = scroll_offset; // 過剰な offset をクランプして書き戻す
+    app.ui.cached_max_scroll_offset = max_offset; // 最古到達判定に使う
+    app.ui.newest_rendered_message = messages.first().map(|m| m.id.clone());
+    if scroll_to_unread {
+        app.ui.scroll_to_unread = false;
+        if unread_notice.is_some() {
+            app.ui.status_message = unread_notice;
+        }
+    }
+    if scroll_target_index.is_some() {
+        app.ui.scroll_to_message = None;
+    }
+    // 選択中のメッセージを画面に収めるスクロール (AppState 側) のために並べた位置を残す
+    let mut extents = Vec::with_capacity(laid.len());
+    let mut start = 0;
+    for (idx, entry) in laid.iter().enumerate() {
+        let end = start + entry.height as u32;
+        extents.push((entry.msg.id.clone(), start, end));
+        start = end + u32::from(separator_at_index == Some(idx));
+    }
+    app.ui.cached_message_extents = extents;
+    app.ui.cached_viewport_height = inner.height;
+
+    // 各メッセージの上端 y と未読セパレータの y を求める。
+    // oldest-first: 最新の底辺を inner 下端 (offset>0 ならその分下) に置いて上へ積む。
+    // newest-first: 最新の上端を inner 上端 (offset>0 ならその分上) に置いて下へ積む
+    let mut tops: Vec<i32> = Vec::with_capacity(laid.len());
+    let mut separator_y: Option<i32> = None;
+    if newest_first {
+        let mut y = inner_top - scroll_offset as i32;
+        for (idx, entry) in laid.iter().enumerate() {
+            tops.push(y);
+            y += entry.height as i32;
+            if Some(idx) == separator_at_index {
+                separator_y = Some(y);
+                y += 1;
+            }
+        }
+    } else {
+        let mut y_bottom = inner_bottom + scroll_offset as i32;
+        for (idx, entry) in laid.iter().enumerate() {
+            let y_top = y_bottom - entry.height as i32;
+            tops.push(y_top);
+            y_bottom = y_top;
+            if Some(idx) == separator_at_index {
+                separator_y = Some(y_top - 1);
+                y_bottom -= 1;
+            }
+        }
+    }
+
+    // 画像キャッシュを area_w に合わせて準備 (アスペクトは保持してリサイズ)。
+    // リサイズは重いので、画面内と前後 1 画面分のメッセージの画像だけを対象にする
     {
+        let margin = inner.height as i32;
         let sources = &app.discord.image_sources;
         let protocols = &mut app.discord.image_protocols;
         let resized_cache = &mut app.discord.image_resized;
         if let Some(picker) = app.picker.as_mut() {
-            for (_, _, imgs) in entries.iter() {
-                for (att_id, _) in imgs {
+            for (entry, &y_top) in laid.iter().zip(&tops) {
+                let near_screen = y_top < inner_bottom + margin
+                    && y_top + entry.height as i32 > inner_top - margin;
+                if !near_screen {
+                    continue;
+                }
+                for (att_id, _) in &entry.images {
                     let cached_w = protocols.get(att_id).map(|(w, _, _)| *w);
                     if cached_w == Some(area_w) {
                         continue;
@@ -373,74 +954,114 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
         }
     }
 
-    // セパレータ挿入位置を判定。
-    // entries は新→古順なので、entries[i] が境界より新しく entries[i+1] が境界以前なら
-    // entries[i] の直上 (= 古い側のメッセージとの境目) にセパレータを描画する。
-    let boundary = app
-        .ui
-        .selected_channel
-        .as_deref()
-        .and_then(|cid| app.ui.unread_boundaries.get(cid).cloned());
-    let separator_at_index: Option<usize> = match boundary {
-        Some(b) => {
-            let mut found = None;
-            for (i, (msg, _, _)) in entries.iter().enumerate() {
-                let is_newer = app.snowflake_gt(&msg.id, b.as_str());
-                if is_newer {
-                    let next_is_newer = entries
-                        .get(i + 1)
-                        .map(|(next, _, _)| app.snowflake_gt(&next.id, b.as_str()))
-                        .unwrap_or(true);
-                    if !next_is_newer {
-                        found = Some(i);
-                        break;
-                    }
-                }
-            }
-            found
-        }
-        None => None,
-    };
-    let separator_height: u32 = if separator_at_index.is_some() { 1 } else { 0 };
-
-    // 全体高さからスクロール offset の上限を決めてクランプ
-    let total_height: u32 =
-        entries.iter().map(|(_, h, _)| *h as u32).sum::<u32>() + separator_height;
-    let max_offset = total_height.saturating_sub(inner.height as u32) as usize;
-    let scroll_offset = app.ui.message_scroll_offset.min(max_offset);
-    app.ui.message_scroll_offset = scroll_offset; // 過剰な offset をクランプして書き戻す
-    app.ui.cached_max_scroll_offset = max_offset; // 最古到達判定に使う
-
-    // 最新メッセージの底辺 y を求める。offset 0 で inner 下端ぴったり、offset>0 で下に押し下げる
-    let mut y_bottom: i32 = inner_bottom + scroll_offset as i32;
+    // 画面に出た最新のメッセージ (次に開いたときの既読位置として記録する)
+    let mut newest_visible: Option<&str> = None;
+    for (entry, &y_top) in laid.iter().zip(&tops) {
+        let msg = entry.msg;
+        let y_bottom = y_top + entry.height as i32;
 
-    for (idx, (msg, h, images)) in entries.iter().enumerate() {
-        let y_top = y_bottom - *h as i32;
-
-        // 画面下端より下にメッセージ全体がある場合 (offset 大きすぎ等) → skip して次へ
-        if y_top >= inner_bottom {
-            y_bottom = if Some(idx) == separator_at_index {
-                y_top - 1
-            } else {
-                y_top
-            };
+        // ここから先のメッセージ (より古い側) はすべて画面外なので描画不要
+        let past_screen = if newest_first {
+            y_top >= inner_bottom
+        } else {
+            y_bottom <= inner_top
+        };
+        if past_screen {
+            break;
+        }
+        // 最新側の画面外 (offset 大きすぎ等) → skip して次へ
+        if y_top >= inner_bottom || y_bottom <= inner_top {
             continue;
         }
-        // 画面上端より上にメッセージ全体が抜けたら、これより古いメッセージは描画不要
-        if y_bottom <= inner_top {
-            break;
+        newest_visible.get_or_insert(&msg.id);
+
+        // 日付の区切り線 (画面内なら描画)
+        let mut text_y = y_top;
+        if let Some(date) = entry.date_break {
+            if y_top >= inner_top && y_top < inner_bottom {
+                let date_area = Rect {
+                    x: inner.x,
+                    y: y_top as u16,
+                    width: inner.width,
+                    height: 1,
+                };
+                let line =
+                    build_date_separator_line(date, current_year, inner.width, app.glyphs().rule);
+                frame.render_widget(Paragraph::new(line), date_area);
+            }
+            text_y += 1;
         }
 
-        // テキスト行 (画面内なら描画)
-        if y_top >= inner_top && y_top < inner_bottom {
+        // テキスト行 (Comfortable なら作者名の行 + 本文の行)
+        let timestamp = TimestampFormat::new(app);
+        let options = BodyOptions {
+            keywords: &app.config.highlight_keywords,
+            reveal_spoilers: app.spoilers_revealed(&msg.channel_id),
+            glyphs: app.glyphs(),
+            active_link: app.active_link(&msg.id),
+            max_attachments: app.attachment_limit(&msg.channel_id),
+            max_embeds: app.embed_limit(&msg.channel_id),
+        };
+        let emoji_text = |id: &str, name: &str| app.custom_emoji_text(id, name);
+        let comfortable = app.config.density == Density::Comfortable && msg.is_regular();
+        let rows = if entry.author_row {
+            let (author, avatar_x) =
+                build_author_line(msg, author_color(app, msg), show_avatars, timestamp);
+            let (body, emoji_positions) =
+                build_indented_body_line(msg, timestamp, options, &emoji_text);
+            vec![(author, Vec::new(), avatar_x), (body, emoji_positions, None)]
+        } else if comfortable {
+            // まとめた 2 件目以降は本文の行だけ
+            let (body, emoji_positions) =
+                build_indented_body_line(msg, timestamp, options, &emoji_text);
+            vec![(body, emoji_positions, None)]
+        } else {
+            vec![build_message_line(
+                msg,
+                author_color(app, msg),
+                show_avatars,
+                entry.grouped,
+                timestamp,
+                options,
+                &emoji_text,
+            )]
+        };
+        let row_count = rows.len() as i32;
+        let row_style = if app.ui.input_mode == InputMode::MessageSelect
+            && app.ui.selected_message.as_deref() == Some(msg.id.as_str())
+        {
+            Style::default().bg(Color::Blue)
+        } else if app.ui.highlighted_message.as_deref() == Some(msg.id.as_str()) {
+            Style::default().bg(Color::DarkGray)
+        } else {
+            Style::default()
+        };
+        for (row_y, (line, emoji_positions, avatar_x)) in (text_y..).zip(rows) {
+            // 画面外の行は描画しない
+            if row_y < inner_top || row_y >= inner_bottom {
+                continue;
+            }
             let text_area = Rect {
                 x: inner.x,
-                y: y_top as u16,
+                y: row_y as u16,
                 width: inner.width,
                 height: 1,
             };
-            let (line, emoji_positions) = build_message_line(msg);
-            frame.render_widget(Paragraph::new(line), text_area);
+            frame.render_widget(Paragraph::new(line).style(row_style), text_area);
+            // アバターを作者名の直前 (2 セル幅 x 1 セル高) にオーバーレイ
+            if let Some(x_off) = avatar_x {
+                if x_off + 2 <= text_area.width {
+                    if let Some(protocol) = app.discord.avatar_protocols.get_mut(&msg.author.id) {
+                        let avatar_area = Rect {
+                            x: text_area.x + x_off,
+                            y: text_area.y,
+                            width: 2,
+                            height: 1,
+                        };
+                        frame.render_stateful_widget(StatefulImage::new(None), avatar_area, protocol);
+                    }
+                }
+            }
             // カスタム絵文字を 2 セル幅 x 1 セル高でテキスト行上にオーバーレイ
             for (x_off, emoji_id) in emoji_positions {
                 if x_off + 2 > text_area.width {
@@ -459,9 +1080,9 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
             }
         }
 
-        // 画像領域 (テキストの 1 行下から)
-        let mut img_y = y_top + 1;
-        for (att_id, img_h) in images {
+        // 画像領域 (テキストの下から)
+        let mut img_y = text_y + row_count;
+        for (att_id, img_h) in &entry.images {
             let img_top = img_y;
             let img_bottom = img_top + *img_h as i32;
 
@@ -578,117 +1199,190 @@ fn render_message_list(frame: &mut Frame, app: &mut AppState, area: ratatui::lay
             img_y = img_bottom;
         }
 
-        // このメッセージの直上 (古い側) に未読セパレータを置く場合は描画
-        if Some(idx) == separator_at_index {
-            let sep_y = y_top - 1;
-            if sep_y >= inner_top && sep_y < inner_bottom {
-                let sep_area = Rect {
-                    x: inner.x,
-                    y: sep_y as u16,
-                    width: inner.width,
-                    height: 1,
-                };
-                frame.render_widget(
-                    Paragraph::new(build_unread_separator_line(inner.width)),
-                    sep_area,
-                );
-            }
-            y_bottom = sep_y;
-        } else {
-            y_bottom = y_top;
-        }
     }
-}
-
-/// 未読/既読の境界線を表す 1 行を構築する
-fn build_unread_separator_line(width: u16) -> Line<'static> {
-    let label = " New messages ";
-    let label_w = label.chars().count() as u16;
-    let total_dashes = width.saturating_sub(label_w);
-    let left = (total_dashes / 2) as usize;
-    let right = (total_dashes - total_dashes / 2) as usize;
-    let line = format!(
-        "{}{}{}",
-        "─".repeat(left),
-        label,
-        "─".repeat(right)
-    );
-    Line::from(Span::styled(
-        line,
-        Style::default()
-            .fg(Color::Red)
-            .add_modifier(Modifier::BOLD),
-    ))
-}
-
 
-/// 1メッセージ分のテキスト行と、カスタム絵文字の (x cell オフセット, emoji_id) リストを構築
-fn build_message_line(msg: &Message) -> (Line<'static>, Vec<(u16, String)>) {
-    let time = format_timestamp(&msg.timestamp);
-    let time_str = format!("[{}] ", time);
-    let user_str = format!("{}: ", msg.author_display_name());
+    // 画面に出た最新のメッセージは、描画後に既読位置として状態へ反映する
+    let viewed = newest_visible
+        .map(str::to_string)
+        .zip(app.ui.selected_channel.clone())
+        .map(|(message_id, channel_id)| AppEvent::MessageViewed {
+            channel_id,
+            message_id,
+        });
+
+    // 未読セパレータ (画面内なら描画)。画面上端より上にあるかは読み進めて通過した判定に使う
+    let mut separator_visible = false;
+    // 並べた範囲より古い側のセパレータは、oldest-first なら画面より上にある
+    let separator_above = match separator_y {
+        Some(y) => y < inner_top,
+        None => separator_at_index.is_some() && !newest_first,
+    };
+    if let Some(sep_y) = separator_y.filter(|y| (inner_top..inner_bottom).contains(y)) {
+        separator_visible = true;
+        let sep_area = Rect {
+            x: inner.x,
+            y: sep_y as u16,
+            width: inner.width,
+            height: 1,
+        };
+        frame.render_widget(
+            Paragraph::new(build_unread_separator_line(inner.width, app.glyphs().rule)),
+            sep_area,
+        );
+    }
 
-    let mut col_offset: u16 = (time_str.as_str().width() + user_str.as_str().width()) as u16;
-    let mut spans: Vec<Span<'static>> = vec![
-        Span::styled(time_str, Style::default().fg(Color::DarkGray)),
-        Span::styled(
-            user_str,
-            Style::default()
-                .fg(Color::Green)
-                .add_modifier(Modifier::BOLD),
-        ),
-    ];
-    let mut emoji_positions: Vec<(u16, String)> = Vec::new();
-
-    if !msg.content.is_empty() {
-        for seg in crate::emoji::parse_message_segments(&msg.content) {
-            match seg {
-                crate::emoji::MessageSegment::Text(t) => {
-                    let w = t.as_str().width() as u16;
-                    spans.push(Span::raw(t));
-                    col_offset = col_offset.saturating_add(w);
-                }
-                crate::emoji::MessageSegment::Emoji { id, .. } => {
-                    // 2 セル幅占位 (画像オーバーレイ用)
-                    spans.push(Span::raw("  ".to_string()));
-                    emoji_positions.push((col_offset, id));
-                    col_offset = col_offset.saturating_add(2);
-                }
-            }
+    // 一度表示したセパレータが画面上端より上へ流れた (= 新着を読み進めた) ら消す
+    if separator_visible {
+        app.ui.unread_divider_seen = true;
+    } else if separator_above && app.ui.unread_divider_seen {
+        if let Some(channel_id) = app.ui.selected_channel.clone() {
+            app.ui.unread_boundaries.remove(&channel_id);
         }
+        app.ui.unread_divider_seen = false;
     }
+    viewed
+}
 
-    for (i, attachment) in msg.attachments.iter().enumerate() {
-        if i > 0 || !msg.content.is_empty() {
-            spans.push(Span::raw(" ".to_string()));
-            col_offset = col_offset.saturating_add(1);
-        }
-        let txt = attachment.display_text();
-        col_offset = col_offset.saturating_add(txt.as_str().width() as u16);
-        spans.push(Span::styled(
-            txt,
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::ITALIC),
-        ));
+/// 入力欄の表示行数の上限 (超えた分は末尾の行だけ表示)
+const INPUT_MAX_LINES: usize = 5;
+
+/// 送信待ちの表示行数の上限 (超えた分は最新のものだけ表示)
+const PENDING_SENDS_MAX_LINES: usize = 3;
+
+/// 送信中・送信に失敗したメッセージの表示行数の上限 (超えた分は最新のものだけ表示)
+const OUTGOING_SENDS_MAX_LINES: usize = 5;
+
+/// 選択中チャンネルの送信中・送信に失敗したメッセージを、メッセージ一覧の最新側
+/// (oldest-first なら下端、newest-first なら上端) に描画し、残りの領域を返す
+fn render_outgoing_sends(frame: &mut Frame, app: &AppState, inner: Rect) -> Rect {
+    let outgoing = app.current_outgoing_sends();
+    // 少なくとも 1 行はメッセージのために残す
+    let height = outgoing
+        .len()
+        .min(OUTGOING_SENDS_MAX_LINES)
+        .min(inner.height.saturating_sub(1) as usize) as u16;
+    if height == 0 {
+        return inner;
     }
+    let author = app
+        .discord
+        .current_user
+        .as_ref()
+        .map(|u| u.username.clone())
+        .unwrap_or_default();
+    let mut lines: Vec<Line> = outgoing
+        .iter()
+        .skip(outgoing.len() - height as usize)
+        .map(|s| outgoing_send_line(s, &author, app.glyphs()))
+        .collect();
+    let rest_height = inner.height - height;
+    let (area, rest) = if app.newest_first() {
+        lines.reverse();
+        (
+            Rect { height, ..inner },
+            Rect { y: inner.y + height, height: rest_height, ..inner },
+        )
+    } else {
+        (
+            Rect { y: inner.y + rest_height, height, ..inner },
+            Rect { height: rest_height, ..inner },
+        )
+    };
+    frame.render_widget(Paragraph::new(lines), area);
+    rest
+}
 
-    (Line::from(spans), emoji_positions)
+/// 切断中に送信待ちにしたメッセージを入力欄の上に薄く表示
+fn render_pending_sends(frame: &mut Frame, app: &AppState, area: ratatui::layout::Rect) {
+    let pending = app.current_pending_sends();
+    let skip = pending.len().saturating_sub(PENDING_SENDS_MAX_LINES);
+    let lines: Vec<Line> = pending
+        .iter()
+        .skip(skip)
+        .map(|p| pending_send_line(p))
+        .collect();
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+/// 返信中なら入力欄の上に "Replying to @author: snippet" を描画
+fn render_reply_banner(frame: &mut Frame, app: &AppState, area: ratatui::layout::Rect) {
+    let Some(reply) = app.current_reply() else {
+        return;
+    };
+    frame.render_widget(Paragraph::new(reply_banner_line(reply)), area);
 }
 
 /// 入力エリアを描画
 fn render_input_area(frame: &mut Frame, app: &mut AppState, area: ratatui::layout::Rect) {
+    if app.read_only {
+        // 読み取り専用モードでは入力欄をグレーアウト
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Read-only mode (sending disabled)")
+            .border_style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(block, area);
+        return;
+    }
+
     let style = match app.ui.input_mode {
         InputMode::Editing => Style::default().fg(Color::Yellow),
-        InputMode::Normal => Style::default(),
+        InputMode::PurgeConfirm => Style::default().fg(Color::Red),
+        InputMode::JumpPrompt => Style::default().fg(Color::Cyan),
+        InputMode::Normal | InputMode::MessageSelect => Style::default(),
     };
 
-    let title = match app.ui.input_mode {
-        InputMode::Editing => "Input (Press Esc to exit, Enter to send)",
-        InputMode::Normal => "Input (Press 'i' to edit)",
+    // 送信先チャンネル名 (どこに投稿するかを常に表示する)
+    let target = input_target_name(app);
+    let destination = target
+        .as_deref()
+        .map(|name| format!(" to {}", name))
+        .unwrap_or_default();
+    let mut title = match app.ui.input_mode {
+        InputMode::Editing if app.ui.editing_message.is_some() => {
+            "Edit message (Press Esc to cancel, Enter to save)".to_string()
+        }
+        InputMode::Editing => format!("Input{} (Press Esc to exit, Enter to send)", destination),
+        InputMode::PurgeConfirm => {
+            "Delete your own messages: type 'delete <N>' and Enter (Esc: cancel)".to_string()
+        }
+        InputMode::JumpPrompt => {
+            "Jump to message: paste a Discord message link and Enter (Esc: cancel)".to_string()
+        }
+        InputMode::Normal | InputMode::MessageSelect => {
+            format!("Input{} (Press 'i' to edit)", destination)
+        }
     };
+    // レート制限 / slowmode のクールダウン中は次の送信までの待ち時間を表示
+    if let Some((secs, slowmode)) = app.current_send_wait() {
+        let reason = if slowmode { "slowmode" } else { "rate limited" };
+        title.push_str(&format!(" - {}: wait {}s", reason, secs));
+    } else if let Some(secs) = app.current_slowmode_secs() {
+        title.push_str(&format!(" - slowmode {}s", secs));
+    }
+    if app.ui.input_mode == InputMode::Editing {
+        let state = if app.mass_mentions_allowed() { "on" } else { "off" };
+        title.push_str(&format!(" - @everyone: {}", state));
+    }
 
-    let input = Paragraph::new(app.ui.input_buffer.as_str())
+    let text = match app.ui.input_mode {
+        InputMode::PurgeConfirm => app.ui.purge_buffer.as_str(),
+        InputMode::JumpPrompt => app.ui.jump_buffer.as_str(),
+        _ => app.ui.input_buffer.as_str(),
+    };
+    // 編集中で未入力ならプレースホルダーを薄く表示 (入力を始めたら消える)
+    let content = match (&app.ui.input_mode, &target) {
+        (InputMode::Editing, Some(name)) if text.is_empty() => Text::from(Span::styled(
+            format!("Message {}", name),
+            Style::default().fg(Color::DarkGray),
+        )),
+        _ => Text::from(text),
+    };
+    // 入力欄に収まらない行数なら、カーソルのある末尾の行が見えるようにずらす
+    let line_count = text.split('\n').count();
+    let visible_lines = (area.height.saturating_sub(2) as usize).max(1);
+    let hidden_lines = line_count.saturating_sub(visible_lines);
+    let input = Paragraph::new(content)
+        .scroll((hidden_lines as u16, 0))
         .style(style)
         .block(
             Block::default()
@@ -701,21 +1395,50 @@ fn render_input_area(frame: &mut Frame, app: &mut AppState, area: ratatui::layou
     frame.render_widget(input, area);
 
     // カーソル表示（編集モードの場合）
-    if app.ui.input_mode == InputMode::Editing {
+    if app.ui.input_mode != InputMode::Normal {
         // 全角文字を考慮し、バイト長ではなく表示幅でカーソル位置を計算
-        let cursor_x = area.x + app.ui.input_buffer.width() as u16 + 1;
-        let cursor_y = area.y + 1;
+        let last_line = text.rsplit('\n').next().unwrap_or_default();
+        let cursor_x = area.x + last_line.width() as u16 + 1;
+        let cursor_y = area.y + (line_count - hidden_lines) as u16;
         frame.set_cursor_position((cursor_x, cursor_y));
     }
 }
 
+/// 入力欄に表示する送信先の名前 (`#general` / `@user` / スレッド名)
+fn input_target_name(app: &AppState) -> Option<String> {
+    let channel_id = app.ui.selected_channel.as_ref()?;
+    let channel = app.discord.channels.get(channel_id)?;
+    let name = channel.display_name();
+    Some(match channel.channel_type {
+        0 | 5 => format!("#{}", name),
+        1 | 3 => format!("@{}", name),
+        _ => name,
+    })
+}
+
 /// ステータスバーを描画
 fn render_status_bar(frame: &mut Frame, app: &mut AppState, area: ratatui::layout::Rect) {
-    let status = if app.discord.connected {
+    let status = if app.discord.rest_outage {
+        Span::styled(
+            " Discord down ",
+            Style::default().fg(Color::Black).bg(Color::Red),
+        )
+    } else if app.polling && app.discord.connected {
+        // Gateway を使わないので、新着は数秒遅れ、編集・削除は反映されない
+        Span::styled(
+            " Polling (no gateway) ",
+            Style::default().fg(Color::Black).bg(Color::Magenta),
+        )
+    } else if app.discord.connected {
         Span::styled(
             " Connected ",
             Style::default().fg(Color::Black).bg(Color::Green),
         )
+    } else if app.discord.reconnecting {
+        Span::styled(
+            format!(" Reconnecting{} ", app.glyphs().ellipsis),
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        )
     } else {
         Span::styled(
             " Disconnected ",
@@ -725,37 +1448,284 @@ fn render_status_bar(frame: &mut Frame, app: &mut AppState, area: ratatui::layou
 
     let help = if app.ui.search_mode {
         // 検索モード
-        Span::raw(" Esc: Exit search | ↑/↓: Navigate | Enter: Select ")
+        Span::raw(" Esc: Exit search | ↑/↓: Navigate | Enter: Select | Tab: Select & type ")
     } else {
         match app.ui.input_mode {
+            InputMode::Normal if app.is_forum_selected() => {
+                Span::raw(" q: Quit | e/d: Select post | Enter: Open post | /: Search | f: Fav | F5: Reload | ↑/k ↓/j ")
+            }
+            InputMode::Normal if app.ui.forum_return.is_some() => {
+                Span::raw(" q: Quit | i: Reply | Backspace: Back to forum | e/^U d/^D: Scroll | ↑/k ↓/j ")
+            }
             InputMode::Normal => {
-                Span::raw(" q: Quit | i: Edit | /: Search | f: Fav | u/Tab: Switch list | o: Open | e/^U d/^D: Scroll | ↑/k ↓/j ")
+                Span::raw(" ?: Help | q: Quit | i: Edit | R: Reply | /: Search | f: Fav | u/Tab: Switch list | o: Open | m: Jump to link | V: Select message | T: Text only | e/^U d/^D: Scroll | a: Follow | p: Status | A: Account | F5: Reload | ↑/k ↓/j ")
             }
             InputMode::Editing => Span::raw(" Esc: Normal mode | Enter: Send message "),
+            InputMode::PurgeConfirm => Span::raw(" Esc: Cancel | Enter: Confirm "),
+            InputMode::JumpPrompt => Span::raw(" Esc: Cancel | Enter: Jump "),
+            InputMode::MessageSelect => Span::raw(concat!(
+                " Esc/V: Exit | ↑/k ↓/j: Select | r: React | R: Reply | e: Edit | dd: Delete",
+                " | y: Copy | L/Enter: Open link | ?: Help ",
+            )),
         }
     };
 
-    let status_line = Line::from(vec![status, help]);
+    let follow = if app.ui.auto_follow {
+        Span::styled(" Follow ", Style::default().fg(Color::Black).bg(Color::Cyan))
+    } else {
+        Span::styled(" No follow ", Style::default().fg(Color::Black).bg(Color::DarkGray))
+    };
+    let presence_color = match app.config.status {
+        PresenceStatus::Online => Color::Green,
+        PresenceStatus::Idle => Color::Yellow,
+        PresenceStatus::Dnd => Color::Red,
+        PresenceStatus::Invisible => Color::DarkGray,
+    };
+    let presence = Span::styled(
+        format!(" {} ", app.config.status.as_str()),
+        Style::default().fg(Color::Black).bg(presence_color),
+    );
+    // 使用中のアカウント (default 以外はプロファイル名も併記)
+    let user = app
+        .discord
+        .current_user
+        .as_ref()
+        .map(|u| u.global_name.clone().unwrap_or_else(|| u.username.clone()));
+    let account_text = match user {
+        Some(user) if app.profile == crate::profile::DEFAULT_PROFILE => format!(" {} ", user),
+        Some(user) => format!(" {}: {} ", app.profile, user),
+        None => format!(" {} ", app.profile),
+    };
+    let account = Span::styled(
+        account_text,
+        Style::default().fg(Color::Black).bg(Color::Magenta),
+    );
+    let mut spans = vec![status, account, follow, presence];
+    if let Some(spinner) = app.loading_spinner() {
+        spans.push(Span::raw(format!(" {} ", spinner)));
+    }
+    if let Some(secs) = app.rest_rate_limit_wait() {
+        spans.push(Span::styled(
+            format!(" Rate limited, retrying in {}s ", secs),
+            Style::default().fg(Color::Black).bg(Color::LightRed),
+        ));
+    }
+    if let Some(message) = &app.ui.status_message {
+        spans.push(Span::styled(
+            format!(" {} ", message),
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        ));
+    }
+    // 一括削除の進捗 / 結果
+    if let Some(purge) = &app.ui.purge {
+        let text = match &purge.result {
+            Some(result) => format!(" {} ", result),
+            None if purge.total == 0 => " Purge: scanning history... (Esc: cancel) ".to_string(),
+            None => format!(
+                " Purge: {}/{} deleted (Esc: cancel) ",
+                purge.deleted, purge.total
+            ),
+        };
+        spans.push(Span::styled(
+            text,
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        ));
+    }
+    spans.push(help);
+    let status_line = Line::from(spans);
     let paragraph = Paragraph::new(status_line).alignment(Alignment::Left);
 
     frame.render_widget(paragraph, area);
 }
 
-/// 検索オーバーレイを描画（Spotlightスタイル）
-fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
-    let area = frame.area();
+/// オーバーレイの最小幅 (これより狭くなる場合は余白を削る)
+const OVERLAY_MIN_WIDTH: u16 = 30;
+/// オーバーレイの最小高さ (入力欄 3 行 + 枠付きリスト数件分)
+const OVERLAY_MIN_HEIGHT: u16 = 8;
 
+/// 画面中央のオーバーレイ領域 (検索・絵文字ピッカー・アカウント切り替え共通)。
+/// 余白を取ると最小サイズを下回る小さな端末では余白を削り、
+/// 画面自体が最小サイズより小さければ画面全体を使う
+fn overlay_rect(area: Rect) -> Rect {
     // 画面中央に配置するための計算
     let vertical_margin = area.height / 6; // 上部の余白
     let horizontal_margin = area.width / 5; // 左右の余白
 
-    // オーバーレイの領域を計算
-    let overlay_area = Rect {
-        x: area.x + horizontal_margin,
-        y: area.y + vertical_margin,
-        width: area.width.saturating_sub(horizontal_margin * 2),
-        height: area.height.saturating_sub(vertical_margin * 2),
+    let width = area
+        .width
+        .saturating_sub(horizontal_margin * 2)
+        .max(OVERLAY_MIN_WIDTH)
+        .min(area.width);
+    let height = area
+        .height
+        .saturating_sub(vertical_margin * 2)
+        .max(OVERLAY_MIN_HEIGHT)
+        .min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// 終了確認の小さなオーバーレイを画面中央に描画
+fn render_quit_confirm(frame: &mut Frame) {
+    let area = frame.area();
+    let width = 30.min(area.width);
+    let height = 3.min(area.height);
+    let confirm_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    frame.render_widget(Clear, confirm_area);
+    let confirm = Paragraph::new("Quit hakuhyo? (y/N)")
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                .style(Style::default().bg(Color::Black)),
+        );
+    frame.render_widget(confirm, confirm_area);
+}
+
+/// キーバインド一覧 (モードごとの (キー, 動作))。`quit_key` など設定で変わるものは
+/// 現在の設定を反映する
+fn help_sections(app: &AppState) -> Vec<(&'static str, Vec<(&'static str, &'static str)>)> {
+    let quit = match app.config.quit_key {
+        QuitKey::Quit => "Quit",
+        QuitKey::Confirm => "Quit (asks for confirmation)",
+        QuitKey::Disabled => "Disabled (quit_key = \"disabled\")",
     };
+    vec![
+        (
+            "Normal",
+            vec![
+                ("?", "Show this help"),
+                ("q", quit),
+                ("Ctrl+C", "Quit immediately"),
+                ("↑/k ↓/j", "Select channel"),
+                ("Enter", "Open the selected channel"),
+                ("u / Tab", "Switch between Favorites and Unread"),
+                ("/", "Search channels"),
+                ("f", "Add / remove favorite"),
+                ("K / J", "Move favorite up / down"),
+                ("i", "Write a message"),
+                ("R", "Reply to the newest message (retries a failed send first)"),
+                ("r", "React to the newest message"),
+                ("V", "Select a message"),
+                ("L", "Open the next link in the newest message with links"),
+                ("m", "Jump to a message link"),
+                ("o", "Open the channel in the Discord app"),
+                ("e/^U d/^D", "Scroll messages"),
+                ("gg / G", "Jump to the oldest / newest message"),
+                ("a", "Toggle following new messages"),
+                ("T", "Toggle text channels only"),
+                ("v", "Toggle image previews of the newest message with images"),
+                ("E", "Show all attachments and embeds"),
+                ("s", "Reveal / hide spoilers"),
+                ("p", "Cycle online status"),
+                ("P", "Toggle activity"),
+                ("A", "Switch account"),
+                ("X", "Delete your messages in bulk"),
+                ("F5", "Reload"),
+            ],
+        ),
+        (
+            "Forum",
+            vec![
+                ("e / d", "Select post"),
+                ("Enter", "Open post"),
+                ("Backspace", "Back to the forum"),
+            ],
+        ),
+        (
+            "Editing",
+            vec![
+                ("Enter", "Send message"),
+                ("Esc", "Back to Normal mode (cancels a reply)"),
+                ("Tab", "Toggle pinging the reply author"),
+                ("Shift+Tab", "Toggle @everyone / @here / role pings"),
+                ("/to <target> <text>", "Send to another channel"),
+            ],
+        ),
+        (
+            "Search",
+            vec![
+                ("Type", "Filter channels by name"),
+                ("↑ / ↓", "Select result"),
+                ("Enter", "Open the selected channel"),
+                ("Tab", "Open the selected channel and start typing"),
+                ("Esc", "Leave search"),
+            ],
+        ),
+        (
+            "Message select",
+            vec![
+                ("↑/k ↓/j", "Select older / newer message"),
+                ("r", "React to the selected message"),
+                ("R", "Reply to the selected message"),
+                ("e", "Edit the selected message (your own)"),
+                ("d d", "Delete the selected message (your own)"),
+                ("y", "Copy the text of the selected message"),
+                ("L / Enter", "Open the next link in the selected message"),
+                ("v", "Toggle image previews of the selected message"),
+                ("Esc / V", "Leave message select"),
+            ],
+        ),
+    ]
+}
+
+/// キーバインド一覧を画面全体のオーバーレイとして描画 (`?` / Esc で閉じる)
+fn render_help_overlay(frame: &mut Frame, app: &mut AppState) {
+    let area = frame.area();
+    let sections = help_sections(app);
+    let key_width = sections
+        .iter()
+        .flat_map(|(_, bindings)| bindings.iter().map(|(key, _)| key.width()))
+        .max()
+        .unwrap_or(0);
+
+    let mut lines: Vec<Line> = Vec::new();
+    for (mode, bindings) in sections {
+        if !lines.is_empty() {
+            lines.push(Line::from(""));
+        }
+        lines.push(Line::from(Span::styled(
+            mode,
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        )));
+        for (key, action) in bindings {
+            let padding = " ".repeat(key_width - key.width());
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {}{}  ", key, padding), Style::default().fg(Color::Yellow)),
+                Span::raw(action),
+            ]));
+        }
+    }
+
+    // 枠の分を除き、最後の行が下端に来るところまでスクロールできる
+    let max_scroll = (lines.len() as u16).saturating_sub(area.height.saturating_sub(2));
+    app.ui.help_scroll = app.ui.help_scroll.min(max_scroll);
+
+    frame.render_widget(Clear, area);
+    let help = Paragraph::new(lines).scroll((app.ui.help_scroll, 0)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Keybindings - ↑/k ↓/j: Scroll | ?/Esc: Close ")
+            .border_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+            .style(Style::default().bg(Color::Black)),
+    );
+    frame.render_widget(help, area);
+}
+
+/// 検索オーバーレイを描画（Spotlightスタイル）
+fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
+    let overlay_area = overlay_rect(frame.area());
 
     // 検索結果を取得
     let results = app.search_channels(&app.ui.search_buffer);
@@ -799,8 +1769,8 @@ fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
         .iter()
         .take(max_results)
         .map(|channel| {
-            let prefix = channel.type_prefix();
-            let name = channel.display_name();
+            let prefix = app.channel_prefix(channel);
+            let name = format!("{}{}", channel.display_name(), voice_count_suffix(app, channel));
 
             // ギルド名を取得
             let guild_name = if let Some(guild_id) = &channel.guild_id {
@@ -822,8 +1792,8 @@ fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
                 .unwrap_or_default();
 
             // お気に入りマークを追加
-            let favorite_mark = if app.ui.favorites.contains(&channel.id) {
-                "⭐ "
+            let favorite_mark = if app.is_favorite(&channel.id) {
+                app.favorite_marker()
             } else {
                 ""
             };
@@ -834,6 +1804,7 @@ fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
         })
         .collect();
 
+    let highlight_symbol = app.highlight_symbol().to_string();
     let results_list = List::new(items)
         .block(
             Block::default()
@@ -847,20 +1818,493 @@ fn render_search_overlay(frame: &mut Frame, app: &mut AppState) {
                 .fg(Color::White)
                 .add_modifier(Modifier::BOLD),
         )
-        .highlight_symbol(">> ");
+        .highlight_symbol(&highlight_symbol);
 
     frame.render_stateful_widget(results_list, overlay_chunks[1], &mut app.ui.channel_list_state);
 }
 
-/// タイムスタンプを "HH:MM" 形式に整形（日本時間）
-fn format_timestamp(timestamp: &str) -> String {
-    if let Ok(dt) = timestamp.parse::<DateTime<Utc>>() {
-        // UTC+9（日本時間）に変換
-        use chrono::offset::FixedOffset;
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let dt_jst = dt.with_timezone(&jst);
-        dt_jst.format("%H:%M").to_string()
-    } else {
-        "??:??".to_string()
+/// リアクション用の絵文字ピッカーを描画 (検索オーバーレイと同じ見た目)
+fn render_emoji_picker(frame: &mut Frame, app: &mut AppState) {
+    let overlay_area = overlay_rect(frame.area());
+    let results = app.emoji_picker_results();
+    let Some(picker) = app.ui.emoji_picker.as_ref() else {
+        return;
+    };
+
+    let overlay_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // 検索ボックス
+            Constraint::Min(1),    // 候補リスト
+        ])
+        .split(overlay_area);
+
+    frame.render_widget(Clear, overlay_area);
+
+    let query_input = Paragraph::new(picker.query.as_str())
+        .style(Style::default().fg(Color::White))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(
+                    " React ({} emoji) - Enter: add | Esc: cancel ",
+                    results.len()
+                ))
+                .border_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .style(Style::default().bg(Color::Black)),
+        );
+    frame.render_widget(query_input, overlay_chunks[0]);
+
+    let cursor_x = overlay_chunks[0].x + picker.query.width() as u16 + 1;
+    let cursor_y = overlay_chunks[0].y + 1;
+    frame.set_cursor_position((cursor_x, cursor_y));
+
+    let items: Vec<ListItem> = results
+        .iter()
+        .map(|emoji| ListItem::new(emoji.label()))
+        .collect();
+    let highlight_symbol = app.highlight_symbol().to_string();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan))
+                .style(Style::default().bg(Color::Black)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Blue)
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(&highlight_symbol);
+
+    let Some(picker) = app.ui.emoji_picker.as_mut() else {
+        return;
+    };
+    frame.render_stateful_widget(list, overlay_chunks[1], &mut picker.list_state);
+}
+
+/// アカウント (プロファイル) 切り替えオーバーレイを描画
+fn render_account_switcher(frame: &mut Frame, app: &mut AppState) {
+    let overlay_area = overlay_rect(frame.area());
+    let current = app.profile.clone();
+    let highlight_symbol = app.highlight_symbol().to_string();
+    let Some(switcher) = app.ui.account_switcher.as_mut() else {
+        return;
+    };
+
+    frame.render_widget(Clear, overlay_area);
+
+    let items: Vec<ListItem> = switcher
+        .profiles
+        .iter()
+        .map(|profile| {
+            if *profile == current {
+                ListItem::new(format!("{} (current)", profile))
+                    .style(Style::default().fg(Color::Green))
+            } else {
+                ListItem::new(profile.as_str())
+            }
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Switch account - Enter: switch | Esc: cancel ")
+                .border_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .style(Style::default().bg(Color::Black)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Blue)
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(&highlight_symbol);
+    frame.render_stateful_widget(list, overlay_area, &mut switcher.list_state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MessageOrder, TimestampLayout};
+    use crate::events::AppEvent;
+    use crate::test_fixtures::{connected_app, message, with_messages, GENERAL_ID};
+    use crossterm::event::KeyCode;
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::time::{Duration, Instant};
+
+    /// app を width x height の TestBackend に描画し、画面を行ごとの文字列で返す。
+    /// 描画が返したイベントは main と同じく app に反映する
+    fn render_lines(app: &mut AppState, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let mut viewed = None;
+        terminal.draw(|f| viewed = render(f, app)).unwrap();
+        if let Some(event) = viewed {
+            app.update(event);
+        }
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    fn press(app: &mut AppState, keys: &[KeyCode]) {
+        for key in keys {
+            app.update(AppEvent::KeyPress(*key));
+        }
+    }
+
+    #[test]
+    fn renders_empty_channel() {
+        let mut app = connected_app();
+        let lines = render_lines(&mut app, 80, 16);
+        assert!(lines[0].contains(" [Hakuhyo Dev] # general "));
+        assert!(lines.iter().any(|l| l.contains("No messages")));
+        assert!(lines[12].contains("Input to #general (Press 'i' to edit)"));
+        assert!(lines[15].contains(" Connected "));
+    }
+
+    #[test]
+    fn status_bar_counts_down_a_rest_rate_limit() {
+        let mut app = connected_app();
+        app.update(AppEvent::RestRateLimited(Some(
+            Instant::now() + Duration::from_millis(2500),
+        )));
+        let lines = render_lines(&mut app, 120, 16);
+        assert!(lines[15].contains(" Rate limited, retrying in 3s "));
+
+        // 待ち終わったら消える
+        app.update(AppEvent::RestRateLimited(None));
+        let lines = render_lines(&mut app, 120, 16);
+        assert!(!lines[15].contains("Rate limited"));
+    }
+
+    #[test]
+    fn spinner_shows_while_loading_and_pauses_while_rate_limited() {
+        let mut app = connected_app();
+        press(&mut app, &[KeyCode::F(5)]);
+        let lines = render_lines(&mut app, 120, 16);
+        assert!(lines.iter().any(|l| l.contains("⠋ Loading messages…")));
+        assert!(lines[15].contains(" ⠋ "));
+
+        app.update(AppEvent::Tick);
+        assert!(render_lines(&mut app, 120, 16)[15].contains(" ⠙ "));
+        // 429 で待っている間は止める
+        app.update(AppEvent::RestRateLimited(Some(Instant::now() + Duration::from_secs(5))));
+        app.update(AppEvent::Tick);
+        assert!(render_lines(&mut app, 120, 16)[15].contains(" ⠙ "));
+        app.update(AppEvent::RestRateLimited(None));
+
+        app.update(AppEvent::MessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: Vec::new(),
+        });
+        let lines = render_lines(&mut app, 120, 16);
+        assert!(lines.iter().any(|l| l.contains("No messages")));
+        assert!(!lines[15].contains(" ⠙ "));
+    }
+
+    #[test]
+    fn outgoing_messages_render_below_the_newest_message() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('o'), KeyCode::Enter, KeyCode::Esc]);
+        app.update(AppEvent::SendFailed {
+            channel_id: GENERAL_ID.to_string(),
+            error: "HTTP 500".to_string(),
+        });
+        let lines = render_lines(&mut app, 80, 12);
+        let newest = lines.iter().position(|l| l.contains("bob: see you")).unwrap();
+        assert!(lines[newest + 1].contains("✗ failed — press R to retry me: o (HTTP 500)"));
+    }
+
+    #[test]
+    fn help_overlay_lists_bindings_by_mode_and_closes_with_esc() {
+        let mut app = connected_app();
+        app.config.quit_key = QuitKey::Confirm;
+        press(&mut app, &[KeyCode::Char('?')]);
+        let lines = render_lines(&mut app, 80, 20);
+        assert!(lines[0].contains(" Keybindings - ↑/k ↓/j: Scroll | ?/Esc: Close "));
+        assert!(lines[1].contains("Normal"));
+        let quit = "Quit (asks for confirmation)";
+        assert!(lines.iter().any(|l| l.contains("q   ") && l.contains(quit)));
+
+        // 下端までスクロールすると最後のモードが見え、それ以上は進まない
+        let keys = [KeyCode::Char('j'); 200];
+        press(&mut app, &keys);
+        let lines = render_lines(&mut app, 80, 20);
+        assert!(lines[18].contains("Esc / V") && lines[18].contains("Leave message select"));
+
+        // 表示中のキーは他の操作に使われず、Esc で閉じる
+        assert_eq!(app.ui.selected_channel.as_deref(), Some(GENERAL_ID));
+        press(&mut app, &[KeyCode::Esc]);
+        assert!(!app.ui.help_open);
+        assert!(!render_lines(&mut app, 80, 20)[0].contains("Keybindings"));
+    }
+
+    #[test]
+    fn renders_messages_newest_at_bottom() {
+        let mut app = with_messages();
+        let lines = render_lines(&mut app, 60, 12);
+        // 全角の ⭐ は 2 セル幅なので、続くセルの空白の分だけ文字数が 1 つ少なく見える
+        let expected = [
+            "┌Favorites────┐┌ [Hakuhyo Dev] # general ──────────────────┐",
+            "│>> ⭐  HD [Hak││                                           │",
+            "│             ││                                           │",
+            "│             ││                                           │",
+            "│             ││[10:00] alice: hello                       │",
+            "└─────────────┘│[10:01] me: hi alice                       │",
+            "┌Unread (0)───┐│[10:02] bob: see you                       │",
+            "│             │└───────────────────────────────────────────┘",
+            "│             │┌Input to #general (Press 'i' to edit)──────┐",
+            "│             ││                                           │",
+            "│             │└───────────────────────────────────────────┘",
+            "└─────────────┘ Connected  me  Follow  online  ?: Help | q: ",
+        ];
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn long_histories_are_laid_out_only_around_the_viewport() {
+        let mut app = connected_app();
+        let messages = (0..1000)
+            .rev()
+            .map(|i| {
+                let author = if i % 2 == 0 { "alice" } else { "bob" };
+                let id = (10_000 + i).to_string();
+                message(&id, GENERAL_ID, author, &format!("message {}", i), "01:00")
+            })
+            .collect();
+        app.discord.messages.insert(GENERAL_ID.to_string(), messages);
+        let lines = render_lines(&mut app, 80, 12);
+        assert!(lines.iter().any(|l| l.contains("message 999")));
+        // 最古まで並べていないので、スクロールの上限はまだ分からない
+        assert_eq!(app.ui.cached_max_scroll_offset, usize::MAX);
+
+        // 最古へのジャンプでは最後まで並べて上限が決まる
+        press(&mut app, &[KeyCode::Char('g'), KeyCode::Char('g')]);
+        let lines = render_lines(&mut app, 80, 12);
+        assert!(lines.iter().any(|l| l.contains("message 0 ")));
+        assert!(app.ui.cached_max_scroll_offset < usize::MAX);
+        assert_eq!(app.ui.message_scroll_offset, app.ui.cached_max_scroll_offset);
+    }
+
+    #[test]
+    fn comfortable_density_puts_author_above_indented_body() {
+        let mut app = with_messages();
+        app.config.density = Density::Comfortable;
+        let lines = render_lines(&mut app, 60, 12);
+        let expected = [
+            "┌Favorites────┐┌ [Hakuhyo Dev] # general ──────────────────┐",
+            "│>> ⭐  HD [Hak││[10:00] alice                              │",
+            "│             ││          hello                            │",
+            "│             ││[10:01] me                                 │",
+            "│             ││          hi alice                         │",
+            "└─────────────┘│[10:02] bob                                │",
+            "┌Unread (0)───┐│          see you                          │",
+        ];
+        assert_eq!(lines[..7], expected);
+    }
+
+    #[test]
+    fn newest_first_order_puts_latest_at_top() {
+        let mut app = with_messages();
+        app.config.message_order = MessageOrder::NewestFirst;
+        let lines = render_lines(&mut app, 60, 12);
+        let expected = [
+            "┌Favorites────┐┌ [Hakuhyo Dev] # general ──────────────────┐",
+            "│>> ⭐  HD [Hak││[10:02] bob: see you                       │",
+            "│             ││[10:01] me: hi alice                       │",
+            "│             ││[10:00] alice: hello                       │",
+            "│             ││                                           │",
+        ];
+        assert_eq!(lines[..5], expected);
+
+        // 未読の境目は新着 (上) と既読 (下) の間に入る
+        app.ui.unread_boundaries.insert(GENERAL_ID.to_string(), "301".to_string());
+        let lines = render_lines(&mut app, 60, 12);
+        assert!(lines[3].contains("─ New messages ─"));
+        assert!(lines[4].contains("[10:00] alice: hello"));
+    }
+
+    #[test]
+    fn reopening_scrolls_to_the_last_read_position() {
+        let mut app = with_messages();
+        app.ui.unread_boundaries.insert(GENERAL_ID.to_string(), "301".to_string());
+        app.ui.scroll_to_unread = true;
+        // メッセージ欄は 2 行しかないので、境目を上端に置いて続きの 1 件目から見せる
+        let lines = render_lines(&mut app, 60, 8);
+        assert!(lines[1].contains("─ New messages ─"));
+        assert!(lines[2].contains("[10:01] me: hi alice"));
+        assert_eq!(app.ui.message_scroll_offset, 1);
+        assert!(!app.ui.scroll_to_unread);
+        // 画面に出た最新のメッセージを既読位置として記録する
+        assert_eq!(app.config.last_viewed[GENERAL_ID], "302");
+
+        // 描画そのものは設定を書き換えず、記録するイベントを返すだけ
+        app.config.last_viewed.clear();
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        let mut viewed = None;
+        terminal.draw(|f| viewed = render(f, &mut app)).unwrap();
+        assert!(app.config.last_viewed.is_empty());
+        assert!(matches!(
+            viewed,
+            Some(AppEvent::MessageViewed { channel_id, message_id })
+                if channel_id == GENERAL_ID && message_id == "302"
+        ));
+    }
+
+    #[test]
+    fn deleted_channel_shows_a_notice_instead_of_an_empty_pane() {
+        let mut app = with_messages();
+        app.discord.channels.remove(GENERAL_ID);
+        let lines = render_lines(&mut app, 80, 16);
+        assert!(lines.iter().any(|l| l.contains("This channel is no longer available")));
+        assert!(!lines.iter().any(|l| l.contains("see you") || l.contains("No messages")));
+    }
+
+    #[test]
+    fn ascii_mode_switches_every_glyph() {
+        let mut app = with_messages();
+        app.set_ascii_mode(true);
+        app.config.timestamp_layout = TimestampLayout::Gutter;
+        let lines = render_lines(&mut app, 60, 12);
+        assert!(lines[1].starts_with("│> * HD [Hakuh"));
+        assert!(lines[4].contains("│10:00 | alice: hello"));
+    }
+
+    #[test]
+    fn timestamp_layouts_place_loaded_messages() {
+        let mut app = connected_app();
+        app.update(AppEvent::MessagesLoaded {
+            channel_id: GENERAL_ID.to_string(),
+            messages: vec![
+                message("402", GENERAL_ID, "bob", "later", "13:05"),
+                message("401", GENERAL_ID, "alice", "early", "01:00"),
+            ],
+        });
+        let message_rows = |app: &mut AppState| -> Vec<String> {
+            render_lines(app, 80, 12)
+                .into_iter()
+                .filter(|l| l.contains("early") || l.contains("later"))
+                .collect()
+        };
+        let expect = |app: &mut AppState, rows: [&str; 2]| {
+            let lines = message_rows(app);
+            assert_eq!(lines.len(), 2);
+            for (line, row) in lines.iter().zip(rows) {
+                assert!(line.contains(&format!("│{}", row)), "{:?}", line);
+            }
+        };
+        expect(&mut app, ["[10:00] alice: early", "[22:05] bob: later"]);
+
+        app.config.timestamp_layout = TimestampLayout::Gutter;
+        app.config.clock_24h = false;
+        expect(&mut app, ["10:00 AM │ alice: early", "10:05 PM │ bob: later"]);
+
+        app.config.timestamp_layout = TimestampLayout::Hidden;
+        expect(&mut app, ["alice: early", "bob: later"]);
+    }
+
+    #[test]
+    fn guild_channels_get_a_colored_initial_badge() {
+        let mut app = connected_app();
+        let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+        terminal
+            .draw(|f| {
+                render(f, &mut app);
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        // "│>> ⭐  " の後ろに "HD" のバッジ (画像非対応端末なので頭文字)
+        let row: String = (0..15).map(|x| buffer[(x, 1)].symbol()).collect();
+        assert!(row.starts_with("│>> ⭐  HD [Hak"));
+        let x = (0..15).find(|&x| buffer[(x, 1)].symbol() == "H").unwrap();
+        let badge = &buffer[(x, 1)];
+        assert_eq!(badge.fg, Color::Black);
+        assert_ne!(badge.bg, Color::Reset);
+
+        app.config.guild_badges = false;
+        let lines = render_lines(&mut app, 80, 16);
+        assert!(lines[1].starts_with("│>> ⭐  [Hakuhy"));
+    }
+
+    #[test]
+    fn renders_search_overlay() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('r'), KeyCode::Char('a')]);
+        let lines = render_lines(&mut app, 80, 24);
+        // "ra" は general にも random にも一致する
+        assert!(lines[4].contains("┌ Search (2 results) ─"));
+        assert!(lines[5].contains("│ra "));
+        assert!(lines[8].contains(">> ⭐  [Hakuhyo Dev] # general"));
+        assert!(lines[9].contains("   [Hakuhyo Dev] # random"));
+        assert!(lines[23].contains("Esc: Exit search"));
+
+        press(&mut app, &[KeyCode::Char('n')]);
+        let lines = render_lines(&mut app, 80, 24);
+        assert!(lines[4].contains("┌ Search (1 results) ─"));
+        assert!(lines[8].contains(">> [Hakuhyo Dev] # random"));
+    }
+
+    #[test]
+    fn search_sidebar_setting_keeps_or_dims_the_favorites() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('/')]);
+        let lines = render_lines(&mut app, 80, 24);
+        assert!(lines[1].starts_with("│                  │"));
+
+        app.config.search_sidebar = SearchSidebar::Show;
+        let lines = render_lines(&mut app, 80, 24);
+        assert!(lines[1].starts_with("│⭐  HD [Hakuhyo Dev"));
+
+        app.config.search_sidebar = SearchSidebar::Dim;
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal
+            .draw(|f| {
+                render(f, &mut app);
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        // 選択中のチャンネル (普段は黄色) も灰色になる
+        assert_eq!(buffer[(1, 1)].symbol(), "⭐");
+        assert_eq!(buffer[(1, 1)].fg, Color::DarkGray);
+    }
+
+    #[test]
+    fn renders_editing_mode_with_typed_text() {
+        let mut app = with_messages();
+        press(&mut app, &[KeyCode::Char('i'), KeyCode::Char('h'), KeyCode::Char('i')]);
+        let lines = render_lines(&mut app, 80, 16);
+        assert!(lines[12].contains("Input to #general (Press Esc to exit, Enter to send)"));
+        assert!(lines[13].contains("│hi "));
+    }
+
+    #[test]
+    fn overlay_uses_whole_screen_on_tiny_terminal() {
+        let area = Rect::new(0, 0, 10, 5);
+        let overlay = overlay_rect(area);
+        assert!(overlay.width > 0 && overlay.height > 0);
+        assert_eq!(overlay, area);
+    }
+
+    #[test]
+    fn overlay_keeps_minimum_size_when_margins_would_shrink_it() {
+        let area = Rect::new(0, 0, 40, 10);
+        let overlay = overlay_rect(area);
+        assert_eq!((overlay.width, overlay.height), (OVERLAY_MIN_WIDTH, OVERLAY_MIN_HEIGHT));
+        assert_eq!(area.intersection(overlay), overlay);
+    }
+
+    #[test]
+    fn overlay_is_centered_with_margins_on_large_terminal() {
+        let area = Rect::new(0, 0, 100, 60);
+        assert_eq!(overlay_rect(area), Rect::new(20, 10, 60, 40));
     }
 }